@@ -43,6 +43,7 @@ pub mod test_packets {
     }
 
     include!(concat!(env!("OUT_DIR"), "/rust_test_packets.rs"));
+    include!(concat!(env!("OUT_DIR"), "/rust_test_packets_tests.rs"));
 }
 
 #[cfg(test)]
@@ -0,0 +1,323 @@
+//! Semantic helpers shared by the PDL backends.
+//!
+//! The functions in this module expect a grammar that was validated
+//! by the linter: all identifiers resolve to a declaration of the
+//! expected kind and the inheritance graph is acyclic.
+
+use std::collections::HashMap;
+
+use crate::ast::*;
+
+/// Gather information about the full grammar declaration.
+pub struct Scope<'d> {
+    /// Collection of Group, Packet, Enum, Struct, Checksum, and
+    /// CustomField declarations, indexed by identifier.
+    pub typedef: HashMap<String, &'d Decl>,
+
+    /// Packet and Struct declarations, indexed by parent identifier.
+    /// Children are listed in declaration order.
+    pub children: HashMap<String, Vec<&'d Decl>>,
+
+    /// Byte order used for all the declarations of the grammar.
+    pub endianness: EndiannessValue,
+}
+
+impl<'d> Scope<'d> {
+    pub fn new(grammar: &'d Grammar) -> Scope<'d> {
+        let mut typedef = HashMap::new();
+        let mut children: HashMap<String, Vec<&'d Decl>> = HashMap::new();
+        for decl in &grammar.declarations {
+            if let Some(id) = decl.id() {
+                typedef.insert(id.clone(), decl);
+            }
+            if let Some(parent_id) = decl.parent_id() {
+                children.entry(parent_id.clone()).or_default().push(decl);
+            }
+        }
+        Scope {
+            typedef,
+            children,
+            endianness: grammar
+                .endianness
+                .as_ref()
+                .map_or(EndiannessValue::LittleEndian, |e| e.value),
+        }
+    }
+
+    /// Return the parent declaration of a Packet or Struct declaration.
+    pub fn get_parent(&self, decl: &Decl) -> Option<&'d Decl> {
+        decl.parent_id().and_then(|id| self.typedef.get(id).copied())
+    }
+
+    /// Return the inheritance chain of a declaration, starting from
+    /// the root ancestor and ending with the declaration itself.
+    pub fn get_lineage(&self, decl: &'d Decl) -> Vec<&'d Decl> {
+        let mut lineage = vec![decl];
+        while let Some(parent) = self.get_parent(lineage.last().unwrap()) {
+            lineage.push(parent);
+        }
+        lineage.reverse();
+        lineage
+    }
+
+    /// Return the direct children of a Packet or Struct declaration.
+    pub fn get_children(&self, decl: &Decl) -> &[&'d Decl] {
+        decl.id().and_then(|id| self.children.get(id)).map_or(&[], |c| c.as_slice())
+    }
+
+    /// Look up a named field in a declaration or its ancestors.
+    pub fn get_field(&self, decl: &'d Decl, id: &str) -> Option<&'d Field> {
+        self.get_lineage(decl)
+            .into_iter()
+            .rev()
+            .flat_map(|d| d.fields())
+            .find(|f| f.id().map(|s| s.as_str()) == Some(id))
+    }
+
+    /// Return the static width in bits of a declaration,
+    /// or `None` if the declaration has a variable size.
+    pub fn get_decl_width(&self, decl: &Decl) -> Option<usize> {
+        match decl {
+            Decl::Enum { width, .. } | Decl::Checksum { width, .. } => Some(*width),
+            Decl::CustomField { width, .. } => *width,
+            Decl::Struct { .. } if decl.parent_id().is_none() => {
+                if !self.get_children(decl).is_empty() {
+                    return None;
+                }
+                decl.fields().map(|f| self.get_field_width(f)).sum()
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the static width in bits of a field,
+    /// or `None` if the field has a variable size.
+    pub fn get_field_width(&self, field: &Field) -> Option<usize> {
+        if field.cond().is_some() {
+            return None;
+        }
+        match field {
+            Field::Scalar { width, .. }
+            | Field::Reserved { width, .. }
+            | Field::Size { width, .. }
+            | Field::Count { width, .. } => Some(*width),
+            Field::Fixed { width: Some(width), .. } => Some(*width),
+            Field::Fixed { enum_id: Some(enum_id), .. } => {
+                self.typedef.get(enum_id).and_then(|d| self.get_decl_width(d))
+            }
+            Field::Fixed { .. } => None,
+            Field::Typedef { type_id, .. } => {
+                self.typedef.get(type_id).and_then(|d| self.get_decl_width(d))
+            }
+            Field::Array { size: Some(size), width: Some(width), .. } => Some(size * width),
+            Field::Array { size: Some(size), type_id: Some(type_id), .. } => self
+                .typedef
+                .get(type_id)
+                .and_then(|d| self.get_decl_width(d))
+                .map(|width| size * width),
+            Field::Array { .. } => None,
+            Field::Checksum { .. } => Some(0),
+            Field::Padding { .. }
+            | Field::Body { .. }
+            | Field::Payload { .. }
+            | Field::Group { .. } => None,
+        }
+    }
+
+    /// Return the static width in bits of the fields following
+    /// `fields[index]`, or `None` if any of them has a variable size.
+    /// Padding fields account for the array they complete.
+    pub fn get_trailing_width(&self, fields: &[&Field], index: usize) -> Option<usize> {
+        let mut width = 0;
+        let mut index = index + 1;
+        while index < fields.len() {
+            match (fields[index], fields.get(index + 1)) {
+                (Field::Array { .. }, Some(Field::Padding { width: padding, .. })) => {
+                    width += padding * 8;
+                    index += 1;
+                }
+                (Field::Padding { width: padding, .. }, _) => width += padding * 8,
+                (field, _) => width += self.get_field_width(field)?,
+            }
+            index += 1;
+        }
+        Some(width)
+    }
+
+    /// Return true if the field is a scalar value packed into
+    /// a bit chunk: scalar, fixed, reserved, size, count, enum and
+    /// checksum fields. Optional fields are not packed.
+    pub fn is_bitfield(&self, field: &Field) -> bool {
+        if field.cond().is_some() {
+            return false;
+        }
+        match field {
+            Field::Scalar { .. }
+            | Field::Fixed { .. }
+            | Field::Reserved { .. }
+            | Field::Size { .. }
+            | Field::Count { .. } => true,
+            Field::Typedef { type_id, .. } => matches!(
+                self.typedef.get(type_id),
+                Some(Decl::Enum { .. }) | Some(Decl::Checksum { .. })
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Evaluate a size modifier such as `+2*8`.
+/// The result is expressed in bits and is added to the actual size
+/// of the sized field to obtain the value of the size field.
+pub fn size_modifier_bits(modifier: &str) -> isize {
+    // Evaluate a sum of products, the grammar does not allow
+    // parentheses.
+    let mut sum = 0;
+    let mut sign = 1;
+    let mut term: Option<isize> = None;
+    let mut op = '*';
+    let mut number = String::new();
+    for c in modifier.chars().chain(std::iter::once('+')) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if !number.is_empty() {
+            let value = number.parse::<isize>().unwrap();
+            number.clear();
+            term = Some(match (term, op) {
+                (None, _) => value,
+                (Some(term), '/') => term / value,
+                (Some(term), _) => term * value,
+            });
+        }
+        match c {
+            '*' | '/' => op = c,
+            '+' | '-' => {
+                sum += sign * term.unwrap_or(0);
+                term = None;
+                op = '*';
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            _ => (),
+        }
+    }
+    sum
+}
+
+/// Replace Group fields by the fields of the referenced group
+/// declaration. Group constraints are substituted by Fixed fields.
+pub fn inline_groups(grammar: &Grammar) -> Grammar {
+    fn inline_fields<'a>(
+        groups: &HashMap<&String, &'a Decl>,
+        fields: impl Iterator<Item = &'a Field>,
+        constraints: &HashMap<String, &Constraint>,
+    ) -> Vec<Field> {
+        let mut inlined = vec![];
+        for field in fields {
+            match field {
+                Field::Group { group_id, constraints: group_constraints, .. } => {
+                    let mut constraints = constraints.clone();
+                    for c in group_constraints {
+                        constraints.insert(c.id.clone(), c);
+                    }
+                    inlined.extend(inline_fields(groups, groups[group_id].fields(), &constraints));
+                }
+                Field::Scalar { loc, id, width, .. } if constraints.contains_key(id) => inlined
+                    .push(Field::Fixed {
+                        loc: loc.clone(),
+                        width: Some(*width),
+                        value: constraints[id].value.evaluate().ok(),
+                        enum_id: None,
+                        tag_id: None,
+                    }),
+                Field::Typedef { loc, id, type_id, .. } if constraints.contains_key(id) => inlined
+                    .push(Field::Fixed {
+                        loc: loc.clone(),
+                        width: None,
+                        value: None,
+                        enum_id: Some(type_id.clone()),
+                        tag_id: match &constraints[id].value {
+                            Expr::Identifier { name, .. } => Some(name.clone()),
+                            _ => None,
+                        },
+                    }),
+                _ => inlined.push(field.clone()),
+            }
+        }
+        inlined
+    }
+
+    let groups = grammar
+        .declarations
+        .iter()
+        .filter(|d| matches!(d, Decl::Group { .. }))
+        .map(|d| (d.id().unwrap(), d))
+        .collect::<HashMap<_, _>>();
+
+    let mut inlined = Grammar::new(grammar.file);
+    inlined.version = grammar.version.clone();
+    inlined.comments = grammar.comments.clone();
+    inlined.endianness = grammar.endianness.clone();
+    inlined.declarations = grammar
+        .declarations
+        .iter()
+        .filter(|d| !matches!(d, Decl::Group { .. }))
+        .map(|d| match d {
+            Decl::Packet { id, loc, constraints, fields, parent_id } => Decl::Packet {
+                id: id.clone(),
+                loc: loc.clone(),
+                constraints: constraints.clone(),
+                fields: inline_fields(&groups, fields.iter(), &HashMap::new()),
+                parent_id: parent_id.clone(),
+            },
+            Decl::Struct { id, loc, constraints, fields, parent_id } => Decl::Struct {
+                id: id.clone(),
+                loc: loc.clone(),
+                constraints: constraints.clone(),
+                fields: inline_fields(&groups, fields.iter(), &HashMap::new()),
+                parent_id: parent_id.clone(),
+            },
+            _ => d.clone(),
+        })
+        .collect();
+    inlined
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analyzer::*;
+    use crate::parser::parse_inline;
+
+    #[test]
+    fn test_size_modifier_bits() {
+        assert_eq!(size_modifier_bits("+8"), 8);
+        assert_eq!(size_modifier_bits("+2*8"), 16);
+        assert_eq!(size_modifier_bits("-1*8"), -8);
+        assert_eq!(size_modifier_bits("+1*8+4"), 12);
+    }
+
+    #[test]
+    fn test_inline_groups() {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "stdin".to_owned(),
+            r#"
+            little_endian_packets
+            enum E : 8 { A = 1, B = 2 }
+            group G { a: 8, e: E }
+            packet P { G { a = 1, e = B }, b: 16 }
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+        let grammar = inline_groups(&grammar);
+        let scope = Scope::new(&grammar);
+        let fields = scope.typedef["P"].fields().collect::<Vec<_>>();
+        assert_eq!(fields.len(), 3);
+        assert!(matches!(fields[0], Field::Fixed { value: Some(1), .. }));
+        assert!(matches!(fields[1], Field::Fixed { tag_id: Some(t), .. } if t == "B"));
+        assert!(matches!(fields[2], Field::Scalar { .. }));
+    }
+}
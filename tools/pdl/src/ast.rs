@@ -1,6 +1,6 @@
 use codespan_reporting::diagnostic;
 use codespan_reporting::files;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::ops;
 
@@ -12,7 +12,12 @@ pub type FileId = usize;
 /// Stores the source file contents for reference.
 pub type SourceDatabase = files::SimpleFiles<String, String>;
 
-#[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+/// Version of the JSON representation of the AST, described by the
+/// schema `schema/ast.schema.json`. The minor version is incremented
+/// for backward compatible changes, the major version otherwise.
+pub const VERSION: &str = "1.2";
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SourceLocation {
     /// Byte offset into the file (counted from zero).
     pub offset: usize,
@@ -22,35 +27,43 @@ pub struct SourceLocation {
     pub column: usize,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct SourceRange {
     pub file: FileId,
     pub start: SourceLocation,
     pub end: SourceLocation,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", rename = "comment")]
 pub struct Comment {
     pub loc: SourceRange,
     pub text: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum EndiannessValue {
     LittleEndian,
     BigEndian,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", rename = "endianness_declaration")]
 pub struct Endianness {
     pub loc: SourceRange,
     pub value: EndiannessValue,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename = "import_declaration")]
+pub struct Import {
+    pub loc: SourceRange,
+    /// Path of the imported file, relative to the importing file.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind")]
 pub enum Expr {
     #[serde(rename = "identifier")]
@@ -63,15 +76,20 @@ pub enum Expr {
     Binary { loc: SourceRange, op: String, operands: Box<(Expr, Expr)> },
 }
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "kind", rename = "tag")]
-pub struct Tag {
-    pub id: String,
-    pub loc: SourceRange,
-    pub value: usize,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Tag {
+    #[serde(rename = "tag")]
+    Value { id: String, loc: SourceRange, value: usize },
+    /// Tag matching all the values of the inclusive range `start..end`.
+    #[serde(rename = "tag_range")]
+    Range { id: String, loc: SourceRange, start: usize, end: usize },
+    /// Tag matching all the values not matched by another tag.
+    #[serde(rename = "tag_default")]
+    Default { id: String, loc: SourceRange },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", rename = "constraint")]
 pub struct Constraint {
     pub id: String,
@@ -79,7 +97,7 @@ pub struct Constraint {
     pub value: Expr,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind")]
 pub enum Field {
     #[serde(rename = "checksum_field")]
@@ -114,21 +132,42 @@ pub enum Field {
         size: Option<usize>,
     },
     #[serde(rename = "scalar_field")]
-    Scalar { loc: SourceRange, id: String, width: usize },
+    Scalar {
+        loc: SourceRange,
+        id: String,
+        width: usize,
+        /// The field is present only when the condition holds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cond: Option<Constraint>,
+    },
     #[serde(rename = "typedef_field")]
-    Typedef { loc: SourceRange, id: String, type_id: String },
+    Typedef {
+        loc: SourceRange,
+        id: String,
+        type_id: String,
+        /// The field is present only when the condition holds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cond: Option<Constraint>,
+    },
     #[serde(rename = "group_field")]
     Group { loc: SourceRange, group_id: String, constraints: Vec<Constraint> },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", rename = "test_case")]
 pub struct TestCase {
     pub loc: SourceRange,
+    /// Input bytes, as a quoted string literal with `\xNN` escapes.
     pub input: String,
+    /// Expected specialization of the parsed packet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packet_id: Option<String>,
+    /// Expected values of the parsed fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<Constraint>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind")]
 pub enum Decl {
     #[serde(rename = "checksum_declaration")]
@@ -159,12 +198,16 @@ pub enum Decl {
     Test { loc: SourceRange, type_id: String, test_cases: Vec<TestCase> },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Grammar {
+    /// Version of the JSON representation, see [`VERSION`].
     pub version: String,
     pub file: FileId,
     pub comments: Vec<Comment>,
     pub endianness: Option<Endianness>,
+    pub imports: Vec<Import>,
+    /// Declarations of the grammar file, followed by the declarations
+    /// of the imported files.
     pub declarations: Vec<Decl>,
 }
 
@@ -222,12 +265,82 @@ impl ops::Add<SourceRange> for SourceRange {
     }
 }
 
+/// Return the precedence of a binary operator. Operators with
+/// a higher precedence bind tighter.
+pub fn binary_precedence(op: &str) -> usize {
+    match op {
+        "*" | "/" | "%" => 5,
+        "+" | "-" => 4,
+        "<<" | ">>" => 3,
+        "&" => 2,
+        "^" => 1,
+        _ => 0,
+    }
+}
+
+impl Expr {
+    pub fn loc(&self) -> &SourceRange {
+        match self {
+            Expr::Identifier { loc, .. }
+            | Expr::Integer { loc, .. }
+            | Expr::Unary { loc, .. }
+            | Expr::Binary { loc, .. } => loc,
+        }
+    }
+
+    /// Evaluate a constant integer expression.
+    /// Returns the location of the failing sub-expression and an error
+    /// message if the expression references an identifier, overflows,
+    /// or divides by zero.
+    pub fn evaluate(&self) -> Result<usize, (SourceRange, String)> {
+        let err = |message: &str| Err((self.loc().clone(), message.to_owned()));
+        match self {
+            Expr::Integer { value, .. } => Ok(*value),
+            Expr::Identifier { name, .. } => {
+                Err((self.loc().clone(), format!("`{}` is not a constant", name)))
+            }
+            Expr::Unary { op, operand, .. } => {
+                let value = operand.evaluate()?;
+                match op.as_str() {
+                    "-" if value == 0 => Ok(0),
+                    "-" => err("negative value"),
+                    "~" => Ok(!value),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Binary { op, operands, .. } => {
+                let (lhs, rhs) = (operands.0.evaluate()?, operands.1.evaluate()?);
+                let value = match op.as_str() {
+                    "+" => lhs.checked_add(rhs),
+                    "-" => lhs.checked_sub(rhs),
+                    "*" => lhs.checked_mul(rhs),
+                    "/" => lhs.checked_div(rhs),
+                    "%" => lhs.checked_rem(rhs),
+                    "<<" => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shl(rhs)),
+                    ">>" => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shr(rhs)),
+                    "&" => Some(lhs & rhs),
+                    "|" => Some(lhs | rhs),
+                    "^" => Some(lhs ^ rhs),
+                    _ => unreachable!(),
+                };
+                match (value, op.as_str()) {
+                    (Some(value), _) => Ok(value),
+                    (None, "/" | "%") => err("division by zero"),
+                    (None, "-") => err("negative value"),
+                    (None, _) => err("arithmetic overflow"),
+                }
+            }
+        }
+    }
+}
+
 impl Grammar {
     pub fn new(file: FileId) -> Grammar {
         Grammar {
-            version: "1,0".to_owned(),
+            version: VERSION.to_owned(),
             comments: vec![],
             endianness: None,
+            imports: vec![],
             declarations: vec![],
             file,
         }
@@ -258,6 +371,57 @@ impl Decl {
             | Decl::Group { id, .. } => Some(id),
         }
     }
+
+    pub fn parent_id(&self) -> Option<&String> {
+        match self {
+            Decl::Packet { parent_id, .. } | Decl::Struct { parent_id, .. } => parent_id.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn constraints(&self) -> impl Iterator<Item = &Constraint> {
+        match self {
+            Decl::Packet { constraints, .. } | Decl::Struct { constraints, .. } => {
+                Some(constraints.iter())
+            }
+            _ => None,
+        }
+        .into_iter()
+        .flatten()
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &Field> {
+        match self {
+            Decl::Packet { fields, .. }
+            | Decl::Struct { fields, .. }
+            | Decl::Group { fields, .. } => Some(fields.iter()),
+            _ => None,
+        }
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl Tag {
+    pub fn loc(&self) -> &SourceRange {
+        match self {
+            Tag::Value { loc, .. } | Tag::Range { loc, .. } | Tag::Default { loc, .. } => loc,
+        }
+    }
+
+    pub fn id(&self) -> &String {
+        match self {
+            Tag::Value { id, .. } | Tag::Range { id, .. } | Tag::Default { id, .. } => id,
+        }
+    }
+
+    /// Return the value of the tag, if the tag matches a single value.
+    pub fn value(&self) -> Option<usize> {
+        match self {
+            Tag::Value { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
 }
 
 impl Field {
@@ -294,12 +458,71 @@ impl Field {
             }
         }
     }
+
+    /// Return the presence condition of an optional field.
+    pub fn cond(&self) -> Option<&Constraint> {
+        match self {
+            Field::Scalar { cond, .. } | Field::Typedef { cond, .. } => cond.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl TestCase {
+    /// Return the input bytes of the test case. The characters of the
+    /// string literal stand for their UTF-8 encoding, except for the
+    /// escape sequences `\xNN` and `\\`.
+    pub fn bytes(&self) -> Result<Vec<u8>, String> {
+        let input = self.input.trim_matches('"');
+        let mut bytes = vec![];
+        let mut chars = input.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+            match chars.next() {
+                Some('x') => {
+                    let digits = chars.by_ref().take(2).collect::<String>();
+                    let byte = u8::from_str_radix(&digits, 16)
+                        .ok()
+                        .filter(|_| digits.len() == 2)
+                        .ok_or_else(|| format!("invalid escape sequence `\\x{}`", digits))?;
+                    bytes.push(byte);
+                }
+                Some('\\') => bytes.push(b'\\'),
+                next => {
+                    return Err(format!(
+                        "invalid escape sequence `\\{}`",
+                        next.map(String::from).unwrap_or_default()
+                    ))
+                }
+            }
+        }
+        Ok(bytes)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_case_bytes() {
+        let test_case = |input: &str| TestCase {
+            loc: SourceRange::default(),
+            input: input.to_owned(),
+            packet_id: None,
+            constraints: vec![],
+        };
+        assert_eq!(test_case(r#""\x00\x1f\xFF""#).bytes(), Ok(vec![0x00, 0x1f, 0xff]));
+        assert_eq!(test_case(r#""a\\é""#).bytes(), Ok(vec![b'a', b'\\', 0xc3, 0xa9]));
+        assert_eq!(test_case(r#""""#).bytes(), Ok(vec![]));
+        assert!(test_case(r#""\x0""#).bytes().is_err());
+        assert!(test_case(r#""\xzz""#).bytes().is_err());
+        assert!(test_case(r#""\n""#).bytes().is_err());
+    }
+
     #[test]
     fn source_location_new() {
         let line_starts = &[0, 20, 80, 120, 150];
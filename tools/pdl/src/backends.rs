@@ -0,0 +1,10 @@
+//! Compiler backends.
+
+pub mod cxx;
+pub mod docs;
+pub mod fuzz;
+pub mod json;
+pub mod python;
+pub mod rust;
+pub mod rust_tests;
+pub mod wireshark;
@@ -0,0 +1,1914 @@
+//! C++ header backend.
+//!
+//! Generates a header-only C++ implementation of the grammar, with the
+//! API of the packet library of `system/gd/packet`: each packet gets a
+//! `View` class validating and decoding the packet bytes, and a
+//! `Builder` class serializing the packet. Structs are generated as
+//! classes with public members and `Parse`, `Serialize` methods.
+//!
+//! Views validate the packet in a single pass when `IsValid` is
+//! called, recording the offsets of the fields; the getters decode
+//! the fields from the recorded offsets.
+//!
+//! Checksum and custom field declarations whose function is a
+//! directory, e.g. `"hci/"`, are included from the header
+//! `hci/<snake_case_type>.h` and referenced from the namespace of the
+//! directory under the root namespace, e.g. `::bluetooth::hci::Address`
+//! when generating the namespace `bluetooth::l2cap`.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::backends::rust::{to_camel_case, to_snake_case};
+
+/// Preprocessor condition guarding the methods used for testing.
+const TESTING: &str =
+    "#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)";
+
+/// Return the C++ integer type holding a value of `width` bits.
+fn scalar_type(width: usize) -> String {
+    let width = match width {
+        0..=8 => 8,
+        9..=16 => 16,
+        17..=32 => 32,
+        _ => 64,
+    };
+    format!("uint{}_t", width)
+}
+
+/// Return the mask of a value of `width` bits.
+fn mask(width: usize) -> String {
+    let mask = if width >= 64 { u64::MAX } else { (1 << width) - 1 };
+    format!("0x{:x}", mask)
+}
+
+/// Return the name of the getter of the field `id`.
+fn getter(id: &str) -> String {
+    format!("Get{}", to_camel_case(id))
+}
+
+/// Return the variable holding the size in bytes of the field
+/// `field_id`, as read from its size field.
+fn size_variable(field_id: &str) -> String {
+    match field_id {
+        "_payload_" | "_body_" => "payload_size".to_owned(),
+        _ => format!("{}_size", field_id),
+    }
+}
+
+/// Return the variable holding the element count of the array
+/// `field_id`, as read from its count field.
+fn count_variable(field_id: &str) -> String {
+    format!("{}_count", field_id)
+}
+
+/// Return the expression extracting a value of `width` bits at bit
+/// offset `shift` of a chunk of `chunk_width` bits.
+fn extract_bits(chunk: &str, shift: usize, width: usize, chunk_width: usize) -> String {
+    match shift {
+        0 if width == chunk_width => chunk.to_owned(),
+        0 => format!("({} & {})", chunk, mask(width)),
+        _ => format!("(({} >> {}) & {})", chunk, shift, mask(width)),
+    }
+}
+
+/// Write the statements reading `bytes` bytes from the iterator `it`
+/// into the integer variable `var`, in the byte order `endianness`.
+fn read_chunk(var: &str, it: &str, bytes: usize, endianness: &str, code: &mut String) {
+    let mut offset = 0;
+    while offset < bytes {
+        let piece = [8, 4, 2, 1].into_iter().find(|piece| offset + piece <= bytes).unwrap();
+        let read = format!("{}.extract<{}>()", it, scalar_type(piece * 8));
+        match (offset, endianness) {
+            (0, _) => writeln!(code, "uint64_t {} = {};", var, read),
+            (_, "kLittleEndian") => {
+                writeln!(code, "{} |= static_cast<uint64_t>({}) << {};", var, read, offset * 8)
+            }
+            _ => writeln!(code, "{} = ({} << {}) | {};", var, var, piece * 8, read),
+        }
+        .unwrap();
+        offset += piece;
+    }
+}
+
+/// Indent the generated code: two spaces per nested block, access
+/// specifiers are indented by one space less than the class members,
+/// and the statements following a case label by two spaces more.
+/// Namespaces and preprocessor directives are not indented.
+fn indent(code: &str) -> String {
+    let mut output = String::new();
+    // Indentation of the contents of each open block, and whether
+    // the block contains case labels.
+    let mut blocks: Vec<(usize, bool)> = vec![(0, false)];
+    for line in code.lines() {
+        let line = line.trim();
+        if line.starts_with('}') && !line.starts_with("}  // namespace") && blocks.len() > 1 {
+            blocks.pop();
+        }
+        let is_label = line.starts_with("case ") || line == "default:";
+        if is_label {
+            blocks.last_mut().unwrap().1 = true;
+        }
+        let (level, has_labels) = *blocks.last().unwrap();
+        let width = match line {
+            "" => 0,
+            _ if line.starts_with('#') => 0,
+            "public:" | "protected:" | "private:" => level.saturating_sub(1),
+            _ if has_labels && !is_label => level + 2,
+            _ => level,
+        };
+        writeln!(output, "{:width$}{}", "", line, width = width).unwrap();
+        if line.ends_with('{') && !line.starts_with("namespace ") {
+            blocks.push((width + 2, false));
+        }
+    }
+    output
+}
+
+/// Return the field `id` of the list `fields`.
+fn find_field<'d>(fields: &[&'d Field], id: &str) -> Option<&'d Field> {
+    fields.iter().copied().find(|f| f.id().map(String::as_str) == Some(id))
+}
+
+/// C++ representation of the values of a field or array element.
+#[derive(Clone, Copy)]
+enum ValueType<'d> {
+    Scalar(usize),
+    Enum(&'d str, usize),
+    Checksum(&'d str, usize),
+    Custom(&'d str, Option<usize>),
+    Struct(&'d str),
+}
+
+impl ValueType<'_> {
+    /// Return the C++ type of the values.
+    fn cxx_type(&self) -> String {
+        match self {
+            ValueType::Scalar(width) | ValueType::Checksum(_, width) => scalar_type(*width),
+            ValueType::Enum(id, _) | ValueType::Custom(id, _) | ValueType::Struct(id) => {
+                id.to_string()
+            }
+        }
+    }
+
+    /// Return the static width of the values decoded without calling
+    /// a `Parse` method.
+    fn static_width(&self) -> Option<usize> {
+        match self {
+            ValueType::Scalar(width)
+            | ValueType::Enum(_, width)
+            | ValueType::Checksum(_, width)
+            | ValueType::Custom(_, Some(width)) => Some(*width),
+            ValueType::Custom(_, None) | ValueType::Struct(_) => None,
+        }
+    }
+
+    /// Return the expression formatting the value `value` to a stream.
+    fn format(&self, value: &str) -> String {
+        match self {
+            ValueType::Scalar(_) | ValueType::Checksum(..) => format!("+{}", value),
+            ValueType::Enum(id, _) => format!("{}Text({})", id, value),
+            ValueType::Custom(..) | ValueType::Struct(_) => format!("{}.ToString()", value),
+        }
+    }
+}
+
+/// Fields of a declaration, grouped as they are read and written.
+enum Item<'d> {
+    /// Bit fields packed in a chunk of `width` bits, with their bit
+    /// offset in the chunk.
+    Chunk { fields: Vec<(&'d Field, usize)>, width: usize },
+    /// Any other field, with its index in the declaration.
+    Field(usize, &'d Field),
+}
+
+/// Destination of the fields decoded by [`Generator::parse_fields`].
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    /// The fields are stored in the members of the struct `to_fill`.
+    Struct,
+    /// The offsets of the fields are stored in the members of the view.
+    View,
+}
+
+impl Target {
+    /// Statement returning from the parser on failure.
+    fn fail(&self) -> &'static str {
+        match self {
+            Target::Struct => "return {};",
+            Target::View => "return false;",
+        }
+    }
+}
+
+struct Generator<'a, 'd> {
+    scope: &'a Scope<'d>,
+    /// Endianness parameter of the packet library templates.
+    endianness: &'static str,
+}
+
+impl<'a, 'd> Generator<'a, 'd> {
+    fn value_type(&self, field: &'d Field) -> Option<ValueType<'d>> {
+        match field {
+            Field::Scalar { width, .. } | Field::Array { width: Some(width), .. } => {
+                Some(ValueType::Scalar(*width))
+            }
+            Field::Typedef { type_id, .. } | Field::Array { type_id: Some(type_id), .. } => {
+                match self.scope.typedef.get(type_id) {
+                    Some(Decl::Enum { width, .. }) => Some(ValueType::Enum(type_id, *width)),
+                    Some(Decl::Checksum { width, .. }) => {
+                        Some(ValueType::Checksum(type_id, *width))
+                    }
+                    Some(Decl::CustomField { width, .. }) => {
+                        Some(ValueType::Custom(type_id, *width))
+                    }
+                    Some(Decl::Struct { .. }) => Some(ValueType::Struct(type_id)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the C++ type of a value field.
+    fn field_type(&self, field: &'d Field) -> String {
+        let value_type = self.value_type(field).unwrap().cxx_type();
+        match field {
+            Field::Array { size: Some(size), .. } => {
+                format!("std::array<{}, {}>", value_type, size)
+            }
+            Field::Array { .. } => format!("std::vector<{}>", value_type),
+            _ => value_type,
+        }
+    }
+
+    /// Return the C++ type of the builder parameter of a value field.
+    fn param_type(&self, field: &'d Field) -> String {
+        match (field, self.value_type(field)) {
+            (Field::Array { .. }, _)
+            | (_, Some(ValueType::Custom(..)))
+            | (_, Some(ValueType::Struct(_))) => format!("const {}&", self.field_type(field)),
+            _ => self.field_type(field),
+        }
+    }
+
+    /// Return true if the field has a getter in views and a member in
+    /// builders and structs.
+    fn is_value_field(&self, field: &'d Field) -> bool {
+        field.id().is_some() && !matches!(self.value_type(field), Some(ValueType::Checksum(..)))
+    }
+
+    fn tag_value(&self, enum_id: &str, tag_id: &str) -> usize {
+        match self.scope.typedef.get(enum_id) {
+            Some(Decl::Enum { tags, .. }) => {
+                tags.iter().find(|t| t.id() == tag_id).unwrap().value().unwrap()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the value of a constraint of the declaration `decl`.
+    fn constraint_value(&self, decl: &'d Decl, constraint: &Constraint) -> Result<usize, String> {
+        match (&constraint.value, self.scope.get_field(decl, &constraint.id)) {
+            (Expr::Identifier { name, .. }, Some(Field::Typedef { type_id, .. })) => {
+                Ok(self.tag_value(type_id, name))
+            }
+            (expr, _) => expr.evaluate().map_err(|(_, message)| message),
+        }
+    }
+
+    /// Return the C++ expression of the value of a constraint, passed
+    /// to the builder of the parent declaration.
+    fn constraint_expr(&self, decl: &'d Decl, constraint: &Constraint) -> Result<String, String> {
+        match (&constraint.value, self.scope.get_field(decl, &constraint.id)) {
+            (Expr::Identifier { name, .. }, Some(Field::Typedef { type_id, .. })) => {
+                Ok(format!("{}::{}", type_id, name))
+            }
+            _ => Ok(format!("0x{:x}", self.constraint_value(decl, constraint)?)),
+        }
+    }
+
+    /// Group the fields of the declaration in chunks of bit fields.
+    fn items(&self, decl: &'d Decl) -> Result<Vec<Item<'d>>, String> {
+        let id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let mut items = vec![];
+        let mut chunk = vec![];
+        let mut chunk_width = 0;
+        for (index, field) in fields.iter().copied().enumerate() {
+            if self.scope.is_bitfield(field) {
+                chunk.push((field, chunk_width));
+                chunk_width += self.scope.get_field_width(field).unwrap();
+                match (chunk_width / 8, chunk_width % 8) {
+                    (9.., _) => {
+                        return Err(format!("{}: bit fields spanning more than 64 bits", id))
+                    }
+                    (_, 0) => {
+                        items.push(Item::Chunk { fields: chunk, width: chunk_width });
+                        chunk = vec![];
+                        chunk_width = 0;
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+            if !chunk.is_empty() {
+                return Err(format!("{}: bit fields are not aligned to an octet boundary", id));
+            }
+            match field {
+                Field::Padding { .. }
+                    if !matches!(fields.get(index.wrapping_sub(1)), Some(Field::Array { .. })) =>
+                {
+                    return Err(format!("{}: padding field not following an array", id))
+                }
+                Field::Scalar { .. } | Field::Typedef { .. } if field.cond().is_some() => {
+                    return Err(format!("{}: conditional fields are not supported", id))
+                }
+                Field::Typedef { type_id, .. }
+                    if !matches!(
+                        self.scope.typedef.get(type_id),
+                        Some(Decl::Struct { .. } | Decl::CustomField { .. })
+                    ) =>
+                {
+                    return Err(format!("{}: unsupported typedef field type `{}`", id, type_id))
+                }
+                _ => items.push(Item::Field(index, field)),
+            }
+        }
+        if !chunk.is_empty() {
+            return Err(format!("{}: bit fields are not aligned to an octet boundary", id));
+        }
+        Ok(items)
+    }
+
+    /// Return the chunk containing the bit field `field` of the
+    /// declaration `decl` or its ancestors: the bit offset of the
+    /// field and the width of the chunk.
+    fn chunk_location(&self, decl: &'d Decl, field: &Field) -> (usize, usize) {
+        for decl in self.scope.get_lineage(decl) {
+            for item in self.items(decl).unwrap() {
+                if let Item::Chunk { fields, width } = item {
+                    if let Some((_, shift)) = fields.iter().find(|(f, _)| std::ptr::eq(*f, field)) {
+                        return (*shift, width);
+                    }
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    /// Return the size modifier of the array or payload `field_id`,
+    /// in bytes.
+    fn size_modifier(&self, fields: &[&'d Field], field_id: &str) -> isize {
+        let size_modifier = fields.iter().find_map(|f| match f {
+            Field::Array { id, size_modifier, .. } if id == field_id => size_modifier.as_ref(),
+            Field::Payload { size_modifier, .. } if field_id == "_payload_" => {
+                size_modifier.as_ref()
+            }
+            _ => None,
+        });
+        size_modifier.map_or(0, |m| analyzer::size_modifier_bits(m) / 8)
+    }
+
+    /// Write the statements declaring `elem`, decoded from the
+    /// iterator `it`.
+    fn parse_element(&self, value_type: ValueType, it: &str, fail: &str, code: &mut String) {
+        if let Some(width) = value_type.static_width() {
+            writeln!(code, "if ({}.NumBytesRemaining() < {}) {{", it, width / 8).unwrap();
+            writeln!(code, "{}", fail).unwrap();
+            writeln!(code, "}}").unwrap();
+        }
+        let ty = value_type.cxx_type();
+        match value_type {
+            ValueType::Scalar(width) | ValueType::Enum(_, width) => {
+                let read = match width {
+                    8 | 16 | 32 | 64 => format!("{}.extract<{}>()", it, scalar_type(width)),
+                    _ => {
+                        read_chunk("value", it, width / 8, self.endianness, code);
+                        "value".to_owned()
+                    }
+                };
+                writeln!(code, "{} elem = static_cast<{}>({});", ty, ty, read).unwrap();
+            }
+            ValueType::Custom(_, Some(_)) => {
+                writeln!(code, "{} elem = {}.extract<{}>();", ty, it, ty).unwrap();
+            }
+            _ => {
+                writeln!(code, "{} elem;", ty).unwrap();
+                writeln!(code, "auto elem_it = {}::Parse(&elem, {});", ty, it).unwrap();
+                writeln!(code, "if (!elem_it) {{").unwrap();
+                writeln!(code, "{}", fail).unwrap();
+                writeln!(code, "}}").unwrap();
+                writeln!(code, "{} = *elem_it;", it).unwrap();
+            }
+        }
+    }
+
+    /// Write the statements decoding a chunk of bit fields from the
+    /// iterator `it`.
+    fn parse_chunk(
+        &self,
+        fields: &[&'d Field],
+        chunk: &[(&'d Field, usize)],
+        width: usize,
+        var: &str,
+        target: Target,
+        code: &mut String,
+    ) {
+        let fail = target.fail();
+        writeln!(code, "if (it.NumBytesRemaining() < {}) {{", width / 8).unwrap();
+        writeln!(code, "{}", fail).unwrap();
+        writeln!(code, "}}").unwrap();
+        if target == Target::View {
+            for (field, _) in chunk.iter().filter(|(field, _)| self.is_value_field(field)) {
+                let id = field.id().unwrap();
+                writeln!(code, "{}_offset_ = end - it.NumBytesRemaining();", id).unwrap();
+            }
+        }
+        for (field, _) in chunk {
+            if let Some(ValueType::Checksum(..)) = self.value_type(field) {
+                writeln!(code, "auto {}_end = it;", field.id().unwrap()).unwrap();
+            }
+        }
+
+        // The value of the chunk is only read if needed.
+        let is_read = |field: &Field| match field {
+            Field::Scalar { .. } | Field::Typedef { .. } => {
+                target == Target::Struct
+                    || matches!(self.value_type(field), Some(ValueType::Checksum(..)))
+            }
+            Field::Reserved { .. } => false,
+            _ => true,
+        };
+        if !chunk.iter().any(|(field, _)| is_read(field)) {
+            writeln!(code, "it += {};", width / 8).unwrap();
+            return;
+        }
+        read_chunk(var, "it", width / 8, self.endianness, code);
+
+        for (field, shift) in chunk {
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let value = extract_bits(var, *shift, field_width, width);
+            match field {
+                Field::Scalar { id, .. } | Field::Typedef { id, .. } => {
+                    match self.value_type(field).unwrap() {
+                        ValueType::Checksum(type_id, checksum_width) => {
+                            writeln!(code, "{} {}_checksum;", type_id, id).unwrap();
+                            writeln!(code, "{}_checksum.Initialize();", id).unwrap();
+                            writeln!(
+                                code,
+                                "for (auto byte_it = {}_start; byte_it < {}_end; ++byte_it) {{",
+                                id, id
+                            )
+                            .unwrap();
+                            writeln!(code, "{}_checksum.AddByte(*byte_it);", id).unwrap();
+                            writeln!(code, "}}").unwrap();
+                            writeln!(
+                                code,
+                                "if ({}_checksum.GetChecksum() != static_cast<{}>({})) {{",
+                                id,
+                                scalar_type(checksum_width),
+                                value
+                            )
+                            .unwrap();
+                            writeln!(code, "{}", fail).unwrap();
+                            writeln!(code, "}}").unwrap();
+                        }
+                        value_type if target == Target::Struct => {
+                            let ty = value_type.cxx_type();
+                            writeln!(code, "to_fill->{}_ = static_cast<{}>({});", id, ty, value)
+                                .unwrap();
+                        }
+                        _ => (),
+                    }
+                }
+                Field::Fixed { value: fixed_value, enum_id, tag_id, .. } => {
+                    let expected = match (fixed_value, enum_id, tag_id) {
+                        (Some(value), _, _) => *value,
+                        (_, Some(enum_id), Some(tag_id)) => self.tag_value(enum_id, tag_id),
+                        _ => unreachable!(),
+                    };
+                    writeln!(code, "if ({} != 0x{:x}) {{", value, expected).unwrap();
+                    writeln!(code, "{}", fail).unwrap();
+                    writeln!(code, "}}").unwrap();
+                }
+                Field::Size { field_id, .. } => {
+                    let var = size_variable(field_id);
+                    writeln!(code, "size_t {} = {};", var, value).unwrap();
+                    match self.size_modifier(fields, field_id) {
+                        0 => (),
+                        modifier if modifier > 0 => {
+                            writeln!(code, "if ({} < {}) {{", var, modifier).unwrap();
+                            writeln!(code, "{}", fail).unwrap();
+                            writeln!(code, "}}").unwrap();
+                            writeln!(code, "{} -= {};", var, modifier).unwrap();
+                        }
+                        modifier => writeln!(code, "{} += {};", var, -modifier).unwrap(),
+                    }
+                }
+                Field::Count { field_id, .. } => {
+                    writeln!(code, "size_t {} = {};", count_variable(field_id), value).unwrap();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Write the statements decoding an array field from the
+    /// iterator `it`.
+    fn parse_array(
+        &self,
+        fields: &[&'d Field],
+        index: usize,
+        target: Target,
+        code: &mut String,
+    ) -> Result<(), String> {
+        let field = fields[index];
+        let (id, size) = match field {
+            Field::Array { id, size, .. } => (id, size),
+            _ => unreachable!(),
+        };
+        let fail = target.fail();
+        let value_type = self.value_type(field).unwrap();
+        let static_width = value_type.static_width();
+        let padding = match fields.get(index + 1) {
+            Some(Field::Padding { width, .. }) => Some(*width),
+            _ => None,
+        };
+        let has_count_field =
+            fields.iter().any(|f| matches!(f, Field::Count { field_id, .. } if field_id == id));
+        let has_size_field =
+            fields.iter().any(|f| matches!(f, Field::Size { field_id, .. } if field_id == id));
+        let count = match size {
+            Some(size) => Some(size.to_string()),
+            None if has_count_field => Some(count_variable(id)),
+            None => None,
+        };
+        if target == Target::View {
+            writeln!(code, "{}_offset_ = end - it.NumBytesRemaining();", id).unwrap();
+        }
+
+        // The elements are decoded from a byte region bounded by the
+        // padding, the size field or the trailing fields, or from the
+        // count of elements.
+        let region = match (padding, has_size_field) {
+            (Some(padding), _) => Some(padding.to_string()),
+            (None, _) if count.is_some() => None,
+            (None, true) => Some(size_variable(id)),
+            (None, false) => {
+                let trailing = self.trailing_size(fields, index)?;
+                Some(self.remaining_size(id, trailing, fail, code))
+            }
+        };
+        // The elements of a padded array with a size field are decoded
+        // from the beginning of the padding region.
+        let bound = match (padding, has_size_field, &count) {
+            (Some(padding), true, None) => {
+                writeln!(code, "if ({} > {}) {{", size_variable(id), padding).unwrap();
+                writeln!(code, "{}", fail).unwrap();
+                writeln!(code, "}}").unwrap();
+                Some(size_variable(id))
+            }
+            _ => region.clone(),
+        };
+        let it = match (&region, &bound) {
+            (Some(region), Some(bound)) => {
+                if padding.is_some() || has_size_field {
+                    writeln!(code, "if (it.NumBytesRemaining() < {}) {{", region).unwrap();
+                    writeln!(code, "{}", fail).unwrap();
+                    writeln!(code, "}}").unwrap();
+                }
+                writeln!(code, "auto {}_it = it.Subrange(0, {});", id, bound).unwrap();
+                writeln!(code, "it += {};", region).unwrap();
+                format!("{}_it", id)
+            }
+            _ => "it".to_owned(),
+        };
+
+        let store = match (target, size) {
+            (Target::Struct, Some(_)) => format!("to_fill->{}_[n] = elem;\n", id),
+            (Target::Struct, None) => format!("to_fill->{}_.push_back(elem);\n", id),
+            (Target::View, _) => String::new(),
+        };
+        match (&count, target, static_width) {
+            // The views do not decode elements with a static width:
+            // only the size of the array is checked.
+            (Some(count), Target::View, Some(width)) => {
+                let bytes = match size {
+                    Some(size) => (size * width / 8).to_string(),
+                    None if width == 8 => count.clone(),
+                    None => format!("{} * {}", count, width / 8),
+                };
+                writeln!(code, "if ({}.NumBytesRemaining() < {}) {{", it, bytes).unwrap();
+                writeln!(code, "{}", fail).unwrap();
+                writeln!(code, "}}").unwrap();
+                writeln!(code, "{} += {};", it, bytes).unwrap();
+            }
+            (None, Target::View, Some(width)) if width > 8 => {
+                writeln!(code, "if ({}.NumBytesRemaining() % {} != 0) {{", it, width / 8).unwrap();
+                writeln!(code, "{}", fail).unwrap();
+                writeln!(code, "}}").unwrap();
+            }
+            (None, Target::View, Some(_)) => (),
+            (Some(count), _, _) => {
+                writeln!(code, "for (size_t n = 0; n < {}; n++) {{", count).unwrap();
+                self.parse_element(value_type, &it, fail, code);
+                code.push_str(&store);
+                writeln!(code, "}}").unwrap();
+            }
+            (None, _, _) => {
+                writeln!(code, "while ({}.NumBytesRemaining() > 0) {{", it).unwrap();
+                self.parse_element(value_type, &it, fail, code);
+                code.push_str(&store);
+                writeln!(code, "}}").unwrap();
+            }
+        }
+
+        if target == Target::View {
+            match (padding, &count, &bound) {
+                (Some(padding), Some(_), _) => writeln!(
+                    code,
+                    "{}_end_ = {}_offset_ + {} - {}_it.NumBytesRemaining();",
+                    id, id, padding, id
+                ),
+                (_, None, Some(bound)) => {
+                    writeln!(code, "{}_end_ = {}_offset_ + {};", id, id, bound)
+                }
+                _ => writeln!(code, "{}_end_ = end - it.NumBytesRemaining();", id),
+            }
+            .unwrap();
+        }
+        Ok(())
+    }
+
+    /// Return the size in bytes of the fields following the field at
+    /// `index`, which must have a static size.
+    fn trailing_size(&self, fields: &[&'d Field], index: usize) -> Result<usize, String> {
+        self.scope.get_trailing_width(fields, index).map(|width| width / 8).ok_or_else(|| {
+            format!("{}: variable size field declared after a payload or unbounded array", {
+                fields[index].id().map_or("payload", |id| id.as_str())
+            })
+        })
+    }
+
+    /// Write the statements declaring the variable `<id>_size`, the
+    /// number of bytes remaining before `trailing` bytes, and return
+    /// the variable.
+    fn remaining_size(&self, id: &str, trailing: usize, fail: &str, code: &mut String) -> String {
+        let var = size_variable(id);
+        match trailing {
+            0 => writeln!(code, "size_t {} = it.NumBytesRemaining();", var).unwrap(),
+            _ => {
+                writeln!(code, "if (it.NumBytesRemaining() < {}) {{", trailing).unwrap();
+                writeln!(code, "{}", fail).unwrap();
+                writeln!(code, "}}").unwrap();
+                writeln!(code, "size_t {} = it.NumBytesRemaining() - {};", var, trailing).unwrap();
+            }
+        }
+        var
+    }
+
+    /// Write the statements decoding the fields of the declaration
+    /// `decl` from the iterator `it`.
+    fn parse_fields(
+        &self,
+        decl: &'d Decl,
+        target: Target,
+        code: &mut String,
+    ) -> Result<(), String> {
+        let decl_id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let fail = target.fail();
+        for (chunk_index, item) in self.items(decl)?.into_iter().enumerate() {
+            let (index, field) = match item {
+                Item::Chunk { fields: chunk, width } => {
+                    let var = format!("chunk{}", chunk_index);
+                    self.parse_chunk(&fields, &chunk, width, &var, target, code);
+                    continue;
+                }
+                Item::Field(index, field) => (index, field),
+            };
+            match (field, self.value_type(field)) {
+                (Field::Typedef { id, .. }, Some(ValueType::Custom(type_id, Some(width)))) => {
+                    writeln!(code, "if (it.NumBytesRemaining() < {}) {{", width / 8).unwrap();
+                    writeln!(code, "{}", fail).unwrap();
+                    writeln!(code, "}}").unwrap();
+                    match target {
+                        Target::Struct => {
+                            writeln!(code, "to_fill->{}_ = it.extract<{}>();", id, type_id)
+                        }
+                        Target::View => {
+                            writeln!(code, "{}_offset_ = end - it.NumBytesRemaining();", id)
+                                .unwrap();
+                            writeln!(code, "it += {};", width / 8)
+                        }
+                    }
+                    .unwrap();
+                }
+                (Field::Typedef { id, .. }, Some(value_type)) => {
+                    let ty = value_type.cxx_type();
+                    match target {
+                        Target::Struct => {
+                            writeln!(
+                                code,
+                                "auto {}_it = {}::Parse(&to_fill->{}_, it);",
+                                id, ty, id
+                            )
+                            .unwrap();
+                        }
+                        Target::View => {
+                            writeln!(code, "{}_offset_ = end - it.NumBytesRemaining();", id)
+                                .unwrap();
+                            writeln!(code, "{} {}_value;", ty, id).unwrap();
+                            writeln!(code, "auto {}_it = {}::Parse(&{}_value, it);", id, ty, id)
+                                .unwrap();
+                        }
+                    }
+                    writeln!(code, "if (!{}_it) {{", id).unwrap();
+                    writeln!(code, "{}", fail).unwrap();
+                    writeln!(code, "}}").unwrap();
+                    writeln!(code, "it = *{}_it;", id).unwrap();
+                    if target == Target::View {
+                        writeln!(code, "{}_end_ = end - it.NumBytesRemaining();", id).unwrap();
+                    }
+                }
+                (Field::Array { .. }, _) => self.parse_array(&fields, index, target, code)?,
+                (Field::Payload { .. } | Field::Body { .. }, _) if target == Target::View => {
+                    let field_id = match field {
+                        Field::Payload { .. } => "_payload_",
+                        _ => "_body_",
+                    };
+                    let has_size_field = fields
+                        .iter()
+                        .any(|f| matches!(f, Field::Size { field_id: id, .. } if id == field_id));
+                    let region = match has_size_field {
+                        true => {
+                            let region = size_variable(field_id);
+                            writeln!(code, "if (it.NumBytesRemaining() < {}) {{", region).unwrap();
+                            writeln!(code, "{}", fail).unwrap();
+                            writeln!(code, "}}").unwrap();
+                            region
+                        }
+                        false => {
+                            let trailing = self.trailing_size(&fields, index)?;
+                            self.remaining_size(field_id, trailing, fail, code)
+                        }
+                    };
+                    writeln!(code, "payload_begin_ = end - it.NumBytesRemaining();").unwrap();
+                    writeln!(code, "it += {};", region).unwrap();
+                    writeln!(code, "payload_end_ = end - it.NumBytesRemaining();").unwrap();
+                }
+                (Field::Payload { .. } | Field::Body { .. }, _) => {
+                    return Err(format!("{}: struct payloads are not supported", decl_id))
+                }
+                (Field::Checksum { field_id, .. }, _) => {
+                    writeln!(code, "auto {}_start = it;", field_id).unwrap();
+                }
+                (Field::Padding { .. }, _) => (),
+                _ => return Err(format!("{}: unsupported field {:?}", decl_id, field)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the statements declaring the variable `var`, the size in
+    /// bytes of the elements of the array member `<id>_`.
+    fn array_size(&self, field: &'d Field, var: &str, code: &mut String) {
+        let id = field.id().unwrap();
+        match self.value_type(field).unwrap().static_width() {
+            Some(8) => writeln!(code, "size_t {} = {}_.size();", var, id).unwrap(),
+            Some(width) => {
+                writeln!(code, "size_t {} = {}_.size() * {};", var, id, width / 8).unwrap()
+            }
+            None => {
+                writeln!(code, "size_t {} = 0;", var).unwrap();
+                writeln!(code, "for (const auto& elem : {}_) {{", id).unwrap();
+                writeln!(code, "{} += elem.size();", var).unwrap();
+                writeln!(code, "}}").unwrap();
+            }
+        }
+    }
+
+    /// Write the statements serializing the fields `items` of the
+    /// declaration with the fields `fields` to the inserter `i`.
+    fn serialize_items(&self, fields: &[&'d Field], items: &[Item<'d>], code: &mut String) {
+        for item in items {
+            let (index, field) = match item {
+                Item::Chunk { fields: chunk, width } => {
+                    self.serialize_chunk(fields, chunk, *width, code);
+                    continue;
+                }
+                Item::Field(index, field) => (*index, *field),
+            };
+            match (field, self.value_type(field)) {
+                (Field::Typedef { id, .. }, Some(ValueType::Custom(_, Some(_)))) => {
+                    writeln!(code, "insert({}_, i);", id).unwrap();
+                }
+                (Field::Typedef { id, .. }, _) => writeln!(code, "{}_.Serialize(i);", id).unwrap(),
+                (Field::Array { id, .. }, Some(value_type)) => {
+                    writeln!(code, "for (const auto& elem : {}_) {{", id).unwrap();
+                    match value_type {
+                        ValueType::Scalar(8) => writeln!(code, "i.insert_byte(elem);"),
+                        ValueType::Scalar(width) => writeln!(code, "insert(elem, i, {});", width),
+                        ValueType::Enum(_, width) => writeln!(
+                            code,
+                            "insert(static_cast<{}>(elem), i, {});",
+                            scalar_type(width),
+                            width
+                        ),
+                        ValueType::Custom(_, Some(_)) => writeln!(code, "insert(elem, i);"),
+                        _ => writeln!(code, "elem.Serialize(i);"),
+                    }
+                    .unwrap();
+                    writeln!(code, "}}").unwrap();
+                    if let Some(Field::Padding { width, .. }) = fields.get(index + 1) {
+                        let var = format!("{}_bytes", id);
+                        self.array_size(field, &var, code);
+                        writeln!(code, "ASSERT({} <= {});", var, width).unwrap();
+                        writeln!(code, "for (size_t n = {}; n < {}; n++) {{", var, width).unwrap();
+                        writeln!(code, "i.insert_byte(0);").unwrap();
+                        writeln!(code, "}}").unwrap();
+                    }
+                }
+                (Field::Checksum { field_id, .. }, _) => {
+                    let type_id = match find_field(fields, field_id) {
+                        Some(Field::Typedef { type_id, .. }) => type_id,
+                        _ => unreachable!(),
+                    };
+                    let checksum = format!("{}_checksum", field_id);
+                    writeln!(code, "auto {} = std::make_shared<{}>();", checksum, type_id).unwrap();
+                    writeln!(code, "{}->Initialize();", checksum).unwrap();
+                    writeln!(
+                        code,
+                        "auto {}_add_byte = [{}](uint8_t byte) {{ {}->AddByte(byte); }};",
+                        field_id, checksum, checksum
+                    )
+                    .unwrap();
+                    writeln!(
+                        code,
+                        "auto {}_get_value = [{}]() {{ return static_cast<uint64_t>({}->GetChecksum()); }};",
+                        field_id, checksum, checksum
+                    )
+                    .unwrap();
+                    writeln!(
+                        code,
+                        "i.RegisterObserver(ByteObserver({}_add_byte, {}_get_value));",
+                        field_id, field_id
+                    )
+                    .unwrap();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Write the statements serializing a chunk of bit fields.
+    fn serialize_chunk(
+        &self,
+        fields: &[&'d Field],
+        chunk: &[(&'d Field, usize)],
+        width: usize,
+        code: &mut String,
+    ) {
+        let mut terms = vec![];
+        for (field, shift) in chunk {
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let term = match field {
+                Field::Scalar { id, .. } | Field::Typedef { id, .. } => {
+                    match self.value_type(field).unwrap() {
+                        ValueType::Checksum(..) => {
+                            writeln!(
+                                code,
+                                "uint64_t {}_value = i.UnregisterObserver().GetValue();",
+                                id
+                            )
+                            .unwrap();
+                            format!("{}_value", id)
+                        }
+                        ValueType::Scalar(8 | 16 | 32 | 64) | ValueType::Enum(..) => {
+                            format!("static_cast<uint64_t>({}_)", id)
+                        }
+                        _ => format!("(static_cast<uint64_t>({}_) & {})", id, mask(field_width)),
+                    }
+                }
+                Field::Fixed { value: Some(value), .. } => {
+                    format!("static_cast<uint64_t>(0x{:x})", value)
+                }
+                Field::Fixed { enum_id: Some(enum_id), tag_id: Some(tag_id), .. } => {
+                    format!("static_cast<uint64_t>(0x{:x})", self.tag_value(enum_id, tag_id))
+                }
+                Field::Size { field_id, .. } => {
+                    let var = size_variable(field_id);
+                    match field_id.as_str() {
+                        "_payload_" | "_body_" => {
+                            writeln!(code, "size_t {} = GetPayloadSize();", var).unwrap()
+                        }
+                        _ => {
+                            let array = find_field(fields, field_id).unwrap();
+                            self.array_size(array, &var, code)
+                        }
+                    }
+                    let value = match self.size_modifier(fields, field_id) {
+                        0 => var,
+                        modifier if modifier > 0 => format!("{} + {}", var, modifier),
+                        modifier => format!("{} - {}", var, -modifier),
+                    };
+                    if field_width < 64 {
+                        writeln!(
+                            code,
+                            "ASSERT({} < (static_cast<uint64_t>(1) << {}));",
+                            value, field_width
+                        )
+                        .unwrap();
+                    }
+                    format!("static_cast<uint64_t>({})", value)
+                }
+                Field::Count { field_id, .. } => {
+                    if field_width < 64 {
+                        writeln!(
+                            code,
+                            "ASSERT({}_.size() < (static_cast<uint64_t>(1) << {}));",
+                            field_id, field_width
+                        )
+                        .unwrap();
+                    }
+                    format!("static_cast<uint64_t>({}_.size())", field_id)
+                }
+                _ => continue,
+            };
+            terms.push(match shift {
+                0 => term,
+                _ => format!("({} << {})", term, shift),
+            });
+        }
+        let value = match terms.is_empty() {
+            true => "static_cast<uint64_t>(0)".to_owned(),
+            false => terms.join(" | "),
+        };
+        writeln!(code, "insert({}, i, {});", value, width).unwrap();
+    }
+
+    /// Write the statements computing the size in bits of the fields
+    /// `items` into the variable `bits`, initialized with `base`.
+    fn size_items(&self, fields: &[&'d Field], items: &[Item<'d>], base: &str, code: &mut String) {
+        let mut static_bits = 0;
+        let mut dynamic = String::new();
+        for item in items {
+            let (index, field) = match item {
+                Item::Chunk { width, .. } => {
+                    static_bits += width;
+                    continue;
+                }
+                Item::Field(index, field) => (*index, *field),
+            };
+            match (field, self.value_type(field)) {
+                (Field::Typedef { .. }, Some(ValueType::Custom(_, Some(width)))) => {
+                    static_bits += width
+                }
+                (Field::Typedef { id, .. }, _) => {
+                    writeln!(dynamic, "bits += {}_.size() * 8;", id).unwrap()
+                }
+                (Field::Array { .. }, _)
+                    if matches!(fields.get(index + 1), Some(Field::Padding { .. })) => {}
+                (Field::Array { id, .. }, Some(value_type)) => match value_type.static_width() {
+                    Some(width) => {
+                        writeln!(dynamic, "bits += {}_.size() * {};", id, width).unwrap()
+                    }
+                    None => {
+                        writeln!(dynamic, "for (const auto& elem : {}_) {{", id).unwrap();
+                        writeln!(dynamic, "bits += elem.size() * 8;").unwrap();
+                        writeln!(dynamic, "}}").unwrap();
+                    }
+                },
+                (Field::Padding { width, .. }, _) => static_bits += width * 8,
+                _ => (),
+            }
+        }
+        let init = match (base, static_bits) {
+            ("", _) => static_bits.to_string(),
+            (_, 0) => base.to_owned(),
+            _ => format!("{} + {}", base, static_bits),
+        };
+        if dynamic.is_empty() {
+            writeln!(code, "return {};", init).unwrap();
+        } else {
+            writeln!(code, "size_t bits = {};", init).unwrap();
+            code.push_str(&dynamic);
+            writeln!(code, "return bits;").unwrap();
+        }
+    }
+
+    /// Write the `ToString` method of a view or struct, formatting
+    /// the value fields `fields` accessed with `accessor`.
+    fn generate_to_string(
+        &self,
+        name: &str,
+        fields: &[&'d Field],
+        accessor: impl Fn(&str) -> String,
+        code: &mut String,
+    ) {
+        writeln!(code, "std::string ToString() const {{").unwrap();
+        writeln!(code, "std::stringstream ss;").unwrap();
+        writeln!(code, "ss << std::showbase << std::hex << \"{} {{ \";", name).unwrap();
+        for (index, field) in fields.iter().enumerate() {
+            let id = field.id().unwrap();
+            let separator = if index == 0 { "" } else { ", " };
+            let value_type = self.value_type(field).unwrap();
+            match field {
+                Field::Array { .. } => {
+                    writeln!(code, "ss << \"{}{} = VECTOR[\";", separator, id).unwrap();
+                    writeln!(code, "{{").unwrap();
+                    writeln!(code, "auto {} = {};", id, accessor(id)).unwrap();
+                    writeln!(code, "for (size_t n = 0; n < {}.size(); n++) {{", id).unwrap();
+                    writeln!(
+                        code,
+                        "ss << (n == 0 ? \"\" : \", \") << {};",
+                        value_type.format(&format!("{}[n]", id))
+                    )
+                    .unwrap();
+                    writeln!(code, "}}").unwrap();
+                    writeln!(code, "}}").unwrap();
+                    writeln!(code, "ss << \"]\";").unwrap();
+                }
+                _ => writeln!(
+                    code,
+                    "ss << \"{}{} = \" << {};",
+                    separator,
+                    id,
+                    value_type.format(&accessor(id))
+                )
+                .unwrap(),
+            }
+        }
+        writeln!(code, "ss << \" }}\";").unwrap();
+        writeln!(code, "return ss.str();").unwrap();
+        writeln!(code, "}}").unwrap();
+    }
+
+    /// Generate an enum class. Only the value tags are declared as
+    /// enumerators; the values matched by range and default tags are
+    /// named by the text conversion.
+    fn generate_enum(&self, id: &str, tags: &[Tag], width: usize, code: &mut String) {
+        writeln!(code, "enum class {} : {} {{", id, scalar_type(width)).unwrap();
+        for tag in tags {
+            if let Tag::Value { id, value, .. } = tag {
+                writeln!(code, "{} = 0x{:x},", id, value).unwrap();
+            }
+        }
+        writeln!(code, "}};").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "inline std::string {}Text(const {}& param) {{", id, id).unwrap();
+        writeln!(code, "std::stringstream builder;").unwrap();
+        writeln!(code, "switch (param) {{").unwrap();
+        for tag in tags {
+            if let Tag::Value { id: tag_id, .. } = tag {
+                writeln!(code, "case {}::{}:", id, tag_id).unwrap();
+                writeln!(code, "return \"{}\";", tag_id).unwrap();
+            }
+        }
+        writeln!(code, "default:").unwrap();
+        for tag in tags {
+            if let Tag::Range { id: tag_id, start, end, .. } = tag {
+                // Bounds implied by the underlying type are omitted.
+                let value = "static_cast<uint64_t>(param)";
+                let type_width = width.next_power_of_two().max(8);
+                let mut bounds = vec![];
+                if *start > 0 {
+                    bounds.push(format!("{} >= 0x{:x}", value, start));
+                }
+                if type_width < 64 && *end < (1 << type_width) - 1 {
+                    bounds.push(format!("{} <= 0x{:x}", value, end));
+                }
+                if !bounds.is_empty() {
+                    writeln!(code, "if ({}) {{", bounds.join(" && ")).unwrap();
+                }
+                writeln!(code, "builder << \"{}: \" << {};", tag_id, value).unwrap();
+                writeln!(code, "return builder.str();").unwrap();
+                if !bounds.is_empty() {
+                    writeln!(code, "}}").unwrap();
+                }
+            }
+        }
+        match tags.iter().find(|t| matches!(t, Tag::Default { .. })) {
+            Some(tag) => {
+                writeln!(code, "builder << \"{}: \" << static_cast<uint64_t>(param);", tag.id())
+            }
+            None => {
+                writeln!(code, "builder << \"Unknown {}: \" << static_cast<uint64_t>(param);", id)
+            }
+        }
+        .unwrap();
+        writeln!(code, "return builder.str();").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "inline std::ostream& operator<<(std::ostream& os, const {}& param) {{", id)
+            .unwrap();
+        writeln!(code, "return os << {}Text(param);", id).unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+    }
+
+    /// Write the compile time checks of the checksum and custom field
+    /// types.
+    fn generate_checks(&self, decl: &'d Decl, code: &mut String) {
+        match decl {
+            Decl::Checksum { id, width, .. } => writeln!(
+                code,
+                "static_assert(ChecksumTypeChecker<{}, {}>::value, \"{} is not a valid checksum type\");",
+                id,
+                scalar_type(*width),
+                id
+            ),
+            Decl::CustomField { id, width: Some(width), .. } => {
+                writeln!(
+                    code,
+                    "static_assert(std::is_base_of_v<CustomFieldFixedSizeInterface<{}>, {}>, \"{} is not a valid fixed size custom field type\");",
+                    id, id, id
+                )
+                .unwrap();
+                writeln!(
+                    code,
+                    "static_assert(CustomFieldFixedSizeInterface<{}>::length() * 8 == {}, \"{} does not have the size {} declared in the grammar\");",
+                    id, width, id, width
+                )
+            }
+            Decl::CustomField { id, .. } => writeln!(
+                code,
+                "static_assert(CustomTypeChecker<{}, {}>::value, \"{} is not a valid custom field type\");",
+                id, self.endianness, id
+            ),
+            _ => Ok(()),
+        }
+        .unwrap()
+    }
+
+    fn generate_struct(&self, decl: &'d Decl, code: &mut String) -> Result<(), String> {
+        let id = decl.id().unwrap();
+        if decl.parent_id().is_some() || !self.scope.get_children(decl).is_empty() {
+            return Err(format!("{}: struct inheritance is not supported", id));
+        }
+        let fields = decl.fields().collect::<Vec<_>>();
+        let value_fields =
+            fields.iter().copied().filter(|f| self.is_value_field(f)).collect::<Vec<_>>();
+        let items = self.items(decl)?;
+        let iterator = format!("Iterator<{}>", self.endianness);
+
+        writeln!(code, "class {} : public PacketStruct<{}> {{", id, self.endianness).unwrap();
+        writeln!(code, "public:").unwrap();
+        writeln!(code, "{}() = default;", id).unwrap();
+        writeln!(code, "virtual ~{}() = default;", id).unwrap();
+        writeln!(code).unwrap();
+
+        writeln!(
+            code,
+            "static std::optional<{}> Parse({}* to_fill, {} struct_begin_it) {{",
+            iterator, id, iterator
+        )
+        .unwrap();
+        writeln!(code, "auto it = struct_begin_it;").unwrap();
+        self.parse_fields(decl, Target::Struct, code)?;
+        writeln!(code, "return it;").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        let mut serialize = String::new();
+        self.serialize_items(&fields, &items, &mut serialize);
+        let param = if serialize.is_empty() { "" } else { " i" };
+        writeln!(code, "void Serialize(BitInserter&{}) const override {{", param).unwrap();
+        code.push_str(&serialize);
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        writeln!(code, "size_t size() const override {{").unwrap();
+        writeln!(code, "return BitsOfStruct() / 8;").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        self.generate_to_string(id, &value_fields, |id| format!("{}_", id), code);
+        writeln!(code).unwrap();
+
+        for field in &value_fields {
+            let id = field.id().unwrap();
+            match field {
+                Field::Array { .. } => writeln!(code, "{} {}_;", self.field_type(field), id),
+                _ => writeln!(code, "{} {}_{{}};", self.field_type(field), id),
+            }
+            .unwrap();
+        }
+        if !value_fields.is_empty() {
+            writeln!(code).unwrap();
+        }
+        writeln!(code, "private:").unwrap();
+        writeln!(code, "size_t BitsOfStruct() const {{").unwrap();
+        self.size_items(&fields, &items, "", code);
+        writeln!(code, "}}").unwrap();
+        writeln!(code, "}};").unwrap();
+        writeln!(code).unwrap();
+        Ok(())
+    }
+
+    /// Write the getter of a value field of a view.
+    fn generate_view_getter(&self, decl: &'d Decl, field: &'d Field, code: &mut String) {
+        let id = field.id().unwrap();
+        let value_type = self.value_type(field).unwrap();
+        let ty = self.field_type(field);
+        writeln!(code, "{} {}() const {{", ty, getter(id)).unwrap();
+        writeln!(code, "ASSERT(was_validated_);").unwrap();
+        match (field, value_type) {
+            (Field::Array { size, .. }, _) => {
+                writeln!(
+                    code,
+                    "auto it = begin().Subrange({}_offset_, {}_end_ - {}_offset_);",
+                    id, id, id
+                )
+                .unwrap();
+                writeln!(code, "{} {};", ty, id).unwrap();
+                match size {
+                    Some(size) => writeln!(code, "for (size_t n = 0; n < {}; n++) {{", size),
+                    None => writeln!(code, "while (it.NumBytesRemaining() > 0) {{"),
+                }
+                .unwrap();
+                self.parse_element(value_type, "it", "break;", code);
+                match size {
+                    Some(_) => writeln!(code, "{}[n] = elem;", id),
+                    None => writeln!(code, "{}.push_back(elem);", id),
+                }
+                .unwrap();
+                writeln!(code, "}}").unwrap();
+                writeln!(code, "return {};", id).unwrap();
+            }
+            (_, ValueType::Custom(_, Some(_))) => {
+                writeln!(code, "auto it = begin() + {}_offset_;", id).unwrap();
+                writeln!(code, "return it.extract<{}>();", ty).unwrap();
+            }
+            (_, ValueType::Custom(..) | ValueType::Struct(_)) => {
+                writeln!(code, "{} {};", ty, id).unwrap();
+                writeln!(
+                    code,
+                    "{}::Parse(&{}, begin().Subrange({}_offset_, {}_end_ - {}_offset_));",
+                    ty, id, id, id, id
+                )
+                .unwrap();
+                writeln!(code, "return {};", id).unwrap();
+            }
+            _ => {
+                let (shift, width) = self.chunk_location(decl, field);
+                let field_width = self.scope.get_field_width(field).unwrap();
+                writeln!(code, "auto it = begin() + {}_offset_;", id).unwrap();
+                read_chunk("chunk", "it", width / 8, self.endianness, code);
+                writeln!(
+                    code,
+                    "return static_cast<{}>({});",
+                    ty,
+                    extract_bits("chunk", shift, field_width, width)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+    }
+
+    fn generate_view(&self, decl: &'d Decl, code: &mut String) -> Result<(), String> {
+        let id = decl.id().unwrap();
+        let name = format!("{}View", id);
+        let parent = self.scope.get_parent(decl);
+        let base = match parent {
+            Some(parent) => format!("{}View", parent.id().unwrap()),
+            None => format!("PacketView<{}>", self.endianness),
+        };
+        let fields = decl.fields().collect::<Vec<_>>();
+        let value_fields =
+            fields.iter().copied().filter(|f| self.is_value_field(f)).collect::<Vec<_>>();
+        let has_payload =
+            fields.iter().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. }));
+
+        writeln!(code, "class {} : public {} {{", name, base).unwrap();
+        writeln!(code, "public:").unwrap();
+        let arg = if parent.is_some() { "parent" } else { "packet" };
+        writeln!(code, "static {} Create({} {}) {{", name, base, arg).unwrap();
+        writeln!(code, "return {}(std::move({}));", name, arg).unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        writeln!(code, "{}", TESTING).unwrap();
+        writeln!(code, "static {} FromBytes(std::vector<uint8_t> bytes) {{", name).unwrap();
+        match parent {
+            Some(_) => {
+                writeln!(code, "return {}::Create({}::FromBytes(std::move(bytes)));", name, base)
+                    .unwrap()
+            }
+            None => {
+                writeln!(code, "auto vec = std::make_shared<std::vector<uint8_t>>(bytes);")
+                    .unwrap();
+                writeln!(code, "return {}::Create({}(vec));", name, base).unwrap();
+            }
+        }
+        writeln!(code, "}}").unwrap();
+        writeln!(code, "#endif").unwrap();
+        writeln!(code).unwrap();
+
+        for field in &value_fields {
+            self.generate_view_getter(decl, field, code);
+        }
+        if has_payload {
+            for (ty, endianness, subview) in [
+                ("", "kLittleEndian", "GetLittleEndianSubview"),
+                ("BigEndian", "!kLittleEndian", "GetBigEndianSubview"),
+            ] {
+                writeln!(code, "PacketView<{}> GetPayload{}() const {{", endianness, ty).unwrap();
+                writeln!(code, "ASSERT(was_validated_);").unwrap();
+                writeln!(code, "return {}(payload_begin_, payload_end_);", subview).unwrap();
+                writeln!(code, "}}").unwrap();
+                writeln!(code).unwrap();
+            }
+        }
+
+        writeln!(code, "virtual bool IsValid() {{").unwrap();
+        writeln!(code, "if (was_validated_) {{").unwrap();
+        writeln!(code, "return true;").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code, "was_validated_ = IsValid_();").unwrap();
+        writeln!(code, "return was_validated_;").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        let lineage_fields = self
+            .scope
+            .get_lineage(decl)
+            .into_iter()
+            .flat_map(|d| d.fields())
+            .filter(|f| self.is_value_field(f))
+            .collect::<Vec<_>>();
+        self.generate_to_string(id, &lineage_fields, |id| format!("{}()", getter(id)), code);
+        writeln!(code).unwrap();
+
+        writeln!(code, "protected:").unwrap();
+        writeln!(code, "explicit {}({} {}) : {}(std::move({})) {{", name, base, arg, base, arg)
+            .unwrap();
+        writeln!(code, "was_validated_ = false;").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        // The parsing code is generated first, to declare only the
+        // variables it uses.
+        let mut body = String::new();
+        for constraint in decl.constraints() {
+            let field = self.scope.get_field(decl, &constraint.id).unwrap();
+            let (shift, width) = self.chunk_location(decl, field);
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let value = self.constraint_value(decl, constraint)?;
+            writeln!(body, "{{").unwrap();
+            writeln!(body, "auto it = begin() + {}_offset_;", constraint.id).unwrap();
+            read_chunk("chunk", "it", width / 8, self.endianness, &mut body);
+            writeln!(
+                body,
+                "if ({} != 0x{:x}) {{",
+                extract_bits("chunk", shift, field_width, width),
+                value
+            )
+            .unwrap();
+            writeln!(body, "return false;").unwrap();
+            writeln!(body, "}}").unwrap();
+            writeln!(body, "}}").unwrap();
+        }
+        let mut parse = String::new();
+        self.parse_fields(decl, Target::View, &mut parse)?;
+
+        writeln!(code, "bool IsValid_() {{").unwrap();
+        if parent.is_some() {
+            writeln!(code, "if (!{}::IsValid_()) {{", base).unwrap();
+            writeln!(code, "return false;").unwrap();
+            writeln!(code, "}}").unwrap();
+        }
+        code.push_str(&body);
+        if parse.contains("end - ") {
+            match parent {
+                Some(_) => writeln!(code, "size_t end = payload_end_;"),
+                None => writeln!(code, "size_t end = size();"),
+            }
+            .unwrap();
+        }
+        if !parse.is_empty() {
+            match parent {
+                Some(_) => writeln!(
+                    code,
+                    "auto it = begin().Subrange(payload_begin_, payload_end_ - payload_begin_);"
+                ),
+                None => writeln!(code, "auto it = begin();"),
+            }
+            .unwrap();
+        }
+        code.push_str(&parse);
+        writeln!(code, "return true;").unwrap();
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        if parent.is_none() {
+            writeln!(code, "bool was_validated_{{false}};").unwrap();
+            if has_payload {
+                writeln!(code, "size_t payload_begin_{{0}};").unwrap();
+                writeln!(code, "size_t payload_end_{{0}};").unwrap();
+            }
+        }
+        for field in &value_fields {
+            let id = field.id().unwrap();
+            writeln!(code, "size_t {}_offset_{{0}};", id).unwrap();
+            if matches!(field, Field::Array { .. })
+                || self.value_type(field).unwrap().static_width().is_none()
+            {
+                writeln!(code, "size_t {}_end_{{0}};", id).unwrap();
+            }
+        }
+        writeln!(code, "}};").unwrap();
+        writeln!(code).unwrap();
+        Ok(())
+    }
+
+    /// Return the parameters of the constructor of the builder of
+    /// `decl`: the value fields of the declaration and its ancestors,
+    /// except the fields constrained by the declaration or its
+    /// ancestors.
+    fn builder_params(&self, decl: &'d Decl) -> Vec<&'d Field> {
+        let mut params = match self.scope.get_parent(decl) {
+            Some(parent) => self.builder_params(parent),
+            None => vec![],
+        };
+        params.retain(|f| !decl.constraints().any(|c| Some(&c.id) == f.id()));
+        params.extend(decl.fields().filter(|f| self.is_value_field(f)));
+        params
+    }
+
+    fn generate_builder(&self, decl: &'d Decl, code: &mut String) -> Result<(), String> {
+        let id = decl.id().unwrap();
+        let name = format!("{}Builder", id);
+        let parent = self.scope.get_parent(decl);
+        let base = match parent {
+            Some(parent) => format!("{}Builder", parent.id().unwrap()),
+            None => format!("PacketBuilder<{}>", self.endianness),
+        };
+        let fields = decl.fields().collect::<Vec<_>>();
+        let items = self.items(decl)?;
+        let payload_index = items.iter().position(|item| {
+            matches!(item, Item::Field(_, Field::Payload { .. } | Field::Body { .. }))
+        });
+        let (header, footer) = match payload_index {
+            Some(index) => (&items[..index], &items[index + 1..]),
+            None => (&items[..], &items[..0]),
+        };
+        let has_payload = fields.iter().any(|f| matches!(f, Field::Payload { .. }));
+        let has_body = fields.iter().any(|f| matches!(f, Field::Body { .. }));
+        let params = self.builder_params(decl);
+        let param_list = params
+            .iter()
+            .map(|f| format!("{} {}", self.param_type(f), f.id().unwrap()))
+            .collect::<Vec<_>>();
+        let args = params.iter().map(|f| f.id().unwrap().clone()).collect::<Vec<_>>();
+
+        writeln!(code, "class {} : public {} {{", name, base).unwrap();
+        writeln!(code, "public:").unwrap();
+        writeln!(code, "virtual ~{}() = default;", name).unwrap();
+        writeln!(code).unwrap();
+
+        if !has_body {
+            let mut create_params = param_list.clone();
+            if has_payload {
+                create_params.push("std::unique_ptr<BasePacketBuilder> payload".to_owned());
+            }
+            writeln!(
+                code,
+                "static std::unique_ptr<{}> Create({}) {{",
+                name,
+                create_params.join(", ")
+            )
+            .unwrap();
+            writeln!(
+                code,
+                "auto builder = std::unique_ptr<{}>(new {}({}));",
+                name,
+                name,
+                args.join(", ")
+            )
+            .unwrap();
+            if has_payload {
+                writeln!(code, "builder->payload_ = std::move(payload);").unwrap();
+            }
+            writeln!(code, "return builder;").unwrap();
+            writeln!(code, "}}").unwrap();
+            writeln!(code).unwrap();
+
+            let mut view_args = params
+                .iter()
+                .map(|f| format!("view.{}()", getter(f.id().unwrap())))
+                .collect::<Vec<_>>();
+            if has_payload {
+                view_args.push(
+                    "std::make_unique<RawBuilder>(std::vector<uint8_t>(view.GetPayload().begin(), view.GetPayload().end()))"
+                        .to_owned(),
+                );
+            }
+            writeln!(code, "{}", TESTING).unwrap();
+            writeln!(code, "static std::unique_ptr<{}> FromView({}View view) {{", name, id)
+                .unwrap();
+            writeln!(code, "if (!view.IsValid()) {{").unwrap();
+            writeln!(code, "return nullptr;").unwrap();
+            writeln!(code, "}}").unwrap();
+            writeln!(code, "return {}::Create({});", name, view_args.join(", ")).unwrap();
+            writeln!(code, "}}").unwrap();
+            writeln!(code, "#endif").unwrap();
+            writeln!(code).unwrap();
+
+            writeln!(code, "void Serialize(BitInserter& i) const override {{").unwrap();
+            writeln!(code, "SerializeHeader(i);").unwrap();
+            if has_payload {
+                writeln!(code, "payload_->Serialize(i);").unwrap();
+            }
+            writeln!(code, "SerializeFooter(i);").unwrap();
+            writeln!(code, "}}").unwrap();
+            writeln!(code).unwrap();
+
+            writeln!(code, "size_t size() const override {{").unwrap();
+            match has_payload {
+                true => writeln!(
+                    code,
+                    "return (BitsOfHeader() + BitsOfFooter()) / 8 + payload_->size();"
+                ),
+                false => writeln!(code, "return (BitsOfHeader() + BitsOfFooter()) / 8;"),
+            }
+            .unwrap();
+            writeln!(code, "}}").unwrap();
+            writeln!(code).unwrap();
+        }
+
+        writeln!(code, "protected:").unwrap();
+        let explicit = if params.is_empty() { "" } else { "explicit " };
+        let mut initializers = vec![];
+        if let Some(parent) = parent {
+            let parent_args = self
+                .builder_params(parent)
+                .iter()
+                .map(|f| {
+                    let id = f.id().unwrap();
+                    match decl.constraints().find(|c| &c.id == id) {
+                        Some(constraint) => self.constraint_expr(decl, constraint),
+                        None => Ok(id.clone()),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            initializers.push(format!("{}({})", base, parent_args.join(", ")));
+        }
+        for field in fields.iter().filter(|f| self.is_value_field(f)) {
+            let id = field.id().unwrap();
+            initializers.push(format!("{}_({})", id, id));
+        }
+        let initializers = match initializers.is_empty() {
+            true => String::new(),
+            false => format!(" : {}", initializers.join(", ")),
+        };
+        writeln!(code, "{}{}({}){} {{", explicit, name, param_list.join(", "), initializers)
+            .unwrap();
+        for field in fields.iter().filter(|f| self.is_value_field(f)) {
+            if let Field::Scalar { id, width, .. } = field {
+                if !matches!(width, 8 | 16 | 32 | 64) {
+                    writeln!(code, "ASSERT({} < (static_cast<uint64_t>(1) << {}));", id, width)
+                        .unwrap();
+                }
+            }
+        }
+        writeln!(code, "}}").unwrap();
+        writeln!(code).unwrap();
+
+        for (method, items) in [("SerializeHeader", header), ("SerializeFooter", footer)] {
+            let mut serialize = String::new();
+            let call_parent = format!("{}::{}(i);", base, method);
+            if parent.is_some() && method == "SerializeHeader" {
+                writeln!(serialize, "{}", call_parent).unwrap();
+            }
+            self.serialize_items(&fields, items, &mut serialize);
+            if parent.is_some() && method == "SerializeFooter" {
+                writeln!(serialize, "{}", call_parent).unwrap();
+            }
+            let param = if serialize.is_empty() { "" } else { " i" };
+            writeln!(code, "void {}(BitInserter&{}) const {{", method, param).unwrap();
+            code.push_str(&serialize);
+            writeln!(code, "}}").unwrap();
+            writeln!(code).unwrap();
+        }
+
+        for (method, items) in [("BitsOfHeader", header), ("BitsOfFooter", footer)] {
+            let base = match parent {
+                Some(_) => format!("{}::{}()", base, method),
+                None => String::new(),
+            };
+            writeln!(code, "size_t {}() const {{", method).unwrap();
+            self.size_items(&fields, items, &base, code);
+            writeln!(code, "}}").unwrap();
+            writeln!(code).unwrap();
+        }
+
+        if fields.iter().any(|f| {
+            matches!(f, Field::Size { field_id, .. } if field_id == "_payload_" || field_id == "_body_")
+        }) {
+            writeln!(code, "size_t GetPayloadSize() const {{").unwrap();
+            writeln!(code, "return size() - (BitsOfHeader() + BitsOfFooter()) / 8;").unwrap();
+            writeln!(code, "}}").unwrap();
+            writeln!(code).unwrap();
+        }
+
+        for field in fields.iter().filter(|f| self.is_value_field(f)) {
+            let id = field.id().unwrap();
+            match field {
+                Field::Array { .. } => writeln!(code, "{} {}_;", self.field_type(field), id),
+                _ => writeln!(code, "{} {}_{{}};", self.field_type(field), id),
+            }
+            .unwrap();
+        }
+        if has_payload {
+            writeln!(code, "std::unique_ptr<BasePacketBuilder> payload_;").unwrap();
+        }
+        writeln!(code, "}};").unwrap();
+        writeln!(code).unwrap();
+        Ok(())
+    }
+}
+
+/// Return the declarations `decls` sorted so that each declaration
+/// follows its dependencies `deps`.
+fn sort_decls<'d>(decls: &[&'d Decl], deps: impl Fn(&'d Decl) -> Vec<&'d Decl>) -> Vec<&'d Decl> {
+    fn visit<'d>(
+        decl: &'d Decl,
+        deps: &impl Fn(&'d Decl) -> Vec<&'d Decl>,
+        visited: &mut HashSet<&'d str>,
+        sorted: &mut Vec<&'d Decl>,
+    ) {
+        if !visited.insert(decl.id().unwrap()) {
+            return;
+        }
+        for dep in deps(decl) {
+            visit(dep, deps, visited, sorted);
+        }
+        sorted.push(decl);
+    }
+    let mut visited = HashSet::new();
+    let mut sorted = vec![];
+    for decl in decls {
+        visit(decl, &deps, &mut visited, &mut sorted);
+    }
+    sorted
+}
+
+/// Generate the C++ header of the grammar, declared in the namespace
+/// `namespace`, e.g. `bluetooth::hci`.
+pub fn generate(
+    sources: &SourceDatabase,
+    grammar: &Grammar,
+    namespace: &str,
+) -> Result<String, String> {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let path = Path::new(source.name());
+    let filename = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+
+    let grammar = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&grammar);
+    let generator = Generator {
+        scope: &scope,
+        endianness: match scope.endianness {
+            EndiannessValue::LittleEndian => "kLittleEndian",
+            EndiannessValue::BigEndian => "!kLittleEndian",
+        },
+    };
+
+    let mut code = String::new();
+    writeln!(code, "// @generated C++ header from {}", filename).unwrap();
+    writeln!(code).unwrap();
+    writeln!(code, "#pragma once").unwrap();
+    writeln!(code).unwrap();
+    for header in [
+        "array",
+        "cstdint",
+        "memory",
+        "optional",
+        "sstream",
+        "string",
+        "type_traits",
+        "utility",
+        "vector",
+    ] {
+        writeln!(code, "#include <{}>", header).unwrap();
+    }
+    writeln!(code).unwrap();
+    for header in [
+        "os/log.h",
+        "packet/base_packet_builder.h",
+        "packet/bit_inserter.h",
+        "packet/byte_observer.h",
+        "packet/custom_field_fixed_size_interface.h",
+        "packet/iterator.h",
+        "packet/packet_builder.h",
+        "packet/packet_struct.h",
+        "packet/packet_view.h",
+        "packet/parser/checksum_type_checker.h",
+        "packet/parser/custom_type_checker.h",
+    ] {
+        writeln!(code, "#include \"{}\"", header).unwrap();
+    }
+    writeln!(code).unwrap();
+    writeln!(code, "{}", TESTING).unwrap();
+    writeln!(code, "#include \"packet/raw_builder.h\"").unwrap();
+    writeln!(code, "#endif").unwrap();
+
+    // Checksum and custom field types declared in a directory.
+    let root_namespace = namespace.split("::").next().unwrap_or_default();
+    let mut usings = vec![];
+    for decl in &grammar.declarations {
+        if let Decl::Checksum { id, function, .. } | Decl::CustomField { id, function, .. } = decl {
+            // The function is the quoted string of the declaration.
+            let function = function.trim_matches('"');
+            if !function.ends_with('/') {
+                continue;
+            }
+            if usings.is_empty() {
+                writeln!(code).unwrap();
+            }
+            writeln!(code, "#include \"{}{}.h\"", function, to_snake_case(id)).unwrap();
+            let mut path = vec![root_namespace];
+            path.extend(function.split('/').filter(|s| !s.is_empty()));
+            path.push(id);
+            usings.push(path.join("::"));
+        }
+    }
+    writeln!(code).unwrap();
+
+    writeln!(code, "namespace {} {{", namespace).unwrap();
+    writeln!(code).unwrap();
+    for using in usings {
+        writeln!(code, "using ::{};", using).unwrap();
+    }
+    for using in [
+        "BasePacketBuilder",
+        "BitInserter",
+        "ByteObserver",
+        "CustomFieldFixedSizeInterface",
+        "CustomTypeChecker",
+        "Iterator",
+        "kLittleEndian",
+        "PacketBuilder",
+        "PacketStruct",
+        "PacketView",
+        "parser::ChecksumTypeChecker",
+    ] {
+        writeln!(code, "using ::bluetooth::packet::{};", using).unwrap();
+    }
+    writeln!(code, "{}", TESTING).unwrap();
+    writeln!(code, "using ::bluetooth::packet::RawBuilder;").unwrap();
+    writeln!(code, "#endif").unwrap();
+    writeln!(code).unwrap();
+
+    for decl in &grammar.declarations {
+        if let Decl::Enum { id, tags, width, .. } = decl {
+            generator.generate_enum(id, tags, *width, &mut code);
+        }
+    }
+    for decl in &grammar.declarations {
+        generator.generate_checks(decl, &mut code);
+    }
+    if grammar
+        .declarations
+        .iter()
+        .any(|d| matches!(d, Decl::Checksum { .. } | Decl::CustomField { .. }))
+    {
+        writeln!(code).unwrap();
+    }
+
+    let structs = grammar
+        .declarations
+        .iter()
+        .filter(|d| matches!(d, Decl::Struct { .. }))
+        .collect::<Vec<_>>();
+    for decl in sort_decls(&structs, |decl| {
+        decl.fields()
+            .filter_map(|f| match f {
+                Field::Typedef { type_id, .. } | Field::Array { type_id: Some(type_id), .. } => {
+                    scope.typedef.get(type_id).copied()
+                }
+                _ => None,
+            })
+            .filter(|d| matches!(d, Decl::Struct { .. }))
+            .collect()
+    }) {
+        generator.generate_struct(decl, &mut code)?;
+    }
+
+    let packets = grammar
+        .declarations
+        .iter()
+        .filter(|d| matches!(d, Decl::Packet { .. }))
+        .collect::<Vec<_>>();
+    let packets = sort_decls(&packets, |decl| scope.get_parent(decl).into_iter().collect());
+    for decl in &packets {
+        generator.generate_view(decl, &mut code)?;
+    }
+    for decl in &packets {
+        generator.generate_builder(decl, &mut code)?;
+    }
+
+    writeln!(code, "}}  // namespace {}", namespace).unwrap();
+    Ok(indent(&code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::{assert_contains, assert_snapshot_eq};
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        custom_field SixBytes : 48 "packet/parser/test/"
+        custom_field Variable "packet/parser/test/"
+        checksum SimpleSum : 16 "packet/parser/test/"
+        enum OpCode : 8 { RESET = 1, READ = 2, WRITE = 3 }
+        enum Level : 4 { LOW = 1, HIGH = 2 }
+        struct Entry { key: 8, level: Level, _reserved_: 4, value: 16 }
+        struct Blob { _size_(data): 8, data: 8[], name: Variable }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            address: SixBytes,
+            _fixed_ = 0x42 : 8,
+        }
+        packet Read : Command (op_code = READ) {
+            _count_(entries): 8,
+            entries: Entry[],
+            op_codes: OpCode[2],
+            blob: Blob,
+        }
+        packet Write : Command (op_code = WRITE) {
+            _checksum_start_(crc),
+            _size_(values): 8,
+            values: 16[+2*8],
+            names: Variable[],
+            crc: SimpleSum,
+        }
+        packet Frame {
+            kind: 8,
+            _body_,
+            trailer: 16,
+        }
+        packet Data : Frame (kind = 1) {
+            _size_(items): 8,
+            items: 24[],
+            _padding_[9],
+            tail: 12,
+            _fixed_ = 0 : 4,
+        }
+    "#;
+
+    fn generate_inline(grammar: &str) -> Result<String, String> {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test.pdl".to_owned(), grammar.to_owned())
+            .expect("parsing failure");
+        generate(&db, &grammar, "bluetooth::packet::parser::test")
+    }
+
+    #[test]
+    fn test_generate() {
+        let code = generate_inline(GRAMMAR).unwrap();
+        assert_contains(&code, "#include \"packet/parser/test/six_bytes.h\"");
+        assert_contains(&code, "using ::bluetooth::packet::parser::test::SimpleSum;");
+        assert_contains(&code, "class ResetView : public CommandView {");
+        assert_contains(&code, "class DataBuilder : public FrameBuilder {");
+        assert_snapshot_eq("tests/generated/cxx_packets.h", &code);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = generate_inline(
+            r#"
+            little_endian_packets
+            struct Parent { a: 8, _payload_ }
+            struct Child : Parent { b: 8 }
+            "#,
+        )
+        .unwrap_err();
+        assert_contains(&err, "struct inheritance is not supported");
+
+        let err = generate_inline(
+            r#"
+            little_endian_packets
+            packet Test { a: 8[], b: 8[] }
+            "#,
+        )
+        .unwrap_err();
+        assert_contains(&err, "variable size field declared after a payload or unbounded array");
+    }
+}
@@ -0,0 +1,549 @@
+//! Documentation backend.
+//!
+//! Renders the declarations of a grammar as a single Markdown or HTML
+//! page. Every declaration is given an anchor named after its
+//! identifier, and references to other declarations (field types,
+//! parents and children) link to these anchors.
+//!
+//! Comments are attached as documentation to the declaration, field or
+//! tag that immediately follows them, or to the element that ends on
+//! the line where they start.
+
+use codespan_reporting::files::Files;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+/// Fragment of inline text.
+enum Text {
+    Plain(String),
+    Code(String),
+    /// Reference to the declaration with the given identifier.
+    Link(String),
+}
+
+/// Documentation of a declaration.
+struct Section {
+    id: String,
+    kind: &'static str,
+    doc: Vec<String>,
+    /// Properties of the declaration, listed before the table.
+    /// Each property has a list of comma separated items.
+    properties: Vec<(&'static str, Vec<Vec<Text>>)>,
+    header: Vec<&'static str>,
+    rows: Vec<Vec<Vec<Text>>>,
+}
+
+/// Return the lines of text of a comment, without the comment
+/// delimiters.
+fn comment_lines(text: &str) -> Vec<String> {
+    let text = match text.strip_prefix("//") {
+        Some(text) => text.trim_start_matches('/'),
+        None => text.trim_start_matches("/*").trim_end_matches("*/"),
+    };
+    let lines = text
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix('*').unwrap_or(line).trim().to_owned()
+        })
+        .collect::<Vec<_>>();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|line| !line.is_empty()).map_or(start, |end| end + 1);
+    lines[start..end].to_vec()
+}
+
+/// Return the name of a field, spelled as in the PDL source for
+/// unnamed fields.
+pub fn field_name(field: &Field) -> String {
+    match field {
+        Field::Checksum { field_id, .. } => format!("_checksum_start_({})", field_id),
+        Field::Padding { .. } => "_padding_".to_owned(),
+        Field::Size { field_id, .. } => format!("_size_({})", field_id),
+        Field::Count { field_id, .. } => format!("_count_({})", field_id),
+        Field::Body { .. } => "_body_".to_owned(),
+        Field::Payload { .. } => "_payload_".to_owned(),
+        Field::Fixed { .. } => "_fixed_".to_owned(),
+        Field::Reserved { .. } => "_reserved_".to_owned(),
+        Field::Array { id, .. } | Field::Scalar { id, .. } | Field::Typedef { id, .. } => {
+            id.clone()
+        }
+        Field::Group { group_id, .. } => group_id.clone(),
+    }
+}
+
+/// Return the bit offset of the fields of `decl` relative to the start
+/// of its root ancestor, or `None` if any preceding field has a
+/// variable size.
+fn start_offset(scope: &Scope, decl: &Decl) -> Option<usize> {
+    match scope.get_parent(decl) {
+        None => Some(0),
+        Some(parent) => {
+            let mut offset = start_offset(scope, parent)?;
+            for field in parent.fields() {
+                if matches!(field, Field::Payload { .. } | Field::Body { .. }) {
+                    return Some(offset);
+                }
+                offset += scope.get_field_width(field)?;
+            }
+            None
+        }
+    }
+}
+
+/// Return the value of a constraint or condition, as text.
+fn constraint_text(constraint: &Constraint) -> String {
+    let value = match &constraint.value {
+        Expr::Identifier { name, .. } => name.clone(),
+        expr => expr.evaluate().map_or_else(|_| "?".to_owned(), |value| value.to_string()),
+    };
+    format!("{} = {}", constraint.id, value)
+}
+
+struct Generator<'d> {
+    scope: Scope<'d>,
+    sources: &'d SourceDatabase,
+    /// Comments preceded by source text on the same line.
+    trailing: Vec<&'d Comment>,
+    /// Comments starting a line.
+    leading: Vec<&'d Comment>,
+}
+
+impl<'d> Generator<'d> {
+    /// Return the documentation attached to the element at `loc`.
+    /// `end` is the location of the end of the first line of the
+    /// element, where trailing comments are looked up. Leading comments
+    /// must start after the offset `after`.
+    fn doc(&self, loc: &SourceRange, end: &SourceLocation, after: usize) -> Vec<String> {
+        let mut lines = vec![];
+        let mut line = loc.start.line;
+        for comment in self
+            .leading
+            .iter()
+            .rev()
+            .filter(|c| c.loc.file == loc.file && c.loc.start.offset < loc.start.offset)
+            .take_while(|c| c.loc.start.offset >= after)
+        {
+            if comment.loc.end.line + 1 != line {
+                break;
+            }
+            let mut comment_lines = comment_lines(&comment.text);
+            comment_lines.append(&mut lines);
+            lines = comment_lines;
+            line = comment.loc.start.line;
+        }
+        if let Some(comment) = self.trailing.iter().find(|c| {
+            c.loc.file == loc.file
+                && c.loc.start.line == end.line
+                && c.loc.start.offset >= end.offset
+        }) {
+            lines.extend(comment_lines(&comment.text));
+        }
+        lines
+    }
+
+    /// Return the documentation of a declaration. Trailing comments
+    /// are looked up after the opening brace.
+    fn decl_doc(&self, decl: &Decl) -> Vec<String> {
+        let loc = decl.loc();
+        let first_line_end = self
+            .sources
+            .source(loc.file)
+            .ok()
+            .and_then(|source| source.get(loc.start.offset..loc.end.offset))
+            .and_then(|text| text.find(['{', '\n']))
+            .map_or(loc.end, |index| SourceLocation {
+                offset: loc.start.offset + index,
+                line: loc.start.line,
+                column: loc.start.column + index,
+            });
+        self.doc(loc, &first_line_end, 0)
+    }
+
+    fn field_type(field: &Field) -> Vec<Text> {
+        let mut text = match field {
+            Field::Checksum { .. } => vec![Text::Plain("checksum start".to_owned())],
+            Field::Padding { width, .. } => {
+                vec![Text::Plain(format!("padding to {} bytes", width))]
+            }
+            Field::Size { .. } => vec![Text::Plain("size".to_owned())],
+            Field::Count { .. } => vec![Text::Plain("count".to_owned())],
+            Field::Body { .. } => vec![Text::Plain("body".to_owned())],
+            Field::Payload { size_modifier: None, .. } => vec![Text::Plain("payload".to_owned())],
+            Field::Payload { size_modifier: Some(size_modifier), .. } => {
+                vec![Text::Plain("payload".to_owned()), Text::Code(format!("[{}]", size_modifier))]
+            }
+            Field::Fixed { value: Some(value), .. } => {
+                vec![Text::Plain("fixed".to_owned()), Text::Code(format!("{:#x}", value))]
+            }
+            Field::Fixed { tag_id: Some(tag_id), enum_id: Some(enum_id), .. } => vec![
+                Text::Plain("fixed".to_owned()),
+                Text::Code(tag_id.clone()),
+                Text::Link(enum_id.clone()),
+            ],
+            Field::Fixed { .. } => vec![Text::Plain("fixed".to_owned())],
+            Field::Reserved { .. } => vec![Text::Plain("reserved".to_owned())],
+            Field::Array { width, type_id, size_modifier, size, .. } => {
+                let element = match (width, type_id) {
+                    (Some(width), _) => Text::Code(format!("{}-bit", width)),
+                    (_, Some(type_id)) => Text::Link(type_id.clone()),
+                    _ => unreachable!(),
+                };
+                let size = size
+                    .map_or_else(|| size_modifier.clone().unwrap_or_default(), |s| s.to_string());
+                vec![Text::Plain("array".to_owned()), element, Text::Code(format!("[{}]", size))]
+            }
+            Field::Scalar { .. } => vec![Text::Plain("scalar".to_owned())],
+            Field::Typedef { type_id, .. } => vec![Text::Link(type_id.clone())],
+            Field::Group { group_id, .. } => vec![Text::Link(group_id.clone())],
+        };
+        if let Some(cond) = field.cond() {
+            text.push(Text::Plain("if".to_owned()));
+            text.push(Text::Code(constraint_text(cond)));
+        }
+        text
+    }
+
+    fn generate_decl(&self, decl: &'d Decl) -> Option<Section> {
+        let mut section = Section {
+            id: decl.id()?.clone(),
+            kind: "",
+            doc: self.decl_doc(decl),
+            properties: vec![],
+            header: vec![],
+            rows: vec![],
+        };
+        match decl {
+            Decl::Checksum { width, function, .. }
+            | Decl::CustomField { width: Some(width), function, .. } => {
+                section.kind =
+                    if matches!(decl, Decl::Checksum { .. }) { "checksum" } else { "custom field" };
+                section
+                    .properties
+                    .push(("Width", vec![vec![Text::Plain(format!("{} bits", width))]]));
+                section.properties.push(("Function", vec![vec![Text::Code(function.clone())]]));
+            }
+            Decl::CustomField { function, .. } => {
+                section.kind = "custom field";
+                section.properties.push(("Width", vec![vec![Text::Plain("variable".to_owned())]]));
+                section.properties.push(("Function", vec![vec![Text::Code(function.clone())]]));
+            }
+            Decl::Enum { tags, width, .. } => {
+                section.kind = "enum";
+                section
+                    .properties
+                    .push(("Width", vec![vec![Text::Plain(format!("{} bits", width))]]));
+                section.header = vec!["Tag", "Value", "Description"];
+                for tag in tags {
+                    let value = match tag {
+                        Tag::Value { value, .. } => Text::Code(format!("{:#x}", value)),
+                        Tag::Range { start, end, .. } => {
+                            Text::Code(format!("{:#x}..{:#x}", start, end))
+                        }
+                        Tag::Default { .. } => Text::Plain("other values".to_owned()),
+                    };
+                    let loc = tag.loc();
+                    section.rows.push(vec![
+                        vec![Text::Code(tag.id().clone())],
+                        vec![value],
+                        vec![Text::Plain(
+                            self.doc(loc, &loc.end, decl.loc().start.offset).join(" "),
+                        )],
+                    ]);
+                }
+            }
+            Decl::Packet { .. } | Decl::Struct { .. } => {
+                section.kind =
+                    if matches!(decl, Decl::Packet { .. }) { "packet" } else { "struct" };
+                let lineage = self.scope.get_lineage(decl);
+                if lineage.len() > 1 {
+                    let mut chain = vec![];
+                    for ancestor in &lineage[..lineage.len() - 1] {
+                        chain.push(Text::Link(ancestor.id().unwrap().clone()));
+                        chain.push(Text::Plain("→".to_owned()));
+                    }
+                    chain.push(Text::Code(section.id.clone()));
+                    section.properties.push(("Inheritance", vec![chain]));
+                }
+                let constraints = decl
+                    .constraints()
+                    .map(|c| vec![Text::Code(constraint_text(c))])
+                    .collect::<Vec<_>>();
+                if !constraints.is_empty() {
+                    section.properties.push(("Constraints", constraints));
+                }
+                let children = self
+                    .scope
+                    .get_children(decl)
+                    .iter()
+                    .map(|child| vec![Text::Link(child.id().unwrap().clone())])
+                    .collect::<Vec<_>>();
+                if !children.is_empty() {
+                    section.properties.push(("Children", children));
+                }
+                section.header = vec!["Field", "Type", "Offset", "Width", "Description"];
+                let mut offset = start_offset(&self.scope, decl);
+                for field in decl.fields() {
+                    let width = self.scope.get_field_width(field);
+                    let width_text = match (field, width) {
+                        (Field::Padding { width, .. }, _) => format!("{}", width * 8),
+                        (_, Some(width)) => width.to_string(),
+                        (_, None) => "variable".to_owned(),
+                    };
+                    section.rows.push(vec![
+                        vec![Text::Code(field_name(field))],
+                        Self::field_type(field),
+                        vec![Text::Plain(offset.map_or_else(|| "-".to_owned(), |o| o.to_string()))],
+                        vec![Text::Plain(width_text)],
+                        vec![Text::Plain(
+                            self.doc(field.loc(), &field.loc().end, decl.loc().start.offset)
+                                .join(" "),
+                        )],
+                    ]);
+                    offset = offset.zip(width).map(|(offset, width)| offset + width);
+                }
+            }
+            Decl::Group { .. } | Decl::Test { .. } => return None,
+        }
+        Some(section)
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn markdown_text(text: &[Text]) -> String {
+    text.iter()
+        .map(|text| match text {
+            Text::Plain(text) => escape_markdown(text),
+            Text::Code(code) => format!("`{}`", code.replace('|', "\\|")),
+            Text::Link(id) => format!("[`{}`](#{})", id, id),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn html_text(text: &[Text]) -> String {
+    text.iter()
+        .map(|text| match text {
+            Text::Plain(text) => escape_html(text),
+            Text::Code(code) => format!("<code>{}</code>", escape_html(code)),
+            Text::Link(id) => format!("<a href=\"#{}\"><code>{}</code></a>", id, id),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn render_markdown(title: &str, sections: &[Section]) -> String {
+    let mut output = String::new();
+    writeln!(output, "# {}", escape_markdown(title)).unwrap();
+    writeln!(output).unwrap();
+    for section in sections {
+        writeln!(output, "- [{} `{}`](#{})", section.kind, section.id, section.id).unwrap();
+    }
+    for section in sections {
+        writeln!(output).unwrap();
+        writeln!(output, "<a id=\"{}\"></a>", section.id).unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "## {} `{}`", section.kind, section.id).unwrap();
+        if !section.doc.is_empty() {
+            writeln!(output).unwrap();
+            for line in &section.doc {
+                writeln!(output, "{}", escape_markdown(line)).unwrap();
+            }
+        }
+        if !section.properties.is_empty() {
+            writeln!(output).unwrap();
+            for (name, items) in &section.properties {
+                let items = items.iter().map(|item| markdown_text(item)).collect::<Vec<_>>();
+                writeln!(output, "- **{}**: {}", name, items.join(", ")).unwrap();
+            }
+        }
+        if !section.rows.is_empty() {
+            writeln!(output).unwrap();
+            writeln!(output, "| {} |", section.header.join(" | ")).unwrap();
+            writeln!(output, "|{}", " --- |".repeat(section.header.len())).unwrap();
+            for row in &section.rows {
+                let cells = row.iter().map(|cell| markdown_text(cell)).collect::<Vec<_>>();
+                writeln!(output, "| {} |", cells.join(" | ")).unwrap();
+            }
+        }
+    }
+    output
+}
+
+fn render_html(title: &str, sections: &[Section]) -> String {
+    let mut output = String::new();
+    writeln!(output, "<!DOCTYPE html>").unwrap();
+    writeln!(output, "<html>").unwrap();
+    writeln!(output, "<head>").unwrap();
+    writeln!(output, "<meta charset=\"utf-8\">").unwrap();
+    writeln!(output, "<title>{}</title>", escape_html(title)).unwrap();
+    writeln!(output, "<style>").unwrap();
+    writeln!(output, "body {{ font-family: sans-serif; margin: 2em; }}").unwrap();
+    writeln!(output, "table {{ border-collapse: collapse; }}").unwrap();
+    writeln!(output, "th, td {{ border: 1px solid #ccc; padding: 0.2em 0.6em; }}").unwrap();
+    writeln!(output, "</style>").unwrap();
+    writeln!(output, "</head>").unwrap();
+    writeln!(output, "<body>").unwrap();
+    writeln!(output, "<h1>{}</h1>", escape_html(title)).unwrap();
+    writeln!(output, "<ul>").unwrap();
+    for section in sections {
+        writeln!(
+            output,
+            "<li><a href=\"#{}\">{} <code>{}</code></a></li>",
+            section.id, section.kind, section.id
+        )
+        .unwrap();
+    }
+    writeln!(output, "</ul>").unwrap();
+    for section in sections {
+        writeln!(output, "<section id=\"{}\">", section.id).unwrap();
+        writeln!(output, "<h2>{} <code>{}</code></h2>", section.kind, section.id).unwrap();
+        if !section.doc.is_empty() {
+            let doc = section.doc.iter().map(|line| escape_html(line)).collect::<Vec<_>>();
+            writeln!(output, "<p>{}</p>", doc.join("\n")).unwrap();
+        }
+        if !section.properties.is_empty() {
+            writeln!(output, "<ul>").unwrap();
+            for (name, items) in &section.properties {
+                let items = items.iter().map(|item| html_text(item)).collect::<Vec<_>>();
+                writeln!(output, "<li><strong>{}</strong>: {}</li>", name, items.join(", "))
+                    .unwrap();
+            }
+            writeln!(output, "</ul>").unwrap();
+        }
+        if !section.rows.is_empty() {
+            writeln!(output, "<table>").unwrap();
+            let header =
+                section.header.iter().map(|h| format!("<th>{}</th>", h)).collect::<String>();
+            writeln!(output, "<tr>{}</tr>", header).unwrap();
+            for row in &section.rows {
+                let cells = row
+                    .iter()
+                    .map(|cell| format!("<td>{}</td>", html_text(cell)))
+                    .collect::<String>();
+                writeln!(output, "<tr>{}</tr>", cells).unwrap();
+            }
+            writeln!(output, "</table>").unwrap();
+        }
+        writeln!(output, "</section>").unwrap();
+    }
+    writeln!(output, "</body>").unwrap();
+    writeln!(output, "</html>").unwrap();
+    output
+}
+
+/// Generate the documentation of all the declarations of a grammar,
+/// including the declarations of imported files.
+pub fn generate(sources: &SourceDatabase, grammar: &Grammar, format: Format) -> String {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let title = Path::new(source.name()).file_name().unwrap().to_string_lossy().to_string();
+    let grammar = analyzer::inline_groups(grammar);
+    let mut comments = grammar.comments.iter().collect::<Vec<_>>();
+    comments.sort_by_key(|c| (c.loc.file, c.loc.start.offset));
+    // A comment is trailing when it follows source text on its line.
+    let (trailing, leading): (Vec<_>, Vec<_>) = comments.into_iter().partition(|c| {
+        // The source is not available for a JSON AST input.
+        let line =
+            sources.source(c.loc.file).ok().and_then(|source| source.get(..c.loc.start.offset));
+        line.is_some_and(|line| !line[line.rfind('\n').map_or(0, |i| i + 1)..].trim().is_empty())
+    });
+    let generator = Generator { scope: Scope::new(&grammar), sources, trailing, leading };
+    let sections = grammar
+        .declarations
+        .iter()
+        .filter_map(|decl| generator.generate_decl(decl))
+        .collect::<Vec<_>>();
+    match format {
+        Format::Markdown => render_markdown(&title, &sections),
+        Format::Html => render_html(&title, &sections),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::{assert_contains, assert_snapshot_eq};
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+
+        /// Operation codes.
+        enum OpCode : 8 {
+            RESET = 1, // Reset the controller.
+            READ = 2,
+        }
+
+        // Single item of a read command.
+        struct Item { a: 8, b: 16 }
+
+        /* Command header,
+         * common to all commands. */
+        packet Command { // Sent by the host.
+            op_code: OpCode,
+            // Set when the command is urgent.
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+
+        packet Reset : Command (op_code = RESET) {
+            // Must be zero.
+            delay: 8,
+        }
+
+        packet Read : Command (op_code = READ) {
+            _count_(items): 8,
+            items: Item[],
+            extra: 8 if flag = 1, // Never set.
+        }
+    "#;
+
+    fn generate_inline(format: Format) -> String {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test.pdl".to_owned(), GRAMMAR.to_owned())
+            .expect("parsing failure");
+        generate(&db, &grammar, format)
+    }
+
+    #[test]
+    fn test_comment_lines() {
+        assert_eq!(comment_lines("// Comment."), vec!["Comment."]);
+        assert_eq!(comment_lines("/* A\n * B */"), vec!["A", "B"]);
+        assert_eq!(comment_lines("/*\n A\n*/"), vec!["A"]);
+    }
+
+    #[test]
+    fn test_generate_markdown() {
+        let output = generate_inline(Format::Markdown);
+        assert_contains(&output, "| `op_code` | [`OpCode`](#OpCode) | 0 | 8 |  |");
+        assert_contains(&output, "| `flag` | scalar | 8 | 1 | Set when the command is urgent. |");
+        assert_contains(&output, "| `delay` | scalar | 24 | 8 | Must be zero. |");
+        assert_contains(&output, "- **Children**: [`Reset`](#Reset), [`Read`](#Read)");
+        assert_snapshot_eq("tests/generated/docs.md", &output);
+    }
+
+    #[test]
+    fn test_generate_html() {
+        let output = generate_inline(Format::Html);
+        assert_contains(&output, "<section id=\"Command\">");
+        assert_contains(&output, "<a href=\"#Command\"><code>Command</code></a> →");
+        assert_snapshot_eq("tests/generated/docs.html", &output);
+    }
+}
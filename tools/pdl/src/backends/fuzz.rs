@@ -0,0 +1,346 @@
+//! cargo-fuzz backend.
+//!
+//! Generates a cargo-fuzz crate exercising the Rust code generated for
+//! a grammar. Each packet declared without parent gets a fuzz target
+//! parsing arbitrary bytes; when parsing succeeds, the serialized
+//! packet must be equal to the bytes consumed from the input. Reserved
+//! and padding fields are not preserved by the parser, the targets of
+//! packets including them only check that the serialized packet is
+//! not longer than the input and round-trips through the parser.
+//!
+//! The corpus of each target is seeded with a minimal instance of the
+//! packet and of each of its descendants, encoded by the interpreter:
+//! constrained and fixed fields take their declared values, and other
+//! fields take the value zero, the first enum tag, or are left empty.
+
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::backends::rust::to_snake_case;
+use crate::interpreter::{self, encoder};
+
+/// Return the minimal value of a field of type `type_id`, or `None` if
+/// no value can be synthesized.
+fn typedef_value(scope: &Scope, type_id: &str, optional: bool) -> Option<Value> {
+    match scope.typedef[type_id] {
+        Decl::Enum { tags, .. } => Some(match tags.iter().find(|t| t.value().is_some()) {
+            Some(tag) => Value::from(tag.id().clone()),
+            None => match tags.first() {
+                Some(Tag::Range { start, .. }) => Value::from(*start),
+                _ => Value::from(0),
+            },
+        }),
+        Decl::CustomField { width: Some(_), .. } => Some(Value::from(0)),
+        decl @ Decl::Struct { .. } => Some(Value::Object(minimal_fields(scope, decl, optional))),
+        _ => None,
+    }
+}
+
+/// Return the minimal field values of a packet or struct declaration,
+/// in the format expected by the interpreter. Optional fields are
+/// given a value if `optional` is set.
+fn minimal_fields(scope: &Scope, decl: &Decl, optional: bool) -> Map<String, Value> {
+    let lineage = scope.get_lineage(decl);
+    let constrained =
+        lineage.iter().flat_map(|d| d.constraints()).map(|c| &c.id).collect::<Vec<_>>();
+    let mut fields = Map::new();
+    for field in lineage.iter().flat_map(|d| d.fields()) {
+        if field.cond().is_some() && !optional {
+            continue;
+        }
+        let value = match field {
+            Field::Scalar { id, .. } if !constrained.contains(&id) => Some(Value::from(0)),
+            Field::Typedef { id, type_id, .. } if !constrained.contains(&id) => {
+                match scope.typedef[type_id] {
+                    // Checksums of the library are computed.
+                    decl @ Decl::Checksum { .. } => {
+                        interpreter::checksum_algorithm(decl).is_none().then(|| Value::from(0))
+                    }
+                    _ => typedef_value(scope, type_id, optional),
+                }
+            }
+            Field::Array { width, type_id, size, .. } => {
+                let element = match (width, type_id) {
+                    (Some(_), _) => Some(Value::from(0)),
+                    (_, Some(type_id)) => typedef_value(scope, type_id, optional),
+                    _ => unreachable!(),
+                };
+                Some(Value::Array(vec![element.unwrap_or_default(); size.unwrap_or(0)]))
+            }
+            _ => None,
+        };
+        if let Some(value) = value {
+            fields.insert(field.id().unwrap().clone(), value);
+        }
+    }
+    if decl.fields().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. })) {
+        fields.insert("payload".to_owned(), Value::from(""));
+    }
+    fields
+}
+
+/// Return the encoding of a minimal instance of the declaration, or
+/// `None` if no valid instance could be synthesized.
+fn minimal_instance(grammar: &Grammar, scope: &Scope, decl: &Decl) -> Option<Vec<u8>> {
+    let id = decl.id().unwrap();
+    [false, true].into_iter().find_map(|optional| {
+        let fields = minimal_fields(scope, decl, optional);
+        encoder::encode(grammar, id, &Value::Object(fields)).ok()
+    })
+}
+
+/// Return true if the fields of the declaration, or of the structs it
+/// includes, have values discarded by the parser.
+fn has_lossy_fields(scope: &Scope, decl: &Decl) -> bool {
+    decl.fields().any(|field| match field {
+        Field::Reserved { .. } | Field::Padding { .. } => true,
+        Field::Typedef { type_id, .. } | Field::Array { type_id: Some(type_id), .. } => {
+            match scope.typedef.get(type_id) {
+                Some(decl @ Decl::Struct { .. }) => has_lossy_fields(scope, decl),
+                _ => false,
+            }
+        }
+        _ => false,
+    })
+}
+
+/// Return the packet and its descendants, in depth first order.
+fn descendants<'d>(scope: &Scope<'d>, decl: &'d Decl) -> Vec<&'d Decl> {
+    let mut decls = vec![decl];
+    for child in scope.get_children(decl) {
+        decls.extend(descendants(scope, child));
+    }
+    decls
+}
+
+fn generate_target(source_name: &str, module: &str, id: &str, lossy: bool) -> String {
+    let check = if lossy {
+        format!(
+            r#"assert!(bytes.len() <= data.len(), "serialized packet is longer than the input");
+        let reparsed = {id}Packet::parse(&bytes).expect("serialized packet failed to parse");
+        assert_eq!(reparsed.to_vec(), bytes, "serialized packet did not round-trip");"#,
+            id = id
+        )
+    } else {
+        r#"assert_eq!(
+            Some(&bytes[..]),
+            data.get(..bytes.len()),
+            "serialized packet differs from the input"
+        );"#
+        .to_owned()
+    };
+    format!(
+        r#"// @generated fuzz target for the {id} packet of {source_name}
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use {module}::{{{id}Packet, Packet}};
+
+fuzz_target!(|data: &[u8]| {{
+    if let Ok(packet) = {id}Packet::parse(data) {{
+        let bytes = packet.to_vec();
+        {check}
+    }}
+}});
+"#,
+        id = id,
+        module = module,
+        source_name = source_name,
+        check = check
+    )
+}
+
+fn generate_manifest(
+    source_name: &str,
+    module: &str,
+    package: Option<&str>,
+    path: &str,
+    targets: &[String],
+) -> String {
+    let stem = Path::new(source_name).file_stem().unwrap().to_string_lossy();
+    let name = module.split("::").next().unwrap();
+    let dependency = match package {
+        Some(package) if package != name => {
+            format!("{} = {{ package = \"{}\", path = \"{}\" }}", name, package, path)
+        }
+        _ => format!("{} = {{ path = \"{}\" }}", name, path),
+    };
+    let mut manifest = format!(
+        r#"# @generated cargo-fuzz manifest for {source_name}
+[package]
+name = "{name}-fuzz"
+version = "0.0.0"
+publish = false
+edition = "2021"
+
+[package.metadata]
+cargo-fuzz = true
+
+[dependencies]
+libfuzzer-sys = "0.4"
+{dependency}
+"#,
+        source_name = source_name,
+        name = stem.replace('_', "-"),
+        dependency = dependency
+    );
+    for target in targets {
+        manifest.push_str(&format!(
+            "\n[[bin]]\nname = \"{}\"\npath = \"fuzz_targets/{}.rs\"\ntest = false\ndoc = false\n",
+            target, target
+        ));
+    }
+    manifest
+}
+
+/// Generate the files of the cargo-fuzz crate, as paths relative to
+/// the crate directory and file contents. `module` is the path of the
+/// Rust module generated from the grammar, starting with the name of
+/// its crate. The crate is found at `path` relative to the cargo-fuzz
+/// crate, and is published as `package` when its package name differs
+/// from the crate name.
+pub fn generate(
+    sources: &SourceDatabase,
+    grammar: &Grammar,
+    module: &str,
+    package: Option<&str>,
+    path: &str,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, String> {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let source_name = Path::new(source.name()).file_name().unwrap().to_string_lossy().to_string();
+    let inlined = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&inlined);
+
+    let mut files = vec![];
+    let mut targets = vec![];
+    for decl in &inlined.declarations {
+        let id = match decl {
+            Decl::Packet { id, parent_id: None, .. } => id,
+            _ => continue,
+        };
+        let target = to_snake_case(id);
+        let decls = descendants(&scope, decl);
+        let lossy = decls.iter().any(|decl| has_lossy_fields(&scope, decl));
+        files.push((
+            PathBuf::from(format!("fuzz_targets/{}.rs", target)),
+            generate_target(&source_name, module, id, lossy).into_bytes(),
+        ));
+        for decl in decls {
+            if let Some(bytes) = minimal_instance(grammar, &scope, decl) {
+                let seed = to_snake_case(decl.id().unwrap());
+                files.push((PathBuf::from(format!("corpus/{}/{}", target, seed)), bytes));
+            }
+        }
+        targets.push(target);
+    }
+    if targets.is_empty() {
+        return Err("the grammar does not declare any packet".to_owned());
+    }
+    files.insert(
+        0,
+        (
+            PathBuf::from("Cargo.toml"),
+            generate_manifest(&source_name, module, package, path, &targets).into_bytes(),
+        ),
+    );
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::assert_contains;
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        checksum Crc8 : 8 "crc8"
+        enum OpCode : 8 { RESET = 1, READ = 2 }
+        struct Item { a: 8, b: 16 }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            _fixed_ = 0x42 : 8,
+        }
+        packet Read : Command (op_code = READ) {
+            items: Item[2],
+            extra: 8 if flag = 1,
+        }
+        packet Frame {
+            _checksum_start_(crc),
+            a: 8,
+            crc: Crc8,
+        }
+    "#;
+
+    fn generate_inline() -> Vec<(String, Vec<u8>)> {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test_packets.pdl".to_owned(), GRAMMAR.to_owned())
+            .expect("parsing failure");
+        generate(&db, &grammar, "test_packets::test", None, "..")
+            .unwrap()
+            .into_iter()
+            .map(|(path, content)| (path.to_string_lossy().to_string(), content))
+            .collect()
+    }
+
+    #[test]
+    fn test_generate_targets() {
+        let files = generate_inline();
+        let paths = files.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "Cargo.toml",
+                "fuzz_targets/command.rs",
+                "corpus/command/command",
+                "corpus/command/reset",
+                "corpus/command/read",
+                "fuzz_targets/frame.rs",
+                "corpus/frame/frame",
+            ]
+        );
+        let manifest = String::from_utf8(files[0].1.clone()).unwrap();
+        assert_contains(&manifest, "name = \"test-packets-fuzz\"");
+        assert_contains(&manifest, "test_packets = { path = \"..\" }");
+        assert_contains(&manifest, "path = \"fuzz_targets/frame.rs\"");
+        let target = String::from_utf8(files[1].1.clone()).unwrap();
+        assert_contains(&target, "use test_packets::test::{CommandPacket, Packet};");
+        assert_contains(&target, "CommandPacket::parse(data)");
+        assert_contains(&target, "CommandPacket::parse(&bytes)");
+        let target = String::from_utf8(files[5].1.clone()).unwrap();
+        assert_contains(&target, "data.get(..bytes.len())");
+    }
+
+    #[test]
+    fn test_generate_manifest() {
+        let manifest =
+            generate_manifest("test_packets.pdl", "test_packets::test", None, "../..", &[]);
+        assert_contains(&manifest, "test_packets = { path = \"../..\" }");
+        let manifest = generate_manifest(
+            "test_packets.pdl",
+            "test_packets::test",
+            Some("test-packets"),
+            "..",
+            &[],
+        );
+        assert_contains(&manifest, "test_packets = { package = \"test-packets\", path = \"..\" }");
+    }
+
+    #[test]
+    fn test_generate_corpus() {
+        let files = generate_inline();
+        let seed = |name: &str| files.iter().find(|(path, _)| path == name).unwrap().1.clone();
+        assert_eq!(seed("corpus/command/command"), vec![0x01, 0x00, 0x00]);
+        assert_eq!(seed("corpus/command/reset"), vec![0x01, 0x00, 0x01, 0x42]);
+        assert_eq!(seed("corpus/command/read"), vec![0x02, 0x00, 0x06, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(seed("corpus/frame/frame"), vec![0x00, 0x00]);
+    }
+}
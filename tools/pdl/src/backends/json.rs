@@ -0,0 +1,176 @@
+//! JSON compiler backend.
+//!
+//! The JSON representation of the AST is described by the schema
+//! `schema/ast.schema.json`, and can be read back in place of a PDL
+//! source file.
+
+use codespan_reporting::diagnostic::Diagnostic;
+use serde_json::Value;
+
+use crate::ast;
+
+/// Turn the AST into a JSON representation.
+pub fn generate(grammar: &ast::Grammar) -> Result<String, String> {
+    serde_json::to_string_pretty(grammar)
+        .map_err(|err| format!("could not JSON serialize grammar: {}", err))
+}
+
+/// Apply `f` to the file identifiers of a JSON AST: the file of the
+/// grammar, and the files of the source ranges.
+fn visit_file_ids(value: &mut Value, f: &mut impl FnMut(&mut Value)) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match key.as_str() {
+                    "file" => f(value),
+                    _ => visit_file_ids(value, f),
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(|value| visit_file_ids(value, f)),
+        _ => (),
+    }
+}
+
+/// Read a JSON AST previously generated by the JSON backend.
+///
+/// The original source files are not part of the JSON representation:
+/// the source files referenced by the AST are added to the source
+/// database under the name `name`, with empty contents, and the file
+/// identifiers are renumbered to reference them.
+pub fn parse(
+    sources: &mut ast::SourceDatabase,
+    name: String,
+    source: String,
+) -> Result<ast::Grammar, Diagnostic<ast::FileId>> {
+    let err = |message: String| {
+        Diagnostic::error()
+            .with_message(format!("failed to parse input file '{}': {}", &name, message))
+    };
+    let mut value: Value = serde_json::from_str(&source).map_err(|e| err(e.to_string()))?;
+
+    let version = value["version"].as_str().unwrap_or_default();
+    let expected_major = ast::VERSION.split('.').next().unwrap();
+    if version.split(['.', ',']).next() != Some(expected_major) {
+        return Err(err(format!(
+            "unsupported AST version `{}`, expected version {}",
+            version,
+            ast::VERSION
+        )));
+    }
+
+    let mut file_count = 0;
+    visit_file_ids(&mut value, &mut |file| {
+        let id = file.as_u64().map_or(0, |id| id as usize + 1);
+        file_count = std::cmp::max(file_count, id);
+    });
+    let files =
+        (0..file_count).map(|_| sources.add(name.clone(), String::new())).collect::<Vec<_>>();
+    visit_file_ids(&mut value, &mut |file| {
+        if let Some(id) = file.as_u64() {
+            *file = Value::from(files[id as usize]);
+        }
+    });
+    serde_json::from_value(value).map_err(|e| err(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast;
+    use crate::backends::json::*;
+    use crate::parser::parse_inline;
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        // Operation codes.
+        enum OpCode : 8 { RESET = 1, READ = 2, VENDOR = 0xf0..0xfe, OTHER = .. }
+        checksum Crc8 : 8 "crc8"
+        custom_field Address : 48 "hci/"
+        group Header { op_code: OpCode, _fixed_ = 0 : 8 }
+        packet Command {
+            Header { op_code = RESET },
+            _size_(_payload_): 8,
+            _payload_ : [+2],
+        }
+        packet Read : Command (op_code = READ) {
+            _count_(items): 4,
+            _reserved_: 4,
+            items: Address[],
+            bytes: 8[4],
+            flag: 1,
+            extra: 7 if flag = 1 << 0,
+            _padding_[8],
+        }
+        struct Item { _checksum_start_(crc), a: 8, crc: Crc8, _body_ }
+        test Command {
+            "\x01\x00\x00\x00" : Read (flag = 0),
+        }
+    "#;
+
+    #[test]
+    fn test_round_trip() {
+        let mut db = ast::SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test.pdl".to_owned(), GRAMMAR.to_owned())
+            .expect("parsing failure");
+        let json = generate(&grammar).unwrap();
+
+        let mut db = ast::SourceDatabase::new();
+        let parsed = parse(&mut db, "test.json".to_owned(), json.clone()).unwrap();
+        assert_eq!(parsed.version, ast::VERSION);
+        assert_eq!(generate(&parsed).unwrap(), json);
+        assert_eq!(db.get(parsed.file).unwrap().name(), "test.json");
+    }
+
+    #[test]
+    fn test_file_ids() {
+        let mut db = ast::SourceDatabase::new();
+        db.add("first.pdl".to_owned(), String::new());
+        let json = r#"{
+            "version": "1,0", "file": 1, "comments": [], "endianness": null, "imports": [],
+            "declarations": [{
+                "kind": "enum_declaration", "id": "E", "width": 8, "tags": [],
+                "loc": {
+                    "file": 0,
+                    "start": { "offset": 0, "line": 0, "column": 0 },
+                    "end": { "offset": 10, "line": 0, "column": 10 }
+                }
+            }]
+        }"#;
+        let grammar = parse(&mut db, "test.json".to_owned(), json.to_owned()).unwrap();
+        assert_eq!(grammar.file, 2);
+        assert_eq!(grammar.declarations[0].loc().file, 1);
+        assert_eq!(db.get(2).unwrap().name(), "test.json");
+
+        let json = json.replace("1,0", "2.0");
+        let err = parse(&mut db, "test.json".to_owned(), json).unwrap_err();
+        assert!(err.message.contains("unsupported AST version `2.0`"));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: Value =
+            serde_json::from_str(include_str!("../../schema/ast.schema.json")).unwrap();
+        assert!(schema["$id"].as_str().unwrap().ends_with(ast::VERSION));
+
+        // All node kinds of the AST are described by the schema.
+        let mut db = ast::SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test.pdl".to_owned(), GRAMMAR.to_owned())
+            .expect("parsing failure");
+        let mut kinds = vec![];
+        let mut stack = vec![serde_json::to_value(&grammar).unwrap()];
+        while let Some(value) = stack.pop() {
+            match value {
+                Value::Object(object) => {
+                    kinds.extend(object.get("kind").and_then(Value::as_str).map(str::to_owned));
+                    stack.extend(object.into_iter().map(|(_, value)| value));
+                }
+                Value::Array(array) => stack.extend(array),
+                _ => (),
+            }
+        }
+        let schema = schema.to_string();
+        for kind in kinds {
+            assert!(schema.contains(&format!("\"{}\"", kind)), "kind `{}` is not described", kind);
+        }
+    }
+}
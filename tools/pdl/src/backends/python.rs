@@ -0,0 +1,1070 @@
+//! Python backend.
+//!
+//! Generates a Python module with an `enum.IntEnum` class for each
+//! enum declaration, and a dataclass for each packet and struct
+//! declaration. The class of a child packet derives from the class of
+//! its parent: parsing a packet returns an instance of the most
+//! derived class whose constraints match the decoded fields.
+//!
+//! The generated module only depends on the Python standard library.
+//! Custom fields are represented as integers, and checksum fields are
+//! decoded and encoded as plain values, without verification.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+
+/// Python keywords, which cannot be used as field names.
+const KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// Return the Python name of the field `id`.
+fn field_name(id: &str) -> String {
+    match KEYWORDS.contains(&id) {
+        true => format!("{}_", id),
+        false => id.to_owned(),
+    }
+}
+
+/// Return the mask of a value of `width` bits.
+fn mask(width: usize) -> String {
+    let mask = if width >= 64 { u64::MAX } else { (1 << width) - 1 };
+    format!("0x{:x}", mask)
+}
+
+/// Return the local variable holding the size in bytes of the field
+/// `field_id`.
+fn size_variable(field_id: &str) -> String {
+    match field_id {
+        "_payload_" | "_body_" => "payload_size".to_owned(),
+        _ => format!("{}_size", field_id),
+    }
+}
+
+/// Return the local variable holding the element count of the array
+/// `field_id`.
+fn count_variable(field_id: &str) -> String {
+    format!("{}_count", field_id)
+}
+
+/// Fields of a declaration, grouped as they are read and written.
+enum Item<'d> {
+    /// Bit fields packed in a chunk of `width` bits, with their bit
+    /// offset in the chunk.
+    Chunk { fields: Vec<(&'d Field, usize)>, width: usize },
+    /// Any other field, with its index in the declaration.
+    Field(usize, &'d Field),
+}
+
+struct Generator<'a, 'd> {
+    scope: &'a Scope<'d>,
+    /// Byte order argument of `int.from_bytes` and `int.to_bytes`.
+    byteorder: &'static str,
+}
+
+impl<'a, 'd> Generator<'a, 'd> {
+    /// Return the declaration referenced by a typedef or array field.
+    fn type_decl(&self, field: &Field) -> Option<&'d Decl> {
+        match field {
+            Field::Typedef { type_id, .. } | Field::Array { type_id: Some(type_id), .. } => {
+                self.scope.typedef.get(type_id).copied()
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the width of the values of a field decoded as integers,
+    /// or `None` for struct values.
+    fn value_width(&self, field: &Field) -> Option<usize> {
+        match (field, self.type_decl(field)) {
+            (Field::Scalar { width, .. } | Field::Array { width: Some(width), .. }, _) => {
+                Some(*width)
+            }
+            (_, Some(decl)) if !matches!(decl, Decl::Struct { .. }) => {
+                self.scope.get_decl_width(decl)
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the Python type of the values of a field.
+    fn value_type(&self, field: &Field) -> String {
+        match self.type_decl(field) {
+            Some(Decl::Enum { id, .. } | Decl::Struct { id, .. }) => id.clone(),
+            _ => "int".to_owned(),
+        }
+    }
+
+    /// Return the Python expression converting the integer `value`
+    /// to a value of the field.
+    fn decode_value(&self, field: &Field, value: &str) -> String {
+        match self.type_decl(field) {
+            Some(Decl::Enum { id, .. }) => format!("_enum({}, {})", id, value),
+            _ => value.to_owned(),
+        }
+    }
+
+    fn tag_value(&self, enum_id: &str, tag_id: &str) -> usize {
+        match self.scope.typedef.get(enum_id) {
+            Some(Decl::Enum { tags, .. }) => {
+                tags.iter().find(|t| t.id() == tag_id).unwrap().value().unwrap()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the Python expression of the value of a constraint of
+    /// the declaration `decl`.
+    fn constraint_value(&self, decl: &'d Decl, constraint: &Constraint) -> Result<String, String> {
+        match (&constraint.value, self.scope.get_field(decl, &constraint.id)) {
+            (Expr::Identifier { name, .. }, Some(Field::Typedef { type_id, .. })) => {
+                Ok(format!("{}.{}", type_id, name))
+            }
+            (expr, _) => Ok(format!("0x{:x}", expr.evaluate().map_err(|(_, message)| message)?)),
+        }
+    }
+
+    /// Group the fields of the declaration in chunks of bit fields.
+    fn items(&self, decl: &'d Decl) -> Result<Vec<Item<'d>>, String> {
+        let id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let mut items = vec![];
+        let mut chunk = vec![];
+        let mut chunk_width = 0;
+        for (index, field) in fields.iter().copied().enumerate() {
+            if self.scope.is_bitfield(field) {
+                chunk.push((field, chunk_width));
+                chunk_width += self.scope.get_field_width(field).unwrap();
+                if let (_, 0) = (chunk_width / 8, chunk_width % 8) {
+                    items.push(Item::Chunk { fields: chunk, width: chunk_width });
+                    chunk = vec![];
+                    chunk_width = 0;
+                }
+                continue;
+            }
+            if !chunk.is_empty() {
+                return Err(format!("{}: bit fields are not aligned to an octet boundary", id));
+            }
+            match (field, self.type_decl(field)) {
+                (Field::Padding { .. }, _)
+                    if !matches!(fields.get(index.wrapping_sub(1)), Some(Field::Array { .. })) =>
+                {
+                    return Err(format!("{}: padding field not following an array", id))
+                }
+                (_, Some(Decl::CustomField { id: type_id, width: None, .. })) => {
+                    return Err(format!(
+                        "{}: variable size custom field `{}` is not supported",
+                        id, type_id
+                    ))
+                }
+                _ => items.push(Item::Field(index, field)),
+            }
+        }
+        if !chunk.is_empty() {
+            return Err(format!("{}: bit fields are not aligned to an octet boundary", id));
+        }
+        Ok(items)
+    }
+
+    /// Return the size modifier of the array or payload `field_id`,
+    /// in bytes.
+    fn size_modifier(&self, fields: &[&'d Field], field_id: &str) -> isize {
+        let size_modifier = fields.iter().find_map(|f| match f {
+            Field::Array { id, size_modifier, .. } if id == field_id => size_modifier.as_ref(),
+            Field::Payload { size_modifier, .. } if field_id == "_payload_" => {
+                size_modifier.as_ref()
+            }
+            _ => None,
+        });
+        size_modifier.map_or(0, |m| analyzer::size_modifier_bits(m) / 8)
+    }
+
+    /// Return the size in bytes of the fields following the field at
+    /// `index`, which must have a static size.
+    fn trailing_size(&self, fields: &[&'d Field], index: usize) -> Result<usize, String> {
+        self.scope.get_trailing_width(fields, index).map(|width| width / 8).ok_or_else(|| {
+            format!(
+                "{}: variable size field declared after a payload or unbounded array",
+                fields[index].id().map_or("payload", |id| id.as_str())
+            )
+        })
+    }
+
+    /// Write the statements checking that `span` contains at least
+    /// `size` bytes.
+    fn check_size(&self, decl_id: &str, span: &str, size: &str, indent: &str, code: &mut String) {
+        writeln!(code, "{}if len({}) < {}:", indent, span, size).unwrap();
+        writeln!(code, "{}    raise ParseError('{}: unexpected end of input')", indent, decl_id)
+            .unwrap();
+    }
+
+    /// Write the statements decoding a chunk of bit fields from `span`.
+    fn parse_chunk(
+        &self,
+        decl_id: &str,
+        fields: &[&'d Field],
+        chunk: &[(&'d Field, usize)],
+        width: usize,
+        code: &mut String,
+    ) {
+        let size = width / 8;
+        self.check_size(decl_id, "span", &size.to_string(), "        ", code);
+        if chunk.iter().any(|(field, _)| !matches!(field, Field::Reserved { .. })) {
+            writeln!(
+                code,
+                "        chunk = int.from_bytes(span[0:{}], byteorder='{}')",
+                size, self.byteorder
+            )
+            .unwrap();
+        }
+        for (field, shift) in chunk {
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let value = match (*shift, field_width == width) {
+                (0, true) => "chunk".to_owned(),
+                (0, false) => format!("(chunk & {})", mask(field_width)),
+                _ => format!("((chunk >> {}) & {})", shift, mask(field_width)),
+            };
+            match field {
+                Field::Scalar { id, .. } | Field::Typedef { id, .. } => writeln!(
+                    code,
+                    "        fields['{}'] = {}",
+                    field_name(id),
+                    self.decode_value(field, &value)
+                )
+                .unwrap(),
+                Field::Fixed { value: fixed_value, enum_id, tag_id, .. } => {
+                    let expected = match (fixed_value, enum_id, tag_id) {
+                        (Some(value), _, _) => *value,
+                        (_, Some(enum_id), Some(tag_id)) => self.tag_value(enum_id, tag_id),
+                        _ => unreachable!(),
+                    };
+                    writeln!(code, "        if {} != 0x{:x}:", value, expected).unwrap();
+                    writeln!(
+                        code,
+                        "            raise ParseError('{}: invalid fixed field value')",
+                        decl_id
+                    )
+                    .unwrap();
+                }
+                Field::Size { field_id, .. } => {
+                    let var = size_variable(field_id);
+                    writeln!(code, "        {} = {}", var, value).unwrap();
+                    match self.size_modifier(fields, field_id) {
+                        0 => (),
+                        modifier if modifier > 0 => {
+                            writeln!(code, "        if {} < {}:", var, modifier).unwrap();
+                            writeln!(
+                                code,
+                                "            raise ParseError('{}: invalid size field value')",
+                                decl_id
+                            )
+                            .unwrap();
+                            writeln!(code, "        {} -= {}", var, modifier).unwrap();
+                        }
+                        modifier => writeln!(code, "        {} += {}", var, -modifier).unwrap(),
+                    }
+                }
+                Field::Count { field_id, .. } => {
+                    writeln!(code, "        {} = {}", count_variable(field_id), value).unwrap();
+                }
+                _ => (),
+            }
+        }
+        writeln!(code, "        span = span[{}:]", size).unwrap();
+    }
+
+    /// Write the statements decoding the elements of an array from
+    /// `span`, appending them to the list `values`. If `count` is set
+    /// the number of elements is known, otherwise the elements are
+    /// decoded until `span` is empty.
+    fn parse_elements(
+        &self,
+        decl_id: &str,
+        field: &'d Field,
+        span: &str,
+        count: Option<&str>,
+        code: &mut String,
+    ) {
+        match (self.value_width(field), count) {
+            (Some(width), Some(count)) => {
+                let size = width / 8;
+                let total = match size {
+                    1 => count.to_owned(),
+                    _ => format!("{} * {}", count, size),
+                };
+                self.check_size(decl_id, span, &total, "        ", code);
+                let value = match size {
+                    1 => format!("{}[n]", span),
+                    _ => format!(
+                        "int.from_bytes({}[n * {}:(n + 1) * {}], byteorder='{}')",
+                        span, size, size, self.byteorder
+                    ),
+                };
+                writeln!(
+                    code,
+                    "        values = [{} for n in range({})]",
+                    self.decode_value(field, &value),
+                    count
+                )
+                .unwrap();
+                writeln!(code, "        {} = {}[{}:]", span, span, total).unwrap();
+            }
+            (Some(8), None) if self.type_decl(field).is_none() => {
+                writeln!(code, "        values = list({})", span).unwrap();
+            }
+            (Some(width), None) => {
+                let size = width / 8;
+                if size > 1 {
+                    writeln!(code, "        if len({}) % {} != 0:", span, size).unwrap();
+                    writeln!(
+                        code,
+                        "            raise ParseError('{}: array size is not a multiple of the element size')",
+                        decl_id
+                    )
+                    .unwrap();
+                }
+                let value = match size {
+                    1 => format!("{}[n]", span),
+                    _ => format!(
+                        "int.from_bytes({}[n:n + {}], byteorder='{}')",
+                        span, size, self.byteorder
+                    ),
+                };
+                writeln!(
+                    code,
+                    "        values = [{} for n in range(0, len({}), {})]",
+                    self.decode_value(field, &value),
+                    span,
+                    size
+                )
+                .unwrap();
+            }
+            (None, count) => {
+                let type_id = self.value_type(field);
+                writeln!(code, "        values = []").unwrap();
+                match count {
+                    Some(count) => writeln!(code, "        for n in range({}):", count),
+                    None => writeln!(code, "        while len({}) > 0:", span),
+                }
+                .unwrap();
+                writeln!(code, "            value, {} = {}.parse({})", span, type_id, span)
+                    .unwrap();
+                writeln!(code, "            values.append(value)").unwrap();
+            }
+        }
+    }
+
+    /// Write the statements decoding an array field from `span`.
+    fn parse_array(
+        &self,
+        decl_id: &str,
+        fields: &[&'d Field],
+        index: usize,
+        code: &mut String,
+    ) -> Result<(), String> {
+        let field = fields[index];
+        let (id, size) = match field {
+            Field::Array { id, size, .. } => (id, size),
+            _ => unreachable!(),
+        };
+        let padding = match fields.get(index + 1) {
+            Some(Field::Padding { width, .. }) => Some(*width),
+            _ => None,
+        };
+        let has_size_field =
+            fields.iter().any(|f| matches!(f, Field::Size { field_id, .. } if field_id == id));
+        let count = match size {
+            Some(size) => Some(size.to_string()),
+            None if fields
+                .iter()
+                .any(|f| matches!(f, Field::Count { field_id, .. } if field_id == id)) =>
+            {
+                Some(count_variable(id))
+            }
+            None => None,
+        };
+
+        // The elements are decoded from a byte region bounded by the
+        // padding, the size field or the trailing fields, or from the
+        // count of elements.
+        let region = match (padding, has_size_field) {
+            (Some(padding), _) => Some(padding.to_string()),
+            (None, _) if count.is_some() => None,
+            (None, true) => Some(size_variable(id)),
+            (None, false) => {
+                let trailing = self.trailing_size(fields, index)?;
+                Some(match trailing {
+                    0 => "len(span)".to_owned(),
+                    _ => {
+                        self.check_size(decl_id, "span", &trailing.to_string(), "        ", code);
+                        format!("len(span) - {}", trailing)
+                    }
+                })
+            }
+        };
+        let bound = match (padding, has_size_field, &count) {
+            (Some(padding), true, None) => {
+                let var = size_variable(id);
+                writeln!(code, "        if {} > {}:", var, padding).unwrap();
+                writeln!(
+                    code,
+                    "            raise ParseError('{}: array size exceeds padding size')",
+                    decl_id
+                )
+                .unwrap();
+                Some(var)
+            }
+            _ => region.clone(),
+        };
+        let span = match (&region, &bound) {
+            (Some(region), Some(bound)) => {
+                if padding.is_some() || has_size_field {
+                    self.check_size(decl_id, "span", region, "        ", code);
+                }
+                writeln!(code, "        array_span = span[:{}]", bound).unwrap();
+                writeln!(code, "        span = span[{}:]", region).unwrap();
+                "array_span"
+            }
+            _ => "span",
+        };
+        self.parse_elements(decl_id, field, span, count.as_deref(), code);
+        writeln!(code, "        fields['{}'] = values", field_name(id)).unwrap();
+        Ok(())
+    }
+
+    /// Write the `_parse` method of a packet or struct, decoding the
+    /// fields of the declaration from `span` into the dictionary
+    /// `fields`, which already contains the fields of the parents.
+    fn generate_parse(&self, decl: &'d Decl, code: &mut String) -> Result<(), String> {
+        let id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        writeln!(code, "    @staticmethod").unwrap();
+        writeln!(code, "    def _parse(fields: dict, span: bytes) -> Tuple['{}', bytes]:", id)
+            .unwrap();
+        let mut has_payload = false;
+        for item in self.items(decl)? {
+            let (index, field) = match item {
+                Item::Chunk { fields: chunk, width } => {
+                    self.parse_chunk(id, &fields, &chunk, width, code);
+                    continue;
+                }
+                Item::Field(index, field) => (index, field),
+            };
+            match (field, self.type_decl(field)) {
+                (Field::Typedef { id: field_id, .. }, Some(Decl::Struct { id: type_id, .. })) => {
+                    writeln!(
+                        code,
+                        "        fields['{}'], span = {}.parse(span)",
+                        field_name(field_id),
+                        type_id
+                    )
+                    .unwrap();
+                }
+                (Field::Typedef { id: field_id, .. }, _) => {
+                    let size = self.value_width(field).unwrap() / 8;
+                    self.check_size(id, "span", &size.to_string(), "        ", code);
+                    writeln!(
+                        code,
+                        "        fields['{}'] = int.from_bytes(span[0:{}], byteorder='{}')",
+                        field_name(field_id),
+                        size,
+                        self.byteorder
+                    )
+                    .unwrap();
+                    writeln!(code, "        span = span[{}:]", size).unwrap();
+                }
+                (Field::Array { .. }, _) => self.parse_array(id, &fields, index, code)?,
+                (Field::Payload { .. } | Field::Body { .. }, _) => {
+                    let field_id = match field {
+                        Field::Payload { .. } => "_payload_",
+                        _ => "_body_",
+                    };
+                    let has_size_field = fields
+                        .iter()
+                        .any(|f| matches!(f, Field::Size { field_id: id, .. } if id == field_id));
+                    let size = match has_size_field {
+                        true => {
+                            let var = size_variable(field_id);
+                            self.check_size(id, "span", &var, "        ", code);
+                            var
+                        }
+                        false => match self.trailing_size(&fields, index)? {
+                            0 => "len(span)".to_owned(),
+                            trailing => {
+                                self.check_size(
+                                    id,
+                                    "span",
+                                    &trailing.to_string(),
+                                    "        ",
+                                    code,
+                                );
+                                format!("len(span) - {}", trailing)
+                            }
+                        },
+                    };
+                    writeln!(code, "        payload = span[:{}]", size).unwrap();
+                    writeln!(code, "        span = span[{}:]", size).unwrap();
+                    has_payload = true;
+                }
+                _ => (),
+            }
+        }
+
+        // Decode the payload as the first child packet whose
+        // constraints match the parsed fields.
+        let mut children = self.scope.get_children(decl).to_vec();
+        children.sort_by_key(|child| child.constraints().next().is_none());
+        for child in children {
+            let child_id = child.id().unwrap();
+            let conditions = child
+                .constraints()
+                .map(|c| {
+                    Ok(format!(
+                        "fields['{}'] == {}",
+                        field_name(&c.id),
+                        self.constraint_value(child, c)?
+                    ))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let indent = match conditions.is_empty() {
+                true => "        ",
+                false => {
+                    writeln!(code, "        if {}:", conditions.join(" and ")).unwrap();
+                    "            "
+                }
+            };
+            writeln!(code, "{}packet, remain = {}._parse(fields, payload)", indent, child_id)
+                .unwrap();
+            writeln!(code, "{}if len(remain) > 0:", indent).unwrap();
+            writeln!(
+                code,
+                "{}    raise ParseError('{}: unexpected trailing bytes')",
+                indent, child_id
+            )
+            .unwrap();
+            writeln!(code, "{}return packet, span", indent).unwrap();
+            if conditions.is_empty() {
+                break;
+            }
+        }
+        match has_payload {
+            true => writeln!(code, "        return {}(**fields, payload=payload), span", id),
+            false => writeln!(code, "        return {}(**fields), span", id),
+        }
+        .unwrap();
+        writeln!(code).unwrap();
+        Ok(())
+    }
+
+    /// Write the statements encoding a chunk of bit fields to `span`.
+    fn serialize_chunk(
+        &self,
+        decl_id: &str,
+        fields: &[&'d Field],
+        chunk: &[(&'d Field, usize)],
+        width: usize,
+        code: &mut String,
+    ) {
+        let mut terms = vec![];
+        for (field, shift) in chunk {
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let term = match field {
+                Field::Scalar { id, .. } | Field::Typedef { id, .. } => {
+                    let name = field_name(id);
+                    if matches!(field, Field::Scalar { .. }) {
+                        writeln!(code, "        if self.{} > {}:", name, mask(field_width))
+                            .unwrap();
+                        writeln!(
+                            code,
+                            "            raise ValueError(f'{}: invalid value for field {}: {{self.{}}}')",
+                            decl_id, id, name
+                        )
+                        .unwrap();
+                    }
+                    format!("self.{}", name)
+                }
+                Field::Fixed { value: Some(value), .. } => format!("0x{:x}", value),
+                Field::Fixed { enum_id: Some(enum_id), tag_id: Some(tag_id), .. } => {
+                    format!("0x{:x}", self.tag_value(enum_id, tag_id))
+                }
+                Field::Size { field_id, .. } => {
+                    let var = size_variable(field_id);
+                    let size = match field_id.as_str() {
+                        "_payload_" | "_body_" => "len(payload)".to_owned(),
+                        _ => {
+                            let array =
+                                fields.iter().find(|f| f.id() == Some(field_id)).copied().unwrap();
+                            let name = field_name(field_id);
+                            match self.value_width(array) {
+                                Some(8) => format!("len(self.{})", name),
+                                Some(width) => format!("len(self.{}) * {}", name, width / 8),
+                                None => {
+                                    format!("sum(len(e.serialize()) for e in self.{})", name)
+                                }
+                            }
+                        }
+                    };
+                    match self.size_modifier(fields, field_id) {
+                        0 => writeln!(code, "        {} = {}", var, size),
+                        modifier => writeln!(code, "        {} = {} + {}", var, size, modifier),
+                    }
+                    .unwrap();
+                    writeln!(code, "        if {} > {}:", var, mask(field_width)).unwrap();
+                    writeln!(
+                        code,
+                        "            raise ValueError(f'{}: invalid size for field {}: {{{}}}')",
+                        decl_id, field_id, var
+                    )
+                    .unwrap();
+                    var
+                }
+                Field::Count { field_id, .. } => {
+                    let name = field_name(field_id);
+                    writeln!(code, "        if len(self.{}) > {}:", name, mask(field_width))
+                        .unwrap();
+                    writeln!(
+                        code,
+                        "            raise ValueError(f'{}: invalid count for field {}: {{len(self.{})}}')",
+                        decl_id, field_id, name
+                    )
+                    .unwrap();
+                    format!("len(self.{})", name)
+                }
+                _ => continue,
+            };
+            terms.push(match shift {
+                0 => term,
+                _ => format!("({} << {})", term, shift),
+            });
+        }
+        match terms.is_empty() {
+            true => writeln!(code, "        span.extend(bytes({}))", width / 8),
+            false => {
+                writeln!(code, "        value = {}", terms.join(" | ")).unwrap();
+                writeln!(
+                    code,
+                    "        span.extend(value.to_bytes({}, byteorder='{}'))",
+                    width / 8,
+                    self.byteorder
+                )
+            }
+        }
+        .unwrap();
+    }
+
+    /// Write the `serialize` method of a packet or struct. The method
+    /// of a child packet serializes its fields as the payload of the
+    /// parent packet.
+    fn generate_serialize(&self, decl: &'d Decl, code: &mut String) -> Result<(), String> {
+        let id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        writeln!(code, "    def serialize(self, payload: Optional[bytes] = None) -> bytes:")
+            .unwrap();
+        writeln!(code, "        span = bytearray()").unwrap();
+        if fields.iter().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. })) {
+            writeln!(code, "        if payload is None:").unwrap();
+            writeln!(code, "            payload = self.payload or b''").unwrap();
+        }
+        for item in self.items(decl)? {
+            let (index, field) = match item {
+                Item::Chunk { fields: chunk, width } => {
+                    self.serialize_chunk(id, &fields, &chunk, width, code);
+                    continue;
+                }
+                Item::Field(index, field) => (index, field),
+            };
+            match field {
+                Field::Typedef { id: field_id, .. } => match self.value_width(field) {
+                    Some(width) => writeln!(
+                        code,
+                        "        span.extend(self.{}.to_bytes({}, byteorder='{}'))",
+                        field_name(field_id),
+                        width / 8,
+                        self.byteorder
+                    ),
+                    None => writeln!(
+                        code,
+                        "        span.extend(self.{}.serialize())",
+                        field_name(field_id)
+                    ),
+                }
+                .unwrap(),
+                Field::Array { id: field_id, size, .. } => {
+                    let name = field_name(field_id);
+                    if let Some(size) = size {
+                        writeln!(code, "        if len(self.{}) != {}:", name, size).unwrap();
+                        writeln!(
+                            code,
+                            "            raise ValueError(f'{}: invalid length for field {}: {{len(self.{})}}')",
+                            id, field_id, name
+                        )
+                        .unwrap();
+                    }
+                    let padding = match fields.get(index + 1) {
+                        Some(Field::Padding { width, .. }) => Some(*width),
+                        _ => None,
+                    };
+                    if padding.is_some() {
+                        writeln!(code, "        {}_start = len(span)", field_id).unwrap();
+                    }
+                    writeln!(code, "        for e in self.{}:", name).unwrap();
+                    match self.value_width(field) {
+                        Some(width) => writeln!(
+                            code,
+                            "            span.extend(int(e).to_bytes({}, byteorder='{}'))",
+                            width / 8,
+                            self.byteorder
+                        ),
+                        None => writeln!(code, "            span.extend(e.serialize())"),
+                    }
+                    .unwrap();
+                    if let Some(padding) = padding {
+                        writeln!(code, "        if len(span) - {}_start > {}:", field_id, padding)
+                            .unwrap();
+                        writeln!(
+                            code,
+                            "            raise ValueError('{}: field {} exceeds the padding size')",
+                            id, field_id
+                        )
+                        .unwrap();
+                        writeln!(
+                            code,
+                            "        span.extend(bytes({} - (len(span) - {}_start)))",
+                            padding, field_id
+                        )
+                        .unwrap();
+                    }
+                }
+                Field::Payload { .. } | Field::Body { .. } => {
+                    writeln!(code, "        span.extend(payload)").unwrap();
+                }
+                _ => (),
+            }
+        }
+        match self.scope.get_parent(decl) {
+            Some(parent) => writeln!(
+                code,
+                "        return {}.serialize(self, bytes(span))",
+                parent.id().unwrap()
+            ),
+            None => writeln!(code, "        return bytes(span)"),
+        }
+        .unwrap();
+        writeln!(code).unwrap();
+        Ok(())
+    }
+
+    /// Write the dataclass of a packet or struct declaration.
+    fn generate_class(&self, decl: &'d Decl, code: &mut String) -> Result<(), String> {
+        let id = decl.id().unwrap();
+        if matches!(decl, Decl::Struct { .. })
+            && (decl.parent_id().is_some() || !self.scope.get_children(decl).is_empty())
+        {
+            return Err(format!("{}: struct inheritance is not supported", id));
+        }
+        let parent = self.scope.get_parent(decl);
+        let base = parent.map_or("Packet", |parent| parent.id().unwrap());
+
+        writeln!(code, "@dataclass").unwrap();
+        writeln!(code, "class {}({}):", id, base).unwrap();
+        let mut has_members = false;
+        for field in decl.fields() {
+            let Some(field_id) = field.id() else { continue };
+            let ty = self.value_type(field);
+            let (ty, default) = match (field, self.type_decl(field)) {
+                (Field::Array { .. }, _) => {
+                    (format!("List[{}]", ty), "field(default_factory=list)".to_owned())
+                }
+                (_, Some(Decl::Struct { .. })) => {
+                    (ty.clone(), format!("field(default_factory={})", ty))
+                }
+                (_, Some(Decl::Enum { tags, .. })) => match tags.first() {
+                    Some(Tag::Value { id, .. }) => (ty.clone(), format!("{}.{}", ty, id)),
+                    Some(Tag::Range { start, .. }) => (ty.clone(), format!("{}({:#x})", ty, start)),
+                    _ => (ty.clone(), format!("{}(0)", ty)),
+                },
+                _ => (ty, "0".to_owned()),
+            };
+            writeln!(code, "    {}: {} = {}", field_name(field_id), ty, default).unwrap();
+            has_members = true;
+        }
+        if has_members {
+            writeln!(code).unwrap();
+        }
+
+        // Constrained fields of the parent packets are set when the
+        // packet is created.
+        let constraints = self
+            .scope
+            .get_lineage(decl)
+            .into_iter()
+            .flat_map(|d| d.constraints().map(move |c| (d, c)))
+            .collect::<Vec<_>>();
+        if !constraints.is_empty() {
+            writeln!(code, "    def __post_init__(self) -> None:").unwrap();
+            for (d, constraint) in constraints {
+                writeln!(
+                    code,
+                    "        self.{} = {}",
+                    field_name(&constraint.id),
+                    self.constraint_value(d, constraint)?
+                )
+                .unwrap();
+            }
+            writeln!(code).unwrap();
+        }
+
+        writeln!(code, "    @staticmethod").unwrap();
+        writeln!(code, "    def parse(span: bytes) -> Tuple['{}', bytes]:", id).unwrap();
+        match self.scope.get_lineage(decl).first() {
+            Some(root) if !std::ptr::eq(*root, decl) => {
+                let root_id = root.id().unwrap();
+                writeln!(code, "        packet, span = {}.parse(span)", root_id).unwrap();
+                writeln!(code, "        if not isinstance(packet, {}):", id).unwrap();
+                writeln!(code, "            raise ParseError('{}: constraints not satisfied')", id)
+                    .unwrap();
+                writeln!(code, "        return packet, span").unwrap();
+            }
+            _ => writeln!(code, "        return {}._parse({{}}, bytes(span))", id).unwrap(),
+        }
+        writeln!(code).unwrap();
+
+        self.generate_parse(decl, code)?;
+        self.generate_serialize(decl, code)?;
+        writeln!(code).unwrap();
+        Ok(())
+    }
+}
+
+/// Generate the Python module of the grammar.
+pub fn generate(sources: &SourceDatabase, grammar: &Grammar) -> Result<String, String> {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let path = Path::new(source.name());
+    let filename = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+
+    let grammar = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&grammar);
+    let generator = Generator {
+        scope: &scope,
+        byteorder: match scope.endianness {
+            EndiannessValue::LittleEndian => "little",
+            EndiannessValue::BigEndian => "big",
+        },
+    };
+
+    let mut code = String::new();
+    writeln!(code, "# @generated Python module from {}", filename).unwrap();
+    code.push_str(
+        r#"
+from dataclasses import dataclass, field
+import enum
+from typing import List, Optional, Tuple
+
+
+class ParseError(Exception):
+    """The input bytes do not match the grammar."""
+
+
+def _enum(enum_type, value: int):
+    try:
+        return enum_type(value)
+    except ValueError:
+        raise ParseError(f'invalid {enum_type.__name__} value {value:#x}') from None
+
+
+def _enum_member(enum_type, name: str, value: int):
+    member = int.__new__(enum_type, value)
+    member._name_ = name
+    member._value_ = value
+    return member
+
+
+@dataclass
+class Packet:
+    payload: Optional[bytes] = field(default=None, repr=False)
+
+    @classmethod
+    def parse_all(cls, span: bytes) -> 'Packet':
+        packet, remain = getattr(cls, 'parse')(span)
+        if len(remain) > 0:
+            raise ParseError(f'{cls.__name__}: unexpected trailing bytes')
+        return packet
+
+    @property
+    def size(self) -> int:
+        return len(getattr(self, 'serialize')())
+
+
+"#,
+    );
+
+    for decl in &grammar.declarations {
+        if let Decl::Enum { id, tags, width, .. } = decl {
+            writeln!(code, "class {}(enum.IntEnum):", id).unwrap();
+            for tag in tags {
+                if let Tag::Value { id, value, .. } = tag {
+                    writeln!(code, "    {} = 0x{:x}", id, value).unwrap();
+                }
+            }
+            // Values matched by range and default tags are returned
+            // as pseudo members named after the tag.
+            if tags.iter().any(|t| t.value().is_none()) {
+                let mut ranges = tags
+                    .iter()
+                    .filter_map(|t| match t {
+                        Tag::Range { id, start, end, .. } => Some((id, *start, *end)),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(tag) = tags.iter().find(|t| matches!(t, Tag::Default { .. })) {
+                    let max = if *width >= 64 { usize::MAX } else { (1 << width) - 1 };
+                    ranges.push((tag.id(), 0, max));
+                }
+                writeln!(code).unwrap();
+                writeln!(code, "    @classmethod").unwrap();
+                writeln!(code, "    def _missing_(cls, value):").unwrap();
+                for (id, start, end) in ranges {
+                    writeln!(code, "        if 0x{:x} <= value <= 0x{:x}:", start, end).unwrap();
+                    writeln!(code, "            return _enum_member(cls, '{}', value)", id)
+                        .unwrap();
+                }
+                writeln!(code, "        return None").unwrap();
+            } else if tags.is_empty() {
+                writeln!(code, "    pass").unwrap();
+            }
+            writeln!(code).unwrap();
+            writeln!(code).unwrap();
+        }
+    }
+
+    // Classes are declared after their parent and the struct types of
+    // their fields.
+    let mut declared = std::collections::HashSet::new();
+    let mut pending = grammar
+        .declarations
+        .iter()
+        .filter(|d| matches!(d, Decl::Packet { .. } | Decl::Struct { .. }))
+        .collect::<Vec<_>>();
+    while !pending.is_empty() {
+        let count = pending.len();
+        let mut remaining = vec![];
+        for decl in pending {
+            let dependencies = decl.parent_id().into_iter().chain(decl.fields().filter_map(|f| {
+                match generator.type_decl(f) {
+                    Some(Decl::Struct { id, .. }) => Some(id),
+                    _ => None,
+                }
+            }));
+            if dependencies.filter(|d| *d != decl.id().unwrap()).all(|d| declared.contains(d)) {
+                generator.generate_class(decl, &mut code)?;
+                declared.insert(decl.id().unwrap());
+            } else {
+                remaining.push(decl);
+            }
+        }
+        if remaining.len() == count {
+            return Err(format!("{}: recursive declaration", remaining[0].id().unwrap()));
+        }
+        pending = remaining;
+    }
+
+    Ok(code.trim_end().to_owned() + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::{assert_contains, assert_snapshot_eq};
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        custom_field Address : 48 "hci/"
+        checksum Crc16 : 16 "crc16"
+        enum OpCode : 8 { RESET = 1, READ = 2, WRITE = 3 }
+        enum Level : 4 { LOW = 1, HIGH = 2 }
+        struct Entry { key: 8, level: Level, _reserved_: 4, value: 16 }
+        struct Blob { _size_(data): 8, data: 8[] }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            address: Address,
+            _fixed_ = 0x42 : 8,
+        }
+        packet Read : Command (op_code = READ) {
+            _count_(entries): 8,
+            entries: Entry[],
+            op_codes: OpCode[2],
+            blob: Blob,
+        }
+        packet Write : Command (op_code = WRITE) {
+            _checksum_start_(crc),
+            _size_(values): 8,
+            values: 16[+2*8],
+            from: 8[],
+            crc: Crc16,
+        }
+        packet Frame {
+            kind: 8,
+            _body_,
+            trailer: 16,
+        }
+        packet Data : Frame (kind = 1) {
+            _size_(items): 8,
+            items: 24[],
+            _padding_[9],
+            tail: 12,
+            _fixed_ = 0 : 4,
+        }
+    "#;
+
+    fn generate_inline(grammar: &str) -> Result<String, String> {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test.pdl".to_owned(), grammar.to_owned())
+            .expect("parsing failure");
+        generate(&db, &grammar)
+    }
+
+    #[test]
+    fn test_generate() {
+        let code = generate_inline(GRAMMAR).unwrap();
+        assert_contains(&code, "class OpCode(enum.IntEnum):");
+        assert_contains(&code, "class Reset(Command):");
+        assert_contains(&code, "from_: List[int] = field(default_factory=list)");
+        assert_snapshot_eq("tests/generated/python_packets.py", &code);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = generate_inline(
+            r#"
+            little_endian_packets
+            struct Parent { a: 8, _payload_ }
+            struct Child : Parent { b: 8 }
+            "#,
+        )
+        .unwrap_err();
+        assert_contains(&err, "struct inheritance is not supported");
+
+        let err = generate_inline(
+            r#"
+            little_endian_packets
+            custom_field Name "names"
+            packet Test { name: Name }
+            "#,
+        )
+        .unwrap_err();
+        assert_contains(&err, "variable size custom field `Name` is not supported");
+    }
+}
@@ -0,0 +1,1777 @@
+//! Rust compiler backend.
+//!
+//! The generated code follows the API of the legacy
+//! `bluetooth_packetgen` Rust output: each packet is exposed as a
+//! `<Name>Packet` view over shared `<Name>Data` nodes, constructed
+//! either by parsing bytes or from a `<Name>Builder`.
+
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::{format_ident, quote};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+
+mod preamble;
+
+/// Rust keywords that cannot be used as plain identifiers.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "yield",
+];
+
+/// Turn a CONSTANT_CASE identifier into CamelCase.
+pub fn to_camel_case(value: &str) -> String {
+    let mut camel_case = String::new();
+    let mut capitalize = true;
+    for c in value.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            camel_case.push(c.to_ascii_uppercase());
+            capitalize = false;
+        } else {
+            camel_case.push(c.to_ascii_lowercase());
+        }
+    }
+    camel_case
+}
+
+/// Turn a CamelCase identifier into snake_case.
+pub fn to_snake_case(value: &str) -> String {
+    let mut snake_case = String::new();
+    let mut in_word = false;
+    for c in value.chars() {
+        if c.is_ascii_uppercase() {
+            if in_word {
+                snake_case.push('_');
+            }
+            in_word = true;
+        } else if !c.is_ascii_lowercase() && !c.is_ascii_digit() {
+            if in_word {
+                snake_case.push('_');
+            }
+            in_word = false;
+        }
+        snake_case.push(c.to_ascii_lowercase());
+    }
+    snake_case
+}
+
+/// Create an identifier, escaping Rust keywords.
+fn ident(name: &str) -> Ident {
+    if KEYWORDS.contains(&name) {
+        Ident::new_raw(name, Span::call_site())
+    } else {
+        format_ident!("{}", name)
+    }
+}
+
+/// Return the smallest unsigned integer type holding `width` bits.
+fn scalar_type(width: usize) -> Ident {
+    match width {
+        0..=8 => format_ident!("u8"),
+        9..=16 => format_ident!("u16"),
+        17..=32 => format_ident!("u32"),
+        _ => format_ident!("u64"),
+    }
+}
+
+fn usize_literal(value: usize) -> Literal {
+    Literal::usize_unsuffixed(value)
+}
+
+/// Return the integer literal for a value of the given width,
+/// formatted in hexadecimal.
+fn hex_literal(value: usize) -> TokenStream {
+    format!("{:#x}", value).parse().unwrap()
+}
+
+/// Return the bit mask selecting the `width` low bits of a `u64`.
+fn mask_literal(width: usize) -> TokenStream {
+    hex_literal(if width >= 64 { usize::MAX } else { (1usize << width) - 1 })
+}
+
+fn size_variable(field_id: &str) -> Ident {
+    match field_id {
+        "_payload_" | "_body_" => format_ident!("payload_size"),
+        _ => format_ident!("{}_size", field_id),
+    }
+}
+
+fn count_variable(field_id: &str) -> Ident {
+    format_ident!("{}_count", field_id)
+}
+
+/// Return true if the field is stored in the generated structs.
+fn is_value_field(field: &Field) -> bool {
+    matches!(field, Field::Scalar { .. } | Field::Typedef { .. } | Field::Array { .. })
+}
+
+/// Return true if the field getter returns a reference.
+fn is_getter_by_ref(scope: &Scope, field: &Field) -> bool {
+    match field {
+        Field::Array { .. } => true,
+        Field::Typedef { type_id, .. } => {
+            matches!(scope.typedef.get(type_id), Some(Decl::Struct { .. }))
+        }
+        _ => false,
+    }
+}
+
+/// Return the Rust type of a value field.
+fn field_type(scope: &Scope, field: &Field) -> TokenStream {
+    match field {
+        Field::Scalar { width, .. } => {
+            let ty = scalar_type(*width);
+            quote!(#ty)
+        }
+        Field::Typedef { type_id, .. } => match scope.typedef.get(type_id) {
+            Some(Decl::Checksum { width, .. }) => {
+                let ty = scalar_type(*width);
+                quote!(#ty)
+            }
+            _ => {
+                let ty = ident(type_id);
+                quote!(#ty)
+            }
+        },
+        Field::Array { width, type_id, size, .. } => {
+            let element = match (width, type_id) {
+                (Some(width), _) => scalar_type(*width),
+                (_, Some(type_id)) => ident(type_id),
+                _ => unreachable!(),
+            };
+            match size {
+                Some(size) => {
+                    let size = usize_literal(*size);
+                    quote!([#element; #size])
+                }
+                None => quote!(Vec<#element>),
+            }
+        }
+        _ => unreachable!("field has no value"),
+    }
+}
+
+/// Generated code for the fields of a Packet or Struct declaration.
+struct FieldsCode {
+    /// Statements parsing the fields from `bytes: &mut &[u8]`.
+    /// The statements bind each value field to a local variable
+    /// of the same name; the payload, if any, is bound to `payload`.
+    parse: Vec<TokenStream>,
+    /// Statements writing the fields to `buffer: &mut BytesMut`.
+    write: Vec<TokenStream>,
+    /// Size in bytes of the fields with a static size.
+    static_size: usize,
+    /// Expressions evaluating the size in bytes of the fields with
+    /// a dynamic size, payload excluded.
+    dynamic_size: Vec<TokenStream>,
+}
+
+impl FieldsCode {
+    /// Return the expression evaluating the size in bytes of the fields.
+    fn size(&self) -> TokenStream {
+        let static_size = usize_literal(self.static_size);
+        let dynamic_size = &self.dynamic_size;
+        match (self.static_size, dynamic_size.is_empty()) {
+            (_, true) => quote!(#static_size),
+            (0, false) => quote!(#(#dynamic_size)+*),
+            _ => quote!(#static_size #(+ #dynamic_size)*),
+        }
+    }
+}
+
+/// Bit and byte layout of the element of an array field.
+enum Element<'d> {
+    Scalar(usize),
+    Enum(&'d str, usize),
+    Struct(&'d str),
+    Custom(&'d str, usize),
+}
+
+struct FieldsGenerator<'a, 'd> {
+    scope: &'a Scope<'d>,
+    obj: &'a str,
+    fields: Vec<&'d Field>,
+}
+
+impl<'a, 'd> FieldsGenerator<'a, 'd> {
+    fn endianness_suffix(&self) -> &'static str {
+        match self.scope.endianness {
+            EndiannessValue::LittleEndian => "_le",
+            EndiannessValue::BigEndian => "",
+        }
+    }
+
+    fn get_uint(&self) -> Ident {
+        format_ident!("get_uint{}", self.endianness_suffix())
+    }
+
+    fn put_uint(&self) -> Ident {
+        format_ident!("put_uint{}", self.endianness_suffix())
+    }
+
+    fn length_error(&self, field: &str, wanted: TokenStream, got: TokenStream) -> TokenStream {
+        let obj = self.obj;
+        quote! {
+            Error::InvalidLengthError {
+                obj: #obj.to_string(),
+                field: #field.to_string(),
+                wanted: #wanted,
+                got: #got,
+            }
+        }
+    }
+
+    fn size_field(&self, field_id: &str) -> Option<&'d Field> {
+        self.fields
+            .iter()
+            .copied()
+            .find(|f| matches!(f, Field::Size { field_id: id, .. } if id == field_id))
+    }
+
+    fn count_field(&self, field_id: &str) -> Option<&'d Field> {
+        self.fields
+            .iter()
+            .copied()
+            .find(|f| matches!(f, Field::Count { field_id: id, .. } if id == field_id))
+    }
+
+    fn padding(&self, index: usize) -> Option<usize> {
+        match self.fields.get(index + 1) {
+            Some(Field::Padding { width, .. }) => Some(*width),
+            _ => None,
+        }
+    }
+
+    /// Size in bytes of the fields following the field at `index`.
+    /// All the following fields must have a static size.
+    fn trailing_size(&self, index: usize) -> Result<usize, String> {
+        match self.scope.get_trailing_width(&self.fields, index) {
+            Some(width) => Ok(width / 8),
+            None => Err(format!(
+                "{}: variable size field declared after a payload or unbounded array",
+                self.obj
+            )),
+        }
+    }
+
+    /// Expression evaluating the number of bytes available to the
+    /// field at `index`, excluding the bytes of the trailing fields.
+    fn remaining_size(&self, field_id: &str, index: usize) -> Result<TokenStream, String> {
+        Ok(match self.trailing_size(index)? {
+            0 => quote!(bytes.remaining()),
+            trailing => {
+                let trailing = usize_literal(trailing);
+                let error =
+                    self.length_error(field_id, quote!(#trailing), quote!(bytes.remaining()));
+                quote!(bytes.remaining().checked_sub(#trailing).ok_or(#error)?)
+            }
+        })
+    }
+
+    fn element(&self, field: &'d Field) -> Result<Element<'d>, String> {
+        match field {
+            Field::Array { width: Some(width), .. } => Ok(Element::Scalar(*width)),
+            Field::Array { type_id: Some(type_id), .. } => match self.scope.typedef.get(type_id) {
+                Some(Decl::Enum { width, .. }) => Ok(Element::Enum(type_id, *width)),
+                Some(Decl::Struct { .. }) => Ok(Element::Struct(type_id)),
+                Some(Decl::CustomField { width: Some(width), .. }) => {
+                    Ok(Element::Custom(type_id, *width))
+                }
+                _ => Err(format!("{}: unsupported array element type `{}`", self.obj, type_id)),
+            },
+            _ => Err(format!("{}: unsupported array field {:?}", self.obj, field)),
+        }
+    }
+
+    /// Expression parsing one array element from `buf: &mut &[u8]`.
+    fn parse_element(&self, id: &str, element: &Element) -> TokenStream {
+        let get_uint = self.get_uint();
+        let check = |width: usize| {
+            let wanted = usize_literal(width / 8);
+            let error = self.length_error(id, quote!(#wanted), quote!(buf.remaining()));
+            quote! {
+                if buf.remaining() < #wanted {
+                    return Err(#error);
+                }
+            }
+        };
+        match element {
+            Element::Scalar(width) => {
+                let check = check(*width);
+                let ty = scalar_type(*width);
+                let n = usize_literal(width / 8);
+                quote!({ #check buf.#get_uint(#n) as #ty })
+            }
+            Element::Enum(type_id, width) => {
+                let check = check(*width);
+                let obj = self.obj;
+                let ty = ident(type_id);
+                let n = usize_literal(width / 8);
+                quote!({
+                    #check
+                    let value = buf.#get_uint(#n);
+                    #ty::from_u64(value).ok_or_else(|| Error::InvalidEnumValueError {
+                        obj: #obj.to_string(),
+                        field: #id.to_string(),
+                        value,
+                        type_: #type_id.to_string(),
+                    })?
+                })
+            }
+            Element::Struct(type_id) => {
+                let ty = ident(type_id);
+                quote!(#ty::parse_inner(buf)?)
+            }
+            Element::Custom(type_id, width) => {
+                let check = check(*width);
+                let ty = ident(type_id);
+                let n = usize_literal(width / 8);
+                quote!({
+                    #check
+                    let value = #ty::try_from(&buf[..#n]).map_err(|_| Error::InvalidPacketError)?;
+                    buf.advance(#n);
+                    value
+                })
+            }
+        }
+    }
+
+    /// Statement writing one array element `elem` to `buffer`.
+    fn write_element(&self, element: &Element) -> TokenStream {
+        let put_uint = self.put_uint();
+        match element {
+            Element::Scalar(width) => {
+                let n = usize_literal(width / 8);
+                quote!(buffer.#put_uint(*elem as u64, #n);)
+            }
+            Element::Enum(_, width) => {
+                let n = usize_literal(width / 8);
+                quote!(buffer.#put_uint(elem.to_u64().unwrap(), #n);)
+            }
+            Element::Struct(_) => quote!(elem.write_to(buffer);),
+            Element::Custom(_, width) => {
+                let n = usize_literal(width / 8);
+                quote!(buffer.put_slice(&<[u8; #n]>::from(*elem));)
+            }
+        }
+    }
+
+    /// Expression evaluating the size in bytes of the array elements.
+    fn array_size(&self, id: &Ident, element: &Element) -> TokenStream {
+        match element {
+            Element::Scalar(8) => quote!(self.#id.len()),
+            Element::Scalar(width) | Element::Enum(_, width) | Element::Custom(_, width) => {
+                let n = usize_literal(width / 8);
+                quote!(self.#id.len() * #n)
+            }
+            Element::Struct(_) => {
+                quote!(self.#id.iter().map(|elem| elem.get_total_size()).sum::<usize>())
+            }
+        }
+    }
+
+    /// Statement adjusting a parsed size value with a size modifier.
+    fn apply_size_modifier(&self, var: &Ident, size_modifier: &Option<String>) -> TokenStream {
+        let modifier = size_modifier.as_ref().map_or(0, |m| analyzer::size_modifier_bits(m) / 8);
+        if modifier > 0 {
+            let modifier = usize_literal(modifier as usize);
+            quote! {
+                let #var = #var.checked_sub(#modifier).ok_or(Error::InvalidPacketError)?;
+            }
+        } else if modifier < 0 {
+            let modifier = usize_literal(-modifier as usize);
+            quote!(let #var = #var + #modifier;)
+        } else {
+            quote!()
+        }
+    }
+
+    /// Expression evaluating the value written to the size field
+    /// of `field_id`.
+    fn size_value(&self, field_id: &str) -> Result<TokenStream, String> {
+        let (size, size_modifier) = match field_id {
+            "_payload_" | "_body_" => {
+                let size_modifier = self.fields.iter().find_map(|f| match f {
+                    Field::Payload { size_modifier, .. } => size_modifier.clone(),
+                    _ => None,
+                });
+                (quote!(self.child.get_total_size()), size_modifier)
+            }
+            _ => {
+                let (field, size_modifier) = self
+                    .fields
+                    .iter()
+                    .find_map(|f| match f {
+                        Field::Array { id, size_modifier, .. } if id == field_id => {
+                            Some((*f, size_modifier.clone()))
+                        }
+                        _ => None,
+                    })
+                    .ok_or_else(|| {
+                        format!("{}: size field references an unknown array", self.obj)
+                    })?;
+                (self.array_size(&ident(field_id), &self.element(field)?), size_modifier)
+            }
+        };
+        let modifier = size_modifier.as_ref().map_or(0, |m| analyzer::size_modifier_bits(m) / 8);
+        Ok(if modifier > 0 {
+            let modifier = usize_literal(modifier as usize);
+            quote!(((#size) + #modifier))
+        } else if modifier < 0 {
+            let modifier = usize_literal(-modifier as usize);
+            quote!(((#size) - #modifier))
+        } else {
+            quote!((#size))
+        })
+    }
+
+    /// Generate the code for a chunk of bit fields whose
+    /// cumulated width is a multiple of 8.
+    fn generate_chunk(&self, chunk: &[&'d Field], code: &mut FieldsCode) -> Result<(), String> {
+        let width: usize = chunk.iter().map(|f| self.scope.get_field_width(f).unwrap()).sum();
+        if width > 64 {
+            return Err(format!("{}: bit fields spanning more than 64 bits", self.obj));
+        }
+        let n = usize_literal(width / 8);
+        let get_uint = self.get_uint();
+        let put_uint = self.put_uint();
+        let obj = self.obj;
+        let first = chunk
+            .iter()
+            .find_map(|f| match f {
+                Field::Size { field_id, .. } | Field::Count { field_id, .. } => Some(field_id),
+                _ => f.id(),
+            })
+            .map_or("_reserved_", |id| id.as_str());
+        let error = self.length_error(first, quote!(#n), quote!(bytes.remaining()));
+        code.parse.push(quote! {
+            if bytes.remaining() < #n {
+                return Err(#error);
+            }
+            let chunk = bytes.#get_uint(#n);
+        });
+
+        let mut shift = 0;
+        let mut terms = vec![];
+        for field in chunk {
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let value = match (shift, field_width) {
+                (0, w) if w == width => quote!(chunk),
+                (0, w) => {
+                    let mask = mask_literal(w);
+                    quote!((chunk & #mask))
+                }
+                (s, w) => {
+                    let mask = mask_literal(w);
+                    let s = usize_literal(s);
+                    quote!(((chunk >> #s) & #mask))
+                }
+            };
+            let term = match field {
+                Field::Scalar { id, width, .. } => {
+                    let id = ident(id);
+                    let ty = scalar_type(*width);
+                    code.parse.push(quote!(let #id = #value as #ty;));
+                    Some(quote!(self.#id as u64))
+                }
+                Field::Typedef { id, type_id, .. } => {
+                    let field_id = id.as_str();
+                    let id = ident(id);
+                    match self.scope.typedef.get(type_id) {
+                        Some(Decl::Enum { .. }) => {
+                            let ty = ident(type_id);
+                            code.parse.push(quote! {
+                                let #id = {
+                                    let value = #value;
+                                    #ty::from_u64(value).ok_or_else(|| Error::InvalidEnumValueError {
+                                        obj: #obj.to_string(),
+                                        field: #field_id.to_string(),
+                                        value,
+                                        type_: #type_id.to_string(),
+                                    })?
+                                };
+                            });
+                            Some(quote!(self.#id.to_u64().unwrap()))
+                        }
+                        _ => {
+                            let ty = scalar_type(field_width);
+                            code.parse.push(quote!(let #id = #value as #ty;));
+                            Some(quote!(self.#id as u64))
+                        }
+                    }
+                }
+                Field::Fixed { value: fixed_value, enum_id, tag_id, .. } => {
+                    let expected = match (fixed_value, enum_id, tag_id) {
+                        (Some(value), _, _) => *value,
+                        (_, Some(enum_id), Some(tag_id)) => match self.scope.typedef.get(enum_id) {
+                            Some(Decl::Enum { tags, .. }) => {
+                                tags.iter().find(|t| t.id() == tag_id).unwrap().value().unwrap()
+                            }
+                            _ => unreachable!(),
+                        },
+                        _ => unreachable!(),
+                    };
+                    let expected = hex_literal(expected);
+                    code.parse.push(quote! {
+                        let fixed_value = #value;
+                        if fixed_value != #expected {
+                            return Err(Error::InvalidFixedValue {
+                                obj: #obj.to_string(),
+                                expected: #expected,
+                                actual: fixed_value,
+                            });
+                        }
+                    });
+                    Some(quote!(#expected))
+                }
+                Field::Reserved { .. } => None,
+                Field::Size { field_id, .. } => {
+                    let var = size_variable(field_id);
+                    code.parse.push(quote!(let #var = #value as usize;));
+                    let size = self.size_value(field_id)?;
+                    Some(quote!(#size as u64))
+                }
+                Field::Count { field_id, .. } => {
+                    let var = count_variable(field_id);
+                    code.parse.push(quote!(let #var = #value as usize;));
+                    let id = ident(field_id);
+                    Some(quote!(self.#id.len() as u64))
+                }
+                _ => unreachable!(),
+            };
+            if let Some(term) = term {
+                // Enum and fixed values always fit in the field width,
+                // scalar values fit when the width matches their type.
+                let fits = match field {
+                    Field::Scalar { .. } => matches!(field_width, 8 | 16 | 32 | 64),
+                    Field::Typedef { .. } | Field::Fixed { .. } => true,
+                    _ => field_width >= 64,
+                };
+                let term = if fits {
+                    term
+                } else {
+                    let mask = mask_literal(field_width);
+                    quote!(#term & #mask)
+                };
+                terms.push(if shift == 0 {
+                    term
+                } else {
+                    let s = usize_literal(shift);
+                    quote!((#term) << #s)
+                });
+            }
+            shift += field_width;
+        }
+
+        let value = if terms.is_empty() { quote!(0) } else { quote!(#(#terms)|*) };
+        code.write.push(quote! {
+            let chunk: u64 = #value;
+            buffer.#put_uint(chunk, #n);
+        });
+        code.static_size += width / 8;
+        Ok(())
+    }
+
+    /// Generate the code for an array field.
+    fn generate_array(
+        &self,
+        index: usize,
+        field: &'d Field,
+        code: &mut FieldsCode,
+    ) -> Result<(), String> {
+        let (field_id, size, size_modifier) = match field {
+            Field::Array { id, size, size_modifier, .. } => (id.as_str(), size, size_modifier),
+            _ => unreachable!(),
+        };
+        let id = ident(field_id);
+        let element = self.element(field)?;
+        let parse_element = self.parse_element(field_id, &element);
+        let padding = self.padding(index);
+
+        let count = match (size, self.count_field(field_id)) {
+            (Some(size), _) => Some(quote!(#size)),
+            (None, Some(_)) => {
+                let var = count_variable(field_id);
+                Some(quote!(#var))
+            }
+            _ => None,
+        };
+
+        match (count, padding) {
+            (Some(count), None) => code.parse.push(quote! {
+                let mut #id = Vec::with_capacity(#count);
+                for _ in 0..#count {
+                    let buf: &mut &[u8] = &mut *bytes;
+                    #id.push(#parse_element);
+                }
+            }),
+            (_, padding) => {
+                // The elements are parsed from a byte region bounded
+                // by the size field, the padding, or the trailing fields.
+                let region = match (self.size_field(field_id), padding) {
+                    (_, Some(padding)) => {
+                        let padding = usize_literal(padding);
+                        quote!(let region_size = #padding;)
+                    }
+                    (Some(_), None) => {
+                        let var = size_variable(field_id);
+                        let modifier = self.apply_size_modifier(&var, size_modifier);
+                        quote!(#modifier let region_size = #var;)
+                    }
+                    (None, None) => {
+                        let remaining = self.remaining_size(field_id, index)?;
+                        quote!(let region_size = #remaining;)
+                    }
+                };
+                let error =
+                    self.length_error(field_id, quote!(region_size), quote!(bytes.remaining()));
+                code.parse.push(quote! {
+                    #region
+                    if bytes.remaining() < region_size {
+                        return Err(#error);
+                    }
+                    let (mut region, tail) = {
+                        let bytes: &[u8] = *bytes;
+                        bytes.split_at(region_size)
+                    };
+                    *bytes = tail;
+                    let mut #id = Vec::new();
+                    while !region.is_empty() {
+                        let buf: &mut &[u8] = &mut region;
+                        #id.push(#parse_element);
+                    }
+                })
+            }
+        }
+        if size.is_some() {
+            code.parse.push(quote! {
+                let #id = #id.try_into().map_err(|_| Error::InvalidPacketError)?;
+            });
+        }
+
+        let write_element = self.write_element(&element);
+        code.write.push(quote! {
+            for elem in self.#id.iter() {
+                #write_element
+            }
+        });
+        let array_size = self.array_size(&id, &element);
+        match padding {
+            Some(padding) => {
+                let padding_literal = usize_literal(padding);
+                code.write.push(quote! {
+                    let padding_size = #padding_literal - std::cmp::min(#padding_literal, #array_size);
+                    buffer.resize(buffer.len() + padding_size, 0);
+                });
+                code.static_size += padding;
+            }
+            None => code.dynamic_size.push(array_size),
+        }
+        Ok(())
+    }
+
+    /// Generate the code for a typedef field referencing a struct
+    /// or custom field declaration.
+    fn generate_typedef(&self, field: &'d Field, code: &mut FieldsCode) -> Result<(), String> {
+        let (field_id, type_id) = match field {
+            Field::Typedef { id, type_id, .. } => (id, type_id),
+            _ => unreachable!(),
+        };
+        let id = ident(field_id);
+        let ty = ident(type_id);
+        match self.scope.typedef.get(type_id) {
+            Some(Decl::Struct { .. }) => {
+                code.parse.push(quote!(let #id = #ty::parse_inner(bytes)?;));
+                code.write.push(quote!(self.#id.write_to(buffer);));
+                code.dynamic_size.push(quote!(self.#id.get_total_size()));
+            }
+            Some(Decl::CustomField { width: Some(width), .. }) => {
+                let n = usize_literal(width / 8);
+                let error = self.length_error(field_id, quote!(#n), quote!(bytes.remaining()));
+                code.parse.push(quote! {
+                    if bytes.remaining() < #n {
+                        return Err(#error);
+                    }
+                    let #id = #ty::try_from(&bytes[..#n]).map_err(|_| Error::InvalidPacketError)?;
+                    bytes.advance(#n);
+                });
+                code.write.push(quote!(buffer.put_slice(&<[u8; #n]>::from(self.#id));));
+                code.static_size += width / 8;
+            }
+            _ => return Err(format!("{}: unsupported typedef field type `{}`", self.obj, type_id)),
+        }
+        Ok(())
+    }
+
+    /// Generate the code for a payload or body field.
+    /// The payload bytes are bound to the variable `payload`.
+    fn generate_payload(
+        &self,
+        index: usize,
+        field: &'d Field,
+        write_payload: &TokenStream,
+        code: &mut FieldsCode,
+    ) -> Result<(), String> {
+        let size_modifier = match field {
+            Field::Payload { size_modifier, .. } => size_modifier.clone(),
+            _ => None,
+        };
+        let var = size_variable("_payload_");
+        let field_id = match field {
+            Field::Payload { .. } => "_payload_",
+            _ => "_body_",
+        };
+        let size = match self.size_field(field_id) {
+            Some(_) => {
+                let modifier = self.apply_size_modifier(&var, &size_modifier);
+                quote!(#modifier)
+            }
+            None => {
+                let remaining = self.remaining_size("payload", index)?;
+                quote!(let #var = #remaining;)
+            }
+        };
+        let error = self.length_error("payload", quote!(#var), quote!(bytes.remaining()));
+        // As with the legacy generator, a truncated body does not fail
+        // the parsing.
+        let check = match field {
+            Field::Body { .. } => quote!(let #var = std::cmp::min(#var, bytes.remaining());),
+            _ => quote! {
+                if bytes.remaining() < #var {
+                    return Err(#error);
+                }
+            },
+        };
+        code.parse.push(quote! {
+            #size
+            #check
+            let (payload, tail) = {
+                let bytes: &[u8] = *bytes;
+                bytes.split_at(#var)
+            };
+            *bytes = tail;
+        });
+        code.write.push(write_payload.clone());
+        Ok(())
+    }
+
+    /// Generate the parser, serializer and size computation for
+    /// the fields of the declaration.
+    fn generate(&self, write_payload: &TokenStream) -> Result<FieldsCode, String> {
+        let mut code =
+            FieldsCode { parse: vec![], write: vec![], static_size: 0, dynamic_size: vec![] };
+        let mut chunk = vec![];
+        let mut chunk_width = 0;
+        for (index, field) in self.fields.iter().copied().enumerate() {
+            if self.scope.is_bitfield(field) {
+                chunk.push(field);
+                chunk_width += self.scope.get_field_width(field).unwrap();
+                if chunk_width % 8 == 0 {
+                    self.generate_chunk(&chunk, &mut code)?;
+                    chunk.clear();
+                    chunk_width = 0;
+                }
+                continue;
+            }
+            if !chunk.is_empty() && !matches!(field, Field::Checksum { .. }) {
+                return Err(format!(
+                    "{}: bit fields are not aligned to an octet boundary",
+                    self.obj
+                ));
+            }
+            match field {
+                Field::Array { .. } => self.generate_array(index, field, &mut code)?,
+                Field::Typedef { .. } => self.generate_typedef(field, &mut code)?,
+                Field::Payload { .. } | Field::Body { .. } => {
+                    self.generate_payload(index, field, write_payload, &mut code)?
+                }
+                Field::Padding { .. }
+                    if index > 0 && matches!(self.fields[index - 1], Field::Array { .. }) => {}
+                Field::Checksum { .. } => (),
+                _ => return Err(format!("{}: unsupported field {:?}", self.obj, field)),
+            }
+        }
+        if !chunk.is_empty() {
+            return Err(format!("{}: bit fields are not aligned to an octet boundary", self.obj));
+        }
+        Ok(code)
+    }
+}
+
+/// Generate the declaration of an enum.
+fn generate_enum(id: &str, tags: &[Tag], width: usize, try_from: Option<&Decl>) -> TokenStream {
+    let name = ident(id);
+    let variants =
+        tags.iter().map(|t| format_ident!("{}", to_camel_case(t.id()))).collect::<Vec<_>>();
+    let to_ty = format_ident!("to_{}", scalar_type(width));
+    let display_format =
+        tags.iter().map(|t| format!("{{:#0{}X}} ({})", width.div_ceil(8) * 2 + 2, t.id()));
+
+    let try_from = try_from.map(|decl| match decl {
+        Decl::Enum { id: other_id, tags: other_tags, .. } => {
+            let other = ident(other_id);
+            let common = tags
+                .iter()
+                .filter(|t| t.value().is_some() && other_tags.iter().any(|o| o.id() == t.id()))
+                .map(|t| format_ident!("{}", to_camel_case(t.id())))
+                .collect::<Vec<_>>();
+            quote! {
+                impl TryFrom<#other> for #name {
+                    type Error = &'static str;
+                    fn try_from(value: #other) -> std::result::Result<Self, Self::Error> {
+                        match value {
+                            #(#other::#common => Ok(#name::#common),)*
+                            _ => Err("No mapping for provided key"),
+                        }
+                    }
+                }
+            }
+        }
+        _ => unreachable!(),
+    });
+
+    // Enums without range or default tags are plain C-like enums.
+    if tags.iter().all(|t| t.value().is_some()) {
+        let values = tags.iter().map(|t| hex_literal(t.value().unwrap()));
+        return quote! {
+            #[derive(FromPrimitive, ToPrimitive, Debug, Hash, Eq, PartialEq, Clone, Copy)]
+            #[repr(u64)]
+            pub enum #name {
+                #(#variants = #values,)*
+            }
+
+            impl fmt::Display for #name {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    match self {
+                        #(#name::#variants => write!(f, #display_format, self.#to_ty().unwrap()),)*
+                    }
+                }
+            }
+
+            #try_from
+        };
+    }
+
+    // Range and default tags hold the matched value, and the
+    // conversions to and from integers are implemented manually.
+    // Value tags are matched first, then range tags, then the
+    // default tag.
+    let ty = scalar_type(width);
+    let max = mask_literal(width);
+    let mut declarations = vec![];
+    let mut display_variants = vec![];
+    let mut from_arms = vec![];
+    let mut to_arms = vec![];
+    let mut default_arm = quote!(_ => None,);
+    for (tag, variant) in tags.iter().zip(&variants) {
+        match tag {
+            Tag::Value { value, .. } => {
+                let value = hex_literal(*value);
+                declarations.push(quote!(#variant));
+                display_variants.push(quote!(#name::#variant));
+                from_arms.push(quote!(#value => Some(#name::#variant),));
+                to_arms.push(quote!(#name::#variant => #value,));
+            }
+            Tag::Range { start, end, .. } => {
+                let (start, end) = (hex_literal(*start), hex_literal(*end));
+                declarations.push(quote!(#variant(#ty)));
+                display_variants.push(quote!(#name::#variant(_)));
+                from_arms.push(quote!(#start..=#end => Some(#name::#variant(n as #ty)),));
+                to_arms.push(quote!(#name::#variant(n) => *n as u64,));
+            }
+            Tag::Default { .. } => {
+                declarations.push(quote!(#variant(#ty)));
+                display_variants.push(quote!(#name::#variant(_)));
+                default_arm = match width {
+                    64 => quote!(_ => Some(#name::#variant(n)),),
+                    _ => quote!(0..=#max => Some(#name::#variant(n as #ty)), _ => None,),
+                };
+                to_arms.push(quote!(#name::#variant(n) => *n as u64,));
+            }
+        }
+    }
+
+    quote! {
+        #[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+        pub enum #name {
+            #(#declarations,)*
+        }
+
+        impl FromPrimitive for #name {
+            fn from_i64(n: i64) -> Option<Self> {
+                u64::try_from(n).ok().and_then(Self::from_u64)
+            }
+
+            fn from_u64(n: u64) -> Option<Self> {
+                match n {
+                    #(#from_arms)*
+                    #default_arm
+                }
+            }
+        }
+
+        impl ToPrimitive for #name {
+            fn to_i64(&self) -> Option<i64> {
+                self.to_u64().and_then(|n| i64::try_from(n).ok())
+            }
+
+            fn to_u64(&self) -> Option<u64> {
+                Some(match self {
+                    #(#to_arms)*
+                })
+            }
+        }
+
+        impl fmt::Display for #name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    #(#display_variants => write!(f, #display_format, self.#to_ty().unwrap()),)*
+                }
+            }
+        }
+
+        #try_from
+    }
+}
+
+/// Generate the declaration of a struct.
+fn generate_struct(scope: &Scope, decl: &Decl) -> Result<TokenStream, String> {
+    let id = decl.id().unwrap();
+    if decl.parent_id().is_some() || !scope.get_children(decl).is_empty() {
+        return Err(format!("{}: struct inheritance is not supported", id));
+    }
+    let name = ident(id);
+    let fields = decl.fields().collect::<Vec<_>>();
+    let generator = FieldsGenerator { scope, obj: id, fields: fields.clone() };
+    let code = generator.generate(&quote!())?;
+    let value_fields = fields.iter().filter(|f| is_value_field(f)).collect::<Vec<_>>();
+    let names = value_fields.iter().map(|f| ident(f.id().unwrap())).collect::<Vec<_>>();
+    let types = value_fields.iter().map(|f| field_type(scope, f));
+    let size = code.size();
+    let FieldsCode { parse, write, .. } = code;
+
+    Ok(quote! {
+        #[derive(Debug, Clone)]
+        pub struct #name {
+            #(pub #names: #types,)*
+        }
+
+        impl #name {
+            pub fn parse(bytes: &[u8]) -> Result<Self> {
+                let mut bytes = bytes;
+                Self::parse_inner(&mut bytes)
+            }
+
+            fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+                #(#parse)*
+                Ok(Self { #(#names,)* })
+            }
+
+            fn write_to(&self, buffer: &mut BytesMut) {
+                #(#write)*
+            }
+
+            fn get_total_size(&self) -> usize {
+                #size
+            }
+        }
+    })
+}
+
+/// Return the fields of the ancestors of `decl` referenced by
+/// the constraints of its descendants. These values are passed
+/// to the parser of `decl` to select the child specialization.
+fn parse_params<'d>(scope: &Scope<'d>, decl: &'d Decl) -> Vec<&'d Field> {
+    fn constrained_ids<'d>(scope: &Scope<'d>, decl: &'d Decl, ids: &mut HashSet<&'d str>) {
+        for child in scope.get_children(decl) {
+            ids.extend(child.constraints().map(|c| c.id.as_str()));
+            constrained_ids(scope, child, ids);
+        }
+    }
+    let mut ids = HashSet::new();
+    constrained_ids(scope, decl, &mut ids);
+    let lineage = scope.get_lineage(decl);
+    lineage[..lineage.len() - 1]
+        .iter()
+        .flat_map(|d| d.fields())
+        .filter(|f| f.id().is_some_and(|id| ids.contains(id.as_str())))
+        .collect()
+}
+
+/// Return the expression comparing a field with a constraint value.
+fn constraint_value(scope: &Scope, field: &Field, constraint: &Constraint) -> TokenStream {
+    match (&constraint.value, field) {
+        (Expr::Identifier { name, .. }, Field::Typedef { type_id, .. }) => {
+            let ty = ident(type_id);
+            let tag = format_ident!("{}", to_camel_case(name));
+            let _ = scope;
+            quote!(#ty::#tag)
+        }
+        (Expr::Identifier { .. }, _) => unreachable!("invalid constraint"),
+        (expr, _) => {
+            let value = hex_literal(expr.evaluate().expect("invalid constraint"));
+            quote!(#value)
+        }
+    }
+}
+
+/// Return the condition selecting the child specialization `child`
+/// from the parser of `decl`, or `None` if the child cannot be
+/// discriminated from the values available to the parser.
+fn child_condition<'d>(
+    scope: &Scope<'d>,
+    decl: &'d Decl,
+    child: &'d Decl,
+    available: &HashSet<&str>,
+) -> Option<TokenStream> {
+    let constraints = child.constraints().collect::<Vec<_>>();
+    let terms = constraints
+        .iter()
+        .filter(|c| available.contains(c.id.as_str()))
+        .map(|c| {
+            let field = scope.get_field(decl, &c.id).unwrap();
+            let id = ident(&c.id);
+            let value = constraint_value(scope, field, c);
+            quote!(#id == #value)
+        })
+        .collect::<Vec<_>>();
+    if !terms.is_empty() {
+        return Some(quote!(#(#terms)&&*));
+    }
+    if !constraints.is_empty() || scope.get_children(child).is_empty() {
+        return None;
+    }
+    let alternatives = scope
+        .get_children(child)
+        .iter()
+        .map(|grandchild| child_condition(scope, decl, grandchild, available))
+        .collect::<Option<Vec<_>>>()?;
+    Some(quote!(#((#alternatives))||*))
+}
+
+/// Generate the declaration of a packet.
+fn generate_packet(
+    scope: &Scope,
+    decl: &Decl,
+    expectation: Option<&Decl>,
+) -> Result<TokenStream, String> {
+    let id = decl.id().unwrap();
+    let name = ident(id);
+    let data_name = format_ident!("{}Data", id);
+    let data_child_name = format_ident!("{}DataChild", id);
+    let child_name = format_ident!("{}Child", id);
+    let packet_name = format_ident!("{}Packet", id);
+    let builder_name = format_ident!("{}Builder", id);
+    let _ = name;
+
+    let lineage = scope.get_lineage(decl);
+    let root = lineage[0];
+    let root_data_name = format_ident!("{}Data", root.id().unwrap());
+    let root_packet_name = format_ident!("{}Packet", root.id().unwrap());
+    let root_accessor = format_ident!("{}", to_snake_case(root.id().unwrap()));
+    let accessor = format_ident!("{}", to_snake_case(id));
+    let children = scope.get_children(decl);
+    let fields = decl.fields().collect::<Vec<_>>();
+    let has_payload =
+        fields.iter().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. }));
+    let has_child = has_payload || !children.is_empty();
+
+    // Child enums.
+    let child_ids =
+        children.iter().map(|c| format_ident!("{}", c.id().unwrap())).collect::<Vec<_>>();
+    let child_data_names =
+        children.iter().map(|c| format_ident!("{}Data", c.id().unwrap())).collect::<Vec<_>>();
+    let child_packet_names =
+        children.iter().map(|c| format_ident!("{}Packet", c.id().unwrap())).collect::<Vec<_>>();
+    let child_enums = has_child.then(|| {
+        quote! {
+            #[derive(Debug)]
+            enum #data_child_name {
+                #(#child_ids(Arc<#child_data_names>),)*
+                Payload(Bytes),
+                None,
+            }
+
+            impl #data_child_name {
+                fn get_total_size(&self) -> usize {
+                    match self {
+                        #(#data_child_name::#child_ids(value) => value.get_total_size(),)*
+                        #data_child_name::Payload(payload) => payload.len(),
+                        #data_child_name::None => 0,
+                    }
+                }
+            }
+
+            #[derive(Debug)]
+            pub enum #child_name {
+                #(#child_ids(#child_packet_names),)*
+                Payload(Bytes),
+                None,
+            }
+        }
+    });
+
+    // Data declaration.
+    let value_fields = fields.iter().filter(|f| is_value_field(f)).collect::<Vec<_>>();
+    let value_names = value_fields.iter().map(|f| ident(f.id().unwrap())).collect::<Vec<_>>();
+    let value_types = value_fields.iter().map(|f| field_type(scope, f)).collect::<Vec<_>>();
+    let child_field = has_child.then(|| quote!(child: #data_child_name,));
+    let child_value = has_child.then(|| quote!(child,));
+
+    // Parser.
+    let write_payload = quote! {
+        match &self.child {
+            #(#data_child_name::#child_ids(value) => value.write_to(buffer),)*
+            #data_child_name::Payload(payload) => buffer.put_slice(payload),
+            #data_child_name::None => {}
+        }
+    };
+    let generator = FieldsGenerator { scope, obj: id, fields: fields.clone() };
+    let code = generator.generate(&write_payload)?;
+    let size = code.size();
+    let FieldsCode { mut parse, mut write, .. } = code;
+    if has_child && !has_payload {
+        // Children of a packet without payload are parsed from the
+        // trailing bytes.
+        parse.push(quote!(let payload: &[u8] = std::mem::take(bytes);));
+        write.push(write_payload.clone());
+    }
+
+    let params = parse_params(scope, decl);
+    let param_names = params.iter().map(|f| ident(f.id().unwrap())).collect::<Vec<_>>();
+    let param_types = params.iter().map(|f| field_type(scope, f)).collect::<Vec<_>>();
+    let mut available = params.iter().map(|f| f.id().unwrap().as_str()).collect::<HashSet<_>>();
+    available.extend(value_fields.iter().map(|f| f.id().unwrap().as_str()));
+
+    let parse_child = has_child.then(|| {
+        let mut conditional = vec![];
+        let mut unconditional = vec![];
+        for child in children {
+            let child_id = format_ident!("{}", child.id().unwrap());
+            let child_data = format_ident!("{}Data", child.id().unwrap());
+            let args = parse_params(scope, child)
+                .iter()
+                .map(|f| ident(f.id().unwrap()))
+                .collect::<Vec<_>>();
+            let parse = quote! {
+                #data_child_name::#child_id(Arc::new(#child_data::parse_inner(&mut payload, #(#args),*)?))
+            };
+            match child_condition(scope, decl, child, &available) {
+                Some(condition) => conditional.push(quote!(if #condition { #parse })),
+                None => unconditional.push(parse),
+            }
+        }
+        let fallback = match unconditional.into_iter().next() {
+            Some(parse) => quote!({ #parse }),
+            None => quote! {
+                if payload.is_empty() {
+                    #data_child_name::None
+                } else {
+                    #data_child_name::Payload(Bytes::copy_from_slice(payload))
+                }
+            },
+        };
+        quote! {
+            let mut payload = payload;
+            let child = #(#conditional else)* #fallback;
+        }
+    });
+
+    let get_total_size = if has_child {
+        quote!(self.get_size() + self.child.get_total_size())
+    } else {
+        quote!(self.get_size())
+    };
+
+    // Packet accessors.
+    let lineage_accessors = lineage
+        .iter()
+        .map(|d| format_ident!("{}", to_snake_case(d.id().unwrap())))
+        .collect::<Vec<_>>();
+    let lineage_data_names =
+        lineage.iter().map(|d| format_ident!("{}Data", d.id().unwrap())).collect::<Vec<_>>();
+    let new_steps = lineage.windows(2).map(|w| {
+        let parent_accessor = format_ident!("{}", to_snake_case(w[0].id().unwrap()));
+        let parent_child = format_ident!("{}DataChild", w[0].id().unwrap());
+        let child_accessor = format_ident!("{}", to_snake_case(w[1].id().unwrap()));
+        let child_id = format_ident!("{}", w[1].id().unwrap());
+        let message = format!("inconsistent state - child was not {}", w[1].id().unwrap());
+        quote! {
+            let #child_accessor = match &#parent_accessor.child {
+                #parent_child::#child_id(value) => (*value).clone(),
+                _ => return Err(#message),
+            };
+        }
+    });
+
+    let getters = lineage.iter().flat_map(|d| {
+        let accessor = format_ident!("{}", to_snake_case(d.id().unwrap()));
+        d.fields().filter(|f| is_value_field(f)).map(move |f| {
+            let id = ident(f.id().unwrap());
+            let getter = format_ident!("get_{}", f.id().unwrap());
+            let ty = field_type(scope, f);
+            if is_getter_by_ref(scope, f) {
+                quote! {
+                    pub fn #getter(&self) -> &#ty {
+                        &self.#accessor.as_ref().#id
+                    }
+                }
+            } else {
+                quote! {
+                    pub fn #getter(&self) -> #ty {
+                        self.#accessor.as_ref().#id
+                    }
+                }
+            }
+        })
+    });
+
+    let specialize = has_child.then(|| {
+        quote! {
+            pub fn specialize(&self) -> #child_name {
+                match &self.#accessor.child {
+                    #(#data_child_name::#child_ids(_) =>
+                        #child_name::#child_ids(#child_packet_names::new(self.#root_accessor.clone()).unwrap()),)*
+                    #data_child_name::Payload(payload) => #child_name::Payload(payload.clone()),
+                    #data_child_name::None => #child_name::None,
+                }
+            }
+        }
+    });
+
+    let parse_root = (lineage.len() == 1).then(|| {
+        quote! {
+            fn parse(bytes: &[u8]) -> Result<Self> {
+                let mut bytes = bytes;
+                Self::parse_inner(&mut bytes)
+            }
+        }
+    });
+    let parse_packet = if lineage.len() == 1 {
+        quote! {
+            pub fn parse(bytes: &[u8]) -> Result<Self> {
+                Ok(Self::new(Arc::new(#data_name::parse(bytes)?)).unwrap())
+            }
+        }
+    } else {
+        quote! {
+            pub fn parse(bytes: &[u8]) -> Result<Self> {
+                Self::new(Arc::new(#root_data_name::parse(bytes)?))
+                    .map_err(|_| Error::InvalidPacketError)
+            }
+        }
+    };
+
+    let ancestors = lineage[..lineage.len() - 1]
+        .iter()
+        .map(|d| format_ident!("{}Packet", d.id().unwrap()))
+        .collect::<Vec<_>>();
+    let try_from_root = (lineage.len() > 1).then(|| {
+        quote! {
+            impl TryFrom<#root_packet_name> for #packet_name {
+                type Error = TryFromError;
+                fn try_from(value: #root_packet_name) -> std::result::Result<Self, Self::Error> {
+                    Self::new(value.#root_accessor).map_err(TryFromError)
+                }
+            }
+        }
+    });
+
+    // Builder.
+    let mut constraints = std::collections::HashMap::new();
+    for d in lineage.iter().rev() {
+        for c in d.constraints() {
+            constraints.entry(c.id.as_str()).or_insert(c);
+        }
+    }
+    let builder_fields = lineage
+        .iter()
+        .flat_map(|d| d.fields())
+        .filter(|f| is_value_field(f) && !constraints.contains_key(f.id().unwrap().as_str()))
+        .collect::<Vec<_>>();
+    let builder_names = builder_fields.iter().map(|f| ident(f.id().unwrap())).collect::<Vec<_>>();
+    let builder_types = builder_fields.iter().map(|f| field_type(scope, f)).collect::<Vec<_>>();
+    let builder_payload = fields
+        .iter()
+        .any(|f| matches!(f, Field::Payload { .. }))
+        .then(|| quote!(pub payload: Option<Bytes>,));
+
+    let build_steps = lineage.iter().rev().enumerate().map(|(index, d)| {
+        let accessor = format_ident!("{}", to_snake_case(d.id().unwrap()));
+        let data = format_ident!("{}Data", d.id().unwrap());
+        let data_child = format_ident!("{}DataChild", d.id().unwrap());
+        let values = d.fields().filter(|f| is_value_field(f)).map(|f| {
+            let id = ident(f.id().unwrap());
+            match constraints.get(f.id().unwrap().as_str()) {
+                Some(c) => {
+                    let value = constraint_value(scope, f, c);
+                    quote!(#id: #value)
+                }
+                None => quote!(#id: self.#id),
+            }
+        });
+        let has_child = !scope.get_children(d).is_empty()
+            || d.fields().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. }));
+        let child = match (index, has_child) {
+            (_, false) => quote!(),
+            (0, true) if builder_payload.is_some() => quote! {
+                child: match self.payload {
+                    None => #data_child::None,
+                    Some(bytes) => #data_child::Payload(bytes),
+                },
+            },
+            (0, true) => quote!(child: #data_child::None,),
+            (_, true) => {
+                let prev = lineage[lineage.len() - index];
+                let prev_id = format_ident!("{}", prev.id().unwrap());
+                let prev_accessor = format_ident!("{}", to_snake_case(prev.id().unwrap()));
+                quote!(child: #data_child::#prev_id(#prev_accessor),)
+            }
+        };
+        quote! {
+            let #accessor = Arc::new(#data {
+                #(#values,)*
+                #child
+            });
+        }
+    });
+
+    let expectations = expectation.map(|response| {
+        let response_packet = format_ident!("{}Packet", response.id().unwrap());
+        let response_root = scope.get_lineage(response)[0];
+        let response_root_accessor =
+            format_ident!("{}", to_snake_case(response_root.id().unwrap()));
+        quote! {
+            impl CommandExpectations for #packet_name {
+                type ResponseType = #response_packet;
+                fn _to_response_type(pkt: EventPacket) -> Self::ResponseType {
+                    #response_packet::new(pkt.#response_root_accessor.clone()).unwrap()
+                }
+            }
+
+            impl CommandExpectations for #builder_name {
+                type ResponseType = #response_packet;
+                fn _to_response_type(pkt: EventPacket) -> Self::ResponseType {
+                    #response_packet::new(pkt.#response_root_accessor.clone()).unwrap()
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #child_enums
+
+        #[derive(Debug)]
+        struct #data_name {
+            #(#value_names: #value_types,)*
+            #child_field
+        }
+
+        #[derive(Debug, Clone)]
+        pub struct #packet_name {
+            #(#lineage_accessors: Arc<#lineage_data_names>,)*
+        }
+
+        #[derive(Debug)]
+        pub struct #builder_name {
+            #(pub #builder_names: #builder_types,)*
+            #builder_payload
+        }
+
+        impl #data_name {
+            #parse_root
+
+            fn parse_inner(bytes: &mut &[u8] #(, #param_names: #param_types)*) -> Result<Self> {
+                #(#parse)*
+                #parse_child
+                Ok(Self { #(#value_names,)* #child_value })
+            }
+
+            fn write_to(&self, buffer: &mut BytesMut) {
+                #(#write)*
+            }
+
+            fn get_total_size(&self) -> usize {
+                #get_total_size
+            }
+
+            fn get_size(&self) -> usize {
+                #size
+            }
+        }
+
+        #expectations
+
+        impl Packet for #packet_name {
+            fn to_bytes(self) -> Bytes {
+                let mut buffer = BytesMut::with_capacity(self.#root_accessor.get_total_size());
+                self.#root_accessor.write_to(&mut buffer);
+                buffer.freeze()
+            }
+
+            fn to_vec(self) -> Vec<u8> {
+                self.to_bytes().to_vec()
+            }
+        }
+
+        impl From<#packet_name> for Bytes {
+            fn from(packet: #packet_name) -> Self {
+                packet.to_bytes()
+            }
+        }
+
+        impl From<#packet_name> for Vec<u8> {
+            fn from(packet: #packet_name) -> Self {
+                packet.to_vec()
+            }
+        }
+
+        #try_from_root
+
+        impl #packet_name {
+            #parse_packet
+
+            #specialize
+
+            fn new(root: Arc<#root_data_name>) -> std::result::Result<Self, &'static str> {
+                let #root_accessor = root;
+                #(#new_steps)*
+                Ok(Self { #(#lineage_accessors,)* })
+            }
+
+            #(#getters)*
+        }
+
+        #(impl From<#packet_name> for #ancestors {
+            fn from(packet: #packet_name) -> #ancestors {
+                #ancestors::new(packet.#root_accessor).unwrap()
+            }
+        })*
+
+        impl #builder_name {
+            pub fn build(self) -> #packet_name {
+                #(#build_steps)*
+                #packet_name::new(#root_accessor).unwrap()
+            }
+        }
+
+        #(impl From<#builder_name> for #ancestors {
+            fn from(builder: #builder_name) -> #ancestors {
+                builder.build().into()
+            }
+        })*
+    })
+}
+
+/// Find the response packet of an HCI command: the command status or
+/// command complete event constrained with the same opcode.
+fn command_expectation<'d>(
+    scope: &Scope<'d>,
+    grammar: &'d Grammar,
+    decl: &'d Decl,
+) -> Option<&'d Decl> {
+    let has_ancestor = |decl: &'d Decl, names: &[&str]| {
+        let lineage = scope.get_lineage(decl);
+        lineage[..lineage.len() - 1].iter().any(|d| names.contains(&d.id().unwrap().as_str()))
+    };
+    let constraint_value = |decl: &'d Decl, id: &str| {
+        decl.constraints().find(|c| c.id == id).and_then(|c| match &c.value {
+            Expr::Identifier { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+    };
+    if !has_ancestor(decl, &["Command"]) {
+        return None;
+    }
+    let opcode = constraint_value(decl, "op_code")?;
+    grammar.declarations.iter().find(|d| {
+        matches!(d, Decl::Packet { .. })
+            && has_ancestor(d, &["CommandStatus", "CommandComplete"])
+            && constraint_value(d, "command_op_code").as_ref() == Some(&opcode)
+    })
+}
+
+/// Generate Rust code from the AST, or report the first
+/// construct not supported by the backend.
+pub fn generate(sources: &SourceDatabase, grammar: &Grammar) -> Result<String, String> {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let path = Path::new(source.name());
+    let preamble = preamble::generate(path);
+
+    let grammar = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&grammar);
+
+    // Grammars declaring HCI commands and events expose the expected
+    // response of each command, and conversions between the OpCode and
+    // OpCodeIndex enums.
+    let is_hci = ["Command", "Event", "CommandComplete"]
+        .iter()
+        .all(|id| matches!(scope.typedef.get(*id), Some(Decl::Packet { .. })));
+    let mut code = vec![];
+    if is_hci {
+        code.push(quote! {
+            pub trait CommandExpectations {
+                type ResponseType;
+                fn _to_response_type(pkt: EventPacket) -> Self::ResponseType;
+            }
+        });
+    }
+
+    for decl in &grammar.declarations {
+        code.push(match decl {
+            Decl::Enum { id, tags, width, .. } => {
+                let try_from = match id.as_str() {
+                    "OpCodeIndex" if is_hci => scope.typedef.get("OpCode").copied(),
+                    _ => None,
+                };
+                generate_enum(id, tags, *width, try_from)
+            }
+            Decl::Struct { .. } => generate_struct(&scope, decl)?,
+            Decl::Packet { .. } => {
+                let expectation =
+                    is_hci.then(|| command_expectation(&scope, &grammar, decl)).flatten();
+                generate_packet(&scope, decl, expectation)?
+            }
+            _ => quote!(),
+        });
+    }
+
+    Ok(format!("{}\n{}\n", preamble, quote!(#(#code)*)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::{assert_expr_eq, assert_snapshot_eq, rustfmt};
+
+    #[test]
+    fn test_to_camel_case() {
+        assert_eq!(to_camel_case("SUCCESS"), "Success");
+        assert_eq!(to_camel_case("LE_META_EVENT"), "LeMetaEvent");
+        assert_eq!(to_camel_case("EDR_8DPSK"), "Edr8dpsk");
+    }
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("Command"), "command");
+        assert_eq!(to_snake_case("LeMetaEvent"), "le_meta_event");
+        assert_eq!(to_snake_case("ACLPacket"), "a_c_l_packet");
+    }
+
+    #[test]
+    fn test_generate_enum() {
+        let tags = vec![
+            Tag::Value { id: "A".to_owned(), loc: SourceRange::default(), value: 1 },
+            Tag::Value { id: "B_C".to_owned(), loc: SourceRange::default(), value: 2 },
+        ];
+        assert_expr_eq(
+            generate_enum("Foo", &tags, 8, None),
+            quote! {
+                #[derive(FromPrimitive, ToPrimitive, Debug, Hash, Eq, PartialEq, Clone, Copy)]
+                #[repr(u64)]
+                pub enum Foo {
+                    A = 0x1,
+                    BC = 0x2,
+                }
+
+                impl fmt::Display for Foo {
+                    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        match self {
+                            Foo::A => write!(f, "{:#04X} (A)", self.to_u8().unwrap()),
+                            Foo::BC => write!(f, "{:#04X} (B_C)", self.to_u8().unwrap()),
+                        }
+                    }
+                }
+            },
+        );
+    }
+
+    fn generate_snapshot(name: &str, text: &str) {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(&mut db, format!("{}.pdl", name), text.to_owned())
+            .expect("parsing failure");
+        let actual = rustfmt(&generate(&db, &grammar).expect("generation failure"));
+        assert_snapshot_eq(format!("tests/generated/{}.rs", name), &actual);
+    }
+
+    #[test]
+    fn test_generate_enum_ranges() {
+        let tags = vec![
+            Tag::Value { id: "A".to_owned(), loc: SourceRange::default(), value: 1 },
+            Tag::Range { id: "B".to_owned(), loc: SourceRange::default(), start: 2, end: 4 },
+            Tag::Default { id: "C".to_owned(), loc: SourceRange::default() },
+        ];
+        assert_expr_eq(
+            generate_enum("Foo", &tags, 8, None),
+            quote! {
+                #[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+                pub enum Foo {
+                    A,
+                    B(u8),
+                    C(u8),
+                }
+
+                impl FromPrimitive for Foo {
+                    fn from_i64(n: i64) -> Option<Self> {
+                        u64::try_from(n).ok().and_then(Self::from_u64)
+                    }
+
+                    fn from_u64(n: u64) -> Option<Self> {
+                        match n {
+                            0x1 => Some(Foo::A),
+                            0x2..=0x4 => Some(Foo::B(n as u8)),
+                            0..=0xff => Some(Foo::C(n as u8)),
+                            _ => None,
+                        }
+                    }
+                }
+
+                impl ToPrimitive for Foo {
+                    fn to_i64(&self) -> Option<i64> {
+                        self.to_u64().and_then(|n| i64::try_from(n).ok())
+                    }
+
+                    fn to_u64(&self) -> Option<u64> {
+                        Some(match self {
+                            Foo::A => 0x1,
+                            Foo::B(n) => *n as u64,
+                            Foo::C(n) => *n as u64,
+                        })
+                    }
+                }
+
+                impl fmt::Display for Foo {
+                    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        match self {
+                            Foo::A => write!(f, "{:#04X} (A)", self.to_u8().unwrap()),
+                            Foo::B(_) => write!(f, "{:#04X} (B)", self.to_u8().unwrap()),
+                            Foo::C(_) => write!(f, "{:#04X} (C)", self.to_u8().unwrap()),
+                        }
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_generate_little_endian() {
+        generate_snapshot(
+            "packet_decl_little_endian",
+            r#"
+            little_endian_packets
+            enum Enum : 8 { A = 1, B = 2 }
+            struct Struct { a: 8, b: 16[2] }
+            packet Parent {
+                opcode: Enum,
+                flag: 1,
+                _reserved_: 3,
+                _size_(_payload_): 4,
+                _payload_,
+            }
+            packet ChildA : Parent (opcode = A) {
+                _count_(items): 8,
+                items: Struct[],
+            }
+            packet ChildB : Parent (opcode = B) {
+                value: 24,
+                _fixed_ = 0x42: 8,
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_generate_big_endian() {
+        generate_snapshot(
+            "packet_decl_big_endian",
+            r#"
+            big_endian_packets
+            packet Parent {
+                a: 4,
+                b: 12,
+                _size_(data): 8,
+                data: 8[],
+                _payload_,
+            }
+            packet Child : Parent (a = 1) {
+                c: 32,
+            }
+            "#,
+        );
+    }
+
+    #[test]
+    fn test_generate_from_json() {
+        let text = r#"
+            little_endian_packets
+            enum OpCode : 16 { NONE = 0x0000, RESET = 0x0c03 }
+            enum OpCodeIndex : 16 { RESET = 0 }
+            enum EventCode : 8 { COMMAND_COMPLETE = 0x0e }
+            packet Command {
+                op_code: OpCode,
+                _size_(_payload_): 8,
+                _payload_,
+            }
+            packet Event {
+                event_code: EventCode,
+                _size_(_payload_): 8,
+                _payload_,
+            }
+            packet CommandComplete : Event (event_code = COMMAND_COMPLETE) {
+                num_hci_command_packets: 8,
+                command_op_code: OpCode,
+                _payload_,
+            }
+            packet Reset : Command (op_code = RESET) {}
+            packet ResetComplete : CommandComplete (command_op_code = RESET) {
+                status: 8,
+            }
+            "#;
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "hci.pdl".to_owned(), text.to_owned()).expect("parsing failure");
+        let expected = rustfmt(&generate(&db, &grammar).expect("generation failure"));
+        assert!(expected.contains("pub trait CommandExpectations"));
+        assert!(expected.contains("impl TryFrom<OpCode> for OpCodeIndex"));
+
+        let mut db = SourceDatabase::new();
+        let json = crate::backends::json::generate(&grammar).unwrap();
+        let grammar = crate::backends::json::parse(&mut db, "hci.json".to_owned(), json)
+            .expect("parsing failure");
+        let actual = rustfmt(&generate(&db, &grammar).expect("generation failure"));
+
+        // Only the preamble header naming the input file differs.
+        assert_eq!(
+            actual.lines().skip(1).collect::<Vec<_>>(),
+            expected.lines().skip(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_generate_unaligned_bit_fields() {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "unaligned.pdl".to_owned(),
+            r#"
+            little_endian_packets
+            packet Foo {
+                a: 4,
+            }
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+        assert_eq!(
+            generate(&db, &grammar),
+            Err("Foo: bit fields are not aligned to an octet boundary".to_owned())
+        );
+    }
+}
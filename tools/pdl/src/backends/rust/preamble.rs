@@ -0,0 +1,67 @@
+use std::path::Path;
+
+/// Generate the file preamble.
+///
+/// The preamble declares the error type and the `Packet` trait shared
+/// by all the generated declarations, as well as the imports they
+/// depend on.
+pub fn generate(path: &Path) -> String {
+    let filename = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    format!(
+        "// @generated rust packets from {}\n{}",
+        filename,
+        r#"
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+  #[error("Packet parsing failed")]
+  InvalidPacketError,
+  #[error("{field} was {value:x}, which is not known")]
+  ConstraintOutOfBounds {
+    field: String,
+    value: u64,
+  },
+  #[error("when parsing {obj}.{field} needed length of {wanted} but got {got}")]
+  InvalidLengthError {
+    obj: String,
+    field: String,
+    wanted: usize,
+    got: usize,
+  },
+  #[error("Due to size restrictions a struct could not be parsed.")]
+  ImpossibleStructError,
+  #[error("when parsing field {obj}.{field}, {value} is not a valid {type_} value")]
+  InvalidEnumValueError {
+    obj: String,
+    field: String,
+    value: u64,
+    type_: String,
+  },
+  #[error("when parsing {obj}, expected fixed value {expected:x} but got {actual:x}")]
+  InvalidFixedValue {
+    obj: String,
+    expected: u64,
+    actual: u64,
+  },
+}
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct TryFromError(&'static str);
+
+pub trait Packet {
+  fn to_bytes(self) -> Bytes;
+  fn to_vec(self) -> Vec<u8>;
+}
+"#
+    )
+}
@@ -0,0 +1,233 @@
+//! Rust conformance test backend.
+//!
+//! Generates a Rust `#[test]` function for each test case of the
+//! grammar test declarations, exercising the code generated by the
+//! Rust backend. The generated file is meant to be included in the
+//! module containing the generated packets.
+//!
+//! Each test parses the test case input as the tested packet, checks
+//! the resolved specialization and the expected field values, then
+//! serializes the packet and compares the result with the input.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use std::path::Path;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::backends::rust::{to_camel_case, to_snake_case};
+
+/// Return the assertion checking the expected value of a field of
+/// the packet `packet_id`.
+fn generate_field_check(
+    scope: &Scope,
+    packet_id: &str,
+    constraint: &Constraint,
+) -> Result<TokenStream, String> {
+    let decl = scope.typedef[packet_id];
+    let field = scope
+        .get_field(decl, &constraint.id)
+        .ok_or_else(|| format!("`{}` is not a field of `{}`", constraint.id, packet_id))?;
+    let getter = format_ident!("get_{}", constraint.id);
+    let expected = match (field, &constraint.value) {
+        (Field::Typedef { type_id, .. }, Expr::Identifier { name, .. })
+            if matches!(scope.typedef.get(type_id), Some(Decl::Enum { .. })) =>
+        {
+            let ty = format_ident!("{}", type_id);
+            let tag = format_ident!("{}", to_camel_case(name));
+            quote!(#ty::#tag)
+        }
+        (Field::Scalar { .. }, expr) if !matches!(expr, Expr::Identifier { .. }) => {
+            let value = expr.evaluate().map_err(|(_, message)| message)?;
+            let value: TokenStream = format!("{:#x}", value).parse().unwrap();
+            quote!(#value)
+        }
+        _ => {
+            return Err(format!(
+                "unsupported expected value for the field `{}` of `{}`",
+                constraint.id, packet_id
+            ))
+        }
+    };
+    Ok(quote!(assert_eq!(packet.#getter(), #expected);))
+}
+
+/// Return the expression parsing `bytes` as the packet `packet_id`.
+/// Only root packets have a parse method in the code generated by
+/// the legacy generator, other packets are converted from the root
+/// packet.
+fn parse_packet(scope: &Scope, packet_id: &str, message: &str) -> TokenStream {
+    let decl = scope.typedef[packet_id];
+    let root_id = scope.get_lineage(decl)[0].id().unwrap();
+    let packet_name = format_ident!("{}Packet", packet_id);
+    let root_name = format_ident!("{}Packet", root_id);
+    if root_id == packet_id {
+        quote!(#packet_name::parse(bytes).expect(#message))
+    } else {
+        quote! {
+            #packet_name::try_from(#root_name::parse(bytes).expect(#message)).expect(#message)
+        }
+    }
+}
+
+/// Generate the test function of a test case.
+fn generate_test_case(
+    scope: &Scope,
+    type_id: &str,
+    index: usize,
+    test_case: &TestCase,
+) -> Result<TokenStream, String> {
+    let bytes = test_case
+        .bytes()?
+        .into_iter()
+        .map(|b| format!("{:#04x}", b).parse::<TokenStream>().unwrap())
+        .collect::<Vec<_>>();
+    let name = format_ident!("test_{}_{}", to_snake_case(type_id), index);
+
+    // Parse direction: the test case must parse as the tested packet,
+    // resolve to the expected specialization and hold the expected
+    // field values.
+    let packet_id = test_case.packet_id.as_deref().unwrap_or(type_id);
+    let parse_type = parse_packet(scope, type_id, "test case failed to parse");
+    let mut checks = vec![];
+    if packet_id != type_id {
+        let message = format!("test case did not parse as {}", packet_id);
+        let parse = parse_packet(scope, packet_id, &message);
+        checks.push(quote! {
+            let packet = #parse;
+        });
+    }
+    if test_case.packet_id.is_some() {
+        // Each ancestor of the expected packet, starting from the
+        // tested packet, must specialize to the next packet of the
+        // lineage, and the expected packet must not specialize further.
+        let decl = scope.typedef[packet_id];
+        let lineage = scope.get_lineage(decl);
+        let start = lineage
+            .iter()
+            .position(|d| d.id().map(String::as_str) == Some(type_id))
+            .ok_or_else(|| format!("`{}` does not inherit from `{}`", packet_id, type_id))?;
+        for pair in lineage[start..].windows(2) {
+            let parent_id = pair[0].id().unwrap();
+            let child_enum = format_ident!("{}Child", parent_id);
+            let child = format_ident!("{}", pair[1].id().unwrap());
+            let message = format!("test case did not parse as {}", parent_id);
+            let parse = parse_packet(scope, parent_id, &message);
+            checks.push(quote! {
+                assert!(matches!(#parse.specialize(), #child_enum::#child(_)));
+            });
+        }
+        if !scope.get_children(decl).is_empty() {
+            // The legacy generator only declares the payload variant
+            // for packets with a payload field.
+            let child_enum = format_ident!("{}Child", packet_id);
+            let payload = decl
+                .fields()
+                .any(|f| matches!(f, Field::Payload { .. }))
+                .then(|| quote!(#child_enum::Payload(_) |));
+            checks.push(quote! {
+                assert!(matches!(packet.specialize(), #payload #child_enum::None));
+            });
+        }
+    }
+    for constraint in &test_case.constraints {
+        checks.push(generate_field_check(scope, packet_id, constraint)?);
+    }
+
+    Ok(quote! {
+        #[test]
+        fn #name() {
+            let bytes: &[u8] = &[#(#bytes),*];
+            let packet = #parse_type;
+            assert_eq!(packet.clone().to_vec(), bytes);
+            #(#checks)*
+        }
+    })
+}
+
+/// Generate the Rust tests of the test declarations of the grammar.
+pub fn generate(sources: &SourceDatabase, grammar: &Grammar) -> Result<String, String> {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let path = Path::new(source.name());
+    let filename = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+
+    let grammar = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&grammar);
+    let mut tests = vec![];
+    for decl in &grammar.declarations {
+        if let Decl::Test { type_id, test_cases, .. } = decl {
+            for (index, test_case) in test_cases.iter().enumerate() {
+                tests.push(generate_test_case(&scope, type_id, index, test_case)?);
+            }
+        }
+    }
+
+    Ok(format!(
+        "// @generated rust tests from {}\n{}\n",
+        filename,
+        quote! {
+            #[cfg(test)]
+            mod pdl_tests {
+                use super::*;
+
+                #(#tests)*
+            }
+        }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::assert_expr_eq;
+
+    #[test]
+    fn test_generate_test_case() {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "test.pdl".to_owned(),
+            r#"
+            little_endian_packets
+            enum OpCode : 8 { RESET = 1, READ = 2 }
+            packet Command { op_code: OpCode, _payload_ }
+            packet Reset : Command (op_code = RESET) { delay: 16 }
+            test Command {
+                "\x01\x02\x00" : Reset (op_code = RESET, delay = 2),
+            }
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+        let grammar = analyzer::inline_groups(&grammar);
+        let scope = Scope::new(&grammar);
+        let test_case = match &grammar.declarations[3] {
+            Decl::Test { test_cases, .. } => &test_cases[0],
+            _ => unreachable!(),
+        };
+        assert_expr_eq(
+            generate_test_case(&scope, "Command", 0, test_case).unwrap(),
+            quote! {
+                #[test]
+                fn test_command_0() {
+                    let bytes: &[u8] = &[0x01, 0x02, 0x00];
+                    let packet = CommandPacket::parse(bytes).expect("test case failed to parse");
+                    assert_eq!(packet.clone().to_vec(), bytes);
+                    let packet = ResetPacket::try_from(
+                        CommandPacket::parse(bytes).expect("test case did not parse as Reset"),
+                    )
+                    .expect("test case did not parse as Reset");
+                    assert!(matches!(
+                        CommandPacket::parse(bytes)
+                            .expect("test case did not parse as Command")
+                            .specialize(),
+                        CommandChild::Reset(_)
+                    ));
+                    assert_eq!(packet.get_op_code(), OpCode::Reset);
+                    assert_eq!(packet.get_delay(), 0x2);
+                }
+            },
+        );
+    }
+}
@@ -0,0 +1,655 @@
+//! Wireshark Lua dissector backend.
+//!
+//! The generated dissector decodes the selected root packet and
+//! dispatches the payload to the child packets matching the parent
+//! constraints. Field values are registered as `ProtoField`s, with the
+//! enum tags as value strings, or as range strings for enums declaring
+//! range or default tags.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::backends::rust::to_snake_case;
+
+/// Return the Lua expression of a value mask of `width` bits.
+fn mask(width: usize) -> String {
+    let mask = if width >= 64 { u64::MAX } else { (1 << width) - 1 };
+    if width <= 32 {
+        format!("0x{:x}", mask)
+    } else {
+        format!("UInt64.fromhex(\"{:016x}\")", mask)
+    }
+}
+
+/// Return the ProtoField constructor for an integer of `width` bits.
+fn uint_type(width: usize) -> &'static str {
+    match width {
+        0..=8 => "uint8",
+        9..=16 => "uint16",
+        17..=24 => "uint24",
+        25..=32 => "uint32",
+        _ => "uint64",
+    }
+}
+
+/// Check whether an enum declaration has range or default tags,
+/// which are displayed with range strings instead of value strings.
+fn has_range_tags(decl: &Decl) -> bool {
+    matches!(decl, Decl::Enum { tags, .. } if tags.iter().any(|t| t.value().is_none()))
+}
+
+/// Return the Lua expression reading an integer of `width` bits at
+/// the current offset.
+fn read_uint(width: usize) -> String {
+    let read = format!("read_uint(buffer, offset, {})", width / 8);
+    if width <= 32 {
+        format!("{}:tonumber()", read)
+    } else {
+        read
+    }
+}
+
+struct Generator<'d> {
+    scope: Scope<'d>,
+    /// Protocol name, used as prefix for the field abbreviations.
+    proto: String,
+    fields: String,
+    dissectors: String,
+}
+
+impl<'d> Generator<'d> {
+    /// Register the ProtoField displaying the field `id` of the
+    /// declaration `decl_id`.
+    fn proto_field(
+        &mut self,
+        decl_id: &str,
+        id: &str,
+        width: Option<usize>,
+        enum_id: Option<&str>,
+    ) {
+        let abbr = format!("{}.{}.{}", self.proto, to_snake_case(decl_id), id);
+        let field = match (width, enum_id) {
+            (Some(width), Some(enum_id)) => format!(
+                "ProtoField.{}(\"{}\", \"{}\", {}, {}_values)",
+                uint_type(width),
+                abbr,
+                id,
+                if has_range_tags(self.scope.typedef[enum_id]) {
+                    "base.HEX + base.RANGE_STRING"
+                } else {
+                    "base.HEX"
+                },
+                enum_id
+            ),
+            (Some(width), None) => {
+                format!("ProtoField.{}(\"{}\", \"{}\", base.DEC)", uint_type(width), abbr, id)
+            }
+            (None, _) => format!("ProtoField.bytes(\"{}\", \"{}\")", abbr, id),
+        };
+        writeln!(self.fields, "  [\"{}.{}\"] = {},", decl_id, id, field).unwrap();
+    }
+
+    /// Return the value of a constraint, as a Lua expression.
+    fn constraint_value(&self, decl: &'d Decl, constraint: &Constraint) -> String {
+        match &constraint.value {
+            Expr::Identifier { name, .. } => {
+                let tag_value = match self.scope.get_field(decl, &constraint.id) {
+                    Some(Field::Typedef { type_id, .. }) => match self.scope.typedef[type_id] {
+                        Decl::Enum { tags, .. } => tags.iter().find(|t| t.id() == name),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                tag_value
+                    .and_then(Tag::value)
+                    .map_or_else(|| "nil".to_owned(), |value| value.to_string())
+            }
+            expr => expr.evaluate().unwrap().to_string(),
+        }
+    }
+
+    /// Generate the statements dissecting a bit chunk.
+    fn generate_chunk(&mut self, decl: &'d Decl, chunk: &[&'d Field], code: &mut String) {
+        let decl_id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let width = chunk.iter().map(|f| self.scope.get_field_width(f).unwrap()).sum::<usize>();
+        writeln!(code, "  do").unwrap();
+        writeln!(code, "    local chunk = read_uint(buffer, offset, {})", width / 8).unwrap();
+        let mut shift = 0;
+        for field in chunk {
+            let field_width = self.scope.get_field_width(field).unwrap();
+            let value = match (shift, field_width == width) {
+                (_, true) => "chunk".to_owned(),
+                (0, false) => format!("chunk:band({})", mask(field_width)),
+                (_, false) => format!("chunk:rshift({}):band({})", shift, mask(field_width)),
+            };
+            let value = match field_width {
+                0..=32 => format!("{}:tonumber()", value),
+                _ => value,
+            };
+            match field {
+                Field::Scalar { id, .. } | Field::Typedef { id, .. } => {
+                    let enum_id = match field {
+                        Field::Typedef { type_id, .. } => match self.scope.typedef[type_id] {
+                            Decl::Enum { .. } => Some(type_id.as_str()),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    self.proto_field(decl_id, id, Some(field_width), enum_id);
+                    writeln!(code, "    values[\"{}\"] = {}", id, value).unwrap();
+                    writeln!(
+                        code,
+                        "    subtree:add(fields[\"{}.{}\"], buffer(offset, {}), values[\"{}\"])",
+                        decl_id,
+                        id,
+                        width / 8,
+                        id
+                    )
+                    .unwrap();
+                }
+                Field::Size { field_id, .. } => {
+                    let size_modifier = fields.iter().find_map(|f| match f {
+                        Field::Array { id, size_modifier, .. } if id == field_id => {
+                            size_modifier.as_ref()
+                        }
+                        Field::Payload { size_modifier, .. } if field_id == "_payload_" => {
+                            size_modifier.as_ref()
+                        }
+                        _ => None,
+                    });
+                    let modifier = size_modifier.map_or(0, |m| analyzer::size_modifier_bits(m) / 8);
+                    let value = match modifier {
+                        0 => value,
+                        _ => format!("{} - {}", value, modifier),
+                    };
+                    writeln!(code, "    sizes[\"{}\"] = {}", field_id, value).unwrap();
+                }
+                Field::Count { field_id, .. } => {
+                    writeln!(code, "    counts[\"{}\"] = {}", field_id, value).unwrap();
+                }
+                _ => (),
+            }
+            shift += field_width;
+        }
+        writeln!(code, "    offset = offset + {}", width / 8).unwrap();
+        writeln!(code, "  end").unwrap();
+    }
+
+    /// Return the statements dissecting one element of an array,
+    /// or a typedef field. `limit` is the Lua expression of the end
+    /// offset of the element region.
+    fn generate_element(
+        &self,
+        decl_id: &str,
+        id: &str,
+        width: Option<usize>,
+        type_id: Option<&str>,
+        limit: &str,
+    ) -> Vec<String> {
+        let decl = type_id.map(|type_id| self.scope.typedef[type_id]);
+        let width = match decl {
+            Some(Decl::Enum { width, .. }) => Some(*width),
+            _ => width,
+        };
+        match (width, decl) {
+            (Some(width), _) => vec![
+                format!(
+                    "subtree:add(fields[\"{}.{}\"], buffer(offset, {}), {})",
+                    decl_id,
+                    id,
+                    width / 8,
+                    read_uint(width)
+                ),
+                format!("offset = offset + {}", width / 8),
+            ],
+            (_, Some(Decl::CustomField { width: Some(width), .. })) => vec![
+                format!("subtree:add(fields[\"{}.{}\"], buffer(offset, {}))", decl_id, id, width / 8),
+                format!("offset = offset + {}", width / 8),
+            ],
+            (_, Some(Decl::CustomField { .. })) => vec![
+                format!("subtree:add(fields[\"{}.{}\"], buffer(offset, {} - offset))", decl_id, id, limit),
+                format!("offset = {}", limit),
+            ],
+            (_, Some(Decl::Struct { id: struct_id, .. })) => vec![format!(
+                "offset = offset + dissect.{}(buffer(offset, {} - offset):tvb(), pinfo, subtree, {{}})",
+                struct_id, limit
+            )],
+            _ => unreachable!(),
+        }
+    }
+
+    /// Generate the dissector function of a packet or struct
+    /// declaration.
+    fn generate_decl(&mut self, decl: &'d Decl) {
+        let decl_id = decl.id().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let children = self.scope.get_children(decl).to_vec();
+        let kind = match decl {
+            Decl::Packet { .. } => "packet",
+            _ => "struct",
+        };
+
+        let mut code = String::new();
+        writeln!(
+            code,
+            "-- Dissect a {} {}, and return the number of bytes consumed.",
+            decl_id, kind
+        )
+        .unwrap();
+        writeln!(code, "function dissect.{}(buffer, pinfo, tree, values)", decl_id).unwrap();
+        writeln!(code, "  local subtree = tree:add(buffer(), \"{}\")", decl_id).unwrap();
+        writeln!(code, "  local offset = 0").unwrap();
+        writeln!(code, "  local sizes, counts = {{}}, {{}}").unwrap();
+        let mut has_payload = false;
+        let mut chunk = vec![];
+        let mut chunk_width = 0;
+        for (index, field) in fields.iter().enumerate() {
+            if self.scope.is_bitfield(field) {
+                chunk.push(*field);
+                chunk_width += self.scope.get_field_width(field).unwrap();
+                if chunk_width % 8 == 0 {
+                    self.generate_chunk(decl, &chunk, &mut code);
+                    chunk.clear();
+                    chunk_width = 0;
+                }
+                continue;
+            }
+            // Lua expression of the number of bytes available to a
+            // variable size field.
+            let trailing = self.scope.get_trailing_width(&fields, index).unwrap_or(0) / 8;
+            let remaining = match trailing {
+                0 => "buffer:len() - offset".to_owned(),
+                _ => format!("buffer:len() - offset - {}", trailing),
+            };
+            // Optional fields are dissected only when the value of
+            // their discriminator matches the condition.
+            if let Some(cond) = field.cond() {
+                let width = match field {
+                    Field::Scalar { width, .. } => Some((*width, None)),
+                    Field::Typedef { type_id, .. } => match self.scope.typedef[type_id] {
+                        Decl::Enum { width, .. } => Some((*width, Some(type_id.as_str()))),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let id = field.id().unwrap();
+                let lines = match (width, field) {
+                    (Some((width, enum_id)), _) => {
+                        self.proto_field(decl_id, id, Some(width), enum_id);
+                        vec![
+                            format!("values[\"{}\"] = {}", id, read_uint(width)),
+                            format!(
+                                "subtree:add(fields[\"{}.{}\"], buffer(offset, {}), values[\"{}\"])",
+                                decl_id,
+                                id,
+                                width / 8,
+                                id
+                            ),
+                            format!("offset = offset + {}", width / 8),
+                        ]
+                    }
+                    (None, Field::Typedef { type_id, .. }) => {
+                        if let Decl::CustomField { .. } = self.scope.typedef[type_id] {
+                            self.proto_field(decl_id, id, None, None);
+                        }
+                        let limit = match trailing {
+                            0 => "buffer:len()".to_owned(),
+                            _ => format!("buffer:len() - {}", trailing),
+                        };
+                        self.generate_element(decl_id, id, None, Some(type_id), &limit)
+                    }
+                    _ => unreachable!(),
+                };
+                writeln!(
+                    code,
+                    "  if values[\"{}\"] == {} then",
+                    cond.id,
+                    self.constraint_value(decl, cond)
+                )
+                .unwrap();
+                for line in lines {
+                    writeln!(code, "    {}", line).unwrap();
+                }
+                writeln!(code, "  end").unwrap();
+                continue;
+            }
+            match field {
+                Field::Array { id, width, type_id, size, .. } => {
+                    let padding = match fields.get(index + 1) {
+                        Some(Field::Padding { width, .. }) => Some(*width),
+                        _ => None,
+                    };
+                    let element_type = type_id.as_deref().map(|t| self.scope.typedef[t]);
+                    match (width, element_type) {
+                        (Some(width), _) => self.proto_field(decl_id, id, Some(*width), None),
+                        (_, Some(Decl::Enum { width, .. })) => {
+                            self.proto_field(decl_id, id, Some(*width), type_id.as_deref())
+                        }
+                        (_, Some(Decl::CustomField { .. })) => {
+                            self.proto_field(decl_id, id, None, None)
+                        }
+                        _ => (),
+                    }
+                    let count =
+                        size.map_or_else(|| format!("counts[\"{}\"]", id), |size| size.to_string());
+                    let region = match padding {
+                        Some(padding) => padding.to_string(),
+                        None => format!("sizes[\"{}\"] or ({})", id, remaining),
+                    };
+                    writeln!(code, "  do").unwrap();
+                    writeln!(code, "    local limit = offset + ({})", region).unwrap();
+                    writeln!(code, "    local count = {}", count).unwrap();
+                    writeln!(code, "    local index = 0").unwrap();
+                    writeln!(
+                        code,
+                        "    while offset < limit and (count == nil or index < count) do"
+                    )
+                    .unwrap();
+                    for line in
+                        self.generate_element(decl_id, id, *width, type_id.as_deref(), "limit")
+                    {
+                        writeln!(code, "      {}", line).unwrap();
+                    }
+                    writeln!(code, "      index = index + 1").unwrap();
+                    writeln!(code, "    end").unwrap();
+                    if padding.is_some() {
+                        writeln!(code, "    offset = limit").unwrap();
+                    }
+                    writeln!(code, "  end").unwrap();
+                }
+                Field::Typedef { id, type_id, .. } => {
+                    if let Decl::CustomField { .. } = self.scope.typedef[type_id] {
+                        self.proto_field(decl_id, id, None, None);
+                    }
+                    let limit = match trailing {
+                        0 => "buffer:len()".to_owned(),
+                        _ => format!("buffer:len() - {}", trailing),
+                    };
+                    for line in self.generate_element(decl_id, id, None, Some(type_id), &limit) {
+                        writeln!(code, "  {}", line).unwrap();
+                    }
+                }
+                Field::Payload { .. } | Field::Body { .. } => {
+                    let size_id = match field {
+                        Field::Payload { .. } => "_payload_",
+                        _ => "_body_",
+                    };
+                    self.proto_field(decl_id, "payload", None, None);
+                    writeln!(
+                        code,
+                        "  local payload_size = sizes[\"{}\"] or ({})",
+                        size_id, remaining
+                    )
+                    .unwrap();
+                    writeln!(code, "  local payload = buffer(offset, payload_size)").unwrap();
+                    writeln!(code, "  offset = offset + payload_size").unwrap();
+                    has_payload = true;
+                }
+                Field::Padding { width, .. }
+                    if !matches!(fields.get(index.wrapping_sub(1)), Some(Field::Array { .. })) =>
+                {
+                    writeln!(code, "  offset = offset + {}", width).unwrap();
+                }
+                _ => (),
+            }
+        }
+
+        // Children of a declaration without payload are dissected
+        // from the remaining bytes.
+        if !has_payload && !children.is_empty() {
+            self.proto_field(decl_id, "payload", None, None);
+            writeln!(code, "  local payload = buffer(offset, buffer:len() - offset)").unwrap();
+            writeln!(code, "  offset = buffer:len()").unwrap();
+            has_payload = true;
+        }
+        if has_payload {
+            let mut keyword = "if";
+            let mut default = None;
+            for child in &children {
+                let child_id = child.id().unwrap();
+                let conditions = child
+                    .constraints()
+                    .map(|c| format!("values[\"{}\"] == {}", c.id, self.constraint_value(child, c)))
+                    .collect::<Vec<_>>();
+                if conditions.is_empty() {
+                    default = default.or(Some(child_id));
+                    continue;
+                }
+                writeln!(code, "  {} {} then", keyword, conditions.join(" and ")).unwrap();
+                writeln!(code, "    dissect.{}(payload:tvb(), pinfo, subtree, values)", child_id)
+                    .unwrap();
+                keyword = "elseif";
+            }
+            let indent = if keyword == "if" { "  " } else { "    " };
+            if keyword != "if" {
+                writeln!(code, "  else").unwrap();
+            }
+            match default {
+                Some(child_id) => writeln!(
+                    code,
+                    "{}dissect.{}(payload:tvb(), pinfo, subtree, values)",
+                    indent, child_id
+                ),
+                None => writeln!(
+                    code,
+                    "{}subtree:add(fields[\"{}.payload\"], payload)",
+                    indent, decl_id
+                ),
+            }
+            .unwrap();
+            if keyword != "if" {
+                writeln!(code, "  end").unwrap();
+            }
+        }
+        writeln!(code, "  subtree:set_len(offset)").unwrap();
+        writeln!(code, "  return offset").unwrap();
+        writeln!(code, "end").unwrap();
+        writeln!(self.dissectors).unwrap();
+        self.dissectors.push_str(&code);
+    }
+}
+
+/// Return the declarations reachable from the root packet: the root
+/// packet and its descendants, and the declarations of their fields.
+fn reachable_decls<'d>(scope: &Scope<'d>, root: &'d Decl) -> Vec<&'d Decl> {
+    let mut reachable = vec![root];
+    let mut visited = HashSet::from([root.id().unwrap()]);
+    let mut index = 0;
+    while index < reachable.len() {
+        let decl = reachable[index];
+        let type_ids = decl.fields().filter_map(|f| match f {
+            Field::Typedef { type_id, .. } | Field::Array { type_id: Some(type_id), .. } => {
+                Some(type_id)
+            }
+            _ => None,
+        });
+        let next = type_ids
+            .filter_map(|type_id| scope.typedef.get(type_id).copied())
+            .chain(scope.get_children(decl).iter().copied())
+            .collect::<Vec<_>>();
+        for decl in next {
+            if visited.insert(decl.id().unwrap()) {
+                reachable.push(decl);
+            }
+        }
+        index += 1;
+    }
+    reachable
+}
+
+/// Generate a Wireshark Lua dissector for the packet `root_packet`,
+/// or for the first packet without parent if not specified.
+pub fn generate(
+    sources: &SourceDatabase,
+    grammar: &Grammar,
+    root_packet: Option<&str>,
+) -> Result<String, String> {
+    let source = sources.get(grammar.file).expect("could not read source");
+    let filename = Path::new(source.name()).file_name().unwrap().to_string_lossy().to_string();
+    let grammar = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&grammar);
+
+    let root = match root_packet {
+        Some(id) => match scope.typedef.get(id) {
+            Some(decl @ Decl::Packet { .. }) => *decl,
+            _ => return Err(format!("undeclared root packet `{}`", id)),
+        },
+        None => grammar
+            .declarations
+            .iter()
+            .find(|d| matches!(d, Decl::Packet { parent_id: None, .. }))
+            .ok_or("the grammar does not declare any packet")?,
+    };
+    let root_id = root.id().unwrap();
+    let mut generator = Generator {
+        proto: to_snake_case(root_id),
+        fields: String::new(),
+        dissectors: String::new(),
+        scope,
+    };
+
+    let reachable = reachable_decls(&generator.scope, root);
+    let mut value_strings = String::new();
+    // Preserve the declaration order in the generated code.
+    for decl in grammar.declarations.iter().filter(|d| reachable.contains(d)) {
+        match decl {
+            Decl::Enum { id, tags, width, .. } if has_range_tags(decl) => {
+                // Range strings are matched in order: value tags first,
+                // then range tags, then the default tag.
+                writeln!(value_strings, "\nlocal {}_values = {{", id).unwrap();
+                let max = if *width >= 64 { usize::MAX } else { (1 << width) - 1 };
+                let mut ranges = tags
+                    .iter()
+                    .map(|tag| match tag {
+                        Tag::Value { value, .. } => (0, *value, *value, tag.id()),
+                        Tag::Range { start, end, .. } => (1, *start, *end, tag.id()),
+                        Tag::Default { .. } => (2, 0, max, tag.id()),
+                    })
+                    .collect::<Vec<_>>();
+                ranges.sort_by_key(|(order, ..)| *order);
+                for (_, start, end, id) in ranges {
+                    writeln!(value_strings, "  {{0x{:x}, 0x{:x}, \"{}\"}},", start, end, id)
+                        .unwrap();
+                }
+                writeln!(value_strings, "}}").unwrap();
+            }
+            Decl::Enum { id, tags, .. } => {
+                writeln!(value_strings, "\nlocal {}_values = {{", id).unwrap();
+                for tag in tags {
+                    let value = tag.value().unwrap();
+                    writeln!(value_strings, "  [0x{:x}] = \"{}\",", value, tag.id()).unwrap();
+                }
+                writeln!(value_strings, "}}").unwrap();
+            }
+            Decl::Packet { .. } | Decl::Struct { .. } => generator.generate_decl(decl),
+            _ => (),
+        }
+    }
+
+    let read_uint = match generator.scope.endianness {
+        EndiannessValue::LittleEndian => "le_uint64",
+        EndiannessValue::BigEndian => "uint64",
+    };
+    let mut code = String::new();
+    writeln!(code, "-- File generated from {}, with the command:", filename).unwrap();
+    writeln!(code, "--  pdl --output-format wireshark-lua --root-packet {} {}", root_id, filename)
+        .unwrap();
+    writeln!(code, "-- /!\\ Do not edit by hand.").unwrap();
+    writeln!(code).unwrap();
+    writeln!(code, "local proto = Proto(\"{}\", \"{}\")", generator.proto, root_id).unwrap();
+    code.push_str(&value_strings);
+    writeln!(code, "\nlocal fields = {{").unwrap();
+    code.push_str(&generator.fields);
+    writeln!(code, "}}").unwrap();
+    code.push_str(&format!(
+        r#"
+local field_list = {{}}
+for _, field in pairs(fields) do
+  table.insert(field_list, field)
+end
+proto.fields = field_list
+
+-- Read an unsigned integer of `size` bytes as an UInt64 value.
+local function read_uint(buffer, offset, size)
+  return buffer(offset, size):{}()
+end
+
+local dissect = {{}}
+"#,
+        read_uint
+    ));
+    code.push_str(&generator.dissectors);
+    code.push_str(&format!(
+        r#"
+function proto.dissector(buffer, pinfo, tree)
+  pinfo.cols.protocol = proto.name
+  local subtree = tree:add(proto, buffer())
+  dissect.{}(buffer, pinfo, subtree, {{}})
+end
+
+-- Register the dissector for captures using the USER0 link-layer type.
+DissectorTable.get("wtap_encap"):add(wtap.USER0, proto)
+"#,
+        root_id
+    ));
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_inline;
+    use crate::test_utils::{assert_contains, assert_snapshot_eq};
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        custom_field Address : 48 "hci/"
+        enum OpCode : 8 { RESET = 1, READ = 2 }
+        struct Item { a: 8, b: 16 }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            address: Address,
+        }
+        packet Read : Command (op_code = READ) {
+            _count_(items): 8,
+            items: Item[],
+        }
+        packet Unrelated {
+            value: 8,
+        }
+    "#;
+
+    fn generate_inline(root_packet: Option<&str>) -> Result<String, String> {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(&mut db, "test.pdl".to_owned(), GRAMMAR.to_owned())
+            .expect("parsing failure");
+        generate(&db, &grammar, root_packet)
+    }
+
+    #[test]
+    fn test_generate_root_packet() {
+        let code = generate_inline(None).unwrap();
+        assert_contains(&code, "local proto = Proto(\"command\", \"Command\")");
+        assert_contains(&code, "[0x1] = \"RESET\",");
+        assert_contains(&code, "if values[\"op_code\"] == 1 then");
+        assert!(!code.contains("Unrelated"));
+        assert!(generate_inline(Some("Item")).is_err());
+        assert!(generate_inline(Some("Unknown")).is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let code = generate_inline(Some("Command")).unwrap();
+        assert_snapshot_eq("tests/generated/wireshark_dissector.lua", &code);
+    }
+}
@@ -0,0 +1,123 @@
+//! Library of well-known checksum algorithms.
+//!
+//! Checksum declarations name an algorithm of the library with their
+//! function string, e.g. `checksum Fcs : 16 "crc16-ccitt"`. Other
+//! function names are resolved by the host language of the generated
+//! code, and cannot be computed by the tools.
+
+/// Checksum algorithm.
+pub struct Algorithm {
+    /// Name of the algorithm, as referenced by checksum declarations.
+    pub name: &'static str,
+    /// Width in bits of the checksum value.
+    pub width: usize,
+    kind: Kind,
+}
+
+enum Kind {
+    /// Cyclic redundancy check, described by the parameters of the
+    /// Rocksoft model. `poly` and `init` are given in their
+    /// non-reflected form. The input and output are either both
+    /// reflected or both not reflected.
+    Crc { poly: u64, init: u64, reflected: bool, xor_out: u64 },
+    /// Sum of the bytes, modulo 2^width.
+    Sum,
+}
+
+/// Algorithms of the library. The check values are the checksums
+/// of the ASCII string `123456789`.
+const ALGORITHMS: [Algorithm; 5] = [
+    // CRC-8/SMBUS, check value 0xf4.
+    Algorithm {
+        name: "crc8",
+        width: 8,
+        kind: Kind::Crc { poly: 0x07, init: 0, reflected: false, xor_out: 0 },
+    },
+    // CRC-16/IBM-3740, also known as CRC-16/CCITT-FALSE,
+    // check value 0x29b1.
+    Algorithm {
+        name: "crc16-ccitt",
+        width: 16,
+        kind: Kind::Crc { poly: 0x1021, init: 0xffff, reflected: false, xor_out: 0 },
+    },
+    // CRC-24/BLE, with the initial value used on the advertising
+    // channels, check value 0xc25a56.
+    Algorithm {
+        name: "crc24-ble",
+        width: 24,
+        kind: Kind::Crc { poly: 0x00065b, init: 0x555555, reflected: true, xor_out: 0 },
+    },
+    // CRC-32/ISO-HDLC, check value 0xcbf43926.
+    Algorithm {
+        name: "crc32",
+        width: 32,
+        kind: Kind::Crc {
+            poly: 0x04c11db7,
+            init: 0xffffffff,
+            reflected: true,
+            xor_out: 0xffffffff,
+        },
+    },
+    // 8-bit sum of the bytes, check value 0xdd.
+    Algorithm { name: "sum8", width: 8, kind: Kind::Sum },
+];
+
+/// Reverse the `width` least significant bits of `value`.
+fn reflect(value: u64, width: usize) -> u64 {
+    value.reverse_bits() >> (64 - width)
+}
+
+impl Algorithm {
+    /// Compute the checksum of `bytes`.
+    pub fn compute(&self, bytes: &[u8]) -> u64 {
+        let mask = if self.width >= 64 { u64::MAX } else { (1 << self.width) - 1 };
+        match self.kind {
+            Kind::Crc { poly, init, reflected: true, xor_out } => {
+                let poly = reflect(poly, self.width);
+                let mut crc = reflect(init, self.width);
+                for byte in bytes {
+                    crc ^= *byte as u64;
+                    for _ in 0..8 {
+                        crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+                    }
+                }
+                (crc ^ xor_out) & mask
+            }
+            Kind::Crc { poly, init, reflected: false, xor_out } => {
+                let top = 1 << (self.width - 1);
+                let mut crc = init;
+                for byte in bytes {
+                    crc ^= (*byte as u64) << (self.width - 8);
+                    for _ in 0..8 {
+                        crc = if crc & top != 0 { (crc << 1) ^ poly } else { crc << 1 };
+                    }
+                    crc &= mask;
+                }
+                (crc ^ xor_out) & mask
+            }
+            Kind::Sum => bytes.iter().fold(0, |sum, byte| sum + *byte as u64) & mask,
+        }
+    }
+}
+
+/// Return the algorithm named `name`, or `None` if the name does not
+/// reference an algorithm of the library.
+pub fn lookup(name: &str) -> Option<&'static Algorithm> {
+    ALGORITHMS.iter().find(|algorithm| algorithm.name == name)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checksum::*;
+
+    #[test]
+    fn test_check_values() {
+        let check = |name: &str| lookup(name).unwrap().compute(b"123456789");
+        assert_eq!(check("crc8"), 0xf4);
+        assert_eq!(check("crc16-ccitt"), 0x29b1);
+        assert_eq!(check("crc24-ble"), 0xc25a56);
+        assert_eq!(check("crc32"), 0xcbf43926);
+        assert_eq!(check("sum8"), 0xdd);
+        assert!(lookup("l2cap/").is_none());
+    }
+}
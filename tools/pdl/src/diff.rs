@@ -0,0 +1,535 @@
+//! Backward compatibility checker between two versions of a grammar.
+//!
+//! Changes altering the wire format of existing declarations are
+//! reported as errors. Compatible changes, such as new enum tags or new
+//! child packets, are reported as notes.
+
+use codespan_reporting::diagnostic::Diagnostic;
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::lint::LintDiagnostics;
+
+/// Kinds of changes between two versions of a grammar. The change
+/// code is reported with each diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    EndiannessChanged,
+    DeclarationRemoved,
+    DeclarationAdded,
+    DeclarationKindChanged,
+    ChecksumChanged,
+    CustomFieldChanged,
+    EnumWidthChanged,
+    TagRemoved,
+    TagValueChanged,
+    TagAdded,
+    ParentChanged,
+    ConstraintsChanged,
+    FieldRemoved,
+    FieldAdded,
+    FieldReordered,
+    FieldChanged,
+}
+
+impl Change {
+    /// Return the stable code identifying the change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Change::EndiannessChanged => "endianness-changed",
+            Change::DeclarationRemoved => "declaration-removed",
+            Change::DeclarationAdded => "declaration-added",
+            Change::DeclarationKindChanged => "declaration-kind-changed",
+            Change::ChecksumChanged => "checksum-changed",
+            Change::CustomFieldChanged => "custom-field-changed",
+            Change::EnumWidthChanged => "enum-width-changed",
+            Change::TagRemoved => "tag-removed",
+            Change::TagValueChanged => "tag-value-changed",
+            Change::TagAdded => "tag-added",
+            Change::ParentChanged => "parent-changed",
+            Change::ConstraintsChanged => "constraints-changed",
+            Change::FieldRemoved => "field-removed",
+            Change::FieldAdded => "field-added",
+            Change::FieldReordered => "field-reordered",
+            Change::FieldChanged => "field-changed",
+        }
+    }
+}
+
+impl From<Change> for String {
+    fn from(change: Change) -> Self {
+        change.code().to_owned()
+    }
+}
+
+/// Return the key identifying a field within its declaration.
+/// Unnamed fields are identified by their kind and rank.
+fn field_keys(fields: &[Field]) -> Vec<String> {
+    let mut ranks: HashMap<&str, usize> = HashMap::new();
+    fields
+        .iter()
+        .map(|field| match field {
+            Field::Checksum { field_id, .. } => format!("_checksum_start_({})", field_id),
+            Field::Size { field_id, .. } => format!("_size_({})", field_id),
+            Field::Count { field_id, .. } => format!("_count_({})", field_id),
+            Field::Body { .. } => "_body_".to_owned(),
+            Field::Payload { .. } => "_payload_".to_owned(),
+            Field::Array { id, .. } | Field::Scalar { id, .. } | Field::Typedef { id, .. } => {
+                id.clone()
+            }
+            Field::Group { group_id, .. } => group_id.clone(),
+            Field::Padding { .. } | Field::Fixed { .. } | Field::Reserved { .. } => {
+                let kind = match field {
+                    Field::Padding { .. } => "_padding_",
+                    Field::Fixed { .. } => "_fixed_",
+                    _ => "_reserved_",
+                };
+                let rank = ranks.entry(kind).or_default();
+                *rank += 1;
+                format!("{}#{}", kind, rank)
+            }
+        })
+        .collect()
+}
+
+/// Return a description of the layout of a field, excluding its name.
+fn field_layout(field: &Field) -> String {
+    let layout = match field {
+        Field::Checksum { .. } | Field::Body { .. } => String::new(),
+        Field::Padding { width, .. }
+        | Field::Size { width, .. }
+        | Field::Count { width, .. }
+        | Field::Reserved { width, .. }
+        | Field::Scalar { width, .. } => width.to_string(),
+        Field::Payload { size_modifier, .. } => size_modifier.clone().unwrap_or_default(),
+        Field::Fixed { value: Some(value), width: Some(width), .. } => {
+            format!("{:#x} : {}", value, width)
+        }
+        Field::Fixed { tag_id: Some(tag_id), enum_id: Some(enum_id), .. } => {
+            format!("{} : {}", tag_id, enum_id)
+        }
+        Field::Fixed { .. } => unreachable!(),
+        Field::Array { width, type_id, size_modifier, size, .. } => format!(
+            "{}[{}]",
+            width.map_or_else(|| type_id.clone().unwrap_or_default(), |w| w.to_string()),
+            size.map_or_else(|| size_modifier.clone().unwrap_or_default(), |s| s.to_string())
+        ),
+        Field::Typedef { type_id, .. } => type_id.clone(),
+        Field::Group { constraints, .. } => constraints_layout(constraints.iter()),
+    };
+    match field.cond() {
+        Some(cond) => format!("{} if {}", layout, constraints_layout(std::iter::once(cond))),
+        None => layout,
+    }
+}
+
+fn expr_layout(expr: &Expr) -> String {
+    // Constant expressions are compared by value.
+    match expr {
+        Expr::Identifier { name, .. } => name.clone(),
+        expr => expr.evaluate().map_or_else(|_| "?".to_owned(), |value| value.to_string()),
+    }
+}
+
+fn constraints_layout<'a>(constraints: impl Iterator<Item = &'a Constraint>) -> String {
+    let mut constraints =
+        constraints.map(|c| format!("{} = {}", c.id, expr_layout(&c.value))).collect::<Vec<_>>();
+    constraints.sort();
+    constraints.join(", ")
+}
+
+impl LintDiagnostics {
+    /// Report a change breaking the wire format.
+    fn err_breaking(
+        &mut self,
+        change: Change,
+        message: String,
+        new: &SourceRange,
+        old: &SourceRange,
+    ) {
+        self.push(
+            Diagnostic::error().with_code(change).with_message(message).with_labels(vec![
+                new.primary(),
+                old.secondary().with_message("previous declaration"),
+            ]),
+        )
+    }
+
+    /// Report a compatible change.
+    fn note_compatible(&mut self, change: Change, message: String, new: &SourceRange) {
+        self.push(
+            Diagnostic::note()
+                .with_code(change)
+                .with_message(message)
+                .with_labels(vec![new.primary()]),
+        )
+    }
+}
+
+/// Return a description of the values matched by an enum tag.
+fn tag_layout(tag: &Tag) -> String {
+    match tag {
+        Tag::Value { value, .. } => format!("{:#x}", value),
+        Tag::Range { start, end, .. } => format!("{:#x}..{:#x}", start, end),
+        Tag::Default { .. } => "..".to_owned(),
+    }
+}
+
+fn diff_enum(
+    id: &str,
+    (old_tags, old_width): (&[Tag], usize),
+    (new_tags, new_width): (&[Tag], usize),
+    (old_loc, new_loc): (&SourceRange, &SourceRange),
+    result: &mut LintDiagnostics,
+) {
+    if old_width != new_width {
+        result.err_breaking(
+            Change::EnumWidthChanged,
+            format!("width of enum `{}` changed from {} to {}", id, old_width, new_width),
+            new_loc,
+            old_loc,
+        );
+    }
+    for old_tag in old_tags {
+        match new_tags.iter().find(|t| t.id() == old_tag.id()) {
+            None => result.err_breaking(
+                Change::TagRemoved,
+                format!("tag `{}` removed from enum `{}`", old_tag.id(), id),
+                new_loc,
+                old_tag.loc(),
+            ),
+            Some(new_tag) if tag_layout(new_tag) != tag_layout(old_tag) => result.err_breaking(
+                Change::TagValueChanged,
+                format!(
+                    "value of tag `{}::{}` changed from {} to {}",
+                    id,
+                    old_tag.id(),
+                    tag_layout(old_tag),
+                    tag_layout(new_tag)
+                ),
+                new_tag.loc(),
+                old_tag.loc(),
+            ),
+            Some(_) => (),
+        }
+    }
+    for new_tag in new_tags.iter().filter(|t| !old_tags.iter().any(|o| o.id() == t.id())) {
+        result.note_compatible(
+            Change::TagAdded,
+            format!("new tag `{}` in enum `{}`", new_tag.id(), id),
+            new_tag.loc(),
+        )
+    }
+}
+
+fn diff_fields(id: &str, old: &Decl, new: &Decl, result: &mut LintDiagnostics) {
+    let old_fields = old.fields().cloned().collect::<Vec<_>>();
+    let new_fields = new.fields().cloned().collect::<Vec<_>>();
+    let old_keys = field_keys(&old_fields);
+    let new_keys = field_keys(&new_fields);
+
+    for (key, field) in old_keys.iter().zip(old_fields.iter()) {
+        if !new_keys.contains(key) {
+            result.err_breaking(
+                Change::FieldRemoved,
+                format!("field `{}` removed from `{}`", key, id),
+                new.loc(),
+                field.loc(),
+            );
+        }
+    }
+    for (key, field) in new_keys.iter().zip(new_fields.iter()) {
+        if !old_keys.contains(key) {
+            result.err_breaking(
+                Change::FieldAdded,
+                format!("field `{}` added to `{}`", key, id),
+                field.loc(),
+                old.loc(),
+            );
+        }
+    }
+
+    // Compare the relative order of the fields present in both
+    // versions, and report the first field changing position.
+    let common = |keys: &[String], other: &[String]| {
+        keys.iter().filter(|k| other.contains(k)).cloned().collect::<Vec<_>>()
+    };
+    let old_order = common(&old_keys, &new_keys);
+    let new_order = common(&new_keys, &old_keys);
+    for (old_key, new_key) in old_order.iter().zip(new_order.iter()) {
+        if old_key != new_key {
+            let old_field = &old_fields[old_keys.iter().position(|k| k == old_key).unwrap()];
+            let new_field = &new_fields[new_keys.iter().position(|k| k == old_key).unwrap()];
+            result.err_breaking(
+                Change::FieldReordered,
+                format!("field `{}` reordered in `{}`", old_key, id),
+                new_field.loc(),
+                old_field.loc(),
+            );
+            break;
+        }
+    }
+
+    for (new_key, new_field) in new_keys.iter().zip(new_fields.iter()) {
+        let old_field = match old_keys.iter().position(|k| k == new_key) {
+            Some(index) => &old_fields[index],
+            None => continue,
+        };
+        let (old_layout, new_layout) = (field_layout(old_field), field_layout(new_field));
+        if old_layout == new_layout
+            && std::mem::discriminant(old_field) == std::mem::discriminant(new_field)
+        {
+            continue;
+        }
+        let change = match new_field {
+            Field::Padding { .. } => "size",
+            Field::Fixed { .. } => "fixed value",
+            Field::Payload { .. } => "size modifier",
+            Field::Group { .. } => "constraints",
+            Field::Array { .. } | Field::Typedef { .. } => "type",
+            _ => "width",
+        };
+        result.err_breaking(
+            Change::FieldChanged,
+            format!(
+                "{} of field `{}` in `{}` changed from `{}` to `{}`",
+                change, new_key, id, old_layout, new_layout
+            ),
+            new_field.loc(),
+            old_field.loc(),
+        );
+    }
+}
+
+fn diff_decl(old: &Decl, new: &Decl, result: &mut LintDiagnostics) {
+    let id = new.id().unwrap();
+    if std::mem::discriminant(old) != std::mem::discriminant(new) {
+        result.err_breaking(
+            Change::DeclarationKindChanged,
+            format!("declaration `{}` changed from {} to {}", id, old.kind(), new.kind()),
+            new.loc(),
+            old.loc(),
+        );
+        return;
+    }
+    match (old, new) {
+        (
+            Decl::Checksum { width: old_width, function: old_function, .. },
+            Decl::Checksum { width: new_width, function: new_function, .. },
+        ) if old_width != new_width || old_function != new_function => result.err_breaking(
+            Change::ChecksumChanged,
+            format!("checksum `{}` changed", id),
+            new.loc(),
+            old.loc(),
+        ),
+        (
+            Decl::CustomField { width: old_width, function: old_function, .. },
+            Decl::CustomField { width: new_width, function: new_function, .. },
+        ) if old_width != new_width || old_function != new_function => result.err_breaking(
+            Change::CustomFieldChanged,
+            format!("custom field `{}` changed", id),
+            new.loc(),
+            old.loc(),
+        ),
+        (
+            Decl::Enum { tags: old_tags, width: old_width, .. },
+            Decl::Enum { tags: new_tags, width: new_width, .. },
+        ) => diff_enum(
+            id,
+            (old_tags, *old_width),
+            (new_tags, *new_width),
+            (old.loc(), new.loc()),
+            result,
+        ),
+        (Decl::Packet { .. }, Decl::Packet { .. })
+        | (Decl::Struct { .. }, Decl::Struct { .. })
+        | (Decl::Group { .. }, Decl::Group { .. }) => {
+            if old.parent_id() != new.parent_id() {
+                result.err_breaking(
+                    Change::ParentChanged,
+                    format!(
+                        "parent of `{}` changed from `{}` to `{}`",
+                        id,
+                        old.parent_id().map_or("", |p| p.as_str()),
+                        new.parent_id().map_or("", |p| p.as_str())
+                    ),
+                    new.loc(),
+                    old.loc(),
+                );
+            }
+            let old_constraints = constraints_layout(old.constraints());
+            let new_constraints = constraints_layout(new.constraints());
+            if old_constraints != new_constraints {
+                result.err_breaking(
+                    Change::ConstraintsChanged,
+                    format!(
+                        "constraints of `{}` changed from `({})` to `({})`",
+                        id, old_constraints, new_constraints
+                    ),
+                    new.loc(),
+                    old.loc(),
+                );
+            }
+            diff_fields(id, old, new, result)
+        }
+        _ => (),
+    }
+}
+
+/// Compare two versions of a grammar and report the changes
+/// affecting the wire format of the declarations.
+pub fn diff(old: &Grammar, new: &Grammar) -> LintDiagnostics {
+    let mut result = LintDiagnostics::new();
+    let old_decls = old
+        .declarations
+        .iter()
+        .filter_map(|d| d.id().map(|id| (id.as_str(), d)))
+        .collect::<HashMap<_, _>>();
+    let new_decls = new
+        .declarations
+        .iter()
+        .filter_map(|d| d.id().map(|id| (id.as_str(), d)))
+        .collect::<HashMap<_, _>>();
+
+    match (&old.endianness, &new.endianness) {
+        (Some(old_endianness), Some(new_endianness))
+            if old_endianness.value != new_endianness.value =>
+        {
+            result.err_breaking(
+                Change::EndiannessChanged,
+                "endianness changed".to_owned(),
+                &new_endianness.loc,
+                &old_endianness.loc,
+            )
+        }
+        _ => (),
+    }
+
+    for old_decl in old.declarations.iter() {
+        let id = match old_decl.id() {
+            Some(id) => id,
+            None => continue,
+        };
+        match new_decls.get(id.as_str()) {
+            Some(new_decl) => diff_decl(old_decl, new_decl, &mut result),
+            None => result.push(
+                Diagnostic::error()
+                    .with_code(Change::DeclarationRemoved)
+                    .with_message(format!("{} `{}` removed", old_decl.kind(), id))
+                    .with_labels(vec![old_decl.loc().primary()]),
+            ),
+        }
+    }
+
+    for new_decl in new.declarations.iter() {
+        let id = match new_decl.id() {
+            Some(id) if !old_decls.contains_key(id.as_str()) => id,
+            _ => continue,
+        };
+        let message = match new_decl.parent_id() {
+            Some(parent_id) if old_decls.contains_key(parent_id.as_str()) => {
+                format!("new child {} `{}` of `{}`", new_decl.kind(), id, parent_id)
+            }
+            _ => format!("new {} `{}`", new_decl.kind(), id),
+        };
+        result.note_compatible(Change::DeclarationAdded, message, new_decl.loc());
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::*;
+    use crate::diff::diff;
+    use crate::parser::parse_inline;
+
+    fn diff_inline(old: &str, new: &str) -> Vec<(bool, String)> {
+        let mut db = SourceDatabase::new();
+        let old = parse_inline(&mut db, "old".to_owned(), old.to_owned()).expect("parsing failure");
+        let new = parse_inline(&mut db, "new".to_owned(), new.to_owned()).expect("parsing failure");
+        diff(&old, &new)
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.severity == codespan_reporting::diagnostic::Severity::Error, d.message))
+            .collect()
+    }
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        enum OpCode : 8 { RESET = 1, READ = 2 }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            _fixed_ = 0x42: 8,
+        }
+    "#;
+
+    #[test]
+    fn test_diff_compatible() {
+        assert_eq!(diff_inline(GRAMMAR, GRAMMAR), vec![]);
+        assert_eq!(
+            diff_inline(
+                GRAMMAR,
+                &GRAMMAR.replace("READ = 2 }", "READ = 2, WRITE = 3 }").replace(
+                    "packet Reset",
+                    "packet Write : Command (op_code = WRITE) { value: 8 } packet Reset"
+                )
+            ),
+            vec![
+                (false, "new tag `WRITE` in enum `OpCode`".to_owned()),
+                (false, "new child packet `Write` of `Command`".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_codes() {
+        let mut db = SourceDatabase::new();
+        let old =
+            parse_inline(&mut db, "old".to_owned(), GRAMMAR.to_owned()).expect("parsing failure");
+        let new = GRAMMAR
+            .replace("READ = 2 }", "READ = 2, WRITE = 3 }")
+            .replace("flag: 1,\n            _reserved_: 7", "_reserved_: 7, flag: 1");
+        let new = parse_inline(&mut db, "new".to_owned(), new).expect("parsing failure");
+        let codes = diff(&old, &new).diagnostics.into_iter().map(|d| d.code).collect::<Vec<_>>();
+        assert_eq!(codes, vec![Some("tag-added".to_owned()), Some("field-reordered".to_owned())]);
+    }
+
+    #[test]
+    fn test_diff_breaking() {
+        let breaking = |new: String, message: &str| {
+            let result = diff_inline(GRAMMAR, &new);
+            assert!(result.contains(&(true, message.to_owned())), "{:?}", result);
+            assert!(result.iter().all(|(error, _)| *error), "{:?}", result);
+        };
+        breaking(
+            GRAMMAR.replace("flag: 1,\n            _reserved_: 7", "_reserved_: 7, flag: 1"),
+            "field `flag` reordered in `Command`",
+        );
+        breaking(
+            GRAMMAR.replace("flag: 1,\n            _reserved_: 7", "flag: 2, _reserved_: 6"),
+            "width of field `flag` in `Command` changed from `1` to `2`",
+        );
+        breaking(
+            GRAMMAR.replace("READ = 2", "READ = 3"),
+            "value of tag `OpCode::READ` changed from 0x2 to 0x3",
+        );
+        breaking(
+            GRAMMAR.replace("READ = 2", "READ = 2..4"),
+            "value of tag `OpCode::READ` changed from 0x2 to 0x2..0x4",
+        );
+        breaking(GRAMMAR.replace(", READ = 2", ""), "tag `READ` removed from enum `OpCode`");
+        breaking(
+            GRAMMAR.replace("op_code = RESET", "op_code = READ"),
+            "constraints of `Reset` changed from `(op_code = RESET)` to `(op_code = READ)`",
+        );
+        breaking(
+            GRAMMAR.replace("0x42", "0x43"),
+            "fixed value of field `_fixed_#1` in `Reset` changed from `0x42 : 8` to `0x43 : 8`",
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Canonical formatter for PDL grammars.
+//!
+//! The formatter prints a grammar back to source text, preserving
+//! the comments recorded by the parser. Comments are attached to the
+//! element that follows them, or to the element that ends on the same
+//! line. Blank lines separating declarations are normalized, and
+//! blank lines separating fields or tags are preserved but collapsed.
+
+use codespan_reporting::files::Files;
+
+use crate::ast::*;
+
+const INDENT: &str = "  ";
+
+struct Formatter<'a> {
+    /// Source text of the grammar, used to preserve the spelling of
+    /// integer literals.
+    source: Option<&'a str>,
+    /// Comments not yet printed, sorted by source position.
+    comments: std::iter::Peekable<std::vec::IntoIter<&'a Comment>>,
+    output: String,
+    /// Source line where the last printed element ends, or `None`
+    /// after the end of a top-level element.
+    last_line: Option<usize>,
+    /// Ignore blank lines between the next element and the previous
+    /// one, at the start and end of declaration bodies.
+    collapse_gap: bool,
+    /// Set if the last printed element is a top-level declaration.
+    after_decl: bool,
+}
+
+/// Return the literal spelling `text` if it represents `value`,
+/// the decimal representation of `value` otherwise.
+fn literal(text: Option<&str>, value: usize) -> String {
+    let text = text.map(str::trim);
+    let parsed = text.and_then(|text| match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    });
+    match (text, parsed) {
+        (Some(text), Some(parsed)) if parsed == value => text.to_owned(),
+        _ => value.to_string(),
+    }
+}
+
+fn format_expr(expr: &Expr, formatter: &Formatter) -> String {
+    // Operands are parenthesized when the parsed operator precedence
+    // would not rebuild the same expression tree.
+    let operand = |expr: &Expr, precedence: usize| match expr {
+        Expr::Binary { op, .. } if binary_precedence(op) < precedence => {
+            format!("({})", format_expr(expr, formatter))
+        }
+        _ => format_expr(expr, formatter),
+    };
+    match expr {
+        Expr::Identifier { name, .. } => name.clone(),
+        Expr::Integer { loc, value } => literal(formatter.text(loc), *value),
+        Expr::Unary { op, operand: expr, .. } => format!("{}{}", op, operand(expr, usize::MAX)),
+        Expr::Binary { op, operands, .. } => format!(
+            "{} {} {}",
+            operand(&operands.0, binary_precedence(op)),
+            op,
+            operand(&operands.1, binary_precedence(op) + 1)
+        ),
+    }
+}
+
+fn format_constraints(constraints: &[Constraint], formatter: &Formatter) -> String {
+    constraints
+        .iter()
+        .map(|c| format!("{} = {}", c.id, format_expr(&c.value, formatter)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_field(field: &Field, formatter: &Formatter) -> String {
+    match field {
+        Field::Checksum { field_id, .. } => format!("_checksum_start_({})", field_id),
+        Field::Padding { width, .. } => format!("_padding_[{}]", width),
+        Field::Size { field_id, width, .. } => format!("_size_({}) : {}", field_id, width),
+        Field::Count { field_id, width, .. } => format!("_count_({}) : {}", field_id, width),
+        Field::Body { .. } => "_body_".to_owned(),
+        Field::Payload { size_modifier: None, .. } => "_payload_".to_owned(),
+        Field::Payload { size_modifier: Some(size_modifier), .. } => {
+            format!("_payload_ : [{}]", size_modifier)
+        }
+        Field::Fixed { loc, value: Some(value), width: Some(width), .. } => {
+            // The value literal is enclosed by `=` and `:`.
+            let text = formatter
+                .text(loc)
+                .and_then(|text| text.split_once('='))
+                .and_then(|(_, text)| text.split_once(':'))
+                .map(|(text, _)| text);
+            format!("_fixed_ = {} : {}", literal(text, *value), width)
+        }
+        Field::Fixed { tag_id: Some(tag_id), enum_id: Some(enum_id), .. } => {
+            format!("_fixed_ = {} : {}", tag_id, enum_id)
+        }
+        Field::Fixed { .. } => unreachable!(),
+        Field::Reserved { width, .. } => format!("_reserved_ : {}", width),
+        Field::Array { id, width, type_id, size_modifier, size, .. } => format!(
+            "{} : {}[{}]",
+            id,
+            width.map_or_else(|| type_id.clone().unwrap(), |w| w.to_string()),
+            size.map_or_else(|| size_modifier.clone().unwrap_or_default(), |s| s.to_string())
+        ),
+        Field::Scalar { id, width, cond: None, .. } => format!("{} : {}", id, width),
+        Field::Typedef { id, type_id, cond: None, .. } => format!("{} : {}", id, type_id),
+        Field::Scalar { id, width, cond: Some(cond), .. } => {
+            format!(
+                "{} : {} if {}",
+                id,
+                width,
+                format_constraints(std::slice::from_ref(cond), formatter)
+            )
+        }
+        Field::Typedef { id, type_id, cond: Some(cond), .. } => {
+            format!(
+                "{} : {} if {}",
+                id,
+                type_id,
+                format_constraints(std::slice::from_ref(cond), formatter)
+            )
+        }
+        Field::Group { group_id, constraints, .. } if constraints.is_empty() => group_id.clone(),
+        Field::Group { group_id, constraints, .. } => {
+            format!("{} {{ {} }}", group_id, format_constraints(constraints, formatter))
+        }
+    }
+}
+
+fn format_test_case(test_case: &TestCase, formatter: &Formatter) -> String {
+    let mut text = test_case.input.clone();
+    if let Some(packet_id) = &test_case.packet_id {
+        text.push_str(&format!(" : {}", packet_id));
+    }
+    if !test_case.constraints.is_empty() {
+        text.push_str(&format!(" ({})", format_constraints(&test_case.constraints, formatter)));
+    }
+    text
+}
+
+fn format_header(
+    keyword: &str,
+    id: &str,
+    parent_id: &Option<String>,
+    constraints: &[Constraint],
+    formatter: &Formatter,
+) -> String {
+    let mut header = format!("{} {}", keyword, id);
+    if let Some(parent_id) = parent_id {
+        header.push_str(&format!(" : {}", parent_id));
+    }
+    if !constraints.is_empty() {
+        header.push_str(&format!(" ({})", format_constraints(constraints, formatter)));
+    }
+    header.push_str(" {");
+    header
+}
+
+impl<'a> Formatter<'a> {
+    /// Return the source text covered by a source range.
+    fn text(&self, loc: &SourceRange) -> Option<&'a str> {
+        self.source.and_then(|source| source.get(loc.start.offset..loc.end.offset))
+    }
+
+    /// Terminate the current line and start a new one for an element
+    /// located at `loc`. A blank line is inserted if `blank_line` is
+    /// set, or if the element was separated by a blank line in the
+    /// source.
+    fn start_line(&mut self, loc: &SourceRange, indent: &str, blank_line: bool) {
+        if !self.output.is_empty() {
+            self.output.push('\n');
+            let gap =
+                !self.collapse_gap && self.last_line.is_some_and(|line| loc.start.line > line + 1);
+            if blank_line || gap {
+                self.output.push('\n');
+            }
+        }
+        self.collapse_gap = false;
+        self.output.push_str(indent);
+    }
+
+    /// Print the comments located before the source offset `offset`.
+    /// A comment starting on the line where the last element ends is
+    /// printed at the end of this line.
+    fn print_comments(&mut self, offset: usize, indent: &str) {
+        while let Some(comment) = self.comments.next_if(|c| c.loc.start.offset < offset) {
+            if self.last_line == Some(comment.loc.start.line) {
+                self.output.push(' ');
+            } else {
+                let blank_line = indent.is_empty() && self.last_line.is_none();
+                self.start_line(&comment.loc, indent, blank_line);
+                self.after_decl = false;
+            }
+            self.output.push_str(&comment.text);
+            self.last_line = Some(comment.loc.end.line);
+        }
+    }
+
+    /// Print the first line of a top-level element, preceded by its
+    /// comments. Declarations with a body are separated from the
+    /// previous declaration by a blank line.
+    fn print_decl_start(&mut self, loc: &SourceRange, text: &str) {
+        self.print_comments(loc.start.offset, "");
+        let blank_line = self.last_line.is_none() || (self.after_decl && text.ends_with('{'));
+        self.start_line(loc, "", blank_line);
+        self.output.push_str(text);
+        self.last_line = Some(loc.start.line);
+        self.collapse_gap = true;
+    }
+
+    /// Terminate a top-level element. Comments starting on the
+    /// last line of the element are kept on this line.
+    fn print_decl_end(&mut self, loc: &SourceRange) {
+        let has_body = self.output.ends_with('}');
+        self.print_comments(loc.end.offset, INDENT);
+        self.last_line = Some(loc.end.line);
+        while let Some(comment) =
+            self.comments.next_if(|c| Some(c.loc.start.line) == self.last_line)
+        {
+            self.output.push(' ');
+            self.output.push_str(&comment.text);
+            self.last_line = Some(comment.loc.end.line);
+        }
+        // Declarations with a body are always separated from the next
+        // element by a blank line.
+        if has_body {
+            self.last_line = None;
+        }
+        self.collapse_gap = false;
+        self.after_decl = true;
+    }
+
+    /// Print the elements of a declaration body, each preceded by
+    /// its comments, and the closing brace.
+    fn print_body<T>(
+        &mut self,
+        decl_loc: &SourceRange,
+        items: &[T],
+        format: impl Fn(&T, &Self) -> (SourceRange, String),
+    ) {
+        for item in items {
+            let (loc, text) = format(item, self);
+            self.print_comments(loc.start.offset, INDENT);
+            self.start_line(&loc, INDENT, false);
+            self.output.push_str(&text);
+            self.output.push(',');
+            self.last_line = Some(loc.end.line);
+        }
+        let end = SourceRange { start: decl_loc.end, ..decl_loc.clone() };
+        self.print_comments(decl_loc.end.offset.saturating_sub(1), INDENT);
+        self.collapse_gap = true;
+        self.start_line(&end, "", false);
+        self.output.push('}');
+    }
+
+    fn print_decl(&mut self, decl: &Decl) {
+        let field = |f: &Field, formatter: &Self| (f.loc().clone(), format_field(f, formatter));
+        match decl {
+            Decl::Checksum { id, loc, function, width } => {
+                self.print_decl_start(loc, &format!("checksum {} : {} {}", id, width, function))
+            }
+            Decl::CustomField { id, loc, width: Some(width), function } => {
+                self.print_decl_start(loc, &format!("custom_field {} : {} {}", id, width, function))
+            }
+            Decl::CustomField { id, loc, width: None, function } => {
+                self.print_decl_start(loc, &format!("custom_field {} {}", id, function))
+            }
+            Decl::Enum { id, loc, tags, width } => {
+                self.print_decl_start(loc, &format!("enum {} : {} {{", id, width));
+                self.print_body(loc, tags, |tag, formatter| {
+                    let text = formatter
+                        .text(tag.loc())
+                        .and_then(|text| text.split_once('='))
+                        .map(|(_, text)| text);
+                    let value = match tag {
+                        Tag::Value { value, .. } => literal(text, *value),
+                        Tag::Range { start, end, .. } => {
+                            let text = text.and_then(|text| text.split_once(".."));
+                            format!(
+                                "{}..{}",
+                                literal(text.map(|(start, _)| start), *start),
+                                literal(text.map(|(_, end)| end), *end)
+                            )
+                        }
+                        Tag::Default { .. } => "..".to_owned(),
+                    };
+                    (tag.loc().clone(), format!("{} = {}", tag.id(), value))
+                });
+            }
+            Decl::Packet { id, loc, constraints, fields, parent_id } => {
+                let header = format_header("packet", id, parent_id, constraints, self);
+                self.print_decl_start(loc, &header);
+                self.print_body(loc, fields, field);
+            }
+            Decl::Struct { id, loc, constraints, fields, parent_id } => {
+                let header = format_header("struct", id, parent_id, constraints, self);
+                self.print_decl_start(loc, &header);
+                self.print_body(loc, fields, field);
+            }
+            Decl::Group { id, loc, fields } => {
+                self.print_decl_start(loc, &format!("group {} {{", id));
+                self.print_body(loc, fields, field);
+            }
+            Decl::Test { loc, type_id, test_cases } => {
+                self.print_decl_start(loc, &format!("test {} {{", type_id));
+                self.print_body(loc, test_cases, |t, f| (t.loc.clone(), format_test_case(t, f)));
+            }
+        }
+        self.print_decl_end(decl.loc());
+    }
+}
+
+/// Format a grammar to canonical PDL source text.
+pub fn format(sources: &SourceDatabase, grammar: &Grammar) -> String {
+    let mut comments = grammar.comments.iter().collect::<Vec<_>>();
+    comments.sort_by_key(|c| c.loc.start.offset);
+    let mut formatter = Formatter {
+        source: sources.source(grammar.file).ok(),
+        comments: comments.into_iter().peekable(),
+        output: String::new(),
+        last_line: None,
+        collapse_gap: false,
+        after_decl: false,
+    };
+    if let Some(endianness) = &grammar.endianness {
+        let text = match endianness.value {
+            EndiannessValue::LittleEndian => "little_endian_packets",
+            EndiannessValue::BigEndian => "big_endian_packets",
+        };
+        formatter.print_decl_start(&endianness.loc, text);
+        formatter.print_decl_end(&endianness.loc);
+    }
+    for import in &grammar.imports {
+        formatter.print_decl_start(&import.loc, &format!("import \"{}\"", import.path));
+        formatter.print_decl_end(&import.loc);
+    }
+    // Declarations of imported files are not printed.
+    for decl in grammar.declarations.iter().filter(|d| d.loc().file == grammar.file) {
+        formatter.print_decl(decl);
+    }
+    formatter.print_comments(usize::MAX, "");
+    formatter.output.push('\n');
+    formatter.output
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formatter::*;
+    use crate::parser::parse_inline;
+
+    fn format_inline(text: &str) -> String {
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "stdin".to_owned(), text.to_owned()).expect("parsing failure");
+        format(&db, &grammar)
+    }
+
+    /// Remove the source locations from the JSON representation
+    /// of a grammar.
+    fn strip_locations(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.remove("loc");
+                map.values_mut().for_each(strip_locations);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(strip_locations),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format_inline(
+                r#"
+                // Leading comment.
+                little_endian_packets
+                import "common.pdl"
+                custom_field Address: 48 "hci/"
+                enum Enum: 8 { A = 0x1, B = 2, C = 0x10..0x1f, D=.. }
+                packet Packet : Parent(a=A) { // Packet comment.
+
+                    x: Enum,   // Trailing comment.
+                    /* Field comment. */ y: 8[+2],
+
+
+                    _fixed_ = 0x42: 8,
+                }
+                packet Empty {}
+                // Final comment.
+                "#
+            ),
+            r#"// Leading comment.
+little_endian_packets
+import "common.pdl"
+custom_field Address : 48 "hci/"
+
+enum Enum : 8 {
+  A = 0x1,
+  B = 2,
+  C = 0x10..0x1f,
+  D = ..,
+}
+
+packet Packet : Parent (a = A) { // Packet comment.
+  x : Enum, // Trailing comment.
+  /* Field comment. */
+  y : 8[+2],
+
+  _fixed_ = 0x42 : 8,
+}
+
+packet Empty {
+}
+
+// Final comment.
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let mut paths = std::fs::read_dir("test")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "pdl"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let mut db = SourceDatabase::new();
+            let name = path.display().to_string();
+            let grammar =
+                crate::parser::parse_file(&mut db, name.clone()).expect("parsing failure");
+            let formatted = format(&db, &grammar);
+            let reparsed = parse_inline(&mut db, name.clone(), formatted.clone())
+                .unwrap_or_else(|_| panic!("failed to parse formatted {}", name));
+
+            let mut expected = serde_json::to_value(&grammar).unwrap();
+            let mut actual = serde_json::to_value(&reparsed).unwrap();
+            strip_locations(&mut expected);
+            strip_locations(&mut actual);
+            expected["file"] = actual["file"].clone();
+            assert_eq!(actual, expected, "round-trip failure for {}", name);
+            assert_eq!(format(&db, &reparsed), formatted, "non-idempotent format for {}", name);
+        }
+    }
+
+    #[test]
+    fn test_format_test_cases() {
+        assert_eq!(
+            format_inline(
+                r#"
+                little_endian_packets
+                test Command { "\x01",
+                  "\x01\x02":Reset(delay=0x2), "\x02"(op_code=READ) }
+                "#
+            ),
+            r#"little_endian_packets
+
+test Command {
+  "\x01",
+  "\x01\x02" : Reset (delay = 0x2),
+  "\x02" (op_code = READ),
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_expressions() {
+        assert_eq!(
+            format_inline(
+                r#"
+                little_endian_packets
+                packet A { a: 8, b: 16 if a=1 }
+                packet B : A (a = (1+2)*3, b = 1 + (2*3)) {}
+                packet C : A (a = 1 - (2 - 3), b = ~(1 | 2)) {}
+                "#
+            ),
+            r#"little_endian_packets
+
+packet A {
+  a : 8,
+  b : 16 if a = 1,
+}
+
+packet B : A (a = (1 + 2) * 3, b = 1 + 2 * 3) {
+}
+
+packet C : A (a = 1 - (2 - 3), b = ~(1 | 2)) {
+}
+"#
+        );
+    }
+}
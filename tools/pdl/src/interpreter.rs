@@ -0,0 +1,99 @@
+//! Runtime interpreter for PDL grammars.
+//!
+//! The interpreter walks the declarations of a grammar to convert
+//! between raw bytes and JSON field values, without generating code.
+
+pub mod decoder;
+pub mod encoder;
+
+use crate::ast::*;
+use crate::checksum;
+
+/// Return the JSON representation of an enum value: the tag name if
+/// the value is declared by a value tag, the integer value otherwise.
+/// Values matched by range or default tags are kept as integers.
+fn enum_value(decl: &Decl, value: u64) -> serde_json::Value {
+    match decl {
+        Decl::Enum { tags, .. } => match tags.iter().find(|t| t.value() == Some(value as usize)) {
+            Some(tag) => serde_json::Value::String(tag.id().clone()),
+            None => serde_json::Value::from(value),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Return the value of the enum tag `id`, or `None` if the tag
+/// is not declared or does not have a single value.
+fn tag_value(decl: &Decl, id: &str) -> Option<u64> {
+    match decl {
+        Decl::Enum { tags, .. } => {
+            tags.iter().find(|t| t.id() == id).and_then(Tag::value).map(|v| v as u64)
+        }
+        _ => None,
+    }
+}
+
+/// Return the algorithm computing the values of a checksum
+/// declaration, or `None` if the declaration does not name an
+/// algorithm of the checksum library.
+pub fn checksum_algorithm(decl: &Decl) -> Option<&'static checksum::Algorithm> {
+    match decl {
+        Decl::Checksum { function, .. } => checksum::lookup(function.trim_matches('"')),
+        _ => None,
+    }
+}
+
+/// Return true if the JSON value matches the constraint value.
+pub fn constraint_matches(value: &serde_json::Value, constraint: &Constraint) -> bool {
+    match &constraint.value {
+        Expr::Identifier { name, .. } => value.as_str() == Some(name),
+        expr => value.as_u64().is_some_and(|value| expr.evaluate().ok() == Some(value as usize)),
+    }
+}
+
+/// Format bytes as a string of hexadecimal digits.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse a string of hexadecimal digits into bytes.
+/// Bytes may be separated by whitespace, commas or colons,
+/// and prefixed with `0x`. A single digit is read as one byte.
+pub fn from_hex(input: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    for token in input.split(|c: char| c.is_whitespace() || c == ',' || c == ':') {
+        let token = token.trim_start_matches("0x").trim_start_matches("0X");
+        if token.len() == 1 {
+            let byte = u8::from_str_radix(token, 16)
+                .map_err(|_| format!("invalid hexadecimal byte in `{}`", token))?;
+            bytes.push(byte);
+            continue;
+        }
+        if token.len() % 2 != 0 {
+            return Err(format!("odd number of hexadecimal digits in `{}`", token));
+        }
+        for i in (0..token.len()).step_by(2) {
+            let byte = token
+                .get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("invalid hexadecimal byte in `{}`", token))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::*;
+
+    #[test]
+    fn test_from_hex() {
+        assert_eq!(from_hex("0e0401"), Ok(vec![0x0e, 0x04, 0x01]));
+        assert_eq!(from_hex("0x0e, 0x04 01:ff"), Ok(vec![0x0e, 0x04, 0x01, 0xff]));
+        assert_eq!(from_hex("0x1,2"), Ok(vec![0x01, 0x02]));
+        assert_eq!(from_hex(""), Ok(vec![]));
+        assert!(from_hex("0e0").is_err());
+        assert!(from_hex("zz").is_err());
+    }
+}
@@ -0,0 +1,695 @@
+//! Decode raw bytes against a packet declaration.
+//!
+//! The decoder parses the fields of the selected packet, then follows
+//! the constraints of the child declarations to select the most
+//! specific specialization of the packet. Checksum fields are verified
+//! when the checksum declaration names an algorithm of the checksum
+//! library.
+
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::interpreter::{checksum_algorithm, constraint_matches, enum_value, to_hex};
+
+/// Cursor over the bytes being decoded.
+struct Reader<'b> {
+    bytes: &'b [u8],
+    endianness: EndiannessValue,
+}
+
+impl<'b> Reader<'b> {
+    fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read_bytes(&mut self, obj: &str, field: &str, n: usize) -> Result<&'b [u8], String> {
+        if self.bytes.len() < n {
+            return Err(format!(
+                "when parsing {}.{} needed length of {} but got {}",
+                obj,
+                field,
+                n,
+                self.bytes.len()
+            ));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_uint(&mut self, obj: &str, field: &str, n: usize) -> Result<u64, String> {
+        let bytes = self.read_bytes(obj, field, n)?;
+        let value = match self.endianness {
+            EndiannessValue::LittleEndian => {
+                bytes.iter().rev().fold(0, |value, b| (value << 8) | *b as u64)
+            }
+            EndiannessValue::BigEndian => bytes.iter().fold(0, |value, b| (value << 8) | *b as u64),
+        };
+        Ok(value)
+    }
+
+    /// Split the next `n` bytes into a separate reader.
+    fn split(&mut self, obj: &str, field: &str, n: usize) -> Result<Reader<'b>, String> {
+        let bytes = self.read_bytes(obj, field, n)?;
+        Ok(Reader { bytes, endianness: self.endianness })
+    }
+}
+
+/// Decoded Packet or Struct declaration.
+struct Node {
+    id: String,
+    fields: Map<String, Value>,
+    child: Option<Box<Node>>,
+    payload: Option<Vec<u8>>,
+}
+
+impl Node {
+    /// Return the JSON representation of a decoded packet:
+    /// the fields of each declaration of the lineage are nested
+    /// in the object of the parent declaration.
+    fn to_packet_json(&self) -> Value {
+        let mut value = json!({
+            "packet": self.id,
+            "fields": self.fields,
+        });
+        if let Some(child) = &self.child {
+            value["child"] = child.to_packet_json();
+        }
+        if let Some(payload) = &self.payload {
+            value["payload"] = Value::String(to_hex(payload));
+        }
+        value
+    }
+
+    /// Return the JSON representation of a decoded struct:
+    /// the fields of all the declarations of the lineage are merged
+    /// in the same object.
+    fn to_struct_json(&self) -> Value {
+        let mut fields = self.fields.clone();
+        let mut node = self;
+        while let Some(child) = &node.child {
+            fields.extend(child.fields.clone());
+            node = child;
+        }
+        if let Some(payload) = &node.payload {
+            fields.insert("payload".to_owned(), Value::String(to_hex(payload)));
+        }
+        Value::Object(fields)
+    }
+
+    /// Return true if the node or one of its descendants decodes
+    /// the declaration `id`.
+    fn contains(&self, id: &str) -> bool {
+        self.id == id || self.child.as_ref().is_some_and(|child| child.contains(id))
+    }
+}
+
+struct Decoder<'d> {
+    scope: Scope<'d>,
+    /// Lineage of the decoded declaration.
+    target: Vec<&'d Decl>,
+}
+
+impl<'d> Decoder<'d> {
+    /// Decode a single array element.
+    fn decode_element(
+        &self,
+        obj: &str,
+        field: &Field,
+        reader: &mut Reader,
+    ) -> Result<Value, String> {
+        match field {
+            Field::Array { id, width: Some(width), .. } => {
+                Ok(Value::from(reader.read_uint(obj, id, width / 8)?))
+            }
+            Field::Array { id, type_id: Some(type_id), .. } => {
+                self.decode_typedef(obj, id, type_id, reader)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Decode a value of enum, struct, or custom field type.
+    fn decode_typedef(
+        &self,
+        obj: &str,
+        id: &str,
+        type_id: &str,
+        reader: &mut Reader,
+    ) -> Result<Value, String> {
+        let decl = self.scope.typedef[type_id];
+        match decl {
+            Decl::Enum { width, .. } => Ok(enum_value(decl, reader.read_uint(obj, id, width / 8)?)),
+            Decl::Struct { .. } => {
+                let lineage = self.scope.get_lineage(decl);
+                let node = self.decode_decl(lineage[0], reader, &Map::new())?;
+                Ok(node.to_struct_json())
+            }
+            Decl::CustomField { width: Some(width), .. } if *width <= 64 => {
+                Ok(Value::from(reader.read_uint(obj, id, width / 8)?))
+            }
+            Decl::CustomField { width: Some(width), .. } => {
+                Ok(Value::String(to_hex(reader.read_bytes(obj, id, width / 8)?)))
+            }
+            _ => Err(format!("{}.{}: cannot decode values of type `{}`", obj, id, type_id)),
+        }
+    }
+
+    /// Return whether the declaration `decl` is selected by the
+    /// field values of its ancestors, or `None` if the values do not
+    /// discriminate the declaration.
+    fn condition(&self, decl: &Decl, values: &Map<String, Value>) -> Option<bool> {
+        let constraints = decl.constraints().collect::<Vec<_>>();
+        let known = constraints.iter().filter(|c| values.contains_key(&c.id)).collect::<Vec<_>>();
+        if !known.is_empty() {
+            return Some(known.iter().all(|c| constraint_matches(&values[&c.id], c)));
+        }
+        let children = self.scope.get_children(decl);
+        if !constraints.is_empty() || children.is_empty() {
+            return None;
+        }
+        let conditions = children
+            .iter()
+            .map(|child| self.condition(child, values))
+            .collect::<Option<Vec<_>>>()?;
+        Some(conditions.into_iter().any(|c| c))
+    }
+
+    /// Decode the fields of a declaration and of its selected
+    /// descendants. `context` holds the field values of the ancestors.
+    fn decode_decl(
+        &self,
+        decl: &'d Decl,
+        reader: &mut Reader,
+        context: &Map<String, Value>,
+    ) -> Result<Node, String> {
+        let obj = decl.id().unwrap().as_str();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let mut values = Map::new();
+        let mut sizes = HashMap::new();
+        let mut counts = HashMap::new();
+        let mut payload = None;
+        let mut checksum_starts: HashMap<&str, &[u8]> = HashMap::new();
+        let mut checksums = HashMap::new();
+
+        let mut chunk = vec![];
+        let mut chunk_width = 0;
+        for (index, field) in fields.iter().copied().enumerate() {
+            if self.scope.is_bitfield(field) {
+                // Compute the expected checksum value from the bytes
+                // read since the checksum start.
+                if let Field::Typedef { id, type_id, .. } = field {
+                    if let Some(algorithm) = checksum_algorithm(self.scope.typedef[type_id]) {
+                        if !chunk.is_empty() {
+                            return Err(format!(
+                                "{}.{}: checksum field is not aligned to an octet boundary",
+                                obj, id
+                            ));
+                        }
+                        let start = *checksum_starts
+                            .get(id.as_str())
+                            .ok_or_else(|| format!("{}.{}: missing checksum start", obj, id))?;
+                        let bytes = &start[..start.len() - reader.remaining()];
+                        checksums.insert(id.as_str(), algorithm.compute(bytes));
+                    }
+                }
+                chunk.push(field);
+                chunk_width += self.scope.get_field_width(field).unwrap();
+                if chunk_width % 8 == 0 {
+                    let chunk_value = reader.read_uint(obj, "_chunk_", chunk_width / 8)?;
+                    let mut shift = 0;
+                    for field in chunk.drain(..) {
+                        let width = self.scope.get_field_width(field).unwrap();
+                        let value = if width >= 64 {
+                            chunk_value
+                        } else {
+                            (chunk_value >> shift) & ((1 << width) - 1)
+                        };
+                        self.decode_bitfield(
+                            obj,
+                            field,
+                            value,
+                            &mut values,
+                            &mut sizes,
+                            &mut counts,
+                        )?;
+                        match field.id().and_then(|id| Some((id, checksums.get(id.as_str())?))) {
+                            Some((id, expected)) if *expected != value => {
+                                return Err(format!(
+                                    "{}.{}: invalid checksum {:#x}, expected {:#x}",
+                                    obj, id, value, expected
+                                ))
+                            }
+                            _ => (),
+                        }
+                        shift += width;
+                    }
+                    chunk_width = 0;
+                }
+                continue;
+            }
+            if !chunk.is_empty() && !matches!(field, Field::Checksum { .. }) {
+                return Err(format!("{}: bit fields are not aligned to an octet boundary", obj));
+            }
+
+            // Return the number of bytes available to a variable size
+            // field when it is not sized by a size field.
+            let remaining = |reader: &Reader| {
+                let trailing = self.scope.get_trailing_width(&fields, index).ok_or_else(|| {
+                    format!("{}: unbounded field followed by variable size fields", obj)
+                })? / 8;
+                reader.remaining().checked_sub(trailing).ok_or_else(|| {
+                    format!(
+                        "{}: needed {} trailing bytes but got {}",
+                        obj,
+                        trailing,
+                        reader.remaining()
+                    )
+                })
+            };
+
+            match field {
+                Field::Array { id, size, size_modifier, .. } => {
+                    let count = size.or_else(|| counts.get(id.as_str()).copied());
+                    let padding = match fields.get(index + 1) {
+                        Some(Field::Padding { width, .. }) => Some(*width),
+                        _ => None,
+                    };
+                    let mut elements = vec![];
+                    match (count, padding) {
+                        (Some(count), None) => {
+                            for _ in 0..count {
+                                elements.push(self.decode_element(obj, field, reader)?);
+                            }
+                        }
+                        (_, padding) => {
+                            let region_size = match (padding, sizes.get(id.as_str())) {
+                                (Some(padding), _) => padding,
+                                (None, Some(size)) => {
+                                    apply_size_modifier(obj, id, *size, size_modifier)?
+                                }
+                                (None, None) => remaining(reader)?,
+                            };
+                            let mut region = reader.split(obj, id, region_size)?;
+                            match count {
+                                Some(count) => {
+                                    for _ in 0..count {
+                                        elements.push(self.decode_element(
+                                            obj,
+                                            field,
+                                            &mut region,
+                                        )?);
+                                    }
+                                }
+                                None => {
+                                    while region.remaining() > 0 {
+                                        elements.push(self.decode_element(
+                                            obj,
+                                            field,
+                                            &mut region,
+                                        )?);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    values.insert(id.clone(), Value::Array(elements));
+                }
+                Field::Scalar { id, width, cond: Some(cond), .. } => {
+                    if is_present(cond, &values, context) {
+                        values
+                            .insert(id.clone(), Value::from(reader.read_uint(obj, id, width / 8)?));
+                    }
+                }
+                Field::Typedef { id, type_id, cond, .. } => {
+                    if cond.as_ref().is_none_or(|cond| is_present(cond, &values, context)) {
+                        values.insert(id.clone(), self.decode_typedef(obj, id, type_id, reader)?);
+                    }
+                }
+                Field::Payload { size_modifier, .. } => {
+                    let payload_size = match sizes.get("_payload_") {
+                        Some(size) => apply_size_modifier(obj, "_payload_", *size, size_modifier)?,
+                        None => remaining(reader)?,
+                    };
+                    payload = Some(reader.split(obj, "_payload_", payload_size)?);
+                }
+                Field::Body { .. } => {
+                    let body_size = match sizes.get("_body_") {
+                        Some(size) => *size,
+                        None => remaining(reader)?,
+                    };
+                    payload = Some(reader.split(obj, "_body_", body_size)?);
+                }
+                Field::Padding { width, .. } => {
+                    if !matches!(fields[index - 1], Field::Array { .. }) {
+                        reader.read_bytes(obj, "_padding_", *width)?;
+                    }
+                }
+                Field::Checksum { field_id, .. } => {
+                    if !chunk.is_empty() {
+                        return Err(format!(
+                            "{}: checksum start is not aligned to an octet boundary",
+                            obj
+                        ));
+                    }
+                    checksum_starts.insert(field_id.as_str(), reader.bytes);
+                }
+                _ => unreachable!(),
+            }
+        }
+        if !chunk.is_empty() {
+            return Err(format!("{}: bit fields are not aligned to an octet boundary", obj));
+        }
+
+        // Select the child specialization from the values of the
+        // fields of the declaration and of its ancestors.
+        let children = self.scope.get_children(decl);
+        let mut payload = match (payload, children.is_empty()) {
+            (Some(payload), _) => payload,
+            // Children of a declaration without payload are parsed
+            // from the trailing bytes.
+            (None, false) => reader.split(obj, "_payload_", reader.remaining())?,
+            (None, true) => {
+                return Ok(Node { id: obj.to_owned(), fields: values, child: None, payload: None })
+            }
+        };
+        let mut context = context.clone();
+        context.extend(values.clone());
+        let conditions = children
+            .iter()
+            .map(|child| (*child, self.condition(child, &context)))
+            .collect::<Vec<_>>();
+        // The lineage of the decoded declaration is followed when the
+        // values do not exclude it, as siblings may share constraints.
+        let selected = conditions
+            .iter()
+            .find(|(child, c)| {
+                *c != Some(false) && self.target.iter().any(|d| std::ptr::eq(*d, *child))
+            })
+            .or_else(|| conditions.iter().find(|(_, c)| *c == Some(true)))
+            .or_else(|| conditions.iter().find(|(_, c)| c.is_none()))
+            .map(|(child, _)| *child);
+
+        let (child, payload) = match selected {
+            Some(child) => {
+                let node = self.decode_decl(child, &mut payload, &context)?;
+                if payload.remaining() > 0 {
+                    return Err(format!(
+                        "{}: {} trailing bytes after the payload of {}",
+                        obj,
+                        payload.remaining(),
+                        node.id
+                    ));
+                }
+                (Some(Box::new(node)), None)
+            }
+            None if payload.remaining() == 0 => (None, None),
+            None => (None, Some(payload.bytes.to_vec())),
+        };
+        Ok(Node { id: obj.to_owned(), fields: values, child, payload })
+    }
+
+    /// Decode the value of a field packed into a bit chunk.
+    fn decode_bitfield(
+        &self,
+        obj: &str,
+        field: &Field,
+        value: u64,
+        values: &mut Map<String, Value>,
+        sizes: &mut HashMap<String, usize>,
+        counts: &mut HashMap<String, usize>,
+    ) -> Result<(), String> {
+        match field {
+            Field::Scalar { id, .. } => {
+                values.insert(id.clone(), Value::from(value));
+            }
+            Field::Typedef { id, type_id, .. } => {
+                let decl = self.scope.typedef[type_id];
+                let value = match decl {
+                    Decl::Enum { .. } => enum_value(decl, value),
+                    _ => Value::from(value),
+                };
+                values.insert(id.clone(), value);
+            }
+            Field::Fixed { value: Some(expected), .. } if *expected as u64 != value => {
+                return Err(format!(
+                    "when parsing {}, expected fixed value {:#x} but got {:#x}",
+                    obj, expected, value
+                ));
+            }
+            Field::Fixed { enum_id: Some(enum_id), tag_id: Some(tag_id), .. } => {
+                let decl = self.scope.typedef[enum_id];
+                if enum_value(decl, value).as_str() != Some(tag_id) {
+                    return Err(format!(
+                        "when parsing {}, expected fixed value {} but got {:#x}",
+                        obj, tag_id, value
+                    ));
+                }
+            }
+            Field::Size { field_id, .. } => {
+                sizes.insert(field_id.clone(), value as usize);
+            }
+            Field::Count { field_id, .. } => {
+                counts.insert(field_id.clone(), value as usize);
+            }
+            Field::Fixed { .. } | Field::Reserved { .. } => (),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// Return true if the condition of an optional field holds for the
+/// decoded values of the declaration or of its ancestors.
+fn is_present(
+    cond: &Constraint,
+    values: &Map<String, Value>,
+    context: &Map<String, Value>,
+) -> bool {
+    values
+        .get(&cond.id)
+        .or_else(|| context.get(&cond.id))
+        .is_some_and(|value| constraint_matches(value, cond))
+}
+
+/// Subtract the size modifier of a field from the value of its
+/// size field.
+fn apply_size_modifier(
+    obj: &str,
+    id: &str,
+    size: usize,
+    size_modifier: &Option<String>,
+) -> Result<usize, String> {
+    let modifier = size_modifier.as_ref().map_or(0, |m| analyzer::size_modifier_bits(m) / 8);
+    let size = size as isize - modifier;
+    if size < 0 {
+        return Err(format!("{}.{}: invalid size {}", obj, id, size + modifier));
+    }
+    Ok(size as usize)
+}
+
+/// Decode `bytes` as an instance of the packet or struct `id`.
+///
+/// Packets are decoded starting from the root of their inheritance
+/// chain; the decoded specialization must match `id`. The result is a
+/// tree of JSON objects containing the `packet` name and `fields`
+/// values of each declaration, nested under the `child` key of their
+/// parent. Unrecognized payload bytes are reported as a hexadecimal
+/// string under the `payload` key.
+pub fn decode(grammar: &Grammar, id: &str, bytes: &[u8]) -> Result<Value, String> {
+    let grammar = analyzer::inline_groups(grammar);
+    let mut decoder = Decoder { scope: Scope::new(&grammar), target: vec![] };
+    let decl = match decoder.scope.typedef.get(id) {
+        Some(decl @ Decl::Packet { .. }) | Some(decl @ Decl::Struct { .. }) => *decl,
+        _ => return Err(format!("undeclared packet `{}`", id)),
+    };
+    let lineage = decoder.scope.get_lineage(decl);
+    decoder.target = lineage.clone();
+    let mut reader = Reader { bytes, endianness: decoder.scope.endianness };
+    let node = decoder.decode_decl(lineage[0], &mut reader, &Map::new())?;
+    if reader.remaining() > 0 {
+        return Err(format!("{} trailing bytes after {}", reader.remaining(), id));
+    }
+    if !node.contains(id) {
+        return Err(format!("the bytes do not decode as a `{}` packet", id));
+    }
+    match decl {
+        Decl::Struct { .. } => Ok(node.to_struct_json()),
+        _ => Ok(node.to_packet_json()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::decoder::*;
+    use crate::parser::parse_inline;
+
+    fn decode_inline(text: &str, id: &str, bytes: &[u8]) -> Result<Value, String> {
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "stdin".to_owned(), text.to_owned()).expect("parsing failure");
+        decode(&grammar, id, bytes)
+    }
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        enum OpCode : 8 { RESET = 1, READ = 2 }
+        struct Item { a: 8, b: 16 }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            _fixed_ = 0x42: 8,
+        }
+        packet Read : Command (op_code = READ) {
+            _count_(items): 8,
+            items: Item[],
+        }
+    "#;
+
+    #[test]
+    fn test_decode_specialization() {
+        assert_eq!(
+            decode_inline(GRAMMAR, "Command", &[0x01, 0x01, 0x01, 0x42]),
+            Ok(json!({
+                "packet": "Command",
+                "fields": { "op_code": "RESET", "flag": 1 },
+                "child": { "packet": "Reset", "fields": {} },
+            }))
+        );
+        assert_eq!(
+            decode_inline(GRAMMAR, "Read", &[0x02, 0x00, 0x04, 0x01, 0x0a, 0x34, 0x12]),
+            Ok(json!({
+                "packet": "Command",
+                "fields": { "op_code": "READ", "flag": 0 },
+                "child": {
+                    "packet": "Read",
+                    "fields": { "items": [ { "a": 10, "b": 0x1234 } ] },
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn test_decode_shared_constraints() {
+        let grammar = r#"
+            little_endian_packets
+            packet Command { op_code: 8, _payload_ }
+            packet DisableAll : Command (op_code = 1) { _fixed_ = 0: 8 }
+            packet Enable : Command (op_code = 1) { enable: 8 }
+        "#;
+        assert!(decode_inline(grammar, "Command", &[0x01, 0x01]).is_err());
+        assert_eq!(
+            decode_inline(grammar, "Enable", &[0x01, 0x01]),
+            Ok(json!({
+                "packet": "Command",
+                "fields": { "op_code": 1 },
+                "child": { "packet": "Enable", "fields": { "enable": 1 } },
+            }))
+        );
+        assert!(decode_inline(grammar, "Enable", &[0x02, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_decode_payload() {
+        assert_eq!(
+            decode_inline(GRAMMAR, "Command", &[0x03, 0x00, 0x02, 0xab, 0xcd]),
+            Ok(json!({
+                "packet": "Command",
+                "fields": { "op_code": 3, "flag": 0 },
+                "payload": "abcd",
+            }))
+        );
+    }
+
+    #[test]
+    fn test_decode_checksum() {
+        let grammar = r#"
+            little_endian_packets
+            checksum Crc8 : 8 "crc8"
+            packet Frame {
+                _checksum_start_(crc),
+                a: 8,
+                _payload_,
+                crc: Crc8,
+            }
+        "#;
+        assert_eq!(
+            decode_inline(grammar, "Frame", &[0x31, 0x32, 0x33, 0xc0]),
+            Ok(json!({
+                "packet": "Frame",
+                "fields": { "a": 0x31, "crc": 0xc0 },
+                "payload": "3233",
+            }))
+        );
+        assert_eq!(
+            decode_inline(grammar, "Frame", &[0x31, 0x32, 0x33, 0xc1]),
+            Err("Frame.crc: invalid checksum 0xc1, expected 0xc0".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // Truncated payload.
+        assert!(decode_inline(GRAMMAR, "Command", &[0x01, 0x00, 0x02, 0x42]).is_err());
+        // Invalid fixed value.
+        assert!(decode_inline(GRAMMAR, "Command", &[0x01, 0x00, 0x01, 0x43]).is_err());
+        // Specialization mismatch.
+        assert!(decode_inline(GRAMMAR, "Read", &[0x01, 0x00, 0x01, 0x42]).is_err());
+        // Trailing bytes.
+        assert!(decode_inline(GRAMMAR, "Command", &[0x01, 0x00, 0x01, 0x42, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_decode_big_endian() {
+        assert_eq!(
+            decode_inline(
+                r#"
+                big_endian_packets
+                packet P { a: 4, b: 12, c: 16, d: 8[2] }
+                "#,
+                "P",
+                &[0x12, 0x34, 0x56, 0x78, 0x01, 0x02]
+            ),
+            Ok(json!({
+                "packet": "P",
+                "fields": { "a": 0x4, "b": 0x123, "c": 0x5678, "d": [1, 2] },
+            }))
+        );
+    }
+
+    #[test]
+    fn test_decode_optional_fields() {
+        let grammar = r#"
+            little_endian_packets
+            enum Kind : 8 { SHORT = 0, LONG = 1 }
+            packet Frame {
+                kind: Kind,
+                address: 16 if kind = LONG,
+                _payload_,
+            }
+        "#;
+        assert_eq!(
+            decode_inline(grammar, "Frame", &[0x00, 0x42]),
+            Ok(json!({
+                "packet": "Frame",
+                "fields": { "kind": "SHORT" },
+                "payload": "42",
+            }))
+        );
+        assert_eq!(
+            decode_inline(grammar, "Frame", &[0x01, 0x34, 0x12, 0x42]),
+            Ok(json!({
+                "packet": "Frame",
+                "fields": { "kind": "LONG", "address": 0x1234 },
+                "payload": "42",
+            }))
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! Encode JSON field values against a packet declaration.
+//!
+//! The encoder serializes the packet from the most specific
+//! declaration up to the root of its inheritance chain. Size, count,
+//! fixed, reserved and padding fields are filled automatically, and
+//! the fields constrained by the declaration or its ancestors take
+//! the constraint value. Checksum fields are computed when the
+//! checksum declaration names an algorithm of the checksum library.
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::interpreter::{checksum_algorithm, from_hex, tag_value};
+
+/// Buffer receiving the encoded bytes.
+struct Writer {
+    bytes: Vec<u8>,
+    endianness: EndiannessValue,
+}
+
+impl Writer {
+    fn put_uint(&mut self, value: u64, n: usize) {
+        let bytes = (0..n).map(|i| if i < 8 { (value >> (8 * i)) as u8 } else { 0 });
+        match self.endianness {
+            EndiannessValue::LittleEndian => self.bytes.extend(bytes),
+            EndiannessValue::BigEndian => {
+                self.bytes.extend(bytes.collect::<Vec<_>>().into_iter().rev())
+            }
+        }
+    }
+}
+
+/// Check that `value` fits in `width` bits.
+fn check_width(obj: &str, id: &str, value: u64, width: usize) -> Result<u64, String> {
+    if width < 64 && value >> width != 0 {
+        return Err(format!("{}.{}: value {:#x} does not fit in {} bits", obj, id, value, width));
+    }
+    Ok(value)
+}
+
+/// Field values provided by the user, and constrained values.
+struct Values<'a> {
+    fields: &'a Map<String, Value>,
+    constraints: HashMap<&'a str, &'a Constraint>,
+    used: Vec<&'a str>,
+}
+
+impl<'a> Values<'a> {
+    /// Return the value of the field `id`: the constraint value if the
+    /// field is constrained, or the user provided value.
+    fn get(&mut self, obj: &str, id: &'a str) -> Result<Value, String> {
+        let field = self.fields.get(id);
+        if field.is_some() {
+            self.used.push(id);
+        }
+        match (self.constraints.get(id), field) {
+            (Some(c), Some(value)) if !crate::interpreter::constraint_matches(value, c) => {
+                Err(format!("{}.{}: value {} conflicts with the constraint", obj, id, value))
+            }
+            (Some(c), _) => Ok(match &c.value {
+                Expr::Identifier { name, .. } => Value::String(name.clone()),
+                expr => Value::from(expr.evaluate().unwrap() as u64),
+            }),
+            (None, Some(value)) => Ok(value.clone()),
+            (None, None) => Err(format!("{}.{}: missing field value", obj, id)),
+        }
+    }
+}
+
+struct Encoder<'d> {
+    scope: Scope<'d>,
+}
+
+impl<'d> Encoder<'d> {
+    /// Convert a JSON value to an integer value of the given type.
+    fn integer(
+        &self,
+        obj: &str,
+        id: &str,
+        type_id: Option<&str>,
+        value: &Value,
+    ) -> Result<u64, String> {
+        let decl = type_id.map(|type_id| self.scope.typedef[type_id]);
+        let result = match (decl, value) {
+            (Some(decl @ Decl::Enum { .. }), Value::String(tag)) => tag_value(decl, tag),
+            (_, value) => value.as_u64(),
+        };
+        result.ok_or_else(|| format!("{}.{}: invalid value {}", obj, id, value))
+    }
+
+    /// Return true if the condition of an optional field of `decl`
+    /// holds. Enum values may be provided as tags or integers.
+    fn is_present<'v>(
+        &self,
+        decl: &'v Decl,
+        cond: &'v Constraint,
+        values: &mut Values<'v>,
+    ) -> Result<bool, String> {
+        let obj = decl.id().unwrap();
+        let value = values.get(obj, &cond.id)?;
+        let type_id = match self.scope.get_field(decl, &cond.id) {
+            Some(Field::Typedef { type_id, .. }) => Some(type_id.as_str()),
+            _ => None,
+        };
+        let expected = match &cond.value {
+            Expr::Identifier { name, .. } => {
+                type_id.and_then(|type_id| tag_value(self.scope.typedef[type_id], name))
+            }
+            expr => expr.evaluate().ok().map(|value| value as u64),
+        };
+        Ok(Some(self.integer(obj, &cond.id, type_id, &value)?) == expected)
+    }
+
+    /// Encode a value of enum, struct, or custom field type.
+    fn encode_typedef(
+        &self,
+        obj: &str,
+        id: &str,
+        type_id: &str,
+        value: &Value,
+        writer: &mut Writer,
+    ) -> Result<(), String> {
+        match self.scope.typedef[type_id] {
+            Decl::Enum { width, .. } => {
+                let value = self.integer(obj, id, Some(type_id), value)?;
+                writer.put_uint(check_width(obj, id, value, *width)?, width / 8);
+            }
+            decl @ Decl::Struct { .. } => {
+                if !self.scope.get_children(decl).is_empty() {
+                    return Err(format!(
+                        "{}.{}: cannot encode struct `{}` with children",
+                        obj, id, type_id
+                    ));
+                }
+                let fields = value
+                    .as_object()
+                    .ok_or_else(|| format!("{}.{}: expected an object value", obj, id))?;
+                writer.bytes.extend(self.encode_decl(decl, fields)?);
+            }
+            Decl::CustomField { width: Some(width), .. } => match value {
+                Value::String(hex) => {
+                    let bytes = from_hex(hex)?;
+                    if bytes.len() != width / 8 {
+                        return Err(format!("{}.{}: expected {} bytes", obj, id, width / 8));
+                    }
+                    writer.bytes.extend(bytes);
+                }
+                value => {
+                    let value = self.integer(obj, id, None, value)?;
+                    writer.put_uint(check_width(obj, id, value, *width)?, width / 8);
+                }
+            },
+            _ => return Err(format!("{}.{}: cannot encode values of type `{}`", obj, id, type_id)),
+        }
+        Ok(())
+    }
+
+    /// Encode the elements of an array field.
+    fn encode_array(
+        &self,
+        obj: &str,
+        field: &Field,
+        value: &Value,
+    ) -> Result<(Vec<u8>, usize), String> {
+        let (id, width, type_id, size) = match field {
+            Field::Array { id, width, type_id, size, .. } => (id, width, type_id, size),
+            _ => unreachable!(),
+        };
+        let elements =
+            value.as_array().ok_or_else(|| format!("{}.{}: expected an array value", obj, id))?;
+        if let Some(size) = size {
+            if elements.len() != *size {
+                return Err(format!(
+                    "{}.{}: expected {} elements but got {}",
+                    obj,
+                    id,
+                    size,
+                    elements.len()
+                ));
+            }
+        }
+        let mut writer = Writer { bytes: vec![], endianness: self.scope.endianness };
+        for element in elements {
+            match (width, type_id) {
+                (Some(width), _) => {
+                    let value = self.integer(obj, id, None, element)?;
+                    writer.put_uint(check_width(obj, id, value, *width)?, width / 8);
+                }
+                (_, Some(type_id)) => {
+                    self.encode_typedef(obj, id, type_id, element, &mut writer)?
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok((writer.bytes, elements.len()))
+    }
+
+    /// Encode the fields of a declaration and of its ancestors.
+    /// The payload of the declaration, if any, is read from the
+    /// hexadecimal string under the `payload` key.
+    fn encode_decl(&self, decl: &'d Decl, fields: &Map<String, Value>) -> Result<Vec<u8>, String> {
+        let obj = decl.id().unwrap();
+        let lineage = self.scope.get_lineage(decl);
+        let mut values = Values { fields, constraints: HashMap::new(), used: vec![] };
+        for decl in &lineage {
+            for constraint in decl.constraints() {
+                values.constraints.insert(constraint.id.as_str(), constraint);
+            }
+        }
+
+        let has_payload =
+            decl.fields().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. }));
+        let mut payload = match fields.get("payload") {
+            Some(Value::String(hex)) if has_payload => Some(from_hex(hex)?),
+            Some(_) if has_payload => {
+                return Err(format!("{}: expected a hexadecimal payload string", obj))
+            }
+            _ => None,
+        };
+        if payload.is_some() {
+            values.used.push("payload");
+        }
+        for decl in lineage.iter().rev() {
+            payload = Some(self.encode_fields(decl, &mut values, payload)?);
+        }
+
+        if let Some(unused) = fields.keys().find(|k| !values.used.contains(&k.as_str())) {
+            return Err(format!("unexpected field `{}` in the value of `{}`", unused, obj));
+        }
+        Ok(payload.unwrap())
+    }
+
+    fn encode_fields<'v>(
+        &self,
+        decl: &'v Decl,
+        values: &mut Values<'v>,
+        payload: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, String> {
+        let obj = decl.id().unwrap().as_str();
+        let fields = decl.fields().collect::<Vec<_>>();
+
+        // Encode the variable size fields first to compute
+        // the values of the size and count fields.
+        let mut arrays = HashMap::new();
+        for field in &fields {
+            if let Field::Array { id, .. } = field {
+                let value = values.get(obj, id)?;
+                arrays.insert(id.as_str(), self.encode_array(obj, field, &value)?);
+            }
+        }
+        let payload = payload.unwrap_or_default();
+        let size = |field_id: &str| -> Result<u64, String> {
+            let (size, size_modifier) = match field_id {
+                "_payload_" | "_body_" => (
+                    payload.len(),
+                    fields.iter().find_map(|f| match f {
+                        Field::Payload { size_modifier, .. } => size_modifier.as_ref(),
+                        _ => None,
+                    }),
+                ),
+                _ => (
+                    arrays[field_id].0.len(),
+                    fields.iter().find_map(|f| match f {
+                        Field::Array { id, size_modifier, .. } if id == field_id => {
+                            size_modifier.as_ref()
+                        }
+                        _ => None,
+                    }),
+                ),
+            };
+            let modifier = size_modifier.map_or(0, |m| analyzer::size_modifier_bits(m) / 8);
+            Ok((size as isize + modifier) as u64)
+        };
+
+        let mut writer = Writer { bytes: vec![], endianness: self.scope.endianness };
+        let mut checksum_starts = HashMap::new();
+        let mut chunk = vec![];
+        let mut chunk_width = 0;
+        for (index, field) in fields.iter().copied().enumerate() {
+            if self.scope.is_bitfield(field) {
+                let width = self.scope.get_field_width(field).unwrap();
+                let value = match field {
+                    Field::Scalar { id, .. } => {
+                        let value = values.get(obj, id)?;
+                        self.integer(obj, id, None, &value)?
+                    }
+                    Field::Typedef { id, type_id, .. } => {
+                        match checksum_algorithm(self.scope.typedef[type_id.as_str()]) {
+                            // The checksum covers the bytes written since
+                            // the checksum start.
+                            Some(algorithm) => {
+                                if !chunk.is_empty() {
+                                    return Err(format!(
+                                        "{}.{}: checksum field is not aligned to an octet boundary",
+                                        obj, id
+                                    ));
+                                }
+                                let start = checksum_starts.get(id.as_str()).ok_or_else(|| {
+                                    format!("{}.{}: missing checksum start", obj, id)
+                                })?;
+                                algorithm.compute(&writer.bytes[*start..])
+                            }
+                            None => {
+                                let value = values.get(obj, id)?;
+                                self.integer(obj, id, Some(type_id), &value)?
+                            }
+                        }
+                    }
+                    Field::Fixed { value: Some(value), .. } => *value as u64,
+                    Field::Fixed { enum_id: Some(enum_id), tag_id: Some(tag_id), .. } => {
+                        tag_value(self.scope.typedef[enum_id.as_str()], tag_id).unwrap()
+                    }
+                    Field::Size { field_id, .. } => size(field_id)?,
+                    Field::Count { field_id, .. } => arrays[field_id.as_str()].1 as u64,
+                    _ => 0,
+                };
+                let id = field.id().map_or("_chunk_", |id| id.as_str());
+                chunk.push((check_width(obj, id, value, width)?, width));
+                chunk_width += width;
+                if chunk_width % 8 == 0 {
+                    let mut shift = 0;
+                    let mut value = 0;
+                    for (field_value, width) in chunk.drain(..) {
+                        value |= field_value << shift;
+                        shift += width;
+                    }
+                    writer.put_uint(value, chunk_width / 8);
+                    chunk_width = 0;
+                }
+                continue;
+            }
+            if !chunk.is_empty() && !matches!(field, Field::Checksum { .. }) {
+                return Err(format!("{}: bit fields are not aligned to an octet boundary", obj));
+            }
+            match field {
+                Field::Array { id, .. } => {
+                    let bytes = &arrays[id.as_str()].0;
+                    writer.bytes.extend(bytes);
+                    if let Some(Field::Padding { width, .. }) = fields.get(index + 1) {
+                        if bytes.len() > *width {
+                            return Err(format!(
+                                "{}.{}: array size {} exceeds the padding size {}",
+                                obj,
+                                id,
+                                bytes.len(),
+                                width
+                            ));
+                        }
+                        writer.bytes.resize(writer.bytes.len() + width - bytes.len(), 0);
+                    }
+                }
+                Field::Scalar { id, width, cond: Some(cond), .. } => {
+                    if self.is_present(decl, cond, values)? {
+                        let value = values.get(obj, id)?;
+                        let value = self.integer(obj, id, None, &value)?;
+                        writer.put_uint(check_width(obj, id, value, *width)?, width / 8);
+                    }
+                }
+                Field::Typedef { id, type_id, cond, .. } => {
+                    if cond.as_ref().map_or(Ok(true), |cond| self.is_present(decl, cond, values))? {
+                        let value = values.get(obj, id)?;
+                        self.encode_typedef(obj, id, type_id, &value, &mut writer)?
+                    }
+                }
+                Field::Payload { .. } | Field::Body { .. } => writer.bytes.extend(&payload),
+                Field::Padding { width, .. } => {
+                    if !matches!(fields[index - 1], Field::Array { .. }) {
+                        writer.bytes.resize(writer.bytes.len() + width, 0);
+                    }
+                }
+                Field::Checksum { field_id, .. } => {
+                    if !chunk.is_empty() {
+                        return Err(format!(
+                            "{}: checksum start is not aligned to an octet boundary",
+                            obj
+                        ));
+                    }
+                    checksum_starts.insert(field_id.as_str(), writer.bytes.len());
+                }
+                _ => unreachable!(),
+            }
+        }
+        if !chunk.is_empty() {
+            return Err(format!("{}: bit fields are not aligned to an octet boundary", obj));
+        }
+
+        // Children of a declaration without payload are appended
+        // after the fields.
+        if !fields.iter().any(|f| matches!(f, Field::Payload { .. } | Field::Body { .. })) {
+            writer.bytes.extend(&payload);
+        }
+        Ok(writer.bytes)
+    }
+}
+
+/// Encode the JSON field values `value` as an instance of the packet
+/// or struct `id`.
+///
+/// `value` is an object holding the values of the fields of the
+/// declaration and of its ancestors, except for the fields assigned
+/// by constraints. The payload of the declaration, if any, is provided
+/// as a hexadecimal string under the `payload` key.
+pub fn encode(grammar: &Grammar, id: &str, value: &Value) -> Result<Vec<u8>, String> {
+    let grammar = analyzer::inline_groups(grammar);
+    let encoder = Encoder { scope: Scope::new(&grammar) };
+    let decl = match encoder.scope.typedef.get(id) {
+        Some(decl @ Decl::Packet { .. }) | Some(decl @ Decl::Struct { .. }) => *decl,
+        _ => return Err(format!("undeclared packet `{}`", id)),
+    };
+    let fields = value.as_object().ok_or("expected a JSON object of field values")?;
+    encoder.encode_decl(decl, fields)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::decoder::decode;
+    use crate::interpreter::encoder::*;
+    use crate::parser::parse_inline;
+    use serde_json::json;
+
+    const GRAMMAR: &str = r#"
+        little_endian_packets
+        enum OpCode : 8 { RESET = 1, READ = 2 }
+        struct Item { a: 8, b: 16 }
+        packet Command {
+            op_code: OpCode,
+            flag: 1,
+            _reserved_: 7,
+            _size_(_payload_): 8,
+            _payload_,
+        }
+        packet Reset : Command (op_code = RESET) {
+            _fixed_ = 0x42: 8,
+        }
+        packet Read : Command (op_code = READ) {
+            _count_(items): 8,
+            items: Item[],
+            name: 8[4],
+            _padding_[6],
+        }
+    "#;
+
+    fn encode_inline(id: &str, value: Value) -> Result<Vec<u8>, String> {
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "stdin".to_owned(), GRAMMAR.to_owned()).expect("parsing failure");
+        encode(&grammar, id, &value)
+    }
+
+    #[test]
+    fn test_encode_constraints() {
+        assert_eq!(encode_inline("Reset", json!({ "flag": 1 })), Ok(vec![0x01, 0x01, 0x01, 0x42]));
+        assert_eq!(
+            encode_inline(
+                "Read",
+                json!({
+                    "flag": 0,
+                    "items": [ { "a": 10, "b": 0x1234 } ],
+                    "name": [ 1, 2, 3, 4 ],
+                })
+            ),
+            Ok(vec![0x02, 0x00, 0x0a, 0x01, 0x0a, 0x34, 0x12, 1, 2, 3, 4, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_encode_payload() {
+        assert_eq!(
+            encode_inline("Command", json!({ "op_code": 3, "flag": 0, "payload": "abcd" })),
+            Ok(vec![0x03, 0x00, 0x02, 0xab, 0xcd])
+        );
+        assert_eq!(
+            encode_inline("Command", json!({ "op_code": "RESET", "flag": 0 })),
+            Ok(vec![0x01, 0x00, 0x00])
+        );
+    }
+
+    #[test]
+    fn test_encode_checksum() {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "stdin".to_owned(),
+            r#"
+            little_endian_packets
+            checksum Crc8 : 8 "crc8"
+            checksum HostChecksum : 16 "host_checksum"
+            packet Frame {
+                _checksum_start_(crc),
+                a: 8,
+                _payload_,
+                crc: Crc8,
+            }
+            packet Data : Frame (a = 0x31) { b: 16 }
+            packet HostFrame {
+                _checksum_start_(checksum),
+                a: 8,
+                checksum: HostChecksum,
+            }
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+
+        // The checksum covers the payload bytes.
+        assert_eq!(
+            encode(&grammar, "Data", &json!({ "b": 0x3332 })),
+            Ok(vec![0x31, 0x32, 0x33, 0xc0])
+        );
+        // The checksum is computed, and cannot be provided.
+        assert!(encode(&grammar, "Data", &json!({ "b": 0x3332, "crc": 0 })).is_err());
+        // Checksums of unknown algorithms are provided by the user.
+        assert_eq!(
+            encode(&grammar, "HostFrame", &json!({ "a": 1, "checksum": 0x1234 })),
+            Ok(vec![0x01, 0x34, 0x12])
+        );
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        // Missing field.
+        assert!(encode_inline("Reset", json!({})).is_err());
+        // Unknown field.
+        assert!(encode_inline("Reset", json!({ "flag": 0, "foo": 1 })).is_err());
+        // Conflicting constraint.
+        assert!(encode_inline("Reset", json!({ "flag": 0, "op_code": "READ" })).is_err());
+        // Value out of range.
+        assert!(encode_inline("Reset", json!({ "flag": 2 })).is_err());
+        // Invalid static array size.
+        assert!(encode_inline("Read", json!({ "flag": 0, "items": [], "name": [1] })).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "stdin".to_owned(), GRAMMAR.to_owned()).expect("parsing failure");
+        let bytes = encode(
+            &grammar,
+            "Read",
+            &json!({ "flag": 1, "items": [ { "a": 1, "b": 2 } ], "name": [5, 6, 7, 8] }),
+        )
+        .unwrap();
+        assert_eq!(
+            decode(&grammar, "Read", &bytes),
+            Ok(json!({
+                "packet": "Command",
+                "fields": { "op_code": "READ", "flag": 1 },
+                "child": {
+                    "packet": "Read",
+                    "fields": { "items": [ { "a": 1, "b": 2 } ], "name": [5, 6, 7, 8] },
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn test_encode_optional_fields() {
+        let mut db = SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "stdin".to_owned(),
+            r#"
+            little_endian_packets
+            enum Kind : 8 { SHORT = 0, LONG = 1 }
+            packet Frame {
+                kind: Kind,
+                address: 16 if kind = LONG,
+                _payload_,
+            }
+            packet Long : Frame (kind = LONG) {}
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+
+        assert_eq!(
+            encode(&grammar, "Frame", &json!({ "kind": "SHORT", "payload": "42" })),
+            Ok(vec![0x00, 0x42])
+        );
+        assert_eq!(
+            encode(&grammar, "Frame", &json!({ "kind": 1, "address": 0x1234, "payload": "42" })),
+            Ok(vec![0x01, 0x34, 0x12, 0x42])
+        );
+        // The condition is evaluated with the constraints of the children.
+        assert_eq!(
+            encode(&grammar, "Long", &json!({ "address": 0x1234 })),
+            Ok(vec![0x01, 0x34, 0x12])
+        );
+        // Optional fields cannot be set when absent.
+        assert!(
+            encode(&grammar, "Frame", &json!({ "kind": 0, "address": 1, "payload": "" })).is_err()
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Bit layout diagrams.
+//!
+//! Renders the layout of a packet or struct declaration as an RFC 791
+//! style box diagram, 32 bits wide. The fields of the ancestors are
+//! included: the payload or body of each ancestor is replaced by the
+//! fields of its child. Bits are numbered in declaration order.
+//!
+//! Variable size regions, such as the payload of the declaration or
+//! dynamically sized arrays, are drawn as rows delimited by `/`. The
+//! fields following a variable size region are drawn from the start of
+//! a new row, as their offset is not static.
+
+use std::fmt::Write;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::backends::docs::field_name;
+
+/// Width in bits of a diagram row.
+const ROW_WIDTH: usize = 32;
+
+/// Field of the flattened declaration, with its width in bits or
+/// `None` if the field has a variable size.
+struct Region {
+    label: String,
+    width: Option<usize>,
+}
+
+/// Portion of a region drawn on one row, spanning the bits
+/// `start..end` of the row.
+struct Segment {
+    region: usize,
+    start: usize,
+    end: usize,
+    label: Option<String>,
+}
+
+enum Row {
+    Static(Vec<Segment>),
+    Variable { region: usize, label: Option<String> },
+}
+
+impl Row {
+    /// Return the region drawn at the bit `bit` of the row.
+    fn region_at(&self, bit: usize) -> Option<usize> {
+        match self {
+            Row::Static(segments) => {
+                segments.iter().find(|s| s.start <= bit && bit < s.end).map(|s| s.region)
+            }
+            Row::Variable { region, .. } => Some(*region),
+        }
+    }
+
+    /// Return true if a region starts or ends at the bit boundary
+    /// `bit` of the row.
+    fn is_boundary(&self, bit: usize) -> bool {
+        match self {
+            Row::Static(segments) => segments.iter().any(|s| s.start == bit || s.end == bit),
+            Row::Variable { .. } => bit == 0 || bit == ROW_WIDTH,
+        }
+    }
+}
+
+/// Flatten the fields of the declarations of `lineage`, starting from
+/// the root ancestor.
+fn flatten(scope: &Scope, lineage: &[&Decl], regions: &mut Vec<Region>) {
+    let (decl, descendants) = match lineage.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let fields = decl.fields().collect::<Vec<_>>();
+    let mut index = 0;
+    while index < fields.len() {
+        let field = fields[index];
+        match (field, fields.get(index + 1)) {
+            (Field::Payload { .. } | Field::Body { .. }, _) if !descendants.is_empty() => {
+                flatten(scope, descendants, regions)
+            }
+            (Field::Checksum { .. }, _) => (),
+            // Padded arrays occupy the padding size.
+            (Field::Array { .. }, Some(Field::Padding { width, .. })) => {
+                regions.push(Region { label: field_name(field), width: Some(width * 8) });
+                index += 1;
+            }
+            _ => regions
+                .push(Region { label: field_name(field), width: scope.get_field_width(field) }),
+        }
+        index += 1;
+    }
+}
+
+/// Split the regions into the rows of the diagram.
+fn layout_rows(regions: &[Region]) -> Vec<Row> {
+    let mut rows = vec![];
+    let mut segments = vec![];
+    let mut bit = 0;
+    for (index, region) in regions.iter().enumerate() {
+        let label = Some(region.label.clone());
+        match region.width {
+            Some(width) => {
+                let mut label = label;
+                let mut remaining = width;
+                while remaining > 0 {
+                    let end = std::cmp::min(bit + remaining, ROW_WIDTH);
+                    segments.push(Segment { region: index, start: bit, end, label: label.take() });
+                    remaining -= end - bit;
+                    bit = end;
+                    if bit == ROW_WIDTH {
+                        rows.push(Row::Static(std::mem::take(&mut segments)));
+                        bit = 0;
+                    }
+                }
+            }
+            None => {
+                // The region fills the end of the current row, and is
+                // labeled on the variable row.
+                if bit > 0 {
+                    segments.push(Segment {
+                        region: index,
+                        start: bit,
+                        end: ROW_WIDTH,
+                        label: None,
+                    });
+                    rows.push(Row::Static(std::mem::take(&mut segments)));
+                    bit = 0;
+                }
+                rows.push(Row::Variable { region: index, label });
+            }
+        }
+    }
+    if !segments.is_empty() {
+        rows.push(Row::Static(segments));
+    }
+    rows
+}
+
+/// Center `label` in a cell of `width` characters, truncating it if
+/// it does not fit.
+fn center(label: Option<&str>, width: usize) -> String {
+    let label = label.unwrap_or_default().chars().take(width).collect::<String>();
+    let padding = width - label.chars().count();
+    format!("{}{}{}", " ".repeat(padding / 2), label, " ".repeat(padding - padding / 2))
+}
+
+/// Draw the separator line between the rows `above` and `below`.
+fn separator(above: Option<&Row>, below: Option<&Row>) -> String {
+    let mut line = String::new();
+    let region = |row: Option<&Row>, bit: usize| row.and_then(|row| row.region_at(bit));
+    let border = |bit: usize| {
+        let (above, below) = (region(above, bit), region(below, bit));
+        (above.is_some() || below.is_some()) && above != below
+    };
+    for bit in 0..=ROW_WIDTH {
+        let left = bit > 0 && border(bit - 1);
+        let right = bit < ROW_WIDTH && border(bit);
+        let boundary = above.is_some_and(|row| {
+            row.is_boundary(bit) && row.region_at(bit.saturating_sub(1)).is_some()
+        });
+        line.push(match (left || right, boundary) {
+            (true, _) => '+',
+            (false, true) => '|',
+            (false, false) => ' ',
+        });
+        if bit < ROW_WIDTH {
+            line.push(if right { '-' } else { ' ' });
+        }
+    }
+    line.trim_end().to_owned()
+}
+
+/// Draw a row of the diagram.
+fn draw_row(row: &Row) -> String {
+    match row {
+        Row::Static(segments) => {
+            let mut line = String::new();
+            for segment in segments {
+                line.push('|');
+                line.push_str(&center(
+                    segment.label.as_deref(),
+                    2 * (segment.end - segment.start) - 1,
+                ));
+            }
+            line.push('|');
+            line
+        }
+        Row::Variable { label, .. } => {
+            format!("/{}/", center(label.as_deref(), 2 * ROW_WIDTH - 1))
+        }
+    }
+}
+
+/// Render the bit layout diagram of the packet or struct declaration
+/// `id`, followed by the list of the fields with their offsets and
+/// widths in bits.
+pub fn layout(grammar: &Grammar, id: &str) -> Result<String, String> {
+    let grammar = analyzer::inline_groups(grammar);
+    let scope = Scope::new(&grammar);
+    let decl = match scope.typedef.get(id) {
+        Some(decl @ (Decl::Packet { .. } | Decl::Struct { .. })) => *decl,
+        _ => return Err(format!("undeclared packet or struct `{}`", id)),
+    };
+    let mut regions = vec![];
+    flatten(&scope, &scope.get_lineage(decl), &mut regions);
+    let rows = layout_rows(&regions);
+
+    let mut output = String::new();
+    writeln!(output, " 0                   1                   2                   3").unwrap();
+    writeln!(output, " 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1").unwrap();
+    let mut above = None;
+    for row in &rows {
+        writeln!(output, "{}", separator(above, Some(row))).unwrap();
+        writeln!(output, "{}", draw_row(row)).unwrap();
+        above = Some(row);
+    }
+    writeln!(output, "{}", separator(above, None)).unwrap();
+
+    writeln!(output).unwrap();
+    writeln!(output, "{:<8}  {:<8}  field", "offset", "width").unwrap();
+    let mut offset = Some(0);
+    for region in &regions {
+        let offset_text = offset.map_or_else(|| "-".to_owned(), |o: usize| o.to_string());
+        let width_text = region.width.map_or_else(|| "variable".to_owned(), |w| w.to_string());
+        writeln!(output, "{:<8}  {:<8}  {}", offset_text, width_text, region.label).unwrap();
+        offset = offset.zip(region.width).map(|(offset, width)| offset + width);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::layout::*;
+    use crate::parser::parse_inline;
+
+    fn layout_inline(text: &str, id: &str) -> Result<String, String> {
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "stdin".to_owned(), text.to_owned()).expect("parsing failure");
+        layout(&grammar, id)
+    }
+
+    #[test]
+    fn test_layout() {
+        let grammar = r#"
+            little_endian_packets
+            enum OpCode : 16 { RESET = 1 }
+            packet Command {
+                op_code: OpCode,
+                flag: 1,
+                _reserved_: 7,
+                _size_(_payload_): 8,
+                _payload_,
+            }
+            packet Reset : Command (op_code = RESET) {
+                address: 48,
+                _count_(data): 8,
+                data: 8[],
+                trailer: 16,
+            }
+        "#;
+        assert_eq!(
+            layout_inline(grammar, "Reset").unwrap(),
+            r#" 0                   1                   2                   3
+ 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|            op_code            |f| _reserved_  |_size_(_payload|
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                            address                            |
+|                               +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                               | _count_(data) |               |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+               |
+/                             data                              /
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|            trailer            |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+offset    width     field
+0         16        op_code
+16        1         flag
+17        7         _reserved_
+24        8         _size_(_payload_)
+32        48        address
+80        8         _count_(data)
+88        variable  data
+-         16        trailer
+"#
+        );
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let grammar = r#"
+            little_endian_packets
+            enum E : 8 { A = 1 }
+            struct S { a: 8, _payload_ }
+        "#;
+        assert!(layout_inline(grammar, "E").is_err());
+        assert!(layout_inline(grammar, "Unknown").is_err());
+        assert_eq!(
+            layout_inline(grammar, "S").unwrap().lines().nth(5).unwrap(),
+            "/                           _payload_                           /"
+        );
+    }
+}
@@ -1,10 +1,13 @@
-use codespan_reporting::diagnostic::Diagnostic;
-use codespan_reporting::files;
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
+use codespan_reporting::files::{self, Files};
 use codespan_reporting::term;
 use codespan_reporting::term::termcolor;
+use serde_json::json;
 use std::collections::HashMap;
 
 use crate::ast::*;
+use crate::checksum;
+use crate::interpreter::{self, decoder};
 
 /// Aggregate linter diagnostics.
 pub struct LintDiagnostics {
@@ -18,6 +21,108 @@ pub trait Lintable {
     fn lint(&self) -> LintDiagnostics;
 }
 
+/// Lint rules. The rule code is reported with each diagnostic, and
+/// is used to suppress the rule with a directive comment:
+/// `// pdl-lint: allow(<code>, ..)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Redeclared,
+    Undeclared,
+    DuplicateConstraint,
+    RecursiveDeclaration,
+    InvalidParent,
+    InvalidGroup,
+    ShadowedField,
+    InvalidConstraint,
+    OrphanConstraints,
+    InvalidTagValue,
+    InvalidChecksum,
+    InvalidSize,
+    InvalidCount,
+    StaticArray,
+    InvalidFixed,
+    InvalidArray,
+    InvalidTypedef,
+    InvalidCondition,
+    InvalidTest,
+    InvalidDirective,
+}
+
+impl Rule {
+    const ALL: [Rule; 20] = [
+        Rule::Redeclared,
+        Rule::Undeclared,
+        Rule::DuplicateConstraint,
+        Rule::RecursiveDeclaration,
+        Rule::InvalidParent,
+        Rule::InvalidGroup,
+        Rule::ShadowedField,
+        Rule::InvalidConstraint,
+        Rule::OrphanConstraints,
+        Rule::InvalidTagValue,
+        Rule::InvalidChecksum,
+        Rule::InvalidSize,
+        Rule::InvalidCount,
+        Rule::StaticArray,
+        Rule::InvalidFixed,
+        Rule::InvalidArray,
+        Rule::InvalidTypedef,
+        Rule::InvalidCondition,
+        Rule::InvalidTest,
+        Rule::InvalidDirective,
+    ];
+
+    /// Return the stable code identifying the rule.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rule::Redeclared => "redeclared",
+            Rule::Undeclared => "undeclared",
+            Rule::DuplicateConstraint => "duplicate-constraint",
+            Rule::RecursiveDeclaration => "recursive-declaration",
+            Rule::InvalidParent => "invalid-parent",
+            Rule::InvalidGroup => "invalid-group",
+            Rule::ShadowedField => "shadowed-field",
+            Rule::InvalidConstraint => "invalid-constraint",
+            Rule::OrphanConstraints => "orphan-constraints",
+            Rule::InvalidTagValue => "invalid-tag-value",
+            Rule::InvalidChecksum => "invalid-checksum",
+            Rule::InvalidSize => "invalid-size",
+            Rule::InvalidCount => "invalid-count",
+            Rule::StaticArray => "static-array",
+            Rule::InvalidFixed => "invalid-fixed",
+            Rule::InvalidArray => "invalid-array",
+            Rule::InvalidTypedef => "invalid-typedef",
+            Rule::InvalidCondition => "invalid-condition",
+            Rule::InvalidTest => "invalid-test",
+            Rule::InvalidDirective => "invalid-directive",
+        }
+    }
+}
+
+impl std::str::FromStr for Rule {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.code() == input)
+            .ok_or_else(|| format!("unknown lint rule `{}`", input))
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.code().to_owned()
+    }
+}
+
+/// Suppression directive: warnings reported for `rule` inside the
+/// declaration at `loc` are dropped.
+struct Suppression {
+    loc: SourceRange,
+    rule: Rule,
+}
+
 /// Represents a chain of group expansion.
 /// Each field but the last in the chain is a typedef field of a group.
 /// The last field can also be a typedef field of a group if the chain is
@@ -71,13 +176,13 @@ struct PacketScope<'d> {
 }
 
 impl std::cmp::Eq for &Decl {}
-impl<'d> std::cmp::PartialEq for &'d Decl {
+impl std::cmp::PartialEq for &Decl {
     fn eq(&self, other: &Self) -> bool {
         std::ptr::eq(*self, *other)
     }
 }
 
-impl<'d> std::hash::Hash for &'d Decl {
+impl std::hash::Hash for &Decl {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         std::ptr::hash(*self, state);
     }
@@ -90,7 +195,7 @@ impl FieldPath<'_> {
 }
 
 impl LintDiagnostics {
-    fn new() -> LintDiagnostics {
+    pub fn new() -> LintDiagnostics {
         LintDiagnostics { diagnostics: vec![] }
     }
 
@@ -107,13 +212,76 @@ impl LintDiagnostics {
         Ok(())
     }
 
-    fn push(&mut self, diagnostic: Diagnostic<FileId>) {
+    /// Print the diagnostics on stderr as a JSON array. Each
+    /// diagnostic has a severity, an optional rule code, a message,
+    /// labels with one-based line and column numbers, and notes.
+    pub fn print_json(&self, sources: &SourceDatabase) -> Result<(), files::Error> {
+        let mut diagnostics = vec![];
+        for d in self.diagnostics.iter() {
+            let mut labels = vec![];
+            for label in d.labels.iter() {
+                let start = sources.location(label.file_id, label.range.start)?;
+                let end = sources.location(label.file_id, label.range.end)?;
+                labels.push(json!({
+                    "style": match label.style {
+                        LabelStyle::Primary => "primary",
+                        LabelStyle::Secondary => "secondary",
+                    },
+                    "file": sources.name(label.file_id)?,
+                    "start": { "line": start.line_number, "column": start.column_number },
+                    "end": { "line": end.line_number, "column": end.column_number },
+                    "message": label.message,
+                }));
+            }
+            diagnostics.push(json!({
+                "severity": match d.severity {
+                    Severity::Bug => "bug",
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                    Severity::Note => "note",
+                    Severity::Help => "help",
+                },
+                "code": d.code,
+                "message": d.message,
+                "labels": labels,
+                "notes": d.notes,
+            }));
+        }
+        eprintln!("{}", serde_json::to_string_pretty(&diagnostics).unwrap());
+        Ok(())
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic<FileId>) {
         self.diagnostics.push(diagnostic)
     }
 
+    /// Return true if any error was reported.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    /// Drop the warnings matching a suppression directive.
+    /// Errors cannot be suppressed, as they would invalidate
+    /// the generated code.
+    fn suppress(&mut self, suppressions: &[Suppression]) {
+        self.diagnostics.retain(|d| {
+            let primary = d.labels.iter().find(|label| label.style == LabelStyle::Primary);
+            d.severity != Severity::Warning
+                || !suppressions.iter().any(|s| {
+                    d.code.as_deref() == Some(s.rule.code())
+                        && primary.is_some_and(|label| {
+                            label.file_id == s.loc.file
+                                && label.range.start >= s.loc.start.offset
+                                && label.range.end <= s.loc.end.offset
+                        })
+                })
+        })
+    }
+
     fn err_undeclared(&mut self, id: &str, loc: &SourceRange) {
         self.diagnostics.push(
             Diagnostic::error()
+                .with_code(Rule::Undeclared)
                 .with_message(format!("undeclared identifier `{}`", id))
                 .with_labels(vec![loc.primary()]),
         )
@@ -122,6 +290,7 @@ impl LintDiagnostics {
     fn err_redeclared(&mut self, id: &str, kind: &str, loc: &SourceRange, prev: &SourceRange) {
         self.diagnostics.push(
             Diagnostic::error()
+                .with_code(Rule::Redeclared)
                 .with_message(format!("redeclaration of {} identifier `{}`", kind, id))
                 .with_labels(vec![
                     loc.primary(),
@@ -143,6 +312,7 @@ impl<'d> PacketScope<'d> {
                 self.checksums.insert(field_id.clone(), FieldPath(vec![field])).map(|prev| {
                     result.push(
                         Diagnostic::error()
+                            .with_code(Rule::Redeclared)
                             .with_message(format!(
                                 "redeclaration of checksum start for `{}`",
                                 field_id
@@ -163,6 +333,7 @@ impl<'d> PacketScope<'d> {
                 self.sizes.insert(field_id.clone(), FieldPath(vec![field])).map(|prev| {
                     result.push(
                         Diagnostic::error()
+                            .with_code(Rule::Redeclared)
                             .with_message(format!(
                                 "redeclaration of size or count for `{}`",
                                 field_id
@@ -179,6 +350,7 @@ impl<'d> PacketScope<'d> {
                 if let Some(prev) = self.payload.as_ref() {
                     result.push(
                         Diagnostic::error()
+                            .with_code(Rule::Redeclared)
                             .with_message("redeclaration of payload or body field")
                             .with_labels(vec![
                                 loc.primary(),
@@ -203,6 +375,7 @@ impl<'d> PacketScope<'d> {
                 self.groups.insert(group_id.clone(), field).map(|prev| {
                     result.push(
                         Diagnostic::error()
+                            .with_code(Rule::Redeclared)
                             .with_message(format!("duplicate group `{}` insertion", group_id))
                             .with_labels(vec![
                                 loc.primary(),
@@ -234,6 +407,7 @@ impl<'d> PacketScope<'d> {
             if let Some(prev) = self.all_constraints.insert(id, constraint) {
                 result.push(
                     Diagnostic::error()
+                        .with_code(Rule::DuplicateConstraint)
                         .with_message(format!("duplicate constraint on field `{}`", constraint.id))
                         .with_labels(vec![
                             constraint.loc.primary(),
@@ -269,10 +443,11 @@ impl<'d> PacketScope<'d> {
             loc: &SourceRange,
             prev: &SourceRange,
         ) {
-            result.push(Diagnostic::error().with_message(message).with_labels(vec![
-                loc.primary(),
-                prev.secondary().with_message("first declared here"),
-            ]))
+            result.push(
+                Diagnostic::error().with_code(Rule::Redeclared).with_message(message).with_labels(
+                    vec![loc.primary(), prev.secondary().with_message("first declared here")],
+                ),
+            )
         }
 
         for (id, field) in packet_scope.checksums.iter() {
@@ -337,6 +512,7 @@ impl<'d> PacketScope<'d> {
             if let Some(prev) = self.constraints.insert(id, constraint) {
                 result.push(
                     Diagnostic::error()
+                        .with_code(Rule::DuplicateConstraint)
                         .with_message(format!("duplicate constraint on field `{}`", constraint.id))
                         .with_labels(vec![
                             constraint.loc.primary(),
@@ -355,6 +531,7 @@ impl<'d> PacketScope<'d> {
                 if let Some(prev) = self.all_fields.insert(id.clone(), f) {
                     result.push(
                         Diagnostic::warning()
+                            .with_code(Rule::ShadowedField)
                             .with_message(format!("declaration of `{}` shadows parent field", id))
                             .with_labels(vec![
                                 f.loc().primary(),
@@ -377,71 +554,101 @@ fn lint_constraint(
     result: &mut LintDiagnostics,
 ) {
     // Validate constraint value types.
+    // Integer expressions are folded to check the constant value.
     match (packet_scope.all_fields.get(&constraint.id), &constraint.value) {
-        (
-            Some(Field::Scalar { loc: field_loc, width, .. }),
-            Expr::Integer { value, loc: value_loc, .. },
-        ) => {
-            if bit_width(*value) > *width {
-                result.push(
-                    Diagnostic::error().with_message("invalid integer literal").with_labels(vec![
-                        value_loc.primary().with_message(format!(
+        (Some(Field::Scalar { loc: field_loc, .. }), Expr::Identifier { .. }) => result.push(
+            Diagnostic::error()
+                .with_code(Rule::InvalidConstraint)
+                .with_message("invalid literal type")
+                .with_labels(vec![
+                    constraint.loc.primary().with_message("expected integer literal"),
+                    field_loc.secondary().with_message("the value is used here"),
+                ]),
+        ),
+        (Some(Field::Scalar { loc: field_loc, width, .. }), value) => match value.evaluate() {
+            Ok(folded) if bit_width(folded) > *width => result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidConstraint)
+                    .with_message("invalid integer literal")
+                    .with_labels(vec![
+                        value.loc().primary().with_message(format!(
                             "expected maximum value of `{}`",
                             (1 << *width) - 1
                         )),
                         field_loc.secondary().with_message("the value is used here"),
                     ]),
-                )
-            }
-        }
+            ),
+            Ok(_) => (),
+            Err((loc, message)) => result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidConstraint)
+                    .with_message("invalid constant expression")
+                    .with_labels(vec![
+                        loc.primary().with_message(message),
+                        field_loc.secondary().with_message("the value is used here"),
+                    ]),
+            ),
+        },
 
         (Some(Field::Typedef { type_id, loc: field_loc, .. }), _) => {
             match (scope.typedef.get(type_id), &constraint.value) {
                 (Some(Decl::Enum { tags, .. }), Expr::Identifier { name, loc: name_loc, .. }) => {
-                    if !tags.iter().any(|t| &t.id == name) {
-                        result.push(
+                    match tags.iter().find(|t| t.id() == name) {
+                        None => result.push(
                             Diagnostic::error()
+                                .with_code(Rule::Undeclared)
                                 .with_message(format!("undeclared enum tag `{}`", name))
                                 .with_labels(vec![
                                     name_loc.primary(),
                                     field_loc.secondary().with_message("the value is used here"),
                                 ]),
-                        )
+                        ),
+                        Some(tag) if tag.value().is_none() => result.push(
+                            Diagnostic::error()
+                                .with_code(Rule::InvalidConstraint)
+                                .with_message(format!("invalid enum tag `{}`", name))
+                                .with_labels(vec![
+                                    name_loc.primary().with_message("expected a single value tag"),
+                                    tag.loc().secondary().with_message("tag declared here"),
+                                ]),
+                        ),
+                        Some(_) => (),
                     }
                 }
                 (Some(Decl::Enum { .. }), _) => result.push(
-                    Diagnostic::error().with_message("invalid literal type").with_labels(vec![
-                        constraint
-                            .loc
-                            .primary()
-                            .with_message(format!("expected `{}` tag identifier", type_id)),
-                        field_loc.secondary().with_message("the value is used here"),
-                    ]),
+                    Diagnostic::error()
+                        .with_code(Rule::InvalidConstraint)
+                        .with_message("invalid literal type")
+                        .with_labels(vec![
+                            constraint
+                                .loc
+                                .primary()
+                                .with_message(format!("expected `{}` tag identifier", type_id)),
+                            field_loc.secondary().with_message("the value is used here"),
+                        ]),
                 ),
                 (Some(decl), _) => result.push(
-                    Diagnostic::error().with_message("invalid constraint").with_labels(vec![
-                        constraint.loc.primary(),
-                        field_loc.secondary().with_message(format!(
-                            "`{}` has type {}, expected enum field",
-                            constraint.id,
-                            decl.kind()
-                        )),
-                    ]),
+                    Diagnostic::error()
+                        .with_code(Rule::InvalidConstraint)
+                        .with_message("invalid constraint")
+                        .with_labels(vec![
+                            constraint.loc.primary(),
+                            field_loc.secondary().with_message(format!(
+                                "`{}` has type {}, expected enum field",
+                                constraint.id,
+                                decl.kind()
+                            )),
+                        ]),
                 ),
                 // This error will be reported during field linting
                 (None, _) => (),
             }
         }
 
-        (Some(Field::Scalar { loc: field_loc, .. }), _) => {
-            result.push(Diagnostic::error().with_message("invalid literal type").with_labels(vec![
-                constraint.loc.primary().with_message("expected integer literal"),
-                field_loc.secondary().with_message("the value is used here"),
-            ]))
-        }
         (Some(_), _) => unreachable!(),
         (None, _) => result.push(
             Diagnostic::error()
+                .with_code(Rule::Undeclared)
                 .with_message(format!("undeclared identifier `{}`", constraint.id))
                 .with_labels(vec![constraint.loc.primary()]),
         ),
@@ -480,6 +687,7 @@ impl<'d> Scope<'d> {
                 Some(Mark::Temporary) => {
                     result.push(
                         Diagnostic::error()
+                            .with_code(Rule::RecursiveDeclaration)
                             .with_message(format!(
                                 "recursive declaration of {} `{}`",
                                 decl.kind(),
@@ -510,6 +718,7 @@ impl<'d> Scope<'d> {
                         match scope.typedef.get(group_id) {
                             None => result.push(
                                 Diagnostic::error()
+                                    .with_code(Rule::Undeclared)
                                     .with_message(format!(
                                         "undeclared group identifier `{}`",
                                         group_id
@@ -526,6 +735,7 @@ impl<'d> Scope<'d> {
                             }
                             Some(_) => result.push(
                                 Diagnostic::error()
+                                    .with_code(Rule::InvalidGroup)
                                     .with_message(format!(
                                         "invalid group field identifier `{}`",
                                         group_id
@@ -540,6 +750,7 @@ impl<'d> Scope<'d> {
                         match scope.typedef.get(type_id) {
                             None => result.push(
                                 Diagnostic::error()
+                                    .with_code(Rule::Undeclared)
                                     .with_message(format!(
                                         "undeclared typedef identifier `{}`",
                                         type_id
@@ -562,6 +773,7 @@ impl<'d> Scope<'d> {
                 (Decl::Packet { parent_id: Some(_), .. }, None)
                 | (Decl::Struct { parent_id: Some(_), .. }, None) => result.push(
                     Diagnostic::error()
+                        .with_code(Rule::Undeclared)
                         .with_message(format!(
                             "undeclared parent identifier `{}`",
                             parent_id.unwrap()
@@ -572,6 +784,7 @@ impl<'d> Scope<'d> {
                 (Decl::Packet { .. }, Some(Decl::Struct { .. }))
                 | (Decl::Struct { .. }, Some(Decl::Packet { .. })) => result.push(
                     Diagnostic::error()
+                        .with_code(Rule::InvalidParent)
                         .with_message(format!("invalid parent identifier `{}`", parent_id.unwrap()))
                         .with_labels(vec![decl.loc().primary()])
                         .with_notes(vec![format!("hint: expected {} parent", decl.kind())]),
@@ -623,31 +836,176 @@ impl Field {
     }
 }
 
+// Helper for linting the presence condition of an optional field.
+fn lint_condition(
+    scope: &Scope,
+    packet_scope: &PacketScope,
+    index: usize,
+    cond: &Constraint,
+    result: &mut LintDiagnostics,
+) {
+    // Optional fields are not packed into bit chunks,
+    // their width must be a multiple of 8.
+    let field = packet_scope.fields[index].0.last().unwrap();
+    let width = match field {
+        Field::Scalar { width, .. } => Some(*width),
+        Field::Typedef { type_id, .. } => match scope.typedef.get(type_id) {
+            Some(Decl::Enum { width, .. }) => Some(*width),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(width) = width.filter(|width| width % 8 != 0) {
+        result.push(
+            Diagnostic::error()
+                .with_code(Rule::InvalidCondition)
+                .with_message(format!("invalid width for optional field `{}`", field.id().unwrap()))
+                .with_labels(vec![field
+                    .loc()
+                    .primary()
+                    .with_message(format!("expected a multiple of 8, got {}", width))]),
+        )
+    }
+
+    // The discriminator must be a scalar or enum field declared
+    // before the optional field, or inherited from a parent declaration.
+    let local = packet_scope.fields.iter().position(|f| f.0.last().unwrap().id() == Some(&cond.id));
+    let discriminator = match local {
+        Some(position) if position >= index => {
+            return result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidCondition)
+                    .with_message("invalid condition")
+                    .with_labels(vec![
+                        cond.loc.primary().with_message(format!(
+                            "`{}` is not declared before the optional field",
+                            cond.id
+                        )),
+                        packet_scope.fields[position]
+                            .loc()
+                            .secondary()
+                            .with_message(format!("`{}` is declared here", cond.id)),
+                    ]),
+            )
+        }
+        Some(position) => packet_scope.fields[position].0.last().copied(),
+        None => packet_scope.all_fields.get(&cond.id).copied(),
+    };
+    match discriminator {
+        None => return result.err_undeclared(&cond.id, &cond.loc),
+        Some(Field::Scalar { .. }) => (),
+        Some(Field::Typedef { type_id, .. })
+            if matches!(scope.typedef.get(type_id), Some(Decl::Enum { .. })) => {}
+        Some(field) => {
+            return result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidCondition)
+                    .with_message("invalid condition")
+                    .with_labels(vec![
+                        cond.loc.primary(),
+                        field.loc().secondary().with_message(format!(
+                            "`{}` is declared as {} field, expected scalar or enum field",
+                            cond.id,
+                            field.kind()
+                        )),
+                    ]),
+            )
+        }
+    }
+
+    // Check the condition value.
+    lint_constraint(scope, packet_scope, cond, result)
+}
+
 // Helper for linting an enum declaration.
 fn lint_enum(tags: &[Tag], width: usize, result: &mut LintDiagnostics) {
     let mut local_scope = HashMap::new();
+    let mut default_tag: Option<&Tag> = None;
     for tag in tags {
         // Tags must be unique within the scope of the
         // enum declaration.
-        if let Some(prev) = local_scope.insert(tag.id.clone(), tag) {
+        if let Some(prev) = local_scope.insert(tag.id().clone(), tag) {
             result.push(
                 Diagnostic::error()
-                    .with_message(format!("redeclaration of tag identifier `{}`", &tag.id))
+                    .with_code(Rule::Redeclared)
+                    .with_message(format!("redeclaration of tag identifier `{}`", tag.id()))
                     .with_labels(vec![
-                        tag.loc.primary(),
-                        prev.loc.secondary().with_message("first declared here"),
+                        tag.loc().primary(),
+                        prev.loc().secondary().with_message("first declared here"),
                     ]),
             )
         }
 
         // Tag values must fit the enum declared width.
-        if bit_width(tag.value) > width {
-            result.push(Diagnostic::error().with_message("invalid literal value").with_labels(
-                vec![tag.loc.primary().with_message(format!(
+        let (start, end) = match tag {
+            Tag::Value { value, .. } => (*value, *value),
+            Tag::Range { start, end, .. } => (*start, *end),
+            Tag::Default { .. } => {
+                // At most one default tag can be declared.
+                if let Some(prev) = default_tag.replace(tag) {
+                    result.push(
+                        Diagnostic::error()
+                            .with_code(Rule::InvalidTagValue)
+                            .with_message("duplicate default tag")
+                            .with_labels(vec![
+                                tag.loc().primary(),
+                                prev.loc().secondary().with_message("first declared here"),
+                            ]),
+                    )
+                }
+                continue;
+            }
+        };
+        if bit_width(end) > width || bit_width(start) > width {
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidTagValue)
+                    .with_message("invalid literal value")
+                    .with_labels(vec![tag.loc().primary().with_message(format!(
                         "expected maximum value of `{}`",
                         (1 << width) - 1
-                    ))],
-            ))
+                    ))]),
+            )
+        }
+        if start >= end && matches!(tag, Tag::Range { .. }) {
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidTagValue)
+                    .with_message("invalid tag range")
+                    .with_labels(vec![tag
+                        .loc()
+                        .primary()
+                        .with_message(format!("range `{}..{}` is empty or singular", start, end))]),
+            )
+        }
+    }
+
+    // Range tags must not overlap with other range or value tags.
+    let bounds = |tag: &Tag| match tag {
+        Tag::Value { value, .. } => Some((*value, *value)),
+        Tag::Range { start, end, .. } => Some((*start, *end)),
+        Tag::Default { .. } => None,
+    };
+    for (index, tag) in tags.iter().enumerate() {
+        let Some((start, end)) = bounds(tag) else { continue };
+        let overlapping = tags[..index].iter().find(|other| match bounds(other) {
+            Some((other_start, other_end)) => {
+                (matches!(tag, Tag::Range { .. }) || matches!(other, Tag::Range { .. }))
+                    && start <= other_end
+                    && other_start <= end
+            }
+            None => false,
+        });
+        if let Some(other) = overlapping {
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidTagValue)
+                    .with_message(format!("tag `{}` overlaps with tag `{}`", tag.id(), other.id()))
+                    .with_labels(vec![
+                        tag.loc().primary(),
+                        other.loc().secondary().with_message("first declared here"),
+                    ]),
+            )
         }
     }
 }
@@ -660,7 +1018,7 @@ fn lint_checksum(
     field_id: &str,
     result: &mut LintDiagnostics,
 ) {
-    // Checksum field must be declared before
+    // Checksum field must be declared after
     // the checksum start. The field must be a typedef with
     // a valid checksum type.
     let checksum_loc = path.loc();
@@ -673,6 +1031,7 @@ fn lint_checksum(
                 Some(Decl::Checksum { .. }) => (),
                 Some(decl) => result.push(
                     Diagnostic::error()
+                        .with_code(Rule::InvalidChecksum)
                         .with_message(format!("checksum start uses invalid field `{}`", field_id))
                         .with_labels(vec![
                             checksum_loc.primary(),
@@ -690,13 +1049,14 @@ fn lint_checksum(
             };
             // Check declaration order of checksum field.
             match field_decl.and_then(|f| f.0.first()) {
-                Some(decl) if decl.loc().start > checksum_loc.start => result.push(
+                Some(decl) if decl.loc().start < checksum_loc.start => result.push(
                     Diagnostic::error()
+                        .with_code(Rule::InvalidChecksum)
                         .with_message("invalid checksum start declaration")
                         .with_labels(vec![
                             checksum_loc
                                 .primary()
-                                .with_message("checksum start precedes checksum field"),
+                                .with_message("checksum start follows checksum field"),
                             decl.loc().secondary().with_message("checksum field is declared here"),
                         ]),
                 ),
@@ -705,6 +1065,7 @@ fn lint_checksum(
         }
         Some(field) => result.push(
             Diagnostic::error()
+                .with_code(Rule::InvalidChecksum)
                 .with_message(format!("checksum start uses invalid field `{}`", field_id))
                 .with_labels(vec![
                     checksum_loc.primary(),
@@ -719,6 +1080,32 @@ fn lint_checksum(
     }
 }
 
+// Helper for linting a checksum declaration.
+fn lint_checksum_decl(
+    id: &str,
+    loc: &SourceRange,
+    function: &str,
+    width: usize,
+    result: &mut LintDiagnostics,
+) {
+    // The declared width must match the width of the
+    // named algorithm. Functions which are not part of the
+    // library are resolved by the host language.
+    if let Some(algorithm) = checksum::lookup(function.trim_matches('"')) {
+        if algorithm.width != width {
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidChecksum)
+                    .with_message(format!("invalid width for checksum `{}`", id))
+                    .with_labels(vec![loc.primary().with_message(format!(
+                        "expected width {}, `{}` computes {}-bit checksums",
+                        algorithm.width, algorithm.name, algorithm.width
+                    ))]),
+            )
+        }
+    }
+}
+
 // Helper for linting size fields.
 fn lint_size(
     _scope: &Scope,
@@ -739,18 +1126,25 @@ fn lint_size(
         return match packet_scope.payload.as_ref().and_then(|f| f.0.last()) {
             Some(Field::Body { .. }) => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::InvalidSize)
                     .with_message("size field uses undeclared payload field, did you mean _body_ ?")
                     .with_labels(vec![size_loc.primary()]),
             ),
             Some(Field::Payload { .. }) => {
                 match packet_scope.payload.as_ref().and_then(|f| f.0.first()) {
                     Some(field) if field.loc().start < size_loc.start => result.push(
-                        Diagnostic::error().with_message("invalid size field").with_labels(vec![
-                            size_loc
-                                .primary()
-                                .with_message("size field is declared after payload field"),
-                            field.loc().secondary().with_message("payload field is declared here"),
-                        ]),
+                        Diagnostic::error()
+                            .with_code(Rule::InvalidSize)
+                            .with_message("invalid size field")
+                            .with_labels(vec![
+                                size_loc
+                                    .primary()
+                                    .with_message("size field is declared after payload field"),
+                                field
+                                    .loc()
+                                    .secondary()
+                                    .with_message("payload field is declared here"),
+                            ]),
                     ),
                     _ => (),
                 }
@@ -758,6 +1152,7 @@ fn lint_size(
             Some(_) => unreachable!(),
             None => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::InvalidSize)
                     .with_message("size field uses undeclared payload field")
                     .with_labels(vec![size_loc.primary()]),
             ),
@@ -767,18 +1162,22 @@ fn lint_size(
         return match packet_scope.payload.as_ref().and_then(|f| f.0.last()) {
             Some(Field::Payload { .. }) => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::InvalidSize)
                     .with_message("size field uses undeclared body field, did you mean _payload_ ?")
                     .with_labels(vec![size_loc.primary()]),
             ),
             Some(Field::Body { .. }) => {
                 match packet_scope.payload.as_ref().and_then(|f| f.0.first()) {
                     Some(field) if field.loc().start < size_loc.start => result.push(
-                        Diagnostic::error().with_message("invalid size field").with_labels(vec![
-                            size_loc
-                                .primary()
-                                .with_message("size field is declared after body field"),
-                            field.loc().secondary().with_message("body field is declared here"),
-                        ]),
+                        Diagnostic::error()
+                            .with_code(Rule::InvalidSize)
+                            .with_message("invalid size field")
+                            .with_labels(vec![
+                                size_loc
+                                    .primary()
+                                    .with_message("size field is declared after body field"),
+                                field.loc().secondary().with_message("body field is declared here"),
+                            ]),
                     ),
                     _ => (),
                 }
@@ -786,6 +1185,7 @@ fn lint_size(
             Some(_) => unreachable!(),
             None => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::InvalidSize)
                     .with_message("size field uses undeclared body field")
                     .with_labels(vec![size_loc.primary()]),
             ),
@@ -797,6 +1197,7 @@ fn lint_size(
     match field.and_then(|f| f.0.last()) {
         Some(Field::Array { size: Some(_), loc: array_loc, .. }) => result.push(
             Diagnostic::warning()
+                .with_code(Rule::StaticArray)
                 .with_message(format!("size field uses array `{}` with static size", field_id))
                 .with_labels(vec![
                     size_loc.primary(),
@@ -806,6 +1207,7 @@ fn lint_size(
         Some(Field::Array { .. }) => (),
         Some(field) => result.push(
             Diagnostic::error()
+                .with_code(Rule::InvalidSize)
                 .with_message(format!("invalid `{}` field type", field_id))
                 .with_labels(vec![
                     field.loc().primary().with_message(format!(
@@ -822,8 +1224,11 @@ fn lint_size(
         None => result.err_undeclared(field_id, size_loc),
     };
     match field.and_then(|f| f.0.first()) {
-        Some(field) if field.loc().start < size_loc.start => {
-            result.push(Diagnostic::error().with_message("invalid size field").with_labels(vec![
+        Some(field) if field.loc().start < size_loc.start => result.push(
+            Diagnostic::error()
+                .with_code(Rule::InvalidSize)
+                .with_message("invalid size field")
+                .with_labels(vec![
                     size_loc
                         .primary()
                         .with_message(format!("size field is declared after field `{}`", field_id)),
@@ -831,8 +1236,8 @@ fn lint_size(
                         .loc()
                         .secondary()
                         .with_message(format!("`{}` is declared here", field_id)),
-                ]))
-        }
+                ]),
+        ),
         _ => (),
     }
 }
@@ -856,6 +1261,7 @@ fn lint_count(
     match field.and_then(|f| f.0.last()) {
         Some(Field::Array { size: Some(_), loc: array_loc, .. }) => result.push(
             Diagnostic::warning()
+                .with_code(Rule::StaticArray)
                 .with_message(format!("count field uses array `{}` with static size", field_id))
                 .with_labels(vec![
                     count_loc.primary(),
@@ -866,6 +1272,7 @@ fn lint_count(
         Some(Field::Array { .. }) => (),
         Some(field) => result.push(
             Diagnostic::error()
+                .with_code(Rule::InvalidCount)
                 .with_message(format!("invalid `{}` field type", field_id))
                 .with_labels(vec![
                     field.loc().primary().with_message(format!(
@@ -882,8 +1289,11 @@ fn lint_count(
         None => result.err_undeclared(field_id, count_loc),
     };
     match field.and_then(|f| f.0.first()) {
-        Some(field) if field.loc().start < count_loc.start => {
-            result.push(Diagnostic::error().with_message("invalid count field").with_labels(vec![
+        Some(field) if field.loc().start < count_loc.start => result.push(
+            Diagnostic::error()
+                .with_code(Rule::InvalidCount)
+                .with_message("invalid count field")
+                .with_labels(vec![
                     count_loc.primary().with_message(format!(
                         "count field is declared after field `{}`",
                         field_id
@@ -892,8 +1302,8 @@ fn lint_count(
                         .loc()
                         .secondary()
                         .with_message(format!("`{}` is declared here", field_id)),
-                ]))
-        }
+                ]),
+        ),
         _ => (),
     }
 }
@@ -918,22 +1328,38 @@ fn lint_fixed(
     if width.is_some() {
         // The value of a fixed field should have .
         if bit_width(value.unwrap()) > width.unwrap() {
-            result.push(Diagnostic::error().with_message("invalid integer literal").with_labels(
-                vec![fixed_loc.primary().with_message(format!(
-                    "expected maximum value of `{}`",
-                    (1 << width.unwrap()) - 1
-                ))],
-            ))
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidFixed)
+                    .with_message("invalid integer literal")
+                    .with_labels(vec![fixed_loc.primary().with_message(format!(
+                        "expected maximum value of `{}`",
+                        (1 << width.unwrap()) - 1
+                    ))]),
+            )
         }
     } else {
         // The fixed field should reference a valid enum id and tag id
         // association.
         match scope.typedef.get(enum_id.as_ref().unwrap()) {
             Some(Decl::Enum { tags, .. }) => {
-                match tags.iter().find(|t| &t.id == tag_id.as_ref().unwrap()) {
+                match tags.iter().find(|t| t.id() == tag_id.as_ref().unwrap()) {
+                    Some(tag) if tag.value().is_none() => result.push(
+                        Diagnostic::error()
+                            .with_code(Rule::InvalidFixed)
+                            .with_message(format!(
+                                "invalid enum tag `{}`",
+                                tag_id.as_ref().unwrap()
+                            ))
+                            .with_labels(vec![
+                                fixed_loc.primary().with_message("expected a single value tag"),
+                                tag.loc().secondary().with_message("tag declared here"),
+                            ]),
+                    ),
                     Some(_) => (),
                     None => result.push(
                         Diagnostic::error()
+                            .with_code(Rule::Undeclared)
                             .with_message(format!(
                                 "undeclared enum tag `{}`",
                                 tag_id.as_ref().unwrap()
@@ -944,6 +1370,7 @@ fn lint_fixed(
             }
             Some(decl) => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::InvalidFixed)
                     .with_message(format!(
                         "fixed field uses invalid typedef `{}`",
                         decl.id().unwrap()
@@ -956,6 +1383,7 @@ fn lint_fixed(
             ),
             None => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::Undeclared)
                     .with_message(format!("undeclared enum type `{}`", enum_id.as_ref().unwrap()))
                     .with_labels(vec![fixed_loc.primary()]),
             ),
@@ -990,6 +1418,7 @@ fn lint_array(
             | Some(Decl::CustomField { .. }) => (),
             Some(decl) => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::InvalidArray)
                     .with_message(format!(
                         "array field uses invalid {} element type `{}`",
                         decl.kind(),
@@ -1000,6 +1429,7 @@ fn lint_array(
             ),
             None => result.push(
                 Diagnostic::error()
+                    .with_code(Rule::Undeclared)
                     .with_message(format!(
                         "array field uses undeclared element type `{}`",
                         type_id.as_ref().unwrap()
@@ -1033,6 +1463,7 @@ fn lint_typedef(
 
         Some(decl) => result.push(
             Diagnostic::error()
+                .with_code(Rule::InvalidTypedef)
                 .with_message(format!(
                     "typedef field uses invalid {} element type `{}`",
                     decl.kind(),
@@ -1043,6 +1474,7 @@ fn lint_typedef(
         ),
         None => result.push(
             Diagnostic::error()
+                .with_code(Rule::Undeclared)
                 .with_message(format!("typedef field uses undeclared element type `{}`", type_id))
                 .with_labels(vec![typedef_loc.primary()])
                 .with_notes(vec!["hint: expected enum, struct, custom_field, checksum".to_owned()]),
@@ -1103,6 +1535,7 @@ fn lint_packet(
         // no inheritance.
         result.push(
             Diagnostic::warning()
+                .with_code(Rule::OrphanConstraints)
                 .with_message(format!(
                     "packet `{}` has field constraints, but no parent declaration",
                     id
@@ -1116,8 +1549,11 @@ fn lint_packet(
     // Scope validation was done before, so it must exist.
     let packet_scope = &scope.scopes.get(&decl).unwrap();
 
-    for field in packet_scope.fields.iter() {
-        lint_field(scope, packet_scope, field, result)
+    for (index, field) in packet_scope.fields.iter().enumerate() {
+        lint_field(scope, packet_scope, field, result);
+        if let Some(cond) = field.0.last().unwrap().cond() {
+            lint_condition(scope, packet_scope, index, cond, result)
+        }
     }
 }
 
@@ -1141,6 +1577,7 @@ fn lint_struct(
         // no inheritance.
         result.push(
             Diagnostic::warning()
+                .with_code(Rule::OrphanConstraints)
                 .with_message(format!(
                     "struct `{}` has field constraints, but no parent declaration",
                     id
@@ -1154,23 +1591,140 @@ fn lint_struct(
     // Scope validation was done before, so it must exist.
     let packet_scope = &scope.scopes.get(&decl).unwrap();
 
-    for field in packet_scope.fields.iter() {
-        lint_field(scope, packet_scope, field, result)
+    for (index, field) in packet_scope.fields.iter().enumerate() {
+        lint_field(scope, packet_scope, field, result);
+        if let Some(cond) = field.0.last().unwrap().cond() {
+            lint_condition(scope, packet_scope, index, cond, result)
+        }
     }
 }
 
-impl Decl {
-    fn constraints(&self) -> impl Iterator<Item = &Constraint> {
-        match self {
-            Decl::Packet { constraints, .. } | Decl::Struct { constraints, .. } => {
-                Some(constraints.iter())
+// Helper for linting a test declaration.
+fn lint_test(
+    scope: &Scope,
+    type_id: &str,
+    loc: &SourceRange,
+    test_cases: &[TestCase],
+    result: &mut LintDiagnostics,
+) {
+    match scope.typedef.get(type_id) {
+        None => return result.err_undeclared(type_id, loc),
+        Some(Decl::Packet { .. }) => (),
+        Some(decl) => {
+            return result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidTest)
+                    .with_message(format!("invalid test declaration for `{}`", type_id))
+                    .with_labels(vec![
+                        loc.primary(),
+                        decl.loc().secondary().with_message(format!(
+                            "`{}` is declared as {}, expected packet",
+                            type_id,
+                            decl.kind()
+                        )),
+                    ]),
+            )
+        }
+    }
+
+    for test_case in test_cases {
+        if let Err(message) = test_case.bytes() {
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidTest)
+                    .with_message("invalid test case input")
+                    .with_labels(vec![test_case.loc.primary().with_message(message)]),
+            )
+        }
+
+        // The expected specialization must be the tested packet
+        // or one of its descendants.
+        let packet_id = match &test_case.packet_id {
+            Some(packet_id) => packet_id,
+            None => continue,
+        };
+        let mut decl = scope.typedef.get(packet_id).copied();
+        if !matches!(decl, Some(Decl::Packet { .. })) {
+            result.err_undeclared(packet_id, &test_case.loc);
+            continue;
+        }
+        while let Some(current) = decl.filter(|d| d.id().unwrap() != type_id) {
+            decl = current.parent_id().and_then(|id| scope.typedef.get(id)).copied();
+        }
+        if decl.is_none() {
+            result.push(
+                Diagnostic::error()
+                    .with_code(Rule::InvalidTest)
+                    .with_message("invalid test case specialization")
+                    .with_labels(vec![test_case.loc.primary().with_message(format!(
+                        "`{}` is not a specialization of `{}`",
+                        packet_id, type_id
+                    ))]),
+            )
+        }
+    }
+}
+
+/// Verify that the test cases of the grammar parse as the tested
+/// packets, resolve to the expected specializations, and hold the
+/// expected field values. The grammar must not have lint errors.
+/// Failures are reported as warnings, so that generation of the
+/// packets is not blocked by a faulty test vector.
+fn lint_test_cases(grammar: &Grammar, result: &mut LintDiagnostics) {
+    for decl in &grammar.declarations {
+        let (type_id, test_cases) = match decl {
+            Decl::Test { type_id, test_cases, .. } => (type_id, test_cases),
+            _ => continue,
+        };
+        for test_case in test_cases {
+            let invalid = |message: String| {
+                Diagnostic::warning()
+                    .with_code(Rule::InvalidTest)
+                    .with_message(format!("test case failed for `{}`", type_id))
+                    .with_labels(vec![test_case.loc.primary().with_message(message)])
+            };
+            let value = match decoder::decode(grammar, type_id, &test_case.bytes().unwrap()) {
+                Ok(value) => value,
+                Err(message) => {
+                    result.push(invalid(message));
+                    continue;
+                }
+            };
+
+            // Collect the field values of the decoded lineage, and
+            // the most specific decoded packet.
+            let mut fields = serde_json::Map::new();
+            let mut node = &value;
+            loop {
+                fields.extend(node["fields"].as_object().cloned().unwrap_or_default());
+                match node.get("child") {
+                    Some(child) => node = child,
+                    None => break,
+                }
+            }
+            let packet_id = node["packet"].as_str().unwrap();
+            if let Some(expected) = test_case.packet_id.as_ref().filter(|id| *id != packet_id) {
+                result.push(invalid(format!("parsed as `{}`, expected `{}`", packet_id, expected)));
+            }
+            for constraint in &test_case.constraints {
+                match fields.get(&constraint.id) {
+                    None => result.push(invalid(format!(
+                        "`{}` is not a field of `{}`",
+                        constraint.id, packet_id
+                    ))),
+                    Some(value) if !interpreter::constraint_matches(value, constraint) => result
+                        .push(invalid(format!(
+                            "field `{}` does not have the expected value, got {}",
+                            constraint.id, value
+                        ))),
+                    Some(_) => (),
+                }
             }
-            _ => None,
         }
-        .into_iter()
-        .flatten()
     }
+}
 
+impl Decl {
     fn scope<'d>(&'d self, result: &mut LintDiagnostics) -> Option<PacketScope<'d>> {
         match self {
             Decl::Packet { fields, .. }
@@ -1199,7 +1753,10 @@ impl Decl {
 
     fn lint<'d>(&'d self, scope: &Scope<'d>, result: &mut LintDiagnostics) {
         match self {
-            Decl::Checksum { .. } | Decl::CustomField { .. } => (),
+            Decl::Checksum { id, loc, function, width } => {
+                lint_checksum_decl(id, loc, function, *width, result)
+            }
+            Decl::CustomField { .. } => (),
             Decl::Enum { tags, width, .. } => lint_enum(tags, *width, result),
             Decl::Packet { id, loc, constraints, parent_id, .. } => {
                 lint_packet(scope, self, id, loc, constraints, parent_id, result)
@@ -1210,11 +1767,13 @@ impl Decl {
             // Groups are finalizeed before linting, to make sure
             // potential errors are raised only once.
             Decl::Group { .. } => (),
-            Decl::Test { .. } => (),
+            Decl::Test { type_id, loc, test_cases } => {
+                lint_test(scope, type_id, loc, test_cases, result)
+            }
         }
     }
 
-    fn kind(&self) -> &str {
+    pub fn kind(&self) -> &str {
         match self {
             Decl::Checksum { .. } => "checksum",
             Decl::CustomField { .. } => "custom field",
@@ -1249,18 +1808,86 @@ impl Grammar {
         scope.finalize(result);
         scope
     }
+
+    /// Collect the suppression directives from the grammar comments.
+    /// A directive applies to the declaration containing it, or to the
+    /// first declaration following it.
+    fn suppressions(&self, result: &mut LintDiagnostics) -> Vec<Suppression> {
+        let mut suppressions = vec![];
+        for comment in &self.comments {
+            let text = comment.text.trim_start_matches('/').trim_start_matches('*');
+            let text = text.trim_end_matches('/').trim_end_matches('*').trim();
+            let directive = match text.strip_prefix("pdl-lint:") {
+                Some(directive) => directive.trim(),
+                None => continue,
+            };
+            let rules = match directive.strip_prefix("allow(").and_then(|r| r.strip_suffix(')')) {
+                Some(rules) => rules,
+                None => {
+                    result.push(
+                        Diagnostic::warning()
+                            .with_code(Rule::InvalidDirective)
+                            .with_message("invalid lint directive")
+                            .with_labels(vec![comment.loc.primary()])
+                            .with_notes(vec![
+                                "hint: expected `pdl-lint: allow(<rule>, ..)`".to_owned()
+                            ]),
+                    );
+                    continue;
+                }
+            };
+            let decl = self.declarations.iter().find(|decl| {
+                decl.loc().file == comment.loc.file
+                    && decl.loc().end.offset > comment.loc.start.offset
+            });
+            let decl = match decl {
+                Some(decl) => decl,
+                None => {
+                    result.push(
+                        Diagnostic::warning()
+                            .with_code(Rule::InvalidDirective)
+                            .with_message("lint directive does not apply to any declaration")
+                            .with_labels(vec![comment.loc.primary()]),
+                    );
+                    continue;
+                }
+            };
+            for rule in rules.split(',').map(str::trim) {
+                match rule.parse::<Rule>() {
+                    Ok(rule) => suppressions.push(Suppression { loc: decl.loc().clone(), rule }),
+                    Err(message) => result.push(
+                        Diagnostic::warning()
+                            .with_code(Rule::InvalidDirective)
+                            .with_message(message)
+                            .with_labels(vec![comment.loc.primary()]),
+                    ),
+                }
+            }
+        }
+        suppressions
+    }
 }
 
 impl Lintable for Grammar {
     fn lint(&self) -> LintDiagnostics {
+        // Invalid directives are reported last, and do not prevent
+        // the declarations from being linted.
+        let mut directives = LintDiagnostics::new();
+        let suppressions = self.suppressions(&mut directives);
         let mut result = LintDiagnostics::new();
         let scope = self.scope(&mut result);
-        if !result.diagnostics.is_empty() {
-            return result;
+        result.suppress(&suppressions);
+        if result.diagnostics.is_empty() {
+            for decl in &self.declarations {
+                decl.lint(&scope, &mut result)
+            }
+            result.suppress(&suppressions);
         }
-        for decl in &self.declarations {
-            decl.lint(&scope, &mut result)
+        if !result.has_errors() {
+            lint_test_cases(self, &mut result);
+            result.suppress(&suppressions);
         }
+        result.diagnostics.append(&mut directives.diagnostics);
         result
     }
 }
@@ -1270,6 +1897,7 @@ mod test {
     use crate::ast::*;
     use crate::lint::Lintable;
     use crate::parser::parse_inline;
+    use codespan_reporting::diagnostic::Severity;
 
     macro_rules! grammar {
         ($db:expr, $text:literal) => {
@@ -1291,4 +1919,349 @@ mod test {
         let result = grammar.lint();
         assert!(!result.diagnostics.is_empty());
     }
+
+    #[test]
+    fn test_imported_declarations() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path.display().to_string()
+        };
+        write("common.pdl", "little_endian_packets enum Name : 8 { A = 0 }");
+        let valid = write("valid.pdl", "import \"common.pdl\" packet P { a: Name }");
+        let redeclared = write("redeclared.pdl", "import \"common.pdl\" struct Name { }");
+
+        // Declarations of imported files are in scope.
+        let mut db = SourceDatabase::new();
+        let grammar = crate::parser::parse_file(&mut db, valid).expect("parsing failure");
+        assert!(grammar.lint().diagnostics.is_empty());
+
+        // Diagnostics reference the file of each declaration.
+        let mut db = SourceDatabase::new();
+        let grammar = crate::parser::parse_file(&mut db, redeclared).expect("parsing failure");
+        let result = grammar.lint();
+        assert_eq!(result.diagnostics.len(), 1);
+        let files =
+            result.diagnostics[0].labels.iter().map(|label| label.file_id).collect::<Vec<_>>();
+        assert_eq!(files.len(), 2);
+        assert_ne!(files[0], files[1]);
+        assert!(files.contains(&grammar.file));
+    }
+    #[test]
+    fn test_rule_codes() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        packet A { _size_(b) : 8, }
+        "#
+        );
+        let result = grammar.lint();
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].code.as_deref(), Some("undeclared"));
+        assert!(result.has_errors());
+    }
+
+    #[test]
+    fn test_suppression() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        // pdl-lint: allow(orphan-constraints)
+        packet A (a = 1) { }
+        packet B (b = 1) {
+          /* pdl-lint: allow(invalid-size, orphan-constraints) */
+        }
+        packet C (c = 1) { }
+        // pdl-lint: allow(unknown-rule)
+        packet D { }
+        "#
+        );
+        let result = grammar.lint();
+        let codes = result
+            .diagnostics
+            .iter()
+            .map(|d| (d.severity, d.code.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            vec![
+                (Severity::Warning, "orphan-constraints"),
+                (Severity::Warning, "invalid-directive")
+            ]
+        );
+        assert!(!result.has_errors());
+
+        // Errors cannot be suppressed.
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        // pdl-lint: allow(undeclared)
+        packet A { _size_(b) : 8, }
+        "#
+        );
+        assert!(grammar.lint().has_errors());
+    }
+    #[test]
+    fn test_checksum() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        checksum Crc32 : 32 "crc32"
+        checksum Crc24 : 16 "crc24-ble"
+        checksum Host : 16 "host_checksum"
+        packet A { _checksum_start_(crc), a: 8, crc: Crc32 }
+        packet B { _checksum_start_(crc), a: 8, crc: Host }
+        "#
+        );
+        let result = grammar.lint();
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].message, "invalid width for checksum `Crc24`");
+
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        checksum Crc32 : 32 "crc32"
+        packet A { crc: Crc32, a: 8, _checksum_start_(crc) }
+        "#
+        );
+        let result = grammar.lint();
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].message, "invalid checksum start declaration");
+    }
+
+    #[test]
+    fn test_enum_tags() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum Valid : 16 { A = 1, B = 0x10..0x1f, C = 0x20..0xffff, D = .. }
+        packet P { a: Valid, _fixed_ = A : Valid, _payload_ }
+        packet Q : P (a = A) {}
+        "#
+        );
+        assert!(!grammar.lint().has_errors());
+
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum E : 8 {
+            A = 0x10,
+            B = 0x08..0x10,
+            C = 0x0c..0x0f,
+            D = 0x20..0x20,
+            E = 0xf0..0x100,
+            F = ..,
+            G = ..,
+        }
+        "#
+        );
+        let result = grammar.lint();
+        let mut messages =
+            result.diagnostics.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            vec![
+                "duplicate default tag",
+                "invalid literal value",
+                "invalid tag range",
+                "tag `B` overlaps with tag `A`",
+                "tag `C` overlaps with tag `B`",
+            ]
+        );
+
+        // Range and default tags cannot be used as constraint or
+        // fixed values.
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum E : 8 { A = 1, B = 0x10..0x1f, C = .. }
+        packet P { a: E, _fixed_ = B : E }
+        "#
+        );
+        let result = grammar.lint();
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].message, "invalid enum tag `B`");
+
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum E : 8 { A = 1, B = 0x10..0x1f, C = .. }
+        packet P { a: E, _payload_ }
+        packet Q : P (a = C) {}
+        "#
+        );
+        let result = grammar.lint();
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].message, "invalid enum tag `C`");
+    }
+
+    #[test]
+    fn test_constraint_expressions() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum E : 8 { A = 1 }
+        packet P { a: 8, b: 8, e: E, _payload_ }
+        packet C1 : P (a = 0x10 | 0x1, b = 1 << 7) {}
+        packet C2 : P (a = 1 << 8) {}
+        packet C3 : P (a = 1 - 2) {}
+        packet C4 : P (a = A) {}
+        packet C5 : P (e = A + 1) {}
+        "#
+        );
+        let result = grammar.lint();
+        let mut messages =
+            result.diagnostics.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            vec![
+                "invalid constant expression",
+                "invalid integer literal",
+                "invalid literal type",
+                "invalid literal type",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_test_declarations() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum OpCode : 8 { RESET = 1, READ = 2 }
+        struct S { a: 8 }
+        packet Command { op_code: OpCode, _payload_ }
+        packet Reset : Command (op_code = RESET) { delay: 8 }
+        packet Read : Command (op_code = READ) {}
+        packet Other { a: 8 }
+        test Command {
+            "\x01\x02",
+            "\x01\x02" : Reset (op_code = RESET, delay = 2),
+            "\x02" : Read,
+            "\x01\x02" : Read,
+            "\x01\x03" : Reset (delay = 2),
+            "\x01\x02" (length = 2),
+            "\x01",
+        }
+        "#
+        );
+        let result = grammar.lint();
+        let messages = result
+            .diagnostics
+            .iter()
+            .map(|d| (d.severity, d.message.as_str(), d.labels[0].message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    Severity::Warning,
+                    "test case failed for `Command`",
+                    "parsed as `Reset`, expected `Read`"
+                ),
+                (
+                    Severity::Warning,
+                    "test case failed for `Command`",
+                    "field `delay` does not have the expected value, got 3"
+                ),
+                (
+                    Severity::Warning,
+                    "test case failed for `Command`",
+                    "`length` is not a field of `Reset`"
+                ),
+                (
+                    Severity::Warning,
+                    "test case failed for `Command`",
+                    "when parsing Reset._chunk_ needed length of 1 but got 0"
+                ),
+            ]
+        );
+
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        struct S { a: 8 }
+        packet P { a: 8 }
+        packet Q { a: 8 }
+        test S { "\x01" }
+        test P { "\x01" : Q, "\x0" }
+        test R { "\x01" }
+        "#
+        );
+        let result = grammar.lint();
+        let messages = result
+            .diagnostics
+            .iter()
+            .map(|d| (d.code.as_deref().unwrap(), d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                ("invalid-test", "invalid test declaration for `S`"),
+                ("invalid-test", "invalid test case specialization"),
+                ("invalid-test", "invalid test case input"),
+                ("undeclared", "undeclared identifier `R`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditional_fields() {
+        let mut db = SourceDatabase::new();
+        let grammar = grammar!(
+            &mut db,
+            r#"
+        little_endian_packets
+        enum Kind : 8 { SHORT = 0, LONG = 1 }
+        struct S { a: 8 }
+        packet P { kind: Kind, flag: 1, _reserved_: 7, _payload_ }
+        packet A : P { x: 16 if kind = LONG, y: S if flag = 1 }
+        packet B { x: 16 if kind = LONG, kind: Kind }
+        packet C : P { x: 4 if flag = 1, _reserved_: 4 }
+        packet D : P { x: 8 if kind = 2, s: S, y: 8 if s = 1 }
+        "#
+        );
+        let result = grammar.lint();
+        let mut messages = result
+            .diagnostics
+            .iter()
+            .map(|d| (d.code.as_deref().unwrap(), d.message.as_str()))
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            vec![
+                ("invalid-condition", "invalid condition"),
+                ("invalid-condition", "invalid condition"),
+                ("invalid-condition", "invalid width for optional field `x`"),
+                ("invalid-constraint", "invalid literal type"),
+            ]
+        );
+    }
 }
@@ -0,0 +1,897 @@
+//! Language server.
+//!
+//! Implements the Language Server Protocol over stdio for PDL source
+//! files. The server publishes the parser and linter diagnostics of
+//! the open documents, resolves the definition and references of
+//! declaration and enum tag identifiers, shows the width and offset of
+//! fields on hover, and completes declared type names.
+//!
+//! Messages are JSON-RPC 2.0 objects framed with a `Content-Length`
+//! header. Documents are synchronized in full, and analyzed again for
+//! every request.
+
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
+use codespan_reporting::files::Files;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::lint::Lintable;
+use crate::parser;
+
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for invalid JSON messages.
+const PARSE_ERROR: i64 = -32700;
+
+/// Identifier referenced in the grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Symbol {
+    /// Top-level declaration.
+    Decl(String),
+    /// Tag of an enum declaration.
+    Tag(String, String),
+}
+
+/// Occurrence of a symbol in a source file, at the byte range `range`.
+#[derive(Debug)]
+struct Occurrence {
+    symbol: Symbol,
+    file: FileId,
+    range: Range<usize>,
+    definition: bool,
+}
+
+/// Result of the analysis of a document and of the files it imports.
+struct Analysis {
+    sources: SourceDatabase,
+    /// File identifier of the document.
+    file: FileId,
+    grammar: Option<Grammar>,
+    diagnostics: Vec<Diagnostic<FileId>>,
+}
+
+/// Open document.
+struct Document {
+    path: String,
+    text: String,
+    /// Declared type names of the last successful analysis, with their
+    /// kind, kept for completion while the document does not parse.
+    names: Vec<(String, String)>,
+}
+
+/// Language server state.
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+/// Convert a `file` URI to a path.
+fn uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = vec![];
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        let escaped = (b == b'%')
+            .then(|| iter.clone().take(2).collect::<Vec<_>>())
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                iter.nth(1);
+            }
+            None => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Convert a path to a `file` URI.
+fn path_to_uri(path: &str) -> String {
+    let mut uri = "file://".to_owned();
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// Convert a byte offset of `text` to an LSP position. Characters are
+/// counted in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let offset = std::cmp::min(offset, text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let line = text[..line_start].matches('\n').count();
+    let character = text[line_start..offset].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+/// Convert an LSP position to a byte offset of `text`.
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let line_start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let line_text = text[line_start..].split('\n').next().unwrap();
+    let mut units = 0;
+    for (index, c) in line_text.char_indices() {
+        if units >= character {
+            return Some(line_start + index);
+        }
+        units += c.len_utf16();
+    }
+    Some(line_start + line_text.len())
+}
+
+/// Return the byte range of the first occurrence of the identifier
+/// `word` in `source[range]`, starting from the offset `from`.
+fn find_word(source: &str, range: Range<usize>, from: usize, word: &str) -> Option<Range<usize>> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let start = std::cmp::max(range.start, from);
+    let text = source.get(start..range.end)?;
+    text.match_indices(word).map(|(index, _)| start + index).find_map(|index| {
+        let end = index + word.len();
+        let before = source[..index].chars().next_back().is_some_and(is_ident);
+        let after = source[end..].chars().next().is_some_and(is_ident);
+        (!before && !after).then_some(index..end)
+    })
+}
+
+/// Return the inheritance chain of a declaration, starting from the
+/// root ancestor. Inheritance cycles are cut.
+fn lineage<'d>(scope: &Scope<'d>, decl: &'d Decl) -> Vec<&'d Decl> {
+    let mut lineage = vec![decl];
+    while let Some(parent) = scope.get_parent(lineage.last().unwrap()) {
+        if lineage.iter().any(|d| std::ptr::eq(*d, parent)) {
+            break;
+        }
+        lineage.push(parent);
+    }
+    lineage.reverse();
+    lineage
+}
+
+/// Return the enum tag referenced by the value of a constraint on
+/// the field `id` of the declaration `decl` or of its ancestors.
+fn constraint_tag(scope: &Scope, decl: &Decl, constraint: &Constraint) -> Option<Symbol> {
+    let name = match &constraint.value {
+        Expr::Identifier { name, .. } => name,
+        _ => return None,
+    };
+    let field = lineage(scope, decl)
+        .into_iter()
+        .flat_map(|d| d.fields())
+        .find(|f| f.id() == Some(&constraint.id))?;
+    match field {
+        Field::Typedef { type_id, .. } => Some(Symbol::Tag(type_id.clone(), name.clone())),
+        _ => None,
+    }
+}
+
+/// Collect the occurrences of the declaration and enum tag
+/// identifiers of the grammar.
+fn occurrences(sources: &SourceDatabase, grammar: &Grammar) -> Vec<Occurrence> {
+    let scope = Scope::new(grammar);
+    let mut result = vec![];
+    for decl in &grammar.declarations {
+        let loc = decl.loc();
+        let source = match sources.source(loc.file) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        let mut push = |symbol: Symbol, range: Option<Range<usize>>, definition: bool| {
+            if let Some(range) = range {
+                result.push(Occurrence { symbol, file: loc.file, range, definition })
+            }
+        };
+        let span = loc.start.offset..loc.end.offset;
+        // The header ends with the opening brace of the body.
+        let header =
+            span.start..source[span.clone()].find('{').map_or(span.end, |index| span.start + index);
+
+        let mut header_end = header.start;
+        if let Some(id) = decl.id() {
+            let range = find_word(source, header.clone(), header.start + 1, id);
+            header_end = range.as_ref().map_or(header_end, |range| range.end);
+            push(Symbol::Decl(id.clone()), range, true);
+        }
+        if let Some(parent_id) = decl.parent_id() {
+            let range = find_word(source, header.clone(), header_end, parent_id);
+            push(Symbol::Decl(parent_id.clone()), range, false);
+        }
+        let parent = scope.get_parent(decl);
+        for constraint in decl.constraints() {
+            let tag = parent.and_then(|parent| constraint_tag(&scope, parent, constraint));
+            push_tag(&mut push, tag, &constraint.value);
+        }
+
+        match decl {
+            Decl::Enum { id, tags, .. } => {
+                for tag in tags {
+                    let range = tag.loc().start.offset..tag.loc().end.offset;
+                    let range = find_word(source, range.clone(), range.start, tag.id());
+                    push(Symbol::Tag(id.clone(), tag.id().clone()), range, true);
+                }
+            }
+            Decl::Test { type_id, test_cases, .. } => {
+                let range = find_word(source, header, span.start + 1, type_id);
+                push(Symbol::Decl(type_id.clone()), range, false);
+                for test_case in test_cases {
+                    let range = test_case.loc.start.offset..test_case.loc.end.offset;
+                    let from = range.start + test_case.input.len();
+                    let packet_id = test_case.packet_id.as_ref().unwrap_or(type_id);
+                    if let Some(id) = &test_case.packet_id {
+                        push(Symbol::Decl(id.clone()), find_word(source, range, from, id), false);
+                    }
+                    if let Some(decl) = scope.typedef.get(packet_id) {
+                        for constraint in &test_case.constraints {
+                            let tag = constraint_tag(&scope, decl, constraint);
+                            push_tag(&mut push, tag, &constraint.value);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        for field in decl.fields() {
+            let range = field.loc().start.offset..field.loc().end.offset;
+            // Type identifiers follow the field name and colon.
+            let after_colon =
+                source[range.clone()].find(':').map_or(range.start, |index| range.start + index);
+            match field {
+                Field::Typedef { type_id, .. } | Field::Array { type_id: Some(type_id), .. } => {
+                    let range = find_word(source, range, after_colon, type_id);
+                    push(Symbol::Decl(type_id.clone()), range, false);
+                }
+                Field::Fixed { enum_id: Some(enum_id), tag_id: Some(tag_id), .. } => {
+                    let tag_range = find_word(source, range.clone(), range.start + 1, tag_id);
+                    push(Symbol::Tag(enum_id.clone(), tag_id.clone()), tag_range, false);
+                    let range = find_word(source, range, after_colon, enum_id);
+                    push(Symbol::Decl(enum_id.clone()), range, false);
+                }
+                Field::Group { group_id, constraints, .. } => {
+                    let range = find_word(source, range.clone(), range.start, group_id);
+                    push(Symbol::Decl(group_id.clone()), range, false);
+                    if let Some(group) = scope.typedef.get(group_id) {
+                        for constraint in constraints {
+                            let tag = constraint_tag(&scope, group, constraint);
+                            push_tag(&mut push, tag, &constraint.value);
+                        }
+                    }
+                }
+                _ => (),
+            }
+            if let Some(cond) = field.cond() {
+                push_tag(&mut push, constraint_tag(&scope, decl, cond), &cond.value);
+            }
+        }
+    }
+    result
+}
+
+/// Record the reference to an enum tag made by a constraint value.
+fn push_tag(
+    push: &mut impl FnMut(Symbol, Option<Range<usize>>, bool),
+    tag: Option<Symbol>,
+    value: &Expr,
+) {
+    if let Some(tag) = tag {
+        let loc = value.loc();
+        push(tag, Some(loc.start.offset..loc.end.offset), false)
+    }
+}
+
+/// Return the offset in bits of a field from the start of the
+/// declaration, including the fields of its ancestors, or `None` if
+/// the offset is not static.
+fn field_offset(scope: &Scope, decl: &Decl, target: &SourceRange) -> Option<usize> {
+    let lineage = scope.get_lineage(decl);
+    let mut offset = 0;
+    for (depth, d) in lineage.iter().enumerate() {
+        let is_last = depth + 1 == lineage.len();
+        let fields = d.fields().collect::<Vec<_>>();
+        for (index, field) in fields.iter().enumerate() {
+            if is_last && field.loc().start.offset == target.start.offset {
+                return Some(offset);
+            }
+            offset += match (field, fields.get(index + 1)) {
+                // The fields of the child replace the payload.
+                (Field::Payload { .. } | Field::Body { .. }, _) if !is_last => break,
+                (Field::Array { .. }, Some(Field::Padding { width, .. })) => width * 8,
+                (Field::Padding { .. }, _) => 0,
+                _ => scope.get_field_width(field)?,
+            };
+        }
+    }
+    None
+}
+
+/// Return the bit count formatted for display.
+fn bits(value: usize) -> String {
+    let plural = |count: usize, unit: &str| match count {
+        1 => format!("1 {}", unit),
+        _ => format!("{} {}s", count, unit),
+    };
+    match (value / 8, value % 8) {
+        (bytes, 0) if bytes > 0 => format!("{} ({})", plural(value, "bit"), plural(bytes, "byte")),
+        _ => plural(value, "bit"),
+    }
+}
+
+impl Analysis {
+    fn new(path: &str, text: &str) -> Analysis {
+        let mut sources = SourceDatabase::new();
+        // The document is the first file added to the database.
+        let file = 0;
+        match parser::parse_source(&mut sources, path.to_owned(), text.to_owned()) {
+            Ok(grammar) => {
+                let diagnostics = grammar.lint().diagnostics;
+                Analysis { sources, file, grammar: Some(grammar), diagnostics }
+            }
+            Err(diagnostic) => {
+                Analysis { sources, file, grammar: None, diagnostics: vec![diagnostic] }
+            }
+        }
+    }
+
+    /// Return the LSP range of a byte range of a file.
+    fn range(&self, file: FileId, range: &Range<usize>) -> Value {
+        let source = self.sources.source(file).unwrap_or_default();
+        json!({ "start": position(source, range.start), "end": position(source, range.end) })
+    }
+
+    /// Return the LSP location of a byte range of a file.
+    fn location(&self, file: FileId, range: &Range<usize>) -> Value {
+        let name = self.sources.name(file).unwrap_or_default();
+        // Imported files are named relative to the importing file.
+        let path = match file == self.file {
+            true => name,
+            false => {
+                std::fs::canonicalize(&name).map_or(name, |path| path.to_string_lossy().to_string())
+            }
+        };
+        json!({ "uri": path_to_uri(&path), "range": self.range(file, range) })
+    }
+
+    fn diagnostics(&self) -> Vec<Value> {
+        let severity = |severity| match severity {
+            Severity::Bug | Severity::Error => 1,
+            Severity::Warning => 2,
+            Severity::Note => 3,
+            Severity::Help => 4,
+        };
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| {
+                // Diagnostics reported in imported files are attached
+                // to the start of the document.
+                let primary = diagnostic.labels.iter().find(|label| {
+                    label.style == LabelStyle::Primary && label.file_id == self.file
+                });
+                let mut message = diagnostic.message.clone();
+                if let Some(label) = primary.filter(|label| !label.message.is_empty()) {
+                    message = format!("{}: {}", message, label.message);
+                }
+                let related = diagnostic
+                    .labels
+                    .iter()
+                    .filter(|label| label.style == LabelStyle::Secondary)
+                    .map(|label| {
+                        json!({
+                            "location": self.location(label.file_id, &label.range),
+                            "message": label.message,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let mut value = json!({
+                    "range": self.range(self.file, &primary.map_or(0..0, |label| label.range.clone())),
+                    "severity": severity(diagnostic.severity),
+                    "source": "pdl",
+                    "message": message,
+                });
+                if let Some(code) = &diagnostic.code {
+                    value["code"] = json!(code);
+                }
+                if !related.is_empty() {
+                    value["relatedInformation"] = json!(related);
+                }
+                value
+            })
+            .collect()
+    }
+
+    /// Return the occurrences of the symbol at the byte offset
+    /// `offset` of the document.
+    fn symbol_occurrences(&self, offset: usize) -> Vec<Occurrence> {
+        let grammar = match &self.grammar {
+            Some(grammar) => grammar,
+            None => return vec![],
+        };
+        let occurrences = occurrences(&self.sources, grammar);
+        let symbol = occurrences
+            .iter()
+            .find(|o| o.file == self.file && o.range.start <= offset && offset <= o.range.end)
+            .map(|o| o.symbol.clone());
+        match symbol {
+            Some(symbol) => occurrences.into_iter().filter(|o| o.symbol == symbol).collect(),
+            None => vec![],
+        }
+    }
+
+    fn definition(&self, offset: usize) -> Value {
+        let locations = self
+            .symbol_occurrences(offset)
+            .iter()
+            .filter(|o| o.definition)
+            .map(|o| self.location(o.file, &o.range))
+            .collect::<Vec<_>>();
+        match locations.is_empty() {
+            true => Value::Null,
+            false => json!(locations),
+        }
+    }
+
+    fn references(&self, offset: usize, include_declaration: bool) -> Value {
+        json!(self
+            .symbol_occurrences(offset)
+            .iter()
+            .filter(|o| include_declaration || !o.definition)
+            .map(|o| self.location(o.file, &o.range))
+            .collect::<Vec<_>>())
+    }
+
+    /// Return the description of the field at the byte offset
+    /// `offset` of the document, with its width and offset.
+    fn hover(&self, offset: usize) -> Value {
+        let grammar = match &self.grammar {
+            Some(grammar) => grammar,
+            None => return Value::Null,
+        };
+        let contains = |loc: &SourceRange| {
+            loc.file == self.file && loc.start.offset <= offset && offset < loc.end.offset
+        };
+        let found = grammar.declarations.iter().find_map(|decl| {
+            decl.fields().find(|field| contains(field.loc())).map(|field| (decl, field))
+        });
+        let (decl, field) = match found {
+            Some(found) => found,
+            None => return Value::Null,
+        };
+        let loc = field.loc();
+        let source = self.sources.source(self.file).unwrap();
+        let mut lines =
+            vec![format!("```pdl\n{}\n```", source[loc.start.offset..loc.end.offset].trim())];
+
+        // Widths and offsets are computed on a valid grammar only.
+        let has_errors = self.diagnostics.iter().any(|d| d.severity >= Severity::Error);
+        if !has_errors && !matches!(field, Field::Group { .. }) {
+            let inlined = analyzer::inline_groups(grammar);
+            let scope = Scope::new(&inlined);
+            let width = scope.get_field_width(field);
+            lines.push(format!("width: {}", width.map_or_else(|| "variable".to_owned(), bits)));
+            if let Some(decl @ (Decl::Packet { .. } | Decl::Struct { .. })) =
+                decl.id().and_then(|id| scope.typedef.get(id))
+            {
+                let offset = field_offset(&scope, decl, loc);
+                lines.push(format!(
+                    "offset: {}",
+                    offset.map_or_else(|| "variable".to_owned(), bits)
+                ));
+            }
+        }
+        json!({
+            "contents": { "kind": "markdown", "value": lines.join("\n\n") },
+            "range": self.range(self.file, &(loc.start.offset..loc.end.offset)),
+        })
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server { documents: HashMap::new(), shutdown: false }
+    }
+
+    /// Analyze a document, and return the notification publishing its
+    /// diagnostics.
+    fn publish_diagnostics(&mut self, uri: &str) -> Value {
+        let document = self.documents.get_mut(uri).unwrap();
+        let analysis = Analysis::new(&document.path, &document.text);
+        if let Some(grammar) = &analysis.grammar {
+            document.names = grammar
+                .declarations
+                .iter()
+                .filter_map(|decl| Some((decl.id()?.clone(), decl.kind().to_owned())))
+                .collect();
+        }
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": analysis.diagnostics() },
+        })
+    }
+
+    /// Analyze the document targeted by a text document position
+    /// request, and return the analysis with the byte offset of the
+    /// position.
+    fn analyze_position(&self, params: &Value) -> Option<(Analysis, usize)> {
+        let document = self.documents.get(params["textDocument"]["uri"].as_str()?)?;
+        let offset = offset(&document.text, &params["position"])?;
+        Some((Analysis::new(&document.path, &document.text), offset))
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let names = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+            .map_or(&[][..], |document| &document.names);
+        json!(names
+            .iter()
+            .filter(|(_, kind)| kind != "test")
+            .map(|(name, kind)| {
+                let item_kind = match kind.as_str() {
+                    "enum" => 13,
+                    "packet" | "struct" => 22,
+                    _ => 25,
+                };
+                json!({ "label": name, "kind": item_kind, "detail": kind })
+            })
+            .collect::<Vec<_>>())
+    }
+
+    /// Handle a request or notification, and return the messages to
+    /// send back to the client.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let mut notifications = vec![];
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": [":"] },
+                },
+                "serverInfo": { "name": "pdl" },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Value::Null)
+            }
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+                let text = match method {
+                    "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+                    _ => params["contentChanges"].as_array().and_then(|changes| {
+                        changes.last().and_then(|change| change["text"].as_str())
+                    }),
+                };
+                if let Some(text) = text {
+                    let document = self.documents.entry(uri.clone()).or_insert(Document {
+                        path: uri_to_path(&uri),
+                        text: String::new(),
+                        names: vec![],
+                    });
+                    document.text = text.to_owned();
+                    notifications.push(self.publish_diagnostics(&uri));
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                notifications.push(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                }));
+                None
+            }
+            "textDocument/definition" => Some(
+                self.analyze_position(params)
+                    .map_or(Value::Null, |(analysis, offset)| analysis.definition(offset)),
+            ),
+            "textDocument/references" => {
+                let include_declaration =
+                    params["context"]["includeDeclaration"].as_bool().unwrap_or(false);
+                Some(self.analyze_position(params).map_or(Value::Null, |(analysis, offset)| {
+                    analysis.references(offset, include_declaration)
+                }))
+            }
+            "textDocument/hover" => Some(
+                self.analyze_position(params)
+                    .map_or(Value::Null, |(analysis, offset)| analysis.hover(offset)),
+            ),
+            "textDocument/completion" => Some(self.completion(params)),
+            _ => None,
+        };
+
+        // Notifications do not have an identifier, and are not
+        // answered.
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return notifications,
+        };
+        notifications.push(match result {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": METHOD_NOT_FOUND, "message": format!("unknown method `{}`", method) },
+            }),
+        });
+        notifications
+    }
+}
+
+/// Read a message from the client. Return `None` at the end of the
+/// input stream.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Result<Value, String>>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        let count = input.read_line(&mut line).map_err(|err| err.to_string())?;
+        if count == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().map_err(|err| err.to_string())?);
+        }
+    }
+    let length = length.ok_or("missing Content-Length header")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|err| err.to_string())?;
+    Ok(Some(serde_json::from_slice(&body).map_err(|err| err.to_string())))
+}
+
+/// Write a message to the client.
+fn write_message(output: &mut impl Write, message: &Value) -> Result<(), String> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|err| err.to_string())
+}
+
+/// Run the language server on stdin and stdout, until the client
+/// sends the `exit` notification.
+pub fn run() -> Result<(), String> {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    let mut server = Server::new();
+    while let Some(message) = read_message(&mut input)? {
+        let responses = match message {
+            Ok(message) if message["method"] == "exit" => {
+                return match server.shutdown {
+                    true => Ok(()),
+                    false => Err("exit notification received before shutdown".to_owned()),
+                }
+            }
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": err },
+            })],
+        };
+        for response in responses {
+            write_message(&mut output, &response)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lsp::*;
+
+    const URI: &str = "file:///workspace/test%20packets.pdl";
+    const TEXT: &str = r#"little_endian_packets
+
+enum OpCode : 8 {
+    RESET = 1,
+    READ = 2,
+}
+
+packet Command {
+    op_code : OpCode,
+    flag : 1,
+    _reserved_ : 7,
+    _size_(_payload_) : 8,
+    _payload_,
+}
+
+packet Reset : Command (op_code = RESET) {
+    delay : 16,
+}
+
+test Command {
+    "\x01\x00\x02\x00\x00" : Reset,
+}
+"#;
+
+    /// Return the LSP position of the `n`-th occurrence of `pattern`
+    /// in the test document, offset by `delta` bytes.
+    fn at(pattern: &str, n: usize, delta: usize) -> Value {
+        let (index, _) = TEXT.match_indices(pattern).nth(n).unwrap();
+        position(TEXT, index + delta)
+    }
+
+    fn open() -> Server {
+        let mut server = Server::new();
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": URI, "languageId": "pdl", "version": 1, "text": TEXT },
+            },
+        }));
+        server
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let mut responses = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        }));
+        assert_eq!(responses.len(), 1);
+        responses.pop().unwrap()["result"].clone()
+    }
+
+    fn position_params(position: Value) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": position })
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "ab\nçd€f\n";
+        assert_eq!(position(text, 0), json!({ "line": 0, "character": 0 }));
+        assert_eq!(position(text, 3), json!({ "line": 1, "character": 0 }));
+        assert_eq!(position(text, 9), json!({ "line": 1, "character": 3 }));
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 3 })), Some(9));
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 10 })), Some(10));
+        assert_eq!(offset(text, &json!({ "line": 5, "character": 0 })), None);
+        assert_eq!(uri_to_path(URI), "/workspace/test packets.pdl");
+        assert_eq!(path_to_uri("/workspace/test packets.pdl"), URI);
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let mut output = vec![];
+        write_message(&mut output, &json!({ "jsonrpc": "2.0", "method": "exit" })).unwrap();
+        let mut input = std::io::Cursor::new(output);
+        assert_eq!(
+            read_message(&mut input),
+            Ok(Some(Ok(json!({ "jsonrpc": "2.0", "method": "exit" }))))
+        );
+        assert_eq!(read_message(&mut input), Ok(None));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::new();
+        let notifications = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": URI,
+                    "text": "little_endian_packets\npacket A { a: B }\n",
+                },
+            },
+        }));
+        assert_eq!(notifications.len(), 1);
+        let diagnostics = &notifications[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["code"], "undeclared");
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"]["start"], json!({ "line": 1, "character": 11 }));
+
+        let notifications = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": "little_endian_packets\npacket A { a: }\n" }],
+            },
+        }));
+        let diagnostics = &notifications[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["range"]["start"], json!({ "line": 1, "character": 14 }));
+    }
+
+    #[test]
+    fn test_definition_and_references() {
+        let mut server = open();
+
+        // Enum type referenced by a typedef field.
+        let result =
+            request(&mut server, "textDocument/definition", position_params(at("OpCode", 1, 2)));
+        assert_eq!(result[0]["uri"], URI);
+        assert_eq!(result[0]["range"]["start"], at("OpCode", 0, 0));
+        assert_eq!(result[0]["range"]["end"], at("OpCode", 0, 6));
+
+        // Parent packet.
+        let result =
+            request(&mut server, "textDocument/definition", position_params(at("Command", 1, 0)));
+        assert_eq!(result[0]["range"]["start"], at("Command", 0, 0));
+
+        // Enum tag referenced by a constraint.
+        let result =
+            request(&mut server, "textDocument/definition", position_params(at("RESET", 1, 1)));
+        assert_eq!(result[0]["range"]["start"], at("RESET", 0, 0));
+
+        // References of the Command packet, from its declaration.
+        let mut params = position_params(at("Command", 0, 3));
+        params["context"] = json!({ "includeDeclaration": false });
+        let result = request(&mut server, "textDocument/references", params);
+        let starts = result.as_array().unwrap().iter().map(|l| l["range"]["start"].clone());
+        assert_eq!(starts.collect::<Vec<_>>(), vec![at("Command", 1, 0), at("Command", 2, 0)]);
+
+        // The test case specialization references the Reset packet.
+        let mut params = position_params(at("Reset", 0, 0));
+        params["context"] = json!({ "includeDeclaration": true });
+        let result = request(&mut server, "textDocument/references", params);
+        assert_eq!(result.as_array().unwrap().len(), 2);
+
+        // Field names are not symbols.
+        let result =
+            request(&mut server, "textDocument/definition", position_params(at("delay", 0, 0)));
+        assert_eq!(result, Value::Null);
+    }
+
+    #[test]
+    fn test_hover() {
+        let mut server = open();
+        let result = request(&mut server, "textDocument/hover", position_params(at("delay", 0, 1)));
+        assert_eq!(
+            result["contents"]["value"],
+            "```pdl\ndelay : 16\n```\n\nwidth: 16 bits (2 bytes)\n\noffset: 24 bits (3 bytes)"
+        );
+        let result = request(&mut server, "textDocument/hover", position_params(at("flag", 0, 0)));
+        assert_eq!(
+            result["contents"]["value"],
+            "```pdl\nflag : 1\n```\n\nwidth: 1 bit\n\noffset: 8 bits (1 byte)"
+        );
+        let result =
+            request(&mut server, "textDocument/hover", position_params(at("_payload_,", 0, 0)));
+        assert_eq!(
+            result["contents"]["value"],
+            "```pdl\n_payload_\n```\n\nwidth: variable\n\noffset: 24 bits (3 bytes)"
+        );
+        let result = request(&mut server, "textDocument/hover", position_params(at("enum", 0, 0)));
+        assert_eq!(result, Value::Null);
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut server = open();
+        let result =
+            request(&mut server, "textDocument/completion", position_params(at("OpCode", 1, 0)));
+        assert_eq!(
+            result,
+            json!([
+                { "label": "OpCode", "kind": 13, "detail": "enum" },
+                { "label": "Command", "kind": 22, "detail": "packet" },
+                { "label": "Reset", "kind": 22, "detail": "packet" },
+            ])
+        );
+        let result = server.handle(&json!({ "jsonrpc": "2.0", "id": 2, "method": "unknown" }));
+        assert_eq!(result[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -1,14 +1,176 @@
 //! PDL parser and linter.
 
-use codespan_reporting::term::{self, termcolor};
+use codespan_reporting::diagnostic::Diagnostic;
+use codespan_reporting::term::termcolor;
 use structopt::StructOpt;
 
+mod analyzer;
 mod ast;
+mod backends;
+mod checksum;
+mod diff;
+mod formatter;
+mod interpreter;
+mod layout;
 mod lint;
+mod lsp;
 mod parser;
+mod sizes;
+#[cfg(test)]
+mod test_utils;
 
 use crate::lint::Lintable;
 
+#[derive(Debug)]
+enum OutputFormat {
+    Json,
+    Rust,
+    RustTests,
+    WiresharkLua,
+    Markdown,
+    Html,
+    Fuzz,
+    Cxx,
+    Python,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "rust" => Ok(Self::Rust),
+            "rust-tests" => Ok(Self::RustTests),
+            "wireshark-lua" => Ok(Self::WiresharkLua),
+            "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "fuzz" => Ok(Self::Fuzz),
+            "cxx" => Ok(Self::Cxx),
+            "python" => Ok(Self::Python),
+            _ => Err(format!(
+                "could not parse {:?}, valid options are 'json', 'rust', 'rust-tests', \
+                 'wireshark-lua', 'markdown', 'html', 'fuzz', 'cxx' and 'python'.",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DiagnosticsFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for DiagnosticsFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("could not parse {:?}, valid options are 'text' and 'json'.", input)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Decode bytes against a packet declaration and print the decoded
+    /// field values as JSON.
+    Decode {
+        /// Input file, PDL source or JSON AST.
+        #[structopt(name = "FILE")]
+        input_file: String,
+
+        /// Name of the packet declaration to decode.
+        #[structopt(name = "PACKET")]
+        packet: String,
+
+        /// Bytes to decode, in hexadecimal.
+        #[structopt(name = "BYTES")]
+        bytes: Vec<String>,
+
+        /// Read the bytes to decode from a binary file.
+        #[structopt(long)]
+        bytes_file: Option<String>,
+    },
+
+    /// Encode JSON field values against a packet declaration and print
+    /// the encoded bytes in hexadecimal.
+    Encode {
+        /// Input file, PDL source or JSON AST.
+        #[structopt(name = "FILE")]
+        input_file: String,
+
+        /// Name of the packet declaration to encode.
+        #[structopt(name = "PACKET")]
+        packet: String,
+
+        /// JSON object of field values. Read from stdin if omitted.
+        #[structopt(name = "JSON")]
+        json: Option<String>,
+
+        /// Write the encoded bytes to a binary file.
+        #[structopt(long)]
+        bytes_file: Option<String>,
+    },
+
+    /// Compare two versions of a grammar and report the changes
+    /// breaking the wire format of existing declarations.
+    Diff {
+        /// Previous version of the grammar.
+        #[structopt(name = "OLD")]
+        old_file: String,
+
+        /// New version of the grammar.
+        #[structopt(name = "NEW")]
+        new_file: String,
+    },
+
+    /// Print the bit layout diagram of a packet or struct declaration,
+    /// including the fields of its ancestors.
+    Layout {
+        /// Input file, PDL source or JSON AST.
+        #[structopt(name = "FILE")]
+        input_file: String,
+
+        /// Name of the packet or struct declaration.
+        #[structopt(name = "PACKET")]
+        packet: String,
+    },
+
+    /// Print the minimum and maximum encoded size of every packet and
+    /// struct declaration.
+    Sizes {
+        /// Input file, PDL source or JSON AST.
+        #[structopt(name = "FILE")]
+        input_file: String,
+
+        /// Print the report as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
+
+    /// Format the input file canonically and print the result on
+    /// stdout.
+    Fmt {
+        /// Input file.
+        #[structopt(name = "FILE")]
+        input_file: String,
+
+        /// Print nothing and exit with a non-zero status if the input
+        /// file is not canonically formatted.
+        #[structopt(long)]
+        check: bool,
+    },
+
+    /// Run a language server for PDL files, communicating over stdin
+    /// and stdout.
+    Lsp,
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "pdl-parser", about = "Packet Description Language parser tool.")]
 struct Opt {
@@ -16,9 +178,350 @@ struct Opt {
     #[structopt(short, long = "--version")]
     version: bool,
 
-    /// Input file.
+    /// Generate output in this format ("json", "rust", "rust-tests",
+    /// "wireshark-lua", "markdown", "html", "fuzz", "cxx" or "python").
+    /// The output will be printed on stdout, except for the "fuzz"
+    /// format which writes a cargo-fuzz crate to the output directory.
+    #[structopt(long, default_value = "json")]
+    output_format: OutputFormat,
+
+    /// Print diagnostics on stderr in this format ("text" or "json").
+    #[structopt(long, default_value = "text")]
+    diagnostics_format: DiagnosticsFormat,
+
+    /// Packet dissected by the Wireshark dissector. Defaults to the
+    /// first packet declared without parent.
+    #[structopt(long)]
+    root_packet: Option<String>,
+
+    /// Output directory of the cargo-fuzz crate.
+    #[structopt(long)]
+    output_dir: Option<String>,
+
+    /// Path of the Rust module generated from the grammar, used by the
+    /// fuzz targets, e.g. `bt_packets::hci`. Defaults to the name of
+    /// the input file.
+    #[structopt(long)]
+    fuzz_module: Option<String>,
+
+    /// Package name of the crate exporting the fuzzed module, when it
+    /// differs from the crate name.
+    #[structopt(long)]
+    fuzz_crate: Option<String>,
+
+    /// Path of the crate exporting the fuzzed module, relative to the
+    /// output directory.
+    #[structopt(long, default_value = "..")]
+    fuzz_crate_path: String,
+
+    /// Namespace of the C++ declarations generated from the grammar,
+    /// e.g. `bluetooth::hci`. Defaults to the name of the input file.
+    #[structopt(long)]
+    cxx_namespace: Option<String>,
+
+    /// Input file, PDL source or JSON AST.
     #[structopt(name = "FILE")]
+    input_file: Option<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+/// Print diagnostics on stderr in the selected format.
+fn print_diagnostics(
+    sources: &ast::SourceDatabase,
+    diagnostics: &lint::LintDiagnostics,
+    format: DiagnosticsFormat,
+) {
+    let _ = match format {
+        DiagnosticsFormat::Text => diagnostics.print(sources, termcolor::ColorChoice::Always),
+        DiagnosticsFormat::Json => diagnostics.print_json(sources),
+    };
+}
+
+/// Parse the input file: a PDL source, or a JSON AST generated by the
+/// JSON backend if the file has the extension `.json`.
+fn read_grammar(
+    sources: &mut ast::SourceDatabase,
+    input_file: String,
+) -> Result<ast::Grammar, Diagnostic<ast::FileId>> {
+    if !input_file.ends_with(".json") {
+        return parser::parse_file(sources, input_file);
+    }
+    let source = std::fs::read_to_string(&input_file).map_err(|e| {
+        Diagnostic::error()
+            .with_message(format!("failed to read input file '{}': {}", &input_file, e))
+    })?;
+    backends::json::parse(sources, input_file, source)
+}
+
+/// Parse and lint the input file. Diagnostics are added to
+/// `diagnostics`, and printed once the command completes.
+fn parse_and_lint(
+    sources: &mut ast::SourceDatabase,
+    input_file: String,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Option<ast::Grammar> {
+    let is_json = input_file.ends_with(".json");
+    match read_grammar(sources, input_file) {
+        Ok(grammar) => {
+            let mut lint = grammar.lint();
+            // The sources of a JSON AST are not available to display
+            // the labels of the diagnostics.
+            if is_json {
+                lint.diagnostics.iter_mut().for_each(|diagnostic| diagnostic.labels.clear());
+            }
+            diagnostics.diagnostics.extend(lint.diagnostics);
+            Some(grammar)
+        }
+        Err(err) => {
+            diagnostics.push(err);
+            None
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate(
+    input_file: String,
+    output_format: OutputFormat,
+    root_packet: Option<String>,
+    output_dir: Option<String>,
+    fuzz_module: Option<String>,
+    fuzz_crate: Option<String>,
+    fuzz_crate_path: String,
+    cxx_namespace: Option<String>,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let grammar = parse_and_lint(sources, input_file, diagnostics).ok_or("parsing failed")?;
+    match output_format {
+        OutputFormat::Json => {
+            if diagnostics.has_errors() {
+                return Err("JSON generation skipped: the grammar has errors".to_owned());
+            }
+            println!("{}", backends::json::generate(&grammar)?)
+        }
+        OutputFormat::Rust => {
+            if diagnostics.has_errors() {
+                return Err("Rust code generation skipped: the grammar has errors".to_owned());
+            }
+            if grammar.declarations.iter().flat_map(|d| d.fields()).any(|f| f.cond().is_some()) {
+                return Err(
+                    "Rust code generation skipped: conditional fields are not supported".to_owned()
+                );
+            }
+            println!("{}", backends::rust::generate(sources, &grammar)?)
+        }
+        OutputFormat::RustTests => {
+            if diagnostics.has_errors() {
+                return Err("Rust test generation skipped: the grammar has errors".to_owned());
+            }
+            print!("{}", backends::rust_tests::generate(sources, &grammar)?)
+        }
+        OutputFormat::WiresharkLua => {
+            if diagnostics.has_errors() {
+                return Err("Lua code generation skipped: the grammar has errors".to_owned());
+            }
+            print!("{}", backends::wireshark::generate(sources, &grammar, root_packet.as_deref())?)
+        }
+        OutputFormat::Markdown | OutputFormat::Html => {
+            if diagnostics.has_errors() {
+                return Err("documentation generation skipped: the grammar has errors".to_owned());
+            }
+            let format = match output_format {
+                OutputFormat::Markdown => backends::docs::Format::Markdown,
+                _ => backends::docs::Format::Html,
+            };
+            print!("{}", backends::docs::generate(sources, &grammar, format))
+        }
+        OutputFormat::Fuzz => {
+            if diagnostics.has_errors() {
+                return Err("fuzz target generation skipped: the grammar has errors".to_owned());
+            }
+            if grammar.declarations.iter().flat_map(|d| d.fields()).any(|f| f.cond().is_some()) {
+                return Err("fuzz target generation skipped: conditional fields are not supported"
+                    .to_owned());
+            }
+            let output_dir =
+                std::path::PathBuf::from(output_dir.ok_or("missing fuzz output directory")?);
+            let module = fuzz_module.unwrap_or_else(|| {
+                let source = sources.get(grammar.file).unwrap();
+                let path = std::path::Path::new(source.name());
+                path.file_stem().unwrap().to_string_lossy().to_string()
+            });
+            for (path, content) in backends::fuzz::generate(
+                sources,
+                &grammar,
+                &module,
+                fuzz_crate.as_deref(),
+                &fuzz_crate_path,
+            )? {
+                let path = output_dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| std::fs::write(&path, content))
+                    .map_err(|err| format!("failed to write '{}': {}", path.display(), err))?;
+                println!("{}", path.display());
+            }
+        }
+        OutputFormat::Cxx => {
+            if diagnostics.has_errors() {
+                return Err("C++ code generation skipped: the grammar has errors".to_owned());
+            }
+            if grammar.declarations.iter().flat_map(|d| d.fields()).any(|f| f.cond().is_some()) {
+                return Err(
+                    "C++ code generation skipped: conditional fields are not supported".to_owned()
+                );
+            }
+            let namespace = cxx_namespace.unwrap_or_else(|| {
+                let source = sources.get(grammar.file).unwrap();
+                let path = std::path::Path::new(source.name());
+                path.file_stem().unwrap().to_string_lossy().to_string()
+            });
+            print!("{}", backends::cxx::generate(sources, &grammar, &namespace)?)
+        }
+        OutputFormat::Python => {
+            if diagnostics.has_errors() {
+                return Err("Python code generation skipped: the grammar has errors".to_owned());
+            }
+            if grammar.declarations.iter().flat_map(|d| d.fields()).any(|f| f.cond().is_some()) {
+                return Err("Python code generation skipped: conditional fields are not supported"
+                    .to_owned());
+            }
+            print!("{}", backends::python::generate(sources, &grammar)?)
+        }
+    }
+    Ok(())
+}
+
+fn decode(
     input_file: String,
+    packet: String,
+    bytes: Vec<String>,
+    bytes_file: Option<String>,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let grammar = parse_and_lint(sources, input_file, diagnostics).ok_or("parsing failed")?;
+    if diagnostics.has_errors() {
+        return Err("decoding skipped: the grammar has errors".to_owned());
+    }
+    let bytes = match bytes_file {
+        Some(path) => std::fs::read(&path)
+            .map_err(|err| format!("failed to read bytes file '{}': {}", path, err))?,
+        None => interpreter::from_hex(&bytes.join(" "))?,
+    };
+    let value = interpreter::decoder::decode(&grammar, &packet, &bytes)?;
+    println!("{}", serde_json::to_string_pretty(&value).unwrap());
+    Ok(())
+}
+
+fn encode(
+    input_file: String,
+    packet: String,
+    json: Option<String>,
+    bytes_file: Option<String>,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let grammar = parse_and_lint(sources, input_file, diagnostics).ok_or("parsing failed")?;
+    if diagnostics.has_errors() {
+        return Err("encoding skipped: the grammar has errors".to_owned());
+    }
+    let json = match json {
+        Some(json) => json,
+        None => {
+            let mut json = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut json)
+                .map_err(|err| format!("failed to read stdin: {}", err))?;
+            json
+        }
+    };
+    let value: serde_json::Value =
+        serde_json::from_str(&json).map_err(|err| format!("invalid JSON value: {}", err))?;
+    let bytes = interpreter::encoder::encode(&grammar, &packet, &value)?;
+    match bytes_file {
+        Some(path) => std::fs::write(&path, bytes)
+            .map_err(|err| format!("failed to write bytes file '{}': {}", path, err))?,
+        None => println!("{}", interpreter::to_hex(&bytes)),
+    }
+    Ok(())
+}
+
+fn diff(
+    old_file: String,
+    new_file: String,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let old = parse_and_lint(sources, old_file, diagnostics).ok_or("parsing failed")?;
+    let new = parse_and_lint(sources, new_file, diagnostics).ok_or("parsing failed")?;
+    if diagnostics.has_errors() {
+        return Err("comparison skipped: the grammars have errors".to_owned());
+    }
+    diagnostics.diagnostics.extend(diff::diff(&old, &new).diagnostics);
+    if diagnostics.has_errors() {
+        return Err("the new grammar has breaking changes".to_owned());
+    }
+    Ok(())
+}
+
+fn layout(
+    input_file: String,
+    packet: String,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let grammar = parse_and_lint(sources, input_file, diagnostics).ok_or("parsing failed")?;
+    if diagnostics.has_errors() {
+        return Err("layout skipped: the grammar has errors".to_owned());
+    }
+    print!("{}", layout::layout(&grammar, &packet)?);
+    Ok(())
+}
+
+fn sizes(
+    input_file: String,
+    json: bool,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let grammar = parse_and_lint(sources, input_file, diagnostics).ok_or("parsing failed")?;
+    if diagnostics.has_errors() {
+        return Err("size analysis skipped: the grammar has errors".to_owned());
+    }
+    let sizes = sizes::sizes(&grammar);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&sizes).unwrap());
+    } else {
+        print!("{}", sizes::format_text(&sizes));
+    }
+    Ok(())
+}
+
+fn fmt(
+    input_file: String,
+    check: bool,
+    sources: &mut ast::SourceDatabase,
+    diagnostics: &mut lint::LintDiagnostics,
+) -> Result<(), String> {
+    let source = std::fs::read_to_string(&input_file)
+        .map_err(|err| format!("failed to read input file '{}': {}", input_file, err))?;
+    // Imported files are not resolved, only the input file is formatted.
+    let grammar = match parser::parse_inline(sources, input_file.clone(), source) {
+        Ok(grammar) => grammar,
+        Err(err) => {
+            diagnostics.push(err);
+            return Err("parsing failed".to_owned());
+        }
+    };
+    let formatted = formatter::format(sources, &grammar);
+    if !check {
+        print!("{}", formatted);
+    } else if sources.get(grammar.file).unwrap().source() != &formatted {
+        return Err(format!("{} is not formatted", input_file));
+    }
+    Ok(())
 }
 
 fn main() {
@@ -29,16 +532,47 @@ fn main() {
         return;
     }
 
+    // The diagnostics of all the parsed grammars are printed at once,
+    // as a single document in the JSON format.
     let mut sources = ast::SourceDatabase::new();
-    match parser::parse_file(&mut sources, opt.input_file) {
-        Ok(grammar) => {
-            let _ = grammar.lint().print(&sources, termcolor::ColorChoice::Always);
-            println!("{}", serde_json::to_string_pretty(&grammar).unwrap())
+    let mut diagnostics = lint::LintDiagnostics::new();
+    let result = match (opt.command, opt.input_file) {
+        (Some(Command::Decode { input_file, packet, bytes, bytes_file }), _) => {
+            decode(input_file, packet, bytes, bytes_file, &mut sources, &mut diagnostics)
         }
-        Err(err) => {
-            let writer = termcolor::StandardStream::stderr(termcolor::ColorChoice::Always);
-            let config = term::Config::default();
-            _ = term::emit(&mut writer.lock(), &config, &sources, &err);
+        (Some(Command::Encode { input_file, packet, json, bytes_file }), _) => {
+            encode(input_file, packet, json, bytes_file, &mut sources, &mut diagnostics)
+        }
+        (Some(Command::Diff { old_file, new_file }), _) => {
+            diff(old_file, new_file, &mut sources, &mut diagnostics)
+        }
+        (Some(Command::Layout { input_file, packet }), _) => {
+            layout(input_file, packet, &mut sources, &mut diagnostics)
+        }
+        (Some(Command::Sizes { input_file, json }), _) => {
+            sizes(input_file, json, &mut sources, &mut diagnostics)
+        }
+        (Some(Command::Fmt { input_file, check }), _) => {
+            fmt(input_file, check, &mut sources, &mut diagnostics)
         }
+        (Some(Command::Lsp), _) => lsp::run(),
+        (None, Some(input_file)) => generate(
+            input_file,
+            opt.output_format,
+            opt.root_packet,
+            opt.output_dir,
+            opt.fuzz_module,
+            opt.fuzz_crate,
+            opt.fuzz_crate_path,
+            opt.cxx_namespace,
+            &mut sources,
+            &mut diagnostics,
+        ),
+        (None, None) => Err("missing input file".to_owned()),
+    };
+    print_diagnostics(&sources, &diagnostics, opt.diagnostics_format);
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
     }
 }
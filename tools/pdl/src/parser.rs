@@ -1,9 +1,12 @@
 use super::ast;
-use codespan_reporting::diagnostic::Diagnostic;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files;
+use pest::error::InputLocation;
 use pest::iterators::{Pair, Pairs};
 use pest::{Parser, Token};
+use std::collections::HashSet;
 use std::iter::{Filter, Peekable};
+use std::path::{Path, PathBuf};
 
 // Generate the PDL parser.
 // TODO: use #[grammar = "pdl.pest"]
@@ -35,7 +38,12 @@ size_modifier = @{
 
 endianness_declaration = { "little_endian_packets" | "big_endian_packets" }
 
-enum_tag = { identifier ~ "=" ~ integer }
+import_declaration = { "import" ~ string }
+
+enum_value = { identifier ~ "=" ~ integer }
+enum_range = { identifier ~ "=" ~ integer ~ ".." ~ integer }
+enum_default = { identifier ~ "=" ~ ".." }
+enum_tag = { enum_range | enum_value | enum_default }
 enum_tag_list = { enum_tag ~ ("," ~ enum_tag)* ~ ","? }
 enum_declaration = {
     "enum" ~ identifier ~ ":" ~ integer ~ "{" ~
@@ -43,8 +51,14 @@ enum_declaration = {
     "}"
 }
 
-constraint = { identifier ~ "=" ~ (identifier|integer) }
+unary_operator = { "-" | "~" }
+binary_operator = { "<<" | ">>" | "+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" }
+operand = { unary_operator* ~ (integer | identifier | "(" ~ expr ~ ")") }
+expr = { operand ~ (binary_operator ~ operand)* }
+
+constraint = { identifier ~ "=" ~ expr }
 constraint_list = { constraint ~ ("," ~ constraint)* }
+condition = { "if" ~ constraint }
 
 checksum_field = { "_checksum_start_" ~ "(" ~ identifier ~ ")" }
 padding_field = { "_padding_" ~ "[" ~ integer ~ "]" }
@@ -60,8 +74,8 @@ reserved_field = { "_reserved_" ~ ":" ~ integer }
 array_field = { identifier ~ ":" ~ (integer|identifier) ~
     "[" ~ (size_modifier|integer)? ~ "]"
 }
-scalar_field = { identifier ~ ":" ~ integer }
-typedef_field = { identifier ~ ":" ~ identifier }
+scalar_field = { identifier ~ ":" ~ integer ~ condition? }
+typedef_field = { identifier ~ ":" ~ identifier ~ condition? }
 group_field = { identifier ~ ("{" ~ constraint_list ~ "}")? }
 
 field = _{
@@ -110,7 +124,7 @@ custom_field_declaration = {
     "custom_field" ~ identifier ~ (":" ~ integer)? ~ string
 }
 
-test_case = { string }
+test_case = { string ~ (":" ~ identifier)? ~ ("(" ~ constraint_list ~ ")")? }
 test_case_list = _{ test_case ~ ("," ~ test_case)* ~ ","? }
 test_declaration = {
     "test" ~ identifier ~ "{" ~
@@ -131,6 +145,7 @@ declaration = _{
 grammar = {
     SOI ~
     endianness_declaration? ~
+    import_declaration* ~
     declaration* ~
     EOI
 }
@@ -237,24 +252,64 @@ fn parse_string(iter: &mut NodeIterator<'_>) -> Result<String, String> {
     expect(iter, Rule::string).map(|n| n.as_string())
 }
 
-fn parse_atomic_expr(iter: &mut NodeIterator<'_>, context: &Context) -> Result<ast::Expr, String> {
-    match iter.next() {
+fn parse_operand(node: Node<'_>, context: &Context) -> Result<ast::Expr, String> {
+    let mut children = node.children().collect::<Vec<_>>();
+    let mut expr = match children.pop() {
         Some(n) if n.as_rule() == Rule::identifier => {
-            Ok(ast::Expr::Identifier { loc: n.as_loc(context), name: n.as_string() })
+            ast::Expr::Identifier { loc: n.as_loc(context), name: n.as_string() }
         }
         Some(n) if n.as_rule() == Rule::integer => {
-            Ok(ast::Expr::Integer { loc: n.as_loc(context), value: n.as_usize()? })
+            ast::Expr::Integer { loc: n.as_loc(context), value: n.as_usize()? }
         }
-        Some(n) => Err(format!(
-            "expected rule {:?} or {:?}, got {:?}",
-            Rule::identifier,
-            Rule::integer,
-            n.as_rule()
-        )),
-        None => {
-            Err(format!("expected rule {:?} or {:?}, got nothing", Rule::identifier, Rule::integer))
+        Some(n) if n.as_rule() == Rule::expr => parse_expr(n, context)?,
+        Some(n) => {
+            return Err(format!(
+                "expected rule {:?}, {:?} or {:?}, got {:?}",
+                Rule::identifier,
+                Rule::integer,
+                Rule::expr,
+                n.as_rule()
+            ))
         }
+        None => return err_missing_rule(Rule::expr),
+    };
+    // Unary operators apply from right to left.
+    for op in children.into_iter().rev() {
+        let loc = op.as_loc(context) + expr.loc().clone();
+        expr = ast::Expr::Unary { loc, op: op.as_string(), operand: Box::new(expr) };
     }
+    Ok(expr)
+}
+
+fn parse_expr(node: Node<'_>, context: &Context) -> Result<ast::Expr, String> {
+    fn reduce(operands: &mut Vec<ast::Expr>, operators: &mut Vec<String>) {
+        let op = operators.pop().unwrap();
+        let rhs = operands.pop().unwrap();
+        let lhs = operands.pop().unwrap();
+        let loc = lhs.loc().clone() + rhs.loc().clone();
+        operands.push(ast::Expr::Binary { loc, op, operands: Box::new((lhs, rhs)) });
+    }
+
+    // Build the expression tree with operator precedence parsing,
+    // binary operators are left associative.
+    let mut children = node.children();
+    let mut operands = vec![parse_operand(expect(&mut children, Rule::operand)?, context)?];
+    let mut operators: Vec<String> = vec![];
+    while let Some(op) = maybe(&mut children, Rule::binary_operator) {
+        let op = op.as_string();
+        while operators
+            .last()
+            .is_some_and(|top| ast::binary_precedence(top) >= ast::binary_precedence(&op))
+        {
+            reduce(&mut operands, &mut operators);
+        }
+        operators.push(op);
+        operands.push(parse_operand(expect(&mut children, Rule::operand)?, context)?);
+    }
+    while !operators.is_empty() {
+        reduce(&mut operands, &mut operators);
+    }
+    Ok(operands.pop().unwrap())
 }
 
 fn parse_size_modifier_opt(iter: &mut NodeIterator<'_>) -> Option<String> {
@@ -283,7 +338,7 @@ fn parse_constraint(node: Node<'_>, context: &Context) -> Result<ast::Constraint
         let loc = node.as_loc(context);
         let mut children = node.children();
         let id = parse_identifier(&mut children)?;
-        let value = parse_atomic_expr(&mut children, context)?;
+        let value = parse_expr(expect(&mut children, Rule::expr)?, context)?;
         Ok(ast::Constraint { id, loc, value })
     }
 }
@@ -296,15 +351,39 @@ fn parse_constraint_list_opt(
         .map_or(Ok(vec![]), |n| n.children().map(|n| parse_constraint(n, context)).collect())
 }
 
+fn parse_condition_opt(
+    iter: &mut NodeIterator<'_>,
+    context: &Context,
+) -> Result<Option<ast::Constraint>, String> {
+    maybe(iter, Rule::condition)
+        .map(|n| {
+            expect(&mut n.children(), Rule::constraint).and_then(|n| parse_constraint(n, context))
+        })
+        .transpose()
+}
+
 fn parse_enum_tag(node: Node<'_>, context: &Context) -> Result<ast::Tag, String> {
     if node.as_rule() != Rule::enum_tag {
         err_unexpected_rule(Rule::enum_tag, node.as_rule())
     } else {
+        let node = node.children().next().unwrap();
         let loc = node.as_loc(context);
+        let rule = node.as_rule();
         let mut children = node.children();
         let id = parse_identifier(&mut children)?;
-        let value = parse_integer(&mut children)?;
-        Ok(ast::Tag { id, loc, value })
+        Ok(match rule {
+            Rule::enum_value => {
+                let value = parse_integer(&mut children)?;
+                ast::Tag::Value { id, loc, value }
+            }
+            Rule::enum_range => {
+                let start = parse_integer(&mut children)?;
+                let end = parse_integer(&mut children)?;
+                ast::Tag::Range { id, loc, start, end }
+            }
+            Rule::enum_default => ast::Tag::Default { id, loc },
+            _ => unreachable!(),
+        })
     }
 }
 
@@ -380,12 +459,14 @@ fn parse_field(node: Node<'_>, context: &Context) -> Result<ast::Field, String>
         Rule::scalar_field => {
             let id = parse_identifier(&mut children)?;
             let width = parse_integer(&mut children)?;
-            ast::Field::Scalar { loc, id, width }
+            let cond = parse_condition_opt(&mut children, context)?;
+            ast::Field::Scalar { loc, id, width, cond }
         }
         Rule::typedef_field => {
             let id = parse_identifier(&mut children)?;
             let type_id = parse_identifier(&mut children)?;
-            ast::Field::Typedef { loc, id, type_id }
+            let cond = parse_condition_opt(&mut children, context)?;
+            ast::Field::Typedef { loc, id, type_id, cond }
         }
         Rule::group_field => {
             let group_id = parse_identifier(&mut children)?;
@@ -442,6 +523,11 @@ fn parse_grammar(root: Node<'_>, context: &Context) -> Result<ast::Grammar, Stri
             Rule::endianness_declaration => {
                 grammar.endianness = Some(parse_endianness(node, context)?)
             }
+            Rule::import_declaration => {
+                let mut children = node.children();
+                let path = parse_string(&mut children)?.trim_matches('"').to_owned();
+                grammar.imports.push(ast::Import { loc, path })
+            }
             Rule::checksum_declaration => {
                 let mut children = node.children();
                 let id = parse_identifier(&mut children)?;
@@ -497,7 +583,21 @@ fn parse_grammar(root: Node<'_>, context: &Context) -> Result<ast::Grammar, Stri
                 let fields = parse_field_list(&mut children, context)?;
                 grammar.declarations.push(ast::Decl::Group { id, loc, fields })
             }
-            Rule::test_declaration => {}
+            Rule::test_declaration => {
+                let mut children = node.children();
+                let type_id = parse_identifier(&mut children)?;
+                let test_cases = children
+                    .map(|n| {
+                        let loc = n.as_loc(context);
+                        let mut children = n.children();
+                        let input = parse_string(&mut children)?;
+                        let packet_id = parse_identifier_opt(&mut children)?;
+                        let constraints = parse_constraint_list_opt(&mut children, context)?;
+                        Ok(ast::TestCase { loc, input, packet_id, constraints })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                grammar.declarations.push(ast::Decl::Test { loc, type_id, test_cases })
+            }
             Rule::EOI => (),
             _ => unreachable!(),
         }
@@ -514,28 +614,242 @@ pub fn parse_inline(
     name: String,
     source: String,
 ) -> Result<ast::Grammar, Diagnostic<ast::FileId>> {
+    let line_starts: Vec<_> = files::line_starts(&source).collect();
+    let file = sources.add(name.clone(), source.clone());
     let root = PDLParser::parse(Rule::grammar, &source)
         .map_err(|e| {
+            let range = match e.location {
+                InputLocation::Pos(pos) => pos..pos,
+                InputLocation::Span((start, end)) => start..end,
+            };
             Diagnostic::error()
-                .with_message(format!("failed to parse input file '{}': {}", &name, e))
+                .with_message(format!(
+                    "failed to parse input file '{}': {}",
+                    &name,
+                    e.variant.message()
+                ))
+                .with_labels(vec![Label::primary(file, range)])
         })?
         .next()
         .unwrap();
-    let line_starts: Vec<_> = files::line_starts(&source).collect();
-    let file = sources.add(name, source.clone());
     parse_grammar(root, &(file, &line_starts)).map_err(|e| Diagnostic::error().with_message(e))
 }
 
+/// Read and parse a source file, without resolving imports.
+fn read_file(
+    sources: &mut ast::SourceDatabase,
+    name: String,
+) -> Result<ast::Grammar, Diagnostic<ast::FileId>> {
+    let source = std::fs::read_to_string(&name).map_err(|e| {
+        Diagnostic::error().with_message(format!("failed to read input file '{}': {}", &name, e))
+    })?;
+    parse_inline(sources, name, source)
+}
+
+/// Resolve the imports of the file `name`, and append the declarations
+/// of the imported files to the grammar. `stack` holds the canonical
+/// paths of the files currently being imported, for cycle detection,
+/// and `visited` the paths of the files already imported.
+fn resolve_imports(
+    sources: &mut ast::SourceDatabase,
+    grammar: &mut ast::Grammar,
+    name: &str,
+    imports: &[ast::Import],
+    stack: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(), Diagnostic<ast::FileId>> {
+    for import in imports {
+        let path = Path::new(name).parent().unwrap_or_else(|| Path::new("")).join(&import.path);
+        let canonical_path = path.canonicalize().map_err(|e| {
+            Diagnostic::error()
+                .with_message(format!("failed to read imported file '{}': {}", import.path, e))
+                .with_labels(vec![import.loc.primary()])
+        })?;
+        if stack.contains(&canonical_path) {
+            return Err(Diagnostic::error()
+                .with_message(format!("cyclic import of '{}'", import.path))
+                .with_labels(vec![import.loc.primary()]));
+        }
+        if !visited.insert(canonical_path.clone()) {
+            continue;
+        }
+
+        let name = path.display().to_string();
+        let mut imported = read_file(sources, name.clone())?;
+        match (&grammar.endianness, &imported.endianness) {
+            (Some(endianness), Some(imported_endianness))
+                if endianness.value != imported_endianness.value =>
+            {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "imported file '{}' has a different endianness",
+                        import.path
+                    ))
+                    .with_labels(vec![
+                        import.loc.primary(),
+                        imported_endianness.loc.secondary(),
+                        endianness.loc.secondary(),
+                    ]));
+            }
+            _ => (),
+        }
+
+        stack.push(canonical_path);
+        resolve_imports(sources, grammar, &name, &imported.imports, stack, visited)?;
+        stack.pop();
+        grammar.declarations.append(&mut imported.declarations);
+        grammar.comments.append(&mut imported.comments);
+    }
+    Ok(())
+}
+
 /// Parse a new source file.
-/// The source file is fully read and added to the compilation database.
+/// The source file is fully read and added to the compilation database,
+/// along with the files it imports. The declarations and comments of
+/// the imported files are appended to those of the returned grammar.
 /// Returns the constructed AST, or a descriptive error message in case
-/// of syntax error.
+/// of syntax error or import cycle.
 pub fn parse_file(
     sources: &mut ast::SourceDatabase,
     name: String,
 ) -> Result<ast::Grammar, Diagnostic<ast::FileId>> {
-    let source = std::fs::read_to_string(&name).map_err(|e| {
+    let mut grammar = read_file(sources, name.clone())?;
+    resolve_file_imports(sources, &mut grammar, name)?;
+    Ok(grammar)
+}
+
+/// Parse a PDL grammar text, and resolve its imports relative to the
+/// path `name`. Used for unsaved editor buffers, whose content differs
+/// from the file on disk.
+pub fn parse_source(
+    sources: &mut ast::SourceDatabase,
+    name: String,
+    source: String,
+) -> Result<ast::Grammar, Diagnostic<ast::FileId>> {
+    let mut grammar = parse_inline(sources, name.clone(), source)?;
+    resolve_file_imports(sources, &mut grammar, name)?;
+    Ok(grammar)
+}
+
+/// Resolve the imports of the grammar parsed from the file `name`.
+fn resolve_file_imports(
+    sources: &mut ast::SourceDatabase,
+    grammar: &mut ast::Grammar,
+    name: String,
+) -> Result<(), Diagnostic<ast::FileId>> {
+    if grammar.imports.is_empty() {
+        return Ok(());
+    }
+    let canonical_path = Path::new(&name).canonicalize().map_err(|e| {
         Diagnostic::error().with_message(format!("failed to read input file '{}': {}", &name, e))
     })?;
-    parse_inline(sources, name, source)
+    let imports = grammar.imports.clone();
+    let mut stack = vec![canonical_path.clone()];
+    let mut visited = HashSet::from([canonical_path]);
+    resolve_imports(sources, grammar, &name, &imports, &mut stack, &mut visited)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_files(dir: &Path, files: &[(&str, &str)]) {
+        for (name, content) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("common")).unwrap();
+        write_files(
+            dir.path(),
+            &[
+                ("main.pdl", "little_endian_packets import \"common/a.pdl\" packet Main { a: A }"),
+                ("common/a.pdl", "import \"b.pdl\" import \"../c.pdl\" enum A : 8 { X = 0 }"),
+                ("common/b.pdl", "import \"../c.pdl\" struct B { c: C }"),
+                ("c.pdl", "little_endian_packets enum C : 8 { Y = 1 }"),
+            ],
+        );
+
+        let mut db = ast::SourceDatabase::new();
+        let main = dir.path().join("main.pdl").display().to_string();
+        let grammar = parse_file(&mut db, main).expect("parsing failure");
+        let declarations =
+            grammar.declarations.iter().map(|d| d.id().unwrap().as_str()).collect::<Vec<_>>();
+        assert_eq!(declarations, vec!["Main", "C", "B", "A"]);
+        assert_eq!(grammar.imports.len(), 1);
+        // Each declaration references the file where it is declared.
+        assert!(grammar.declarations[1..].iter().all(|d| d.loc().file != grammar.file));
+        assert_eq!(grammar.declarations[0].loc().file, grammar.file);
+    }
+
+    #[test]
+    fn test_parse_import_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        write_files(
+            dir.path(),
+            &[
+                ("cycle.pdl", "import \"a.pdl\""),
+                ("a.pdl", "import \"b.pdl\""),
+                ("b.pdl", "import \"a.pdl\""),
+                ("missing.pdl", "import \"c.pdl\""),
+                ("endianness.pdl", "big_endian_packets import \"little.pdl\""),
+                ("little.pdl", "little_endian_packets"),
+            ],
+        );
+
+        for name in ["cycle.pdl", "missing.pdl", "endianness.pdl"] {
+            let mut db = ast::SourceDatabase::new();
+            let path = dir.path().join(name).display().to_string();
+            assert!(parse_file(&mut db, path).is_err(), "expected error for {}", name);
+        }
+    }
+
+    #[test]
+    fn test_parse_expressions() {
+        let mut db = ast::SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "stdin".to_owned(),
+            r#"
+            little_endian_packets
+            packet A { a: 8, b: 16 if a = 1 }
+            packet B : A (a = 1 + 2 * 3, b = (1 + 2) * 3) {}
+            packet C : A (a = 1 - 2 - 3, b = ~0x1 & 0xf) {}
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+        let values = grammar.declarations[1..]
+            .iter()
+            .flat_map(|d| d.constraints())
+            .map(|c| c.value.evaluate().ok())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(7), Some(9), None, Some(0xe)]);
+        assert!(matches!(
+            grammar.declarations[0].fields().nth(1),
+            Some(ast::Field::Scalar { cond: Some(ast::Constraint { id, .. }), .. }) if id == "a"
+        ));
+    }
+
+    #[test]
+    fn test_parse_enum_tags() {
+        let mut db = ast::SourceDatabase::new();
+        let grammar = parse_inline(
+            &mut db,
+            "stdin".to_owned(),
+            r#"
+            little_endian_packets
+            enum OpCode : 16 { RESET = 0x0c03, VENDOR = 0xfc00..0xffff, UNKNOWN = .. }
+            "#
+            .to_owned(),
+        )
+        .expect("parsing failure");
+        let ast::Decl::Enum { tags, .. } = &grammar.declarations[0] else { panic!() };
+        assert!(matches!(tags[0], ast::Tag::Value { value: 0x0c03, .. }));
+        assert!(matches!(tags[1], ast::Tag::Range { start: 0xfc00, end: 0xffff, .. }));
+        assert!(matches!(tags[2], ast::Tag::Default { .. }));
+    }
 }
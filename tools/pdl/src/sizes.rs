@@ -0,0 +1,352 @@
+//! Static size analysis.
+//!
+//! Computes the minimum and maximum encoded size of every packet and
+//! struct declaration, including the fields of its ancestors. Arrays,
+//! payloads and bodies are bounded by the width of their size or count
+//! field when they have one, and are unbounded otherwise. Optional
+//! fields account for their absence in the minimum size.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::analyzer::{self, Scope};
+use crate::ast::*;
+use crate::backends::docs::field_name;
+
+/// Bounds of the size of a field, in bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    min: usize,
+    /// `None` if the size is unbounded.
+    max: Option<usize>,
+}
+
+impl Bounds {
+    fn fixed(width: usize) -> Bounds {
+        Bounds { min: width, max: Some(width) }
+    }
+
+    fn unbounded() -> Bounds {
+        Bounds { min: 0, max: None }
+    }
+
+    fn add(self, other: Bounds) -> Bounds {
+        Bounds {
+            min: self.min.saturating_add(other.min),
+            max: self.max.zip(other.max).and_then(|(a, b)| a.checked_add(b)),
+        }
+    }
+
+    /// Return the bounds of a repetition of `self`, between `min`
+    /// and `max` times.
+    fn repeat(self, min: usize, max: Option<usize>) -> Bounds {
+        Bounds {
+            min: self.min.saturating_mul(min),
+            max: self.max.zip(max).and_then(|(a, b)| a.checked_mul(b)),
+        }
+    }
+
+    /// Return the bounds of `self` limited to the maximum size `max`.
+    fn limit(self, max: Option<usize>) -> Bounds {
+        let max = match (self.max, max) {
+            (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        };
+        Bounds { min: self.min, max }
+    }
+
+    /// Return the bounds of a field that may be absent.
+    fn optional(self) -> Bounds {
+        Bounds { min: 0, max: self.max }
+    }
+}
+
+/// Return the largest value of an unsigned integer of `width` bits.
+fn max_value(width: usize) -> Option<usize> {
+    match width {
+        0..=63 => Some((1 << width) - 1),
+        _ => usize::try_from(u64::MAX).ok(),
+    }
+}
+
+/// Size report of a packet or struct declaration. Sizes are expressed
+/// in bytes.
+#[derive(Debug, Serialize)]
+pub struct DeclSize {
+    pub name: String,
+    pub kind: &'static str,
+    pub min_size: usize,
+    /// `None` if the size is unbounded.
+    pub max_size: Option<usize>,
+    pub fixed_size: bool,
+    /// Fields of the declaration and its ancestors whose size varies.
+    pub variable_fields: Vec<String>,
+}
+
+struct Analyzer<'d> {
+    scope: Scope<'d>,
+    /// Bounds of the declarations used as field types.
+    cache: HashMap<String, Bounds>,
+}
+
+impl<'d> Analyzer<'d> {
+    /// Return the bounds of a typedef value: enum, checksum, custom
+    /// field or struct.
+    fn typedef_bounds(&mut self, type_id: &str) -> Bounds {
+        if let Some(bounds) = self.cache.get(type_id) {
+            return *bounds;
+        }
+        let decl = self.scope.typedef[type_id];
+        let bounds = match decl {
+            Decl::Enum { width, .. } | Decl::Checksum { width, .. } => Bounds::fixed(*width),
+            Decl::CustomField { width: Some(width), .. } => Bounds::fixed(*width),
+            Decl::CustomField { width: None, .. } => Bounds::unbounded(),
+            _ => self.decl_bounds(decl).0,
+        };
+        self.cache.insert(type_id.to_owned(), bounds);
+        bounds
+    }
+
+    /// Return the bounds of the value of the size or count field
+    /// referencing `field_id` in `fields`, or `None` if there is none.
+    fn sized_by(fields: &[&Field], field_id: &str) -> Option<(usize, bool)> {
+        fields.iter().find_map(|field| match field {
+            Field::Size { field_id: id, width, .. } if id == field_id => Some((*width, true)),
+            Field::Count { field_id: id, width, .. } if id == field_id => Some((*width, false)),
+            _ => None,
+        })
+    }
+
+    /// Return the bounds of a variable size region: a payload, body or
+    /// untyped array of bytes, limited by its size field if any.
+    fn region_bounds(fields: &[&Field], field_id: &str, size_modifier: Option<&str>) -> Bounds {
+        match Self::sized_by(fields, field_id) {
+            Some((width, true)) => {
+                let modifier = size_modifier.map_or(0, analyzer::size_modifier_bits);
+                let max = max_value(width)
+                    .and_then(|max| max.checked_mul(8))
+                    .map(|max| (max as isize - modifier).max(0) as usize);
+                Bounds { min: 0, max }
+            }
+            _ => Bounds::unbounded(),
+        }
+    }
+
+    fn field_bounds(&mut self, fields: &[&Field], index: usize) -> Bounds {
+        let field = fields[index];
+        let bounds = match field {
+            Field::Payload { size_modifier, .. } => {
+                Self::region_bounds(fields, "_payload_", size_modifier.as_deref())
+            }
+            Field::Body { .. } => Self::region_bounds(fields, "_body_", None),
+            Field::Array { id, width, type_id, size_modifier, size, .. } => {
+                if let Some(Field::Padding { width, .. }) = fields.get(index + 1) {
+                    return Bounds::fixed(width * 8);
+                }
+                let element = match (width, type_id) {
+                    (Some(width), _) => Bounds::fixed(*width),
+                    (_, Some(type_id)) => self.typedef_bounds(type_id),
+                    _ => unreachable!(),
+                };
+                match (size, Self::sized_by(fields, id)) {
+                    (Some(size), _) => element.repeat(*size, Some(*size)),
+                    (None, Some((width, false))) => element.repeat(0, max_value(width)),
+                    (None, Some(_)) => Self::region_bounds(fields, id, size_modifier.as_deref()),
+                    (None, None) => Bounds::unbounded(),
+                }
+            }
+            Field::Typedef { type_id, .. } => self.typedef_bounds(type_id),
+            Field::Scalar { width, .. } => Bounds::fixed(*width),
+            // The padding is accounted by the padded array.
+            Field::Padding { .. } => Bounds::fixed(0),
+            field => Bounds::fixed(self.scope.get_field_width(field).unwrap_or(0)),
+        };
+        match field.cond() {
+            Some(_) => bounds.optional(),
+            None => bounds,
+        }
+    }
+
+    /// Return the bounds of the fields of a declaration and of its
+    /// ancestors, and the bounds of the declaration. `lineage` starts
+    /// from the root ancestor. The payload or body of each ancestor is
+    /// replaced by the fields of its child, and limits their size.
+    fn lineage_bounds(
+        &mut self,
+        lineage: &[&'d Decl],
+        result: &mut Vec<(String, Bounds)>,
+    ) -> Bounds {
+        let (decl, descendants) = lineage.split_first().unwrap();
+        let fields = decl.fields().collect::<Vec<_>>();
+        let mut total = Bounds::fixed(0);
+        for (index, field) in fields.iter().enumerate() {
+            let bounds = self.field_bounds(&fields, index);
+            if !descendants.is_empty()
+                && matches!(field, Field::Payload { .. } | Field::Body { .. })
+            {
+                let content = self.lineage_bounds(descendants, result);
+                total = total.add(content.limit(bounds.max));
+            } else {
+                result.push((field_name(field), bounds));
+                total = total.add(bounds);
+            }
+        }
+        total
+    }
+
+    fn decl_bounds(&mut self, decl: &'d Decl) -> (Bounds, Vec<(String, Bounds)>) {
+        let lineage = self.scope.get_lineage(decl);
+        let mut fields = vec![];
+        let bounds = self.lineage_bounds(&lineage, &mut fields);
+        (bounds, fields)
+    }
+}
+
+/// Compute the size report of all packet and struct declarations of a
+/// grammar, in declaration order.
+pub fn sizes(grammar: &Grammar) -> Vec<DeclSize> {
+    let grammar = analyzer::inline_groups(grammar);
+    let mut analyzer = Analyzer { scope: Scope::new(&grammar), cache: HashMap::new() };
+    let mut sizes = vec![];
+    for decl in &grammar.declarations {
+        let kind = match decl {
+            Decl::Packet { .. } => "packet",
+            Decl::Struct { .. } => "struct",
+            _ => continue,
+        };
+        let (bounds, fields) = analyzer.decl_bounds(decl);
+        sizes.push(DeclSize {
+            name: decl.id().unwrap().clone(),
+            kind,
+            min_size: bounds.min.div_ceil(8),
+            max_size: bounds.max.map(|max| max.div_ceil(8)),
+            fixed_size: bounds.max == Some(bounds.min),
+            variable_fields: fields
+                .into_iter()
+                .filter(|(_, bounds)| bounds.max != Some(bounds.min))
+                .map(|(name, _)| name)
+                .collect(),
+        });
+    }
+    sizes
+}
+
+/// Format the size report as a text table.
+pub fn format_text(sizes: &[DeclSize]) -> String {
+    let rows = sizes
+        .iter()
+        .map(|size| {
+            [
+                size.name.clone(),
+                size.min_size.to_string(),
+                size.max_size.map_or_else(|| "unbounded".to_owned(), |max| max.to_string()),
+                if size.fixed_size { "yes" } else { "no" }.to_owned(),
+                size.variable_fields.join(", "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["declaration", "min", "max", "fixed", "variable fields"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = std::cmp::max(*width, cell.len());
+        }
+    }
+    let mut output = String::new();
+    for row in std::iter::once(header.map(str::to_owned)).chain(rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(output, "{}", line.trim_end()).unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::parse_inline;
+    use crate::sizes::*;
+
+    fn sizes_inline(text: &str) -> Vec<(String, usize, Option<usize>, Vec<String>)> {
+        let mut db = SourceDatabase::new();
+        let grammar =
+            parse_inline(&mut db, "stdin".to_owned(), text.to_owned()).expect("parsing failure");
+        sizes(&grammar)
+            .into_iter()
+            .map(|size| (size.name, size.min_size, size.max_size, size.variable_fields))
+            .collect()
+    }
+
+    #[test]
+    fn test_sizes() {
+        let sizes = sizes_inline(
+            r#"
+            little_endian_packets
+            struct Item { a: 8, b: 16 }
+            packet Command {
+                op_code: 8,
+                _size_(_payload_): 8,
+                _payload_,
+            }
+            packet Reset : Command (op_code = 1) {
+                address: 48,
+            }
+            packet Read : Command (op_code = 2) {
+                _count_(items): 4,
+                _reserved_: 4,
+                items: Item[],
+                extra: 16 if op_code = 2,
+            }
+            packet Padded {
+                data: 8[],
+                _padding_[10],
+                bytes: Item[2],
+            }
+            packet Tail {
+                header: 8,
+                _body_,
+            }
+            packet Unsized : Command (op_code = 3) {
+                _payload_,
+            }
+            "#,
+        );
+        let expected = vec![
+            ("Item", 3, Some(3), vec![]),
+            ("Command", 2, Some(257), vec!["_payload_"]),
+            ("Reset", 8, Some(8), vec![]),
+            ("Read", 3, Some(50), vec!["items", "extra"]),
+            ("Padded", 16, Some(16), vec![]),
+            ("Tail", 1, None, vec!["_body_"]),
+            ("Unsized", 2, Some(257), vec!["_payload_"]),
+        ];
+        let expected = expected
+            .into_iter()
+            .map(|(name, min, max, fields)| {
+                (name.to_owned(), min, max, fields.into_iter().map(str::to_owned).collect())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, expected);
+    }
+
+    #[test]
+    fn test_format_text() {
+        let sizes = vec![DeclSize {
+            name: "Command".to_owned(),
+            kind: "packet",
+            min_size: 2,
+            max_size: None,
+            fixed_size: false,
+            variable_fields: vec!["_payload_".to_owned()],
+        }];
+        assert_eq!(
+            format_text(&sizes),
+            "declaration  min  max        fixed  variable fields\n\
+             Command      2    unbounded  no     _payload_\n"
+        );
+    }
+}
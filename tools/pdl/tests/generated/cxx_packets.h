@@ -0,0 +1,1240 @@
+// @generated C++ header from test.pdl
+
+#pragma once
+
+#include <array>
+#include <cstdint>
+#include <memory>
+#include <optional>
+#include <sstream>
+#include <string>
+#include <type_traits>
+#include <utility>
+#include <vector>
+
+#include "os/log.h"
+#include "packet/base_packet_builder.h"
+#include "packet/bit_inserter.h"
+#include "packet/byte_observer.h"
+#include "packet/custom_field_fixed_size_interface.h"
+#include "packet/iterator.h"
+#include "packet/packet_builder.h"
+#include "packet/packet_struct.h"
+#include "packet/packet_view.h"
+#include "packet/parser/checksum_type_checker.h"
+#include "packet/parser/custom_type_checker.h"
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+#include "packet/raw_builder.h"
+#endif
+
+#include "packet/parser/test/six_bytes.h"
+#include "packet/parser/test/variable.h"
+#include "packet/parser/test/simple_sum.h"
+
+namespace bluetooth::packet::parser::test {
+
+using ::bluetooth::packet::parser::test::SixBytes;
+using ::bluetooth::packet::parser::test::Variable;
+using ::bluetooth::packet::parser::test::SimpleSum;
+using ::bluetooth::packet::BasePacketBuilder;
+using ::bluetooth::packet::BitInserter;
+using ::bluetooth::packet::ByteObserver;
+using ::bluetooth::packet::CustomFieldFixedSizeInterface;
+using ::bluetooth::packet::CustomTypeChecker;
+using ::bluetooth::packet::Iterator;
+using ::bluetooth::packet::kLittleEndian;
+using ::bluetooth::packet::PacketBuilder;
+using ::bluetooth::packet::PacketStruct;
+using ::bluetooth::packet::PacketView;
+using ::bluetooth::packet::parser::ChecksumTypeChecker;
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+using ::bluetooth::packet::RawBuilder;
+#endif
+
+enum class OpCode : uint8_t {
+  RESET = 0x1,
+  READ = 0x2,
+  WRITE = 0x3,
+};
+
+inline std::string OpCodeText(const OpCode& param) {
+  std::stringstream builder;
+  switch (param) {
+    case OpCode::RESET:
+      return "RESET";
+    case OpCode::READ:
+      return "READ";
+    case OpCode::WRITE:
+      return "WRITE";
+    default:
+      builder << "Unknown OpCode: " << static_cast<uint64_t>(param);
+      return builder.str();
+  }
+}
+
+inline std::ostream& operator<<(std::ostream& os, const OpCode& param) {
+  return os << OpCodeText(param);
+}
+
+enum class Level : uint8_t {
+  LOW = 0x1,
+  HIGH = 0x2,
+};
+
+inline std::string LevelText(const Level& param) {
+  std::stringstream builder;
+  switch (param) {
+    case Level::LOW:
+      return "LOW";
+    case Level::HIGH:
+      return "HIGH";
+    default:
+      builder << "Unknown Level: " << static_cast<uint64_t>(param);
+      return builder.str();
+  }
+}
+
+inline std::ostream& operator<<(std::ostream& os, const Level& param) {
+  return os << LevelText(param);
+}
+
+static_assert(std::is_base_of_v<CustomFieldFixedSizeInterface<SixBytes>, SixBytes>, "SixBytes is not a valid fixed size custom field type");
+static_assert(CustomFieldFixedSizeInterface<SixBytes>::length() * 8 == 48, "SixBytes does not have the size 48 declared in the grammar");
+static_assert(CustomTypeChecker<Variable, kLittleEndian>::value, "Variable is not a valid custom field type");
+static_assert(ChecksumTypeChecker<SimpleSum, uint16_t>::value, "SimpleSum is not a valid checksum type");
+
+class Entry : public PacketStruct<kLittleEndian> {
+ public:
+  Entry() = default;
+  virtual ~Entry() = default;
+
+  static std::optional<Iterator<kLittleEndian>> Parse(Entry* to_fill, Iterator<kLittleEndian> struct_begin_it) {
+    auto it = struct_begin_it;
+    if (it.NumBytesRemaining() < 1) {
+      return {};
+    }
+    uint64_t chunk0 = it.extract<uint8_t>();
+    to_fill->key_ = static_cast<uint8_t>(chunk0);
+    if (it.NumBytesRemaining() < 1) {
+      return {};
+    }
+    uint64_t chunk1 = it.extract<uint8_t>();
+    to_fill->level_ = static_cast<Level>((chunk1 & 0xf));
+    if (it.NumBytesRemaining() < 2) {
+      return {};
+    }
+    uint64_t chunk2 = it.extract<uint16_t>();
+    to_fill->value_ = static_cast<uint16_t>(chunk2);
+    return it;
+  }
+
+  void Serialize(BitInserter& i) const override {
+    insert(static_cast<uint64_t>(key_), i, 8);
+    insert(static_cast<uint64_t>(level_), i, 8);
+    insert(static_cast<uint64_t>(value_), i, 16);
+  }
+
+  size_t size() const override {
+    return BitsOfStruct() / 8;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Entry { ";
+    ss << "key = " << +key_;
+    ss << ", level = " << LevelText(level_);
+    ss << ", value = " << +value_;
+    ss << " }";
+    return ss.str();
+  }
+
+  uint8_t key_{};
+  Level level_{};
+  uint16_t value_{};
+
+ private:
+  size_t BitsOfStruct() const {
+    return 32;
+  }
+};
+
+class Blob : public PacketStruct<kLittleEndian> {
+ public:
+  Blob() = default;
+  virtual ~Blob() = default;
+
+  static std::optional<Iterator<kLittleEndian>> Parse(Blob* to_fill, Iterator<kLittleEndian> struct_begin_it) {
+    auto it = struct_begin_it;
+    if (it.NumBytesRemaining() < 1) {
+      return {};
+    }
+    uint64_t chunk0 = it.extract<uint8_t>();
+    size_t data_size = chunk0;
+    if (it.NumBytesRemaining() < data_size) {
+      return {};
+    }
+    auto data_it = it.Subrange(0, data_size);
+    it += data_size;
+    while (data_it.NumBytesRemaining() > 0) {
+      if (data_it.NumBytesRemaining() < 1) {
+        return {};
+      }
+      uint8_t elem = static_cast<uint8_t>(data_it.extract<uint8_t>());
+      to_fill->data_.push_back(elem);
+    }
+    auto name_it = Variable::Parse(&to_fill->name_, it);
+    if (!name_it) {
+      return {};
+    }
+    it = *name_it;
+    return it;
+  }
+
+  void Serialize(BitInserter& i) const override {
+    size_t data_size = data_.size();
+    ASSERT(data_size < (static_cast<uint64_t>(1) << 8));
+    insert(static_cast<uint64_t>(data_size), i, 8);
+    for (const auto& elem : data_) {
+      i.insert_byte(elem);
+    }
+    name_.Serialize(i);
+  }
+
+  size_t size() const override {
+    return BitsOfStruct() / 8;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Blob { ";
+    ss << "data = VECTOR[";
+    {
+      auto data = data_;
+      for (size_t n = 0; n < data.size(); n++) {
+        ss << (n == 0 ? "" : ", ") << +data[n];
+      }
+    }
+    ss << "]";
+    ss << ", name = " << name_.ToString();
+    ss << " }";
+    return ss.str();
+  }
+
+  std::vector<uint8_t> data_;
+  Variable name_{};
+
+ private:
+  size_t BitsOfStruct() const {
+    size_t bits = 8;
+    bits += data_.size() * 8;
+    bits += name_.size() * 8;
+    return bits;
+  }
+};
+
+class CommandView : public PacketView<kLittleEndian> {
+ public:
+  static CommandView Create(PacketView<kLittleEndian> packet) {
+    return CommandView(std::move(packet));
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static CommandView FromBytes(std::vector<uint8_t> bytes) {
+    auto vec = std::make_shared<std::vector<uint8_t>>(bytes);
+    return CommandView::Create(PacketView<kLittleEndian>(vec));
+  }
+#endif
+
+  OpCode GetOpCode() const {
+    ASSERT(was_validated_);
+    auto it = begin() + op_code_offset_;
+    uint64_t chunk = it.extract<uint8_t>();
+    return static_cast<OpCode>(chunk);
+  }
+
+  uint8_t GetFlag() const {
+    ASSERT(was_validated_);
+    auto it = begin() + flag_offset_;
+    uint64_t chunk = it.extract<uint8_t>();
+    return static_cast<uint8_t>((chunk & 0x1));
+  }
+
+  PacketView<kLittleEndian> GetPayload() const {
+    ASSERT(was_validated_);
+    return GetLittleEndianSubview(payload_begin_, payload_end_);
+  }
+
+  PacketView<!kLittleEndian> GetPayloadBigEndian() const {
+    ASSERT(was_validated_);
+    return GetBigEndianSubview(payload_begin_, payload_end_);
+  }
+
+  virtual bool IsValid() {
+    if (was_validated_) {
+      return true;
+    }
+    was_validated_ = IsValid_();
+    return was_validated_;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Command { ";
+    ss << "op_code = " << OpCodeText(GetOpCode());
+    ss << ", flag = " << +GetFlag();
+    ss << " }";
+    return ss.str();
+  }
+
+ protected:
+  explicit CommandView(PacketView<kLittleEndian> packet) : PacketView<kLittleEndian>(std::move(packet)) {
+    was_validated_ = false;
+  }
+
+  bool IsValid_() {
+    size_t end = size();
+    auto it = begin();
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    op_code_offset_ = end - it.NumBytesRemaining();
+    it += 1;
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    flag_offset_ = end - it.NumBytesRemaining();
+    it += 1;
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    uint64_t chunk2 = it.extract<uint8_t>();
+    size_t payload_size = chunk2;
+    if (it.NumBytesRemaining() < payload_size) {
+      return false;
+    }
+    payload_begin_ = end - it.NumBytesRemaining();
+    it += payload_size;
+    payload_end_ = end - it.NumBytesRemaining();
+    return true;
+  }
+
+  bool was_validated_{false};
+  size_t payload_begin_{0};
+  size_t payload_end_{0};
+  size_t op_code_offset_{0};
+  size_t flag_offset_{0};
+};
+
+class ResetView : public CommandView {
+ public:
+  static ResetView Create(CommandView parent) {
+    return ResetView(std::move(parent));
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static ResetView FromBytes(std::vector<uint8_t> bytes) {
+    return ResetView::Create(CommandView::FromBytes(std::move(bytes)));
+  }
+#endif
+
+  SixBytes GetAddress() const {
+    ASSERT(was_validated_);
+    auto it = begin() + address_offset_;
+    return it.extract<SixBytes>();
+  }
+
+  virtual bool IsValid() {
+    if (was_validated_) {
+      return true;
+    }
+    was_validated_ = IsValid_();
+    return was_validated_;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Reset { ";
+    ss << "op_code = " << OpCodeText(GetOpCode());
+    ss << ", flag = " << +GetFlag();
+    ss << ", address = " << GetAddress().ToString();
+    ss << " }";
+    return ss.str();
+  }
+
+ protected:
+  explicit ResetView(CommandView parent) : CommandView(std::move(parent)) {
+    was_validated_ = false;
+  }
+
+  bool IsValid_() {
+    if (!CommandView::IsValid_()) {
+      return false;
+    }
+    {
+      auto it = begin() + op_code_offset_;
+      uint64_t chunk = it.extract<uint8_t>();
+      if (chunk != 0x1) {
+        return false;
+      }
+    }
+    size_t end = payload_end_;
+    auto it = begin().Subrange(payload_begin_, payload_end_ - payload_begin_);
+    if (it.NumBytesRemaining() < 6) {
+      return false;
+    }
+    address_offset_ = end - it.NumBytesRemaining();
+    it += 6;
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    uint64_t chunk1 = it.extract<uint8_t>();
+    if (chunk1 != 0x42) {
+      return false;
+    }
+    return true;
+  }
+
+  size_t address_offset_{0};
+};
+
+class ReadView : public CommandView {
+ public:
+  static ReadView Create(CommandView parent) {
+    return ReadView(std::move(parent));
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static ReadView FromBytes(std::vector<uint8_t> bytes) {
+    return ReadView::Create(CommandView::FromBytes(std::move(bytes)));
+  }
+#endif
+
+  std::vector<Entry> GetEntries() const {
+    ASSERT(was_validated_);
+    auto it = begin().Subrange(entries_offset_, entries_end_ - entries_offset_);
+    std::vector<Entry> entries;
+    while (it.NumBytesRemaining() > 0) {
+      Entry elem;
+      auto elem_it = Entry::Parse(&elem, it);
+      if (!elem_it) {
+        break;
+      }
+      it = *elem_it;
+      entries.push_back(elem);
+    }
+    return entries;
+  }
+
+  std::array<OpCode, 2> GetOpCodes() const {
+    ASSERT(was_validated_);
+    auto it = begin().Subrange(op_codes_offset_, op_codes_end_ - op_codes_offset_);
+    std::array<OpCode, 2> op_codes;
+    for (size_t n = 0; n < 2; n++) {
+      if (it.NumBytesRemaining() < 1) {
+        break;
+      }
+      OpCode elem = static_cast<OpCode>(it.extract<uint8_t>());
+      op_codes[n] = elem;
+    }
+    return op_codes;
+  }
+
+  Blob GetBlob() const {
+    ASSERT(was_validated_);
+    Blob blob;
+    Blob::Parse(&blob, begin().Subrange(blob_offset_, blob_end_ - blob_offset_));
+    return blob;
+  }
+
+  virtual bool IsValid() {
+    if (was_validated_) {
+      return true;
+    }
+    was_validated_ = IsValid_();
+    return was_validated_;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Read { ";
+    ss << "op_code = " << OpCodeText(GetOpCode());
+    ss << ", flag = " << +GetFlag();
+    ss << ", entries = VECTOR[";
+    {
+      auto entries = GetEntries();
+      for (size_t n = 0; n < entries.size(); n++) {
+        ss << (n == 0 ? "" : ", ") << entries[n].ToString();
+      }
+    }
+    ss << "]";
+    ss << ", op_codes = VECTOR[";
+    {
+      auto op_codes = GetOpCodes();
+      for (size_t n = 0; n < op_codes.size(); n++) {
+        ss << (n == 0 ? "" : ", ") << OpCodeText(op_codes[n]);
+      }
+    }
+    ss << "]";
+    ss << ", blob = " << GetBlob().ToString();
+    ss << " }";
+    return ss.str();
+  }
+
+ protected:
+  explicit ReadView(CommandView parent) : CommandView(std::move(parent)) {
+    was_validated_ = false;
+  }
+
+  bool IsValid_() {
+    if (!CommandView::IsValid_()) {
+      return false;
+    }
+    {
+      auto it = begin() + op_code_offset_;
+      uint64_t chunk = it.extract<uint8_t>();
+      if (chunk != 0x2) {
+        return false;
+      }
+    }
+    size_t end = payload_end_;
+    auto it = begin().Subrange(payload_begin_, payload_end_ - payload_begin_);
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    uint64_t chunk0 = it.extract<uint8_t>();
+    size_t entries_count = chunk0;
+    entries_offset_ = end - it.NumBytesRemaining();
+    for (size_t n = 0; n < entries_count; n++) {
+      Entry elem;
+      auto elem_it = Entry::Parse(&elem, it);
+      if (!elem_it) {
+        return false;
+      }
+      it = *elem_it;
+    }
+    entries_end_ = end - it.NumBytesRemaining();
+    op_codes_offset_ = end - it.NumBytesRemaining();
+    if (it.NumBytesRemaining() < 2) {
+      return false;
+    }
+    it += 2;
+    op_codes_end_ = end - it.NumBytesRemaining();
+    blob_offset_ = end - it.NumBytesRemaining();
+    Blob blob_value;
+    auto blob_it = Blob::Parse(&blob_value, it);
+    if (!blob_it) {
+      return false;
+    }
+    it = *blob_it;
+    blob_end_ = end - it.NumBytesRemaining();
+    return true;
+  }
+
+  size_t entries_offset_{0};
+  size_t entries_end_{0};
+  size_t op_codes_offset_{0};
+  size_t op_codes_end_{0};
+  size_t blob_offset_{0};
+  size_t blob_end_{0};
+};
+
+class WriteView : public CommandView {
+ public:
+  static WriteView Create(CommandView parent) {
+    return WriteView(std::move(parent));
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static WriteView FromBytes(std::vector<uint8_t> bytes) {
+    return WriteView::Create(CommandView::FromBytes(std::move(bytes)));
+  }
+#endif
+
+  std::vector<uint16_t> GetValues() const {
+    ASSERT(was_validated_);
+    auto it = begin().Subrange(values_offset_, values_end_ - values_offset_);
+    std::vector<uint16_t> values;
+    while (it.NumBytesRemaining() > 0) {
+      if (it.NumBytesRemaining() < 2) {
+        break;
+      }
+      uint16_t elem = static_cast<uint16_t>(it.extract<uint16_t>());
+      values.push_back(elem);
+    }
+    return values;
+  }
+
+  std::vector<Variable> GetNames() const {
+    ASSERT(was_validated_);
+    auto it = begin().Subrange(names_offset_, names_end_ - names_offset_);
+    std::vector<Variable> names;
+    while (it.NumBytesRemaining() > 0) {
+      Variable elem;
+      auto elem_it = Variable::Parse(&elem, it);
+      if (!elem_it) {
+        break;
+      }
+      it = *elem_it;
+      names.push_back(elem);
+    }
+    return names;
+  }
+
+  virtual bool IsValid() {
+    if (was_validated_) {
+      return true;
+    }
+    was_validated_ = IsValid_();
+    return was_validated_;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Write { ";
+    ss << "op_code = " << OpCodeText(GetOpCode());
+    ss << ", flag = " << +GetFlag();
+    ss << ", values = VECTOR[";
+    {
+      auto values = GetValues();
+      for (size_t n = 0; n < values.size(); n++) {
+        ss << (n == 0 ? "" : ", ") << +values[n];
+      }
+    }
+    ss << "]";
+    ss << ", names = VECTOR[";
+    {
+      auto names = GetNames();
+      for (size_t n = 0; n < names.size(); n++) {
+        ss << (n == 0 ? "" : ", ") << names[n].ToString();
+      }
+    }
+    ss << "]";
+    ss << " }";
+    return ss.str();
+  }
+
+ protected:
+  explicit WriteView(CommandView parent) : CommandView(std::move(parent)) {
+    was_validated_ = false;
+  }
+
+  bool IsValid_() {
+    if (!CommandView::IsValid_()) {
+      return false;
+    }
+    {
+      auto it = begin() + op_code_offset_;
+      uint64_t chunk = it.extract<uint8_t>();
+      if (chunk != 0x3) {
+        return false;
+      }
+    }
+    size_t end = payload_end_;
+    auto it = begin().Subrange(payload_begin_, payload_end_ - payload_begin_);
+    auto crc_start = it;
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    uint64_t chunk1 = it.extract<uint8_t>();
+    size_t values_size = chunk1;
+    if (values_size < 2) {
+      return false;
+    }
+    values_size -= 2;
+    values_offset_ = end - it.NumBytesRemaining();
+    if (it.NumBytesRemaining() < values_size) {
+      return false;
+    }
+    auto values_it = it.Subrange(0, values_size);
+    it += values_size;
+    if (values_it.NumBytesRemaining() % 2 != 0) {
+      return false;
+    }
+    values_end_ = values_offset_ + values_size;
+    names_offset_ = end - it.NumBytesRemaining();
+    if (it.NumBytesRemaining() < 2) {
+      return false;
+    }
+    size_t names_size = it.NumBytesRemaining() - 2;
+    auto names_it = it.Subrange(0, names_size);
+    it += names_size;
+    while (names_it.NumBytesRemaining() > 0) {
+      Variable elem;
+      auto elem_it = Variable::Parse(&elem, names_it);
+      if (!elem_it) {
+        return false;
+      }
+      names_it = *elem_it;
+    }
+    names_end_ = names_offset_ + names_size;
+    if (it.NumBytesRemaining() < 2) {
+      return false;
+    }
+    auto crc_end = it;
+    uint64_t chunk4 = it.extract<uint16_t>();
+    SimpleSum crc_checksum;
+    crc_checksum.Initialize();
+    for (auto byte_it = crc_start; byte_it < crc_end; ++byte_it) {
+      crc_checksum.AddByte(*byte_it);
+    }
+    if (crc_checksum.GetChecksum() != static_cast<uint16_t>(chunk4)) {
+      return false;
+    }
+    return true;
+  }
+
+  size_t values_offset_{0};
+  size_t values_end_{0};
+  size_t names_offset_{0};
+  size_t names_end_{0};
+};
+
+class FrameView : public PacketView<kLittleEndian> {
+ public:
+  static FrameView Create(PacketView<kLittleEndian> packet) {
+    return FrameView(std::move(packet));
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static FrameView FromBytes(std::vector<uint8_t> bytes) {
+    auto vec = std::make_shared<std::vector<uint8_t>>(bytes);
+    return FrameView::Create(PacketView<kLittleEndian>(vec));
+  }
+#endif
+
+  uint8_t GetKind() const {
+    ASSERT(was_validated_);
+    auto it = begin() + kind_offset_;
+    uint64_t chunk = it.extract<uint8_t>();
+    return static_cast<uint8_t>(chunk);
+  }
+
+  uint16_t GetTrailer() const {
+    ASSERT(was_validated_);
+    auto it = begin() + trailer_offset_;
+    uint64_t chunk = it.extract<uint16_t>();
+    return static_cast<uint16_t>(chunk);
+  }
+
+  PacketView<kLittleEndian> GetPayload() const {
+    ASSERT(was_validated_);
+    return GetLittleEndianSubview(payload_begin_, payload_end_);
+  }
+
+  PacketView<!kLittleEndian> GetPayloadBigEndian() const {
+    ASSERT(was_validated_);
+    return GetBigEndianSubview(payload_begin_, payload_end_);
+  }
+
+  virtual bool IsValid() {
+    if (was_validated_) {
+      return true;
+    }
+    was_validated_ = IsValid_();
+    return was_validated_;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Frame { ";
+    ss << "kind = " << +GetKind();
+    ss << ", trailer = " << +GetTrailer();
+    ss << " }";
+    return ss.str();
+  }
+
+ protected:
+  explicit FrameView(PacketView<kLittleEndian> packet) : PacketView<kLittleEndian>(std::move(packet)) {
+    was_validated_ = false;
+  }
+
+  bool IsValid_() {
+    size_t end = size();
+    auto it = begin();
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    kind_offset_ = end - it.NumBytesRemaining();
+    it += 1;
+    if (it.NumBytesRemaining() < 2) {
+      return false;
+    }
+    size_t payload_size = it.NumBytesRemaining() - 2;
+    payload_begin_ = end - it.NumBytesRemaining();
+    it += payload_size;
+    payload_end_ = end - it.NumBytesRemaining();
+    if (it.NumBytesRemaining() < 2) {
+      return false;
+    }
+    trailer_offset_ = end - it.NumBytesRemaining();
+    it += 2;
+    return true;
+  }
+
+  bool was_validated_{false};
+  size_t payload_begin_{0};
+  size_t payload_end_{0};
+  size_t kind_offset_{0};
+  size_t trailer_offset_{0};
+};
+
+class DataView : public FrameView {
+ public:
+  static DataView Create(FrameView parent) {
+    return DataView(std::move(parent));
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static DataView FromBytes(std::vector<uint8_t> bytes) {
+    return DataView::Create(FrameView::FromBytes(std::move(bytes)));
+  }
+#endif
+
+  std::vector<uint32_t> GetItems() const {
+    ASSERT(was_validated_);
+    auto it = begin().Subrange(items_offset_, items_end_ - items_offset_);
+    std::vector<uint32_t> items;
+    while (it.NumBytesRemaining() > 0) {
+      if (it.NumBytesRemaining() < 3) {
+        break;
+      }
+      uint64_t value = it.extract<uint16_t>();
+      value |= static_cast<uint64_t>(it.extract<uint8_t>()) << 16;
+      uint32_t elem = static_cast<uint32_t>(value);
+      items.push_back(elem);
+    }
+    return items;
+  }
+
+  uint16_t GetTail() const {
+    ASSERT(was_validated_);
+    auto it = begin() + tail_offset_;
+    uint64_t chunk = it.extract<uint16_t>();
+    return static_cast<uint16_t>((chunk & 0xfff));
+  }
+
+  virtual bool IsValid() {
+    if (was_validated_) {
+      return true;
+    }
+    was_validated_ = IsValid_();
+    return was_validated_;
+  }
+
+  std::string ToString() const {
+    std::stringstream ss;
+    ss << std::showbase << std::hex << "Data { ";
+    ss << "kind = " << +GetKind();
+    ss << ", trailer = " << +GetTrailer();
+    ss << ", items = VECTOR[";
+    {
+      auto items = GetItems();
+      for (size_t n = 0; n < items.size(); n++) {
+        ss << (n == 0 ? "" : ", ") << +items[n];
+      }
+    }
+    ss << "]";
+    ss << ", tail = " << +GetTail();
+    ss << " }";
+    return ss.str();
+  }
+
+ protected:
+  explicit DataView(FrameView parent) : FrameView(std::move(parent)) {
+    was_validated_ = false;
+  }
+
+  bool IsValid_() {
+    if (!FrameView::IsValid_()) {
+      return false;
+    }
+    {
+      auto it = begin() + kind_offset_;
+      uint64_t chunk = it.extract<uint8_t>();
+      if (chunk != 0x1) {
+        return false;
+      }
+    }
+    size_t end = payload_end_;
+    auto it = begin().Subrange(payload_begin_, payload_end_ - payload_begin_);
+    if (it.NumBytesRemaining() < 1) {
+      return false;
+    }
+    uint64_t chunk0 = it.extract<uint8_t>();
+    size_t items_size = chunk0;
+    items_offset_ = end - it.NumBytesRemaining();
+    if (items_size > 9) {
+      return false;
+    }
+    if (it.NumBytesRemaining() < 9) {
+      return false;
+    }
+    auto items_it = it.Subrange(0, items_size);
+    it += 9;
+    if (items_it.NumBytesRemaining() % 3 != 0) {
+      return false;
+    }
+    items_end_ = items_offset_ + items_size;
+    if (it.NumBytesRemaining() < 2) {
+      return false;
+    }
+    tail_offset_ = end - it.NumBytesRemaining();
+    uint64_t chunk3 = it.extract<uint16_t>();
+    if (((chunk3 >> 12) & 0xf) != 0x0) {
+      return false;
+    }
+    return true;
+  }
+
+  size_t items_offset_{0};
+  size_t items_end_{0};
+  size_t tail_offset_{0};
+};
+
+class CommandBuilder : public PacketBuilder<kLittleEndian> {
+ public:
+  virtual ~CommandBuilder() = default;
+
+  static std::unique_ptr<CommandBuilder> Create(OpCode op_code, uint8_t flag, std::unique_ptr<BasePacketBuilder> payload) {
+    auto builder = std::unique_ptr<CommandBuilder>(new CommandBuilder(op_code, flag));
+    builder->payload_ = std::move(payload);
+    return builder;
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static std::unique_ptr<CommandBuilder> FromView(CommandView view) {
+    if (!view.IsValid()) {
+      return nullptr;
+    }
+    return CommandBuilder::Create(view.GetOpCode(), view.GetFlag(), std::make_unique<RawBuilder>(std::vector<uint8_t>(view.GetPayload().begin(), view.GetPayload().end())));
+  }
+#endif
+
+  void Serialize(BitInserter& i) const override {
+    SerializeHeader(i);
+    payload_->Serialize(i);
+    SerializeFooter(i);
+  }
+
+  size_t size() const override {
+    return (BitsOfHeader() + BitsOfFooter()) / 8 + payload_->size();
+  }
+
+ protected:
+  explicit CommandBuilder(OpCode op_code, uint8_t flag) : op_code_(op_code), flag_(flag) {
+    ASSERT(flag < (static_cast<uint64_t>(1) << 1));
+  }
+
+  void SerializeHeader(BitInserter& i) const {
+    insert(static_cast<uint64_t>(op_code_), i, 8);
+    insert((static_cast<uint64_t>(flag_) & 0x1), i, 8);
+    size_t payload_size = GetPayloadSize();
+    ASSERT(payload_size < (static_cast<uint64_t>(1) << 8));
+    insert(static_cast<uint64_t>(payload_size), i, 8);
+  }
+
+  void SerializeFooter(BitInserter&) const {
+  }
+
+  size_t BitsOfHeader() const {
+    return 24;
+  }
+
+  size_t BitsOfFooter() const {
+    return 0;
+  }
+
+  size_t GetPayloadSize() const {
+    return size() - (BitsOfHeader() + BitsOfFooter()) / 8;
+  }
+
+  OpCode op_code_{};
+  uint8_t flag_{};
+  std::unique_ptr<BasePacketBuilder> payload_;
+};
+
+class ResetBuilder : public CommandBuilder {
+ public:
+  virtual ~ResetBuilder() = default;
+
+  static std::unique_ptr<ResetBuilder> Create(uint8_t flag, const SixBytes& address) {
+    auto builder = std::unique_ptr<ResetBuilder>(new ResetBuilder(flag, address));
+    return builder;
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static std::unique_ptr<ResetBuilder> FromView(ResetView view) {
+    if (!view.IsValid()) {
+      return nullptr;
+    }
+    return ResetBuilder::Create(view.GetFlag(), view.GetAddress());
+  }
+#endif
+
+  void Serialize(BitInserter& i) const override {
+    SerializeHeader(i);
+    SerializeFooter(i);
+  }
+
+  size_t size() const override {
+    return (BitsOfHeader() + BitsOfFooter()) / 8;
+  }
+
+ protected:
+  explicit ResetBuilder(uint8_t flag, const SixBytes& address) : CommandBuilder(OpCode::RESET, flag), address_(address) {
+  }
+
+  void SerializeHeader(BitInserter& i) const {
+    CommandBuilder::SerializeHeader(i);
+    insert(address_, i);
+    insert(static_cast<uint64_t>(0x42), i, 8);
+  }
+
+  void SerializeFooter(BitInserter& i) const {
+    CommandBuilder::SerializeFooter(i);
+  }
+
+  size_t BitsOfHeader() const {
+    return CommandBuilder::BitsOfHeader() + 56;
+  }
+
+  size_t BitsOfFooter() const {
+    return CommandBuilder::BitsOfFooter();
+  }
+
+  SixBytes address_{};
+};
+
+class ReadBuilder : public CommandBuilder {
+ public:
+  virtual ~ReadBuilder() = default;
+
+  static std::unique_ptr<ReadBuilder> Create(uint8_t flag, const std::vector<Entry>& entries, const std::array<OpCode, 2>& op_codes, const Blob& blob) {
+    auto builder = std::unique_ptr<ReadBuilder>(new ReadBuilder(flag, entries, op_codes, blob));
+    return builder;
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static std::unique_ptr<ReadBuilder> FromView(ReadView view) {
+    if (!view.IsValid()) {
+      return nullptr;
+    }
+    return ReadBuilder::Create(view.GetFlag(), view.GetEntries(), view.GetOpCodes(), view.GetBlob());
+  }
+#endif
+
+  void Serialize(BitInserter& i) const override {
+    SerializeHeader(i);
+    SerializeFooter(i);
+  }
+
+  size_t size() const override {
+    return (BitsOfHeader() + BitsOfFooter()) / 8;
+  }
+
+ protected:
+  explicit ReadBuilder(uint8_t flag, const std::vector<Entry>& entries, const std::array<OpCode, 2>& op_codes, const Blob& blob) : CommandBuilder(OpCode::READ, flag), entries_(entries), op_codes_(op_codes), blob_(blob) {
+  }
+
+  void SerializeHeader(BitInserter& i) const {
+    CommandBuilder::SerializeHeader(i);
+    ASSERT(entries_.size() < (static_cast<uint64_t>(1) << 8));
+    insert(static_cast<uint64_t>(entries_.size()), i, 8);
+    for (const auto& elem : entries_) {
+      elem.Serialize(i);
+    }
+    for (const auto& elem : op_codes_) {
+      insert(static_cast<uint8_t>(elem), i, 8);
+    }
+    blob_.Serialize(i);
+  }
+
+  void SerializeFooter(BitInserter& i) const {
+    CommandBuilder::SerializeFooter(i);
+  }
+
+  size_t BitsOfHeader() const {
+    size_t bits = CommandBuilder::BitsOfHeader() + 8;
+    for (const auto& elem : entries_) {
+      bits += elem.size() * 8;
+    }
+    bits += op_codes_.size() * 8;
+    bits += blob_.size() * 8;
+    return bits;
+  }
+
+  size_t BitsOfFooter() const {
+    return CommandBuilder::BitsOfFooter();
+  }
+
+  std::vector<Entry> entries_;
+  std::array<OpCode, 2> op_codes_;
+  Blob blob_{};
+};
+
+class WriteBuilder : public CommandBuilder {
+ public:
+  virtual ~WriteBuilder() = default;
+
+  static std::unique_ptr<WriteBuilder> Create(uint8_t flag, const std::vector<uint16_t>& values, const std::vector<Variable>& names) {
+    auto builder = std::unique_ptr<WriteBuilder>(new WriteBuilder(flag, values, names));
+    return builder;
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static std::unique_ptr<WriteBuilder> FromView(WriteView view) {
+    if (!view.IsValid()) {
+      return nullptr;
+    }
+    return WriteBuilder::Create(view.GetFlag(), view.GetValues(), view.GetNames());
+  }
+#endif
+
+  void Serialize(BitInserter& i) const override {
+    SerializeHeader(i);
+    SerializeFooter(i);
+  }
+
+  size_t size() const override {
+    return (BitsOfHeader() + BitsOfFooter()) / 8;
+  }
+
+ protected:
+  explicit WriteBuilder(uint8_t flag, const std::vector<uint16_t>& values, const std::vector<Variable>& names) : CommandBuilder(OpCode::WRITE, flag), values_(values), names_(names) {
+  }
+
+  void SerializeHeader(BitInserter& i) const {
+    CommandBuilder::SerializeHeader(i);
+    auto crc_checksum = std::make_shared<SimpleSum>();
+    crc_checksum->Initialize();
+    auto crc_add_byte = [crc_checksum](uint8_t byte) { crc_checksum->AddByte(byte); };
+    auto crc_get_value = [crc_checksum]() { return static_cast<uint64_t>(crc_checksum->GetChecksum()); };
+    i.RegisterObserver(ByteObserver(crc_add_byte, crc_get_value));
+    size_t values_size = values_.size() * 2;
+    ASSERT(values_size + 2 < (static_cast<uint64_t>(1) << 8));
+    insert(static_cast<uint64_t>(values_size + 2), i, 8);
+    for (const auto& elem : values_) {
+      insert(elem, i, 16);
+    }
+    for (const auto& elem : names_) {
+      elem.Serialize(i);
+    }
+    uint64_t crc_value = i.UnregisterObserver().GetValue();
+    insert(crc_value, i, 16);
+  }
+
+  void SerializeFooter(BitInserter& i) const {
+    CommandBuilder::SerializeFooter(i);
+  }
+
+  size_t BitsOfHeader() const {
+    size_t bits = CommandBuilder::BitsOfHeader() + 24;
+    bits += values_.size() * 16;
+    for (const auto& elem : names_) {
+      bits += elem.size() * 8;
+    }
+    return bits;
+  }
+
+  size_t BitsOfFooter() const {
+    return CommandBuilder::BitsOfFooter();
+  }
+
+  std::vector<uint16_t> values_;
+  std::vector<Variable> names_;
+};
+
+class FrameBuilder : public PacketBuilder<kLittleEndian> {
+ public:
+  virtual ~FrameBuilder() = default;
+
+ protected:
+  explicit FrameBuilder(uint8_t kind, uint16_t trailer) : kind_(kind), trailer_(trailer) {
+  }
+
+  void SerializeHeader(BitInserter& i) const {
+    insert(static_cast<uint64_t>(kind_), i, 8);
+  }
+
+  void SerializeFooter(BitInserter& i) const {
+    insert(static_cast<uint64_t>(trailer_), i, 16);
+  }
+
+  size_t BitsOfHeader() const {
+    return 8;
+  }
+
+  size_t BitsOfFooter() const {
+    return 16;
+  }
+
+  uint8_t kind_{};
+  uint16_t trailer_{};
+};
+
+class DataBuilder : public FrameBuilder {
+ public:
+  virtual ~DataBuilder() = default;
+
+  static std::unique_ptr<DataBuilder> Create(uint16_t trailer, const std::vector<uint32_t>& items, uint16_t tail) {
+    auto builder = std::unique_ptr<DataBuilder>(new DataBuilder(trailer, items, tail));
+    return builder;
+  }
+
+#if defined(PACKET_FUZZ_TESTING) || defined(PACKET_TESTING) || defined(FUZZ_TARGET)
+  static std::unique_ptr<DataBuilder> FromView(DataView view) {
+    if (!view.IsValid()) {
+      return nullptr;
+    }
+    return DataBuilder::Create(view.GetTrailer(), view.GetItems(), view.GetTail());
+  }
+#endif
+
+  void Serialize(BitInserter& i) const override {
+    SerializeHeader(i);
+    SerializeFooter(i);
+  }
+
+  size_t size() const override {
+    return (BitsOfHeader() + BitsOfFooter()) / 8;
+  }
+
+ protected:
+  explicit DataBuilder(uint16_t trailer, const std::vector<uint32_t>& items, uint16_t tail) : FrameBuilder(0x1, trailer), items_(items), tail_(tail) {
+    ASSERT(tail < (static_cast<uint64_t>(1) << 12));
+  }
+
+  void SerializeHeader(BitInserter& i) const {
+    FrameBuilder::SerializeHeader(i);
+    size_t items_size = items_.size() * 3;
+    ASSERT(items_size < (static_cast<uint64_t>(1) << 8));
+    insert(static_cast<uint64_t>(items_size), i, 8);
+    for (const auto& elem : items_) {
+      insert(elem, i, 24);
+    }
+    size_t items_bytes = items_.size() * 3;
+    ASSERT(items_bytes <= 9);
+    for (size_t n = items_bytes; n < 9; n++) {
+      i.insert_byte(0);
+    }
+    insert((static_cast<uint64_t>(tail_) & 0xfff) | (static_cast<uint64_t>(0x0) << 12), i, 16);
+  }
+
+  void SerializeFooter(BitInserter& i) const {
+    FrameBuilder::SerializeFooter(i);
+  }
+
+  size_t BitsOfHeader() const {
+    return FrameBuilder::BitsOfHeader() + 96;
+  }
+
+  size_t BitsOfFooter() const {
+    return FrameBuilder::BitsOfFooter();
+  }
+
+  std::vector<uint32_t> items_;
+  uint16_t tail_{};
+};
+
+}  // namespace bluetooth::packet::parser::test
@@ -0,0 +1,365 @@
+// @generated rust packets from packet_decl_big_endian.pdl
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Packet parsing failed")]
+    InvalidPacketError,
+    #[error("{field} was {value:x}, which is not known")]
+    ConstraintOutOfBounds { field: String, value: u64 },
+    #[error("when parsing {obj}.{field} needed length of {wanted} but got {got}")]
+    InvalidLengthError {
+        obj: String,
+        field: String,
+        wanted: usize,
+        got: usize,
+    },
+    #[error("Due to size restrictions a struct could not be parsed.")]
+    ImpossibleStructError,
+    #[error("when parsing field {obj}.{field}, {value} is not a valid {type_} value")]
+    InvalidEnumValueError {
+        obj: String,
+        field: String,
+        value: u64,
+        type_: String,
+    },
+    #[error("when parsing {obj}, expected fixed value {expected:x} but got {actual:x}")]
+    InvalidFixedValue {
+        obj: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct TryFromError(&'static str);
+
+pub trait Packet {
+    fn to_bytes(self) -> Bytes;
+    fn to_vec(self) -> Vec<u8>;
+}
+
+#[derive(Debug)]
+enum ParentDataChild {
+    Child(Arc<ChildData>),
+    Payload(Bytes),
+    None,
+}
+impl ParentDataChild {
+    fn get_total_size(&self) -> usize {
+        match self {
+            ParentDataChild::Child(value) => value.get_total_size(),
+            ParentDataChild::Payload(payload) => payload.len(),
+            ParentDataChild::None => 0,
+        }
+    }
+}
+#[derive(Debug)]
+pub enum ParentChild {
+    Child(ChildPacket),
+    Payload(Bytes),
+    None,
+}
+#[derive(Debug)]
+struct ParentData {
+    a: u8,
+    b: u16,
+    data: Vec<u8>,
+    child: ParentDataChild,
+}
+#[derive(Debug, Clone)]
+pub struct ParentPacket {
+    parent: Arc<ParentData>,
+}
+#[derive(Debug)]
+pub struct ParentBuilder {
+    pub a: u8,
+    pub b: u16,
+    pub data: Vec<u8>,
+    pub payload: Option<Bytes>,
+}
+impl ParentData {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut bytes = bytes;
+        Self::parse_inner(&mut bytes)
+    }
+    fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+        if bytes.remaining() < 2 {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "a".to_string(),
+                wanted: 2,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint(2);
+        let a = (chunk & 0xf) as u8;
+        let b = ((chunk >> 4) & 0xfff) as u16;
+        if bytes.remaining() < 1 {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "data".to_string(),
+                wanted: 1,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint(1);
+        let data_size = chunk as usize;
+        let region_size = data_size;
+        if bytes.remaining() < region_size {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "data".to_string(),
+                wanted: region_size,
+                got: bytes.remaining(),
+            });
+        }
+        let (mut region, tail) = {
+            let bytes: &[u8] = *bytes;
+            bytes.split_at(region_size)
+        };
+        *bytes = tail;
+        let mut data = Vec::new();
+        while !region.is_empty() {
+            let buf: &mut &[u8] = &mut region;
+            data.push({
+                if buf.remaining() < 1 {
+                    return Err(Error::InvalidLengthError {
+                        obj: "Parent".to_string(),
+                        field: "data".to_string(),
+                        wanted: 1,
+                        got: buf.remaining(),
+                    });
+                }
+                buf.get_uint(1) as u8
+            });
+        }
+        let payload_size = bytes.remaining();
+        if bytes.remaining() < payload_size {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "payload".to_string(),
+                wanted: payload_size,
+                got: bytes.remaining(),
+            });
+        }
+        let (payload, tail) = {
+            let bytes: &[u8] = *bytes;
+            bytes.split_at(payload_size)
+        };
+        *bytes = tail;
+        let mut payload = payload;
+        let child = if a == 0x1 {
+            ParentDataChild::Child(Arc::new(ChildData::parse_inner(&mut payload)?))
+        } else if payload.is_empty() {
+            ParentDataChild::None
+        } else {
+            ParentDataChild::Payload(Bytes::copy_from_slice(payload))
+        };
+        Ok(Self { a, b, data, child })
+    }
+    fn write_to(&self, buffer: &mut BytesMut) {
+        let chunk: u64 = self.a as u64 & 0xf | (self.b as u64 & 0xfff) << 4;
+        buffer.put_uint(chunk, 2);
+        let chunk: u64 = (self.data.len()) as u64 & 0xff;
+        buffer.put_uint(chunk, 1);
+        for elem in self.data.iter() {
+            buffer.put_uint(*elem as u64, 1);
+        }
+        match &self.child {
+            ParentDataChild::Child(value) => value.write_to(buffer),
+            ParentDataChild::Payload(payload) => buffer.put_slice(payload),
+            ParentDataChild::None => {}
+        }
+    }
+    fn get_total_size(&self) -> usize {
+        self.get_size() + self.child.get_total_size()
+    }
+    fn get_size(&self) -> usize {
+        3 + self.data.len()
+    }
+}
+impl Packet for ParentPacket {
+    fn to_bytes(self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(self.parent.get_total_size());
+        self.parent.write_to(&mut buffer);
+        buffer.freeze()
+    }
+    fn to_vec(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+}
+impl From<ParentPacket> for Bytes {
+    fn from(packet: ParentPacket) -> Self {
+        packet.to_bytes()
+    }
+}
+impl From<ParentPacket> for Vec<u8> {
+    fn from(packet: ParentPacket) -> Self {
+        packet.to_vec()
+    }
+}
+impl ParentPacket {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::new(Arc::new(ParentData::parse(bytes)?)).unwrap())
+    }
+    pub fn specialize(&self) -> ParentChild {
+        match &self.parent.child {
+            ParentDataChild::Child(_) => {
+                ParentChild::Child(ChildPacket::new(self.parent.clone()).unwrap())
+            }
+            ParentDataChild::Payload(payload) => ParentChild::Payload(payload.clone()),
+            ParentDataChild::None => ParentChild::None,
+        }
+    }
+    fn new(root: Arc<ParentData>) -> std::result::Result<Self, &'static str> {
+        let parent = root;
+        Ok(Self { parent })
+    }
+    pub fn get_a(&self) -> u8 {
+        self.parent.as_ref().a
+    }
+    pub fn get_b(&self) -> u16 {
+        self.parent.as_ref().b
+    }
+    pub fn get_data(&self) -> &Vec<u8> {
+        &self.parent.as_ref().data
+    }
+}
+impl ParentBuilder {
+    pub fn build(self) -> ParentPacket {
+        let parent = Arc::new(ParentData {
+            a: self.a,
+            b: self.b,
+            data: self.data,
+            child: match self.payload {
+                None => ParentDataChild::None,
+                Some(bytes) => ParentDataChild::Payload(bytes),
+            },
+        });
+        ParentPacket::new(parent).unwrap()
+    }
+}
+#[derive(Debug)]
+struct ChildData {
+    c: u32,
+}
+#[derive(Debug, Clone)]
+pub struct ChildPacket {
+    parent: Arc<ParentData>,
+    child: Arc<ChildData>,
+}
+#[derive(Debug)]
+pub struct ChildBuilder {
+    pub b: u16,
+    pub data: Vec<u8>,
+    pub c: u32,
+}
+impl ChildData {
+    fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+        if bytes.remaining() < 4 {
+            return Err(Error::InvalidLengthError {
+                obj: "Child".to_string(),
+                field: "c".to_string(),
+                wanted: 4,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint(4);
+        let c = chunk as u32;
+        Ok(Self { c })
+    }
+    fn write_to(&self, buffer: &mut BytesMut) {
+        let chunk: u64 = self.c as u64;
+        buffer.put_uint(chunk, 4);
+    }
+    fn get_total_size(&self) -> usize {
+        self.get_size()
+    }
+    fn get_size(&self) -> usize {
+        4
+    }
+}
+impl Packet for ChildPacket {
+    fn to_bytes(self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(self.parent.get_total_size());
+        self.parent.write_to(&mut buffer);
+        buffer.freeze()
+    }
+    fn to_vec(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+}
+impl From<ChildPacket> for Bytes {
+    fn from(packet: ChildPacket) -> Self {
+        packet.to_bytes()
+    }
+}
+impl From<ChildPacket> for Vec<u8> {
+    fn from(packet: ChildPacket) -> Self {
+        packet.to_vec()
+    }
+}
+impl TryFrom<ParentPacket> for ChildPacket {
+    type Error = TryFromError;
+    fn try_from(value: ParentPacket) -> std::result::Result<Self, Self::Error> {
+        Self::new(value.parent).map_err(TryFromError)
+    }
+}
+impl ChildPacket {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        Self::new(Arc::new(ParentData::parse(bytes)?)).map_err(|_| Error::InvalidPacketError)
+    }
+    fn new(root: Arc<ParentData>) -> std::result::Result<Self, &'static str> {
+        let parent = root;
+        let child = match &parent.child {
+            ParentDataChild::Child(value) => (*value).clone(),
+            _ => return Err("inconsistent state - child was not Child"),
+        };
+        Ok(Self { parent, child })
+    }
+    pub fn get_a(&self) -> u8 {
+        self.parent.as_ref().a
+    }
+    pub fn get_b(&self) -> u16 {
+        self.parent.as_ref().b
+    }
+    pub fn get_data(&self) -> &Vec<u8> {
+        &self.parent.as_ref().data
+    }
+    pub fn get_c(&self) -> u32 {
+        self.child.as_ref().c
+    }
+}
+impl From<ChildPacket> for ParentPacket {
+    fn from(packet: ChildPacket) -> ParentPacket {
+        ParentPacket::new(packet.parent).unwrap()
+    }
+}
+impl ChildBuilder {
+    pub fn build(self) -> ChildPacket {
+        let child = Arc::new(ChildData { c: self.c });
+        let parent = Arc::new(ParentData {
+            a: 0x1,
+            b: self.b,
+            data: self.data,
+            child: ParentDataChild::Child(child),
+        });
+        ChildPacket::new(parent).unwrap()
+    }
+}
+impl From<ChildBuilder> for ParentPacket {
+    fn from(builder: ChildBuilder) -> ParentPacket {
+        builder.build().into()
+    }
+}
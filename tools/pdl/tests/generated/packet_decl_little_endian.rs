@@ -0,0 +1,545 @@
+// @generated rust packets from packet_decl_little_endian.pdl
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Packet parsing failed")]
+    InvalidPacketError,
+    #[error("{field} was {value:x}, which is not known")]
+    ConstraintOutOfBounds { field: String, value: u64 },
+    #[error("when parsing {obj}.{field} needed length of {wanted} but got {got}")]
+    InvalidLengthError {
+        obj: String,
+        field: String,
+        wanted: usize,
+        got: usize,
+    },
+    #[error("Due to size restrictions a struct could not be parsed.")]
+    ImpossibleStructError,
+    #[error("when parsing field {obj}.{field}, {value} is not a valid {type_} value")]
+    InvalidEnumValueError {
+        obj: String,
+        field: String,
+        value: u64,
+        type_: String,
+    },
+    #[error("when parsing {obj}, expected fixed value {expected:x} but got {actual:x}")]
+    InvalidFixedValue {
+        obj: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct TryFromError(&'static str);
+
+pub trait Packet {
+    fn to_bytes(self) -> Bytes;
+    fn to_vec(self) -> Vec<u8>;
+}
+
+#[derive(FromPrimitive, ToPrimitive, Debug, Hash, Eq, PartialEq, Clone, Copy)]
+#[repr(u64)]
+pub enum Enum {
+    A = 0x1,
+    B = 0x2,
+}
+impl fmt::Display for Enum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Enum::A => write!(f, "{:#04X} (A)", self.to_u8().unwrap()),
+            Enum::B => write!(f, "{:#04X} (B)", self.to_u8().unwrap()),
+        }
+    }
+}
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub a: u8,
+    pub b: [u16; 2],
+}
+impl Struct {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut bytes = bytes;
+        Self::parse_inner(&mut bytes)
+    }
+    fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+        if bytes.remaining() < 1 {
+            return Err(Error::InvalidLengthError {
+                obj: "Struct".to_string(),
+                field: "a".to_string(),
+                wanted: 1,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint_le(1);
+        let a = chunk as u8;
+        let mut b = Vec::with_capacity(2usize);
+        for _ in 0..2usize {
+            let buf: &mut &[u8] = &mut *bytes;
+            b.push({
+                if buf.remaining() < 2 {
+                    return Err(Error::InvalidLengthError {
+                        obj: "Struct".to_string(),
+                        field: "b".to_string(),
+                        wanted: 2,
+                        got: buf.remaining(),
+                    });
+                }
+                buf.get_uint_le(2) as u16
+            });
+        }
+        let b = b.try_into().map_err(|_| Error::InvalidPacketError)?;
+        Ok(Self { a, b })
+    }
+    fn write_to(&self, buffer: &mut BytesMut) {
+        let chunk: u64 = self.a as u64;
+        buffer.put_uint_le(chunk, 1);
+        for elem in self.b.iter() {
+            buffer.put_uint_le(*elem as u64, 2);
+        }
+    }
+    fn get_total_size(&self) -> usize {
+        1 + self.b.len() * 2
+    }
+}
+#[derive(Debug)]
+enum ParentDataChild {
+    ChildA(Arc<ChildAData>),
+    ChildB(Arc<ChildBData>),
+    Payload(Bytes),
+    None,
+}
+impl ParentDataChild {
+    fn get_total_size(&self) -> usize {
+        match self {
+            ParentDataChild::ChildA(value) => value.get_total_size(),
+            ParentDataChild::ChildB(value) => value.get_total_size(),
+            ParentDataChild::Payload(payload) => payload.len(),
+            ParentDataChild::None => 0,
+        }
+    }
+}
+#[derive(Debug)]
+pub enum ParentChild {
+    ChildA(ChildAPacket),
+    ChildB(ChildBPacket),
+    Payload(Bytes),
+    None,
+}
+#[derive(Debug)]
+struct ParentData {
+    opcode: Enum,
+    flag: u8,
+    child: ParentDataChild,
+}
+#[derive(Debug, Clone)]
+pub struct ParentPacket {
+    parent: Arc<ParentData>,
+}
+#[derive(Debug)]
+pub struct ParentBuilder {
+    pub opcode: Enum,
+    pub flag: u8,
+    pub payload: Option<Bytes>,
+}
+impl ParentData {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut bytes = bytes;
+        Self::parse_inner(&mut bytes)
+    }
+    fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+        if bytes.remaining() < 1 {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "opcode".to_string(),
+                wanted: 1,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint_le(1);
+        let opcode = {
+            let value = chunk;
+            Enum::from_u64(value).ok_or_else(|| Error::InvalidEnumValueError {
+                obj: "Parent".to_string(),
+                field: "opcode".to_string(),
+                value,
+                type_: "Enum".to_string(),
+            })?
+        };
+        if bytes.remaining() < 1 {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "flag".to_string(),
+                wanted: 1,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint_le(1);
+        let flag = (chunk & 0x1) as u8;
+        let payload_size = ((chunk >> 4) & 0xf) as usize;
+        if bytes.remaining() < payload_size {
+            return Err(Error::InvalidLengthError {
+                obj: "Parent".to_string(),
+                field: "payload".to_string(),
+                wanted: payload_size,
+                got: bytes.remaining(),
+            });
+        }
+        let (payload, tail) = {
+            let bytes: &[u8] = *bytes;
+            bytes.split_at(payload_size)
+        };
+        *bytes = tail;
+        let mut payload = payload;
+        let child = if opcode == Enum::A {
+            ParentDataChild::ChildA(Arc::new(ChildAData::parse_inner(&mut payload)?))
+        } else if opcode == Enum::B {
+            ParentDataChild::ChildB(Arc::new(ChildBData::parse_inner(&mut payload)?))
+        } else if payload.is_empty() {
+            ParentDataChild::None
+        } else {
+            ParentDataChild::Payload(Bytes::copy_from_slice(payload))
+        };
+        Ok(Self {
+            opcode,
+            flag,
+            child,
+        })
+    }
+    fn write_to(&self, buffer: &mut BytesMut) {
+        let chunk: u64 = self.opcode.to_u64().unwrap();
+        buffer.put_uint_le(chunk, 1);
+        let chunk: u64 = self.flag as u64 & 0x1 | ((self.child.get_total_size()) as u64 & 0xf) << 4;
+        buffer.put_uint_le(chunk, 1);
+        match &self.child {
+            ParentDataChild::ChildA(value) => value.write_to(buffer),
+            ParentDataChild::ChildB(value) => value.write_to(buffer),
+            ParentDataChild::Payload(payload) => buffer.put_slice(payload),
+            ParentDataChild::None => {}
+        }
+    }
+    fn get_total_size(&self) -> usize {
+        self.get_size() + self.child.get_total_size()
+    }
+    fn get_size(&self) -> usize {
+        2
+    }
+}
+impl Packet for ParentPacket {
+    fn to_bytes(self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(self.parent.get_total_size());
+        self.parent.write_to(&mut buffer);
+        buffer.freeze()
+    }
+    fn to_vec(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+}
+impl From<ParentPacket> for Bytes {
+    fn from(packet: ParentPacket) -> Self {
+        packet.to_bytes()
+    }
+}
+impl From<ParentPacket> for Vec<u8> {
+    fn from(packet: ParentPacket) -> Self {
+        packet.to_vec()
+    }
+}
+impl ParentPacket {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::new(Arc::new(ParentData::parse(bytes)?)).unwrap())
+    }
+    pub fn specialize(&self) -> ParentChild {
+        match &self.parent.child {
+            ParentDataChild::ChildA(_) => {
+                ParentChild::ChildA(ChildAPacket::new(self.parent.clone()).unwrap())
+            }
+            ParentDataChild::ChildB(_) => {
+                ParentChild::ChildB(ChildBPacket::new(self.parent.clone()).unwrap())
+            }
+            ParentDataChild::Payload(payload) => ParentChild::Payload(payload.clone()),
+            ParentDataChild::None => ParentChild::None,
+        }
+    }
+    fn new(root: Arc<ParentData>) -> std::result::Result<Self, &'static str> {
+        let parent = root;
+        Ok(Self { parent })
+    }
+    pub fn get_opcode(&self) -> Enum {
+        self.parent.as_ref().opcode
+    }
+    pub fn get_flag(&self) -> u8 {
+        self.parent.as_ref().flag
+    }
+}
+impl ParentBuilder {
+    pub fn build(self) -> ParentPacket {
+        let parent = Arc::new(ParentData {
+            opcode: self.opcode,
+            flag: self.flag,
+            child: match self.payload {
+                None => ParentDataChild::None,
+                Some(bytes) => ParentDataChild::Payload(bytes),
+            },
+        });
+        ParentPacket::new(parent).unwrap()
+    }
+}
+#[derive(Debug)]
+struct ChildAData {
+    items: Vec<Struct>,
+}
+#[derive(Debug, Clone)]
+pub struct ChildAPacket {
+    parent: Arc<ParentData>,
+    child_a: Arc<ChildAData>,
+}
+#[derive(Debug)]
+pub struct ChildABuilder {
+    pub flag: u8,
+    pub items: Vec<Struct>,
+}
+impl ChildAData {
+    fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+        if bytes.remaining() < 1 {
+            return Err(Error::InvalidLengthError {
+                obj: "ChildA".to_string(),
+                field: "items".to_string(),
+                wanted: 1,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint_le(1);
+        let items_count = chunk as usize;
+        let mut items = Vec::with_capacity(items_count);
+        for _ in 0..items_count {
+            let buf: &mut &[u8] = &mut *bytes;
+            items.push(Struct::parse_inner(buf)?);
+        }
+        Ok(Self { items })
+    }
+    fn write_to(&self, buffer: &mut BytesMut) {
+        let chunk: u64 = self.items.len() as u64 & 0xff;
+        buffer.put_uint_le(chunk, 1);
+        for elem in self.items.iter() {
+            elem.write_to(buffer);
+        }
+    }
+    fn get_total_size(&self) -> usize {
+        self.get_size()
+    }
+    fn get_size(&self) -> usize {
+        1 + self
+            .items
+            .iter()
+            .map(|elem| elem.get_total_size())
+            .sum::<usize>()
+    }
+}
+impl Packet for ChildAPacket {
+    fn to_bytes(self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(self.parent.get_total_size());
+        self.parent.write_to(&mut buffer);
+        buffer.freeze()
+    }
+    fn to_vec(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+}
+impl From<ChildAPacket> for Bytes {
+    fn from(packet: ChildAPacket) -> Self {
+        packet.to_bytes()
+    }
+}
+impl From<ChildAPacket> for Vec<u8> {
+    fn from(packet: ChildAPacket) -> Self {
+        packet.to_vec()
+    }
+}
+impl TryFrom<ParentPacket> for ChildAPacket {
+    type Error = TryFromError;
+    fn try_from(value: ParentPacket) -> std::result::Result<Self, Self::Error> {
+        Self::new(value.parent).map_err(TryFromError)
+    }
+}
+impl ChildAPacket {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        Self::new(Arc::new(ParentData::parse(bytes)?)).map_err(|_| Error::InvalidPacketError)
+    }
+    fn new(root: Arc<ParentData>) -> std::result::Result<Self, &'static str> {
+        let parent = root;
+        let child_a = match &parent.child {
+            ParentDataChild::ChildA(value) => (*value).clone(),
+            _ => return Err("inconsistent state - child was not ChildA"),
+        };
+        Ok(Self { parent, child_a })
+    }
+    pub fn get_opcode(&self) -> Enum {
+        self.parent.as_ref().opcode
+    }
+    pub fn get_flag(&self) -> u8 {
+        self.parent.as_ref().flag
+    }
+    pub fn get_items(&self) -> &Vec<Struct> {
+        &self.child_a.as_ref().items
+    }
+}
+impl From<ChildAPacket> for ParentPacket {
+    fn from(packet: ChildAPacket) -> ParentPacket {
+        ParentPacket::new(packet.parent).unwrap()
+    }
+}
+impl ChildABuilder {
+    pub fn build(self) -> ChildAPacket {
+        let child_a = Arc::new(ChildAData { items: self.items });
+        let parent = Arc::new(ParentData {
+            opcode: Enum::A,
+            flag: self.flag,
+            child: ParentDataChild::ChildA(child_a),
+        });
+        ChildAPacket::new(parent).unwrap()
+    }
+}
+impl From<ChildABuilder> for ParentPacket {
+    fn from(builder: ChildABuilder) -> ParentPacket {
+        builder.build().into()
+    }
+}
+#[derive(Debug)]
+struct ChildBData {
+    value: u32,
+}
+#[derive(Debug, Clone)]
+pub struct ChildBPacket {
+    parent: Arc<ParentData>,
+    child_b: Arc<ChildBData>,
+}
+#[derive(Debug)]
+pub struct ChildBBuilder {
+    pub flag: u8,
+    pub value: u32,
+}
+impl ChildBData {
+    fn parse_inner(bytes: &mut &[u8]) -> Result<Self> {
+        if bytes.remaining() < 3 {
+            return Err(Error::InvalidLengthError {
+                obj: "ChildB".to_string(),
+                field: "value".to_string(),
+                wanted: 3,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint_le(3);
+        let value = chunk as u32;
+        if bytes.remaining() < 1 {
+            return Err(Error::InvalidLengthError {
+                obj: "ChildB".to_string(),
+                field: "_reserved_".to_string(),
+                wanted: 1,
+                got: bytes.remaining(),
+            });
+        }
+        let chunk = bytes.get_uint_le(1);
+        let fixed_value = chunk;
+        if fixed_value != 0x42 {
+            return Err(Error::InvalidFixedValue {
+                obj: "ChildB".to_string(),
+                expected: 0x42,
+                actual: fixed_value,
+            });
+        }
+        Ok(Self { value })
+    }
+    fn write_to(&self, buffer: &mut BytesMut) {
+        let chunk: u64 = self.value as u64 & 0xffffff;
+        buffer.put_uint_le(chunk, 3);
+        let chunk: u64 = 0x42;
+        buffer.put_uint_le(chunk, 1);
+    }
+    fn get_total_size(&self) -> usize {
+        self.get_size()
+    }
+    fn get_size(&self) -> usize {
+        4
+    }
+}
+impl Packet for ChildBPacket {
+    fn to_bytes(self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(self.parent.get_total_size());
+        self.parent.write_to(&mut buffer);
+        buffer.freeze()
+    }
+    fn to_vec(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+}
+impl From<ChildBPacket> for Bytes {
+    fn from(packet: ChildBPacket) -> Self {
+        packet.to_bytes()
+    }
+}
+impl From<ChildBPacket> for Vec<u8> {
+    fn from(packet: ChildBPacket) -> Self {
+        packet.to_vec()
+    }
+}
+impl TryFrom<ParentPacket> for ChildBPacket {
+    type Error = TryFromError;
+    fn try_from(value: ParentPacket) -> std::result::Result<Self, Self::Error> {
+        Self::new(value.parent).map_err(TryFromError)
+    }
+}
+impl ChildBPacket {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        Self::new(Arc::new(ParentData::parse(bytes)?)).map_err(|_| Error::InvalidPacketError)
+    }
+    fn new(root: Arc<ParentData>) -> std::result::Result<Self, &'static str> {
+        let parent = root;
+        let child_b = match &parent.child {
+            ParentDataChild::ChildB(value) => (*value).clone(),
+            _ => return Err("inconsistent state - child was not ChildB"),
+        };
+        Ok(Self { parent, child_b })
+    }
+    pub fn get_opcode(&self) -> Enum {
+        self.parent.as_ref().opcode
+    }
+    pub fn get_flag(&self) -> u8 {
+        self.parent.as_ref().flag
+    }
+    pub fn get_value(&self) -> u32 {
+        self.child_b.as_ref().value
+    }
+}
+impl From<ChildBPacket> for ParentPacket {
+    fn from(packet: ChildBPacket) -> ParentPacket {
+        ParentPacket::new(packet.parent).unwrap()
+    }
+}
+impl ChildBBuilder {
+    pub fn build(self) -> ChildBPacket {
+        let child_b = Arc::new(ChildBData { value: self.value });
+        let parent = Arc::new(ParentData {
+            opcode: Enum::B,
+            flag: self.flag,
+            child: ParentDataChild::ChildB(child_b),
+        });
+        ChildBPacket::new(parent).unwrap()
+    }
+}
+impl From<ChildBBuilder> for ParentPacket {
+    fn from(builder: ChildBBuilder) -> ParentPacket {
+        builder.build().into()
+    }
+}
@@ -0,0 +1,455 @@
+# @generated Python module from test.pdl
+
+from dataclasses import dataclass, field
+import enum
+from typing import List, Optional, Tuple
+
+
+class ParseError(Exception):
+    """The input bytes do not match the grammar."""
+
+
+def _enum(enum_type, value: int):
+    try:
+        return enum_type(value)
+    except ValueError:
+        raise ParseError(f'invalid {enum_type.__name__} value {value:#x}') from None
+
+
+def _enum_member(enum_type, name: str, value: int):
+    member = int.__new__(enum_type, value)
+    member._name_ = name
+    member._value_ = value
+    return member
+
+
+@dataclass
+class Packet:
+    payload: Optional[bytes] = field(default=None, repr=False)
+
+    @classmethod
+    def parse_all(cls, span: bytes) -> 'Packet':
+        packet, remain = getattr(cls, 'parse')(span)
+        if len(remain) > 0:
+            raise ParseError(f'{cls.__name__}: unexpected trailing bytes')
+        return packet
+
+    @property
+    def size(self) -> int:
+        return len(getattr(self, 'serialize')())
+
+
+class OpCode(enum.IntEnum):
+    RESET = 0x1
+    READ = 0x2
+    WRITE = 0x3
+
+
+class Level(enum.IntEnum):
+    LOW = 0x1
+    HIGH = 0x2
+
+
+@dataclass
+class Entry(Packet):
+    key: int = 0
+    level: Level = Level.LOW
+    value: int = 0
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Entry', bytes]:
+        return Entry._parse({}, bytes(span))
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Entry', bytes]:
+        if len(span) < 1:
+            raise ParseError('Entry: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        fields['key'] = chunk
+        span = span[1:]
+        if len(span) < 1:
+            raise ParseError('Entry: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        fields['level'] = _enum(Level, (chunk & 0xf))
+        span = span[1:]
+        if len(span) < 2:
+            raise ParseError('Entry: unexpected end of input')
+        chunk = int.from_bytes(span[0:2], byteorder='little')
+        fields['value'] = chunk
+        span = span[2:]
+        return Entry(**fields), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        if self.key > 0xff:
+            raise ValueError(f'Entry: invalid value for field key: {self.key}')
+        value = self.key
+        span.extend(value.to_bytes(1, byteorder='little'))
+        value = self.level
+        span.extend(value.to_bytes(1, byteorder='little'))
+        if self.value > 0xffff:
+            raise ValueError(f'Entry: invalid value for field value: {self.value}')
+        value = self.value
+        span.extend(value.to_bytes(2, byteorder='little'))
+        return bytes(span)
+
+
+@dataclass
+class Blob(Packet):
+    data: List[int] = field(default_factory=list)
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Blob', bytes]:
+        return Blob._parse({}, bytes(span))
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Blob', bytes]:
+        if len(span) < 1:
+            raise ParseError('Blob: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        data_size = chunk
+        span = span[1:]
+        if len(span) < data_size:
+            raise ParseError('Blob: unexpected end of input')
+        array_span = span[:data_size]
+        span = span[data_size:]
+        values = list(array_span)
+        fields['data'] = values
+        return Blob(**fields), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        data_size = len(self.data)
+        if data_size > 0xff:
+            raise ValueError(f'Blob: invalid size for field data: {data_size}')
+        value = data_size
+        span.extend(value.to_bytes(1, byteorder='little'))
+        for e in self.data:
+            span.extend(int(e).to_bytes(1, byteorder='little'))
+        return bytes(span)
+
+
+@dataclass
+class Command(Packet):
+    op_code: OpCode = OpCode.RESET
+    flag: int = 0
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Command', bytes]:
+        return Command._parse({}, bytes(span))
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Command', bytes]:
+        if len(span) < 1:
+            raise ParseError('Command: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        fields['op_code'] = _enum(OpCode, chunk)
+        span = span[1:]
+        if len(span) < 1:
+            raise ParseError('Command: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        fields['flag'] = (chunk & 0x1)
+        span = span[1:]
+        if len(span) < 1:
+            raise ParseError('Command: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        payload_size = chunk
+        span = span[1:]
+        if len(span) < payload_size:
+            raise ParseError('Command: unexpected end of input')
+        payload = span[:payload_size]
+        span = span[payload_size:]
+        if fields['op_code'] == OpCode.RESET:
+            packet, remain = Reset._parse(fields, payload)
+            if len(remain) > 0:
+                raise ParseError('Reset: unexpected trailing bytes')
+            return packet, span
+        if fields['op_code'] == OpCode.READ:
+            packet, remain = Read._parse(fields, payload)
+            if len(remain) > 0:
+                raise ParseError('Read: unexpected trailing bytes')
+            return packet, span
+        if fields['op_code'] == OpCode.WRITE:
+            packet, remain = Write._parse(fields, payload)
+            if len(remain) > 0:
+                raise ParseError('Write: unexpected trailing bytes')
+            return packet, span
+        return Command(**fields, payload=payload), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        if payload is None:
+            payload = self.payload or b''
+        value = self.op_code
+        span.extend(value.to_bytes(1, byteorder='little'))
+        if self.flag > 0x1:
+            raise ValueError(f'Command: invalid value for field flag: {self.flag}')
+        value = self.flag
+        span.extend(value.to_bytes(1, byteorder='little'))
+        payload_size = len(payload)
+        if payload_size > 0xff:
+            raise ValueError(f'Command: invalid size for field _payload_: {payload_size}')
+        value = payload_size
+        span.extend(value.to_bytes(1, byteorder='little'))
+        span.extend(payload)
+        return bytes(span)
+
+
+@dataclass
+class Reset(Command):
+    address: int = 0
+
+    def __post_init__(self) -> None:
+        self.op_code = OpCode.RESET
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Reset', bytes]:
+        packet, span = Command.parse(span)
+        if not isinstance(packet, Reset):
+            raise ParseError('Reset: constraints not satisfied')
+        return packet, span
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Reset', bytes]:
+        if len(span) < 6:
+            raise ParseError('Reset: unexpected end of input')
+        fields['address'] = int.from_bytes(span[0:6], byteorder='little')
+        span = span[6:]
+        if len(span) < 1:
+            raise ParseError('Reset: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        if chunk != 0x42:
+            raise ParseError('Reset: invalid fixed field value')
+        span = span[1:]
+        return Reset(**fields), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        span.extend(self.address.to_bytes(6, byteorder='little'))
+        value = 0x42
+        span.extend(value.to_bytes(1, byteorder='little'))
+        return Command.serialize(self, bytes(span))
+
+
+@dataclass
+class Read(Command):
+    entries: List[Entry] = field(default_factory=list)
+    op_codes: List[OpCode] = field(default_factory=list)
+    blob: Blob = field(default_factory=Blob)
+
+    def __post_init__(self) -> None:
+        self.op_code = OpCode.READ
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Read', bytes]:
+        packet, span = Command.parse(span)
+        if not isinstance(packet, Read):
+            raise ParseError('Read: constraints not satisfied')
+        return packet, span
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Read', bytes]:
+        if len(span) < 1:
+            raise ParseError('Read: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        entries_count = chunk
+        span = span[1:]
+        values = []
+        for n in range(entries_count):
+            value, span = Entry.parse(span)
+            values.append(value)
+        fields['entries'] = values
+        if len(span) < 2:
+            raise ParseError('Read: unexpected end of input')
+        values = [_enum(OpCode, span[n]) for n in range(2)]
+        span = span[2:]
+        fields['op_codes'] = values
+        fields['blob'], span = Blob.parse(span)
+        return Read(**fields), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        if len(self.entries) > 0xff:
+            raise ValueError(f'Read: invalid count for field entries: {len(self.entries)}')
+        value = len(self.entries)
+        span.extend(value.to_bytes(1, byteorder='little'))
+        for e in self.entries:
+            span.extend(e.serialize())
+        if len(self.op_codes) != 2:
+            raise ValueError(f'Read: invalid length for field op_codes: {len(self.op_codes)}')
+        for e in self.op_codes:
+            span.extend(int(e).to_bytes(1, byteorder='little'))
+        span.extend(self.blob.serialize())
+        return Command.serialize(self, bytes(span))
+
+
+@dataclass
+class Write(Command):
+    values: List[int] = field(default_factory=list)
+    from_: List[int] = field(default_factory=list)
+    crc: int = 0
+
+    def __post_init__(self) -> None:
+        self.op_code = OpCode.WRITE
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Write', bytes]:
+        packet, span = Command.parse(span)
+        if not isinstance(packet, Write):
+            raise ParseError('Write: constraints not satisfied')
+        return packet, span
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Write', bytes]:
+        if len(span) < 1:
+            raise ParseError('Write: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        values_size = chunk
+        if values_size < 2:
+            raise ParseError('Write: invalid size field value')
+        values_size -= 2
+        span = span[1:]
+        if len(span) < values_size:
+            raise ParseError('Write: unexpected end of input')
+        array_span = span[:values_size]
+        span = span[values_size:]
+        if len(array_span) % 2 != 0:
+            raise ParseError('Write: array size is not a multiple of the element size')
+        values = [int.from_bytes(array_span[n:n + 2], byteorder='little') for n in range(0, len(array_span), 2)]
+        fields['values'] = values
+        if len(span) < 2:
+            raise ParseError('Write: unexpected end of input')
+        array_span = span[:len(span) - 2]
+        span = span[len(span) - 2:]
+        values = list(array_span)
+        fields['from_'] = values
+        if len(span) < 2:
+            raise ParseError('Write: unexpected end of input')
+        chunk = int.from_bytes(span[0:2], byteorder='little')
+        fields['crc'] = chunk
+        span = span[2:]
+        return Write(**fields), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        values_size = len(self.values) * 2 + 2
+        if values_size > 0xff:
+            raise ValueError(f'Write: invalid size for field values: {values_size}')
+        value = values_size
+        span.extend(value.to_bytes(1, byteorder='little'))
+        for e in self.values:
+            span.extend(int(e).to_bytes(2, byteorder='little'))
+        for e in self.from_:
+            span.extend(int(e).to_bytes(1, byteorder='little'))
+        value = self.crc
+        span.extend(value.to_bytes(2, byteorder='little'))
+        return Command.serialize(self, bytes(span))
+
+
+@dataclass
+class Frame(Packet):
+    kind: int = 0
+    trailer: int = 0
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Frame', bytes]:
+        return Frame._parse({}, bytes(span))
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Frame', bytes]:
+        if len(span) < 1:
+            raise ParseError('Frame: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        fields['kind'] = chunk
+        span = span[1:]
+        if len(span) < 2:
+            raise ParseError('Frame: unexpected end of input')
+        payload = span[:len(span) - 2]
+        span = span[len(span) - 2:]
+        if len(span) < 2:
+            raise ParseError('Frame: unexpected end of input')
+        chunk = int.from_bytes(span[0:2], byteorder='little')
+        fields['trailer'] = chunk
+        span = span[2:]
+        if fields['kind'] == 0x1:
+            packet, remain = Data._parse(fields, payload)
+            if len(remain) > 0:
+                raise ParseError('Data: unexpected trailing bytes')
+            return packet, span
+        return Frame(**fields, payload=payload), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        if payload is None:
+            payload = self.payload or b''
+        if self.kind > 0xff:
+            raise ValueError(f'Frame: invalid value for field kind: {self.kind}')
+        value = self.kind
+        span.extend(value.to_bytes(1, byteorder='little'))
+        span.extend(payload)
+        if self.trailer > 0xffff:
+            raise ValueError(f'Frame: invalid value for field trailer: {self.trailer}')
+        value = self.trailer
+        span.extend(value.to_bytes(2, byteorder='little'))
+        return bytes(span)
+
+
+@dataclass
+class Data(Frame):
+    items: List[int] = field(default_factory=list)
+    tail: int = 0
+
+    def __post_init__(self) -> None:
+        self.kind = 0x1
+
+    @staticmethod
+    def parse(span: bytes) -> Tuple['Data', bytes]:
+        packet, span = Frame.parse(span)
+        if not isinstance(packet, Data):
+            raise ParseError('Data: constraints not satisfied')
+        return packet, span
+
+    @staticmethod
+    def _parse(fields: dict, span: bytes) -> Tuple['Data', bytes]:
+        if len(span) < 1:
+            raise ParseError('Data: unexpected end of input')
+        chunk = int.from_bytes(span[0:1], byteorder='little')
+        items_size = chunk
+        span = span[1:]
+        if items_size > 9:
+            raise ParseError('Data: array size exceeds padding size')
+        if len(span) < 9:
+            raise ParseError('Data: unexpected end of input')
+        array_span = span[:items_size]
+        span = span[9:]
+        if len(array_span) % 3 != 0:
+            raise ParseError('Data: array size is not a multiple of the element size')
+        values = [int.from_bytes(array_span[n:n + 3], byteorder='little') for n in range(0, len(array_span), 3)]
+        fields['items'] = values
+        if len(span) < 2:
+            raise ParseError('Data: unexpected end of input')
+        chunk = int.from_bytes(span[0:2], byteorder='little')
+        fields['tail'] = (chunk & 0xfff)
+        if ((chunk >> 12) & 0xf) != 0x0:
+            raise ParseError('Data: invalid fixed field value')
+        span = span[2:]
+        return Data(**fields), span
+
+    def serialize(self, payload: Optional[bytes] = None) -> bytes:
+        span = bytearray()
+        items_size = len(self.items) * 3
+        if items_size > 0xff:
+            raise ValueError(f'Data: invalid size for field items: {items_size}')
+        value = items_size
+        span.extend(value.to_bytes(1, byteorder='little'))
+        items_start = len(span)
+        for e in self.items:
+            span.extend(int(e).to_bytes(3, byteorder='little'))
+        if len(span) - items_start > 9:
+            raise ValueError('Data: field items exceeds the padding size')
+        span.extend(bytes(9 - (len(span) - items_start)))
+        if self.tail > 0xfff:
+            raise ValueError(f'Data: invalid value for field tail: {self.tail}')
+        value = self.tail | (0x0 << 12)
+        span.extend(value.to_bytes(2, byteorder='little'))
+        return Frame.serialize(self, bytes(span))
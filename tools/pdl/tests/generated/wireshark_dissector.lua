@@ -0,0 +1,132 @@
+-- File generated from test.pdl, with the command:
+--  pdl --output-format wireshark-lua --root-packet Command test.pdl
+-- /!\ Do not edit by hand.
+
+local proto = Proto("command", "Command")
+
+local OpCode_values = {
+  [0x1] = "RESET",
+  [0x2] = "READ",
+}
+
+local fields = {
+  ["Item.a"] = ProtoField.uint8("command.item.a", "a", base.DEC),
+  ["Item.b"] = ProtoField.uint16("command.item.b", "b", base.DEC),
+  ["Command.op_code"] = ProtoField.uint8("command.command.op_code", "op_code", base.HEX, OpCode_values),
+  ["Command.flag"] = ProtoField.uint8("command.command.flag", "flag", base.DEC),
+  ["Command.payload"] = ProtoField.bytes("command.command.payload", "payload"),
+  ["Reset.address"] = ProtoField.bytes("command.reset.address", "address"),
+}
+
+local field_list = {}
+for _, field in pairs(fields) do
+  table.insert(field_list, field)
+end
+proto.fields = field_list
+
+-- Read an unsigned integer of `size` bytes as an UInt64 value.
+local function read_uint(buffer, offset, size)
+  return buffer(offset, size):le_uint64()
+end
+
+local dissect = {}
+
+-- Dissect a Item struct, and return the number of bytes consumed.
+function dissect.Item(buffer, pinfo, tree, values)
+  local subtree = tree:add(buffer(), "Item")
+  local offset = 0
+  local sizes, counts = {}, {}
+  do
+    local chunk = read_uint(buffer, offset, 1)
+    values["a"] = chunk:tonumber()
+    subtree:add(fields["Item.a"], buffer(offset, 1), values["a"])
+    offset = offset + 1
+  end
+  do
+    local chunk = read_uint(buffer, offset, 2)
+    values["b"] = chunk:tonumber()
+    subtree:add(fields["Item.b"], buffer(offset, 2), values["b"])
+    offset = offset + 2
+  end
+  subtree:set_len(offset)
+  return offset
+end
+
+-- Dissect a Command packet, and return the number of bytes consumed.
+function dissect.Command(buffer, pinfo, tree, values)
+  local subtree = tree:add(buffer(), "Command")
+  local offset = 0
+  local sizes, counts = {}, {}
+  do
+    local chunk = read_uint(buffer, offset, 1)
+    values["op_code"] = chunk:tonumber()
+    subtree:add(fields["Command.op_code"], buffer(offset, 1), values["op_code"])
+    offset = offset + 1
+  end
+  do
+    local chunk = read_uint(buffer, offset, 1)
+    values["flag"] = chunk:band(0x1):tonumber()
+    subtree:add(fields["Command.flag"], buffer(offset, 1), values["flag"])
+    offset = offset + 1
+  end
+  do
+    local chunk = read_uint(buffer, offset, 1)
+    sizes["_payload_"] = chunk:tonumber()
+    offset = offset + 1
+  end
+  local payload_size = sizes["_payload_"] or (buffer:len() - offset)
+  local payload = buffer(offset, payload_size)
+  offset = offset + payload_size
+  if values["op_code"] == 1 then
+    dissect.Reset(payload:tvb(), pinfo, subtree, values)
+  elseif values["op_code"] == 2 then
+    dissect.Read(payload:tvb(), pinfo, subtree, values)
+  else
+    subtree:add(fields["Command.payload"], payload)
+  end
+  subtree:set_len(offset)
+  return offset
+end
+
+-- Dissect a Reset packet, and return the number of bytes consumed.
+function dissect.Reset(buffer, pinfo, tree, values)
+  local subtree = tree:add(buffer(), "Reset")
+  local offset = 0
+  local sizes, counts = {}, {}
+  subtree:add(fields["Reset.address"], buffer(offset, 6))
+  offset = offset + 6
+  subtree:set_len(offset)
+  return offset
+end
+
+-- Dissect a Read packet, and return the number of bytes consumed.
+function dissect.Read(buffer, pinfo, tree, values)
+  local subtree = tree:add(buffer(), "Read")
+  local offset = 0
+  local sizes, counts = {}, {}
+  do
+    local chunk = read_uint(buffer, offset, 1)
+    counts["items"] = chunk:tonumber()
+    offset = offset + 1
+  end
+  do
+    local limit = offset + (sizes["items"] or (buffer:len() - offset))
+    local count = counts["items"]
+    local index = 0
+    while offset < limit and (count == nil or index < count) do
+      offset = offset + dissect.Item(buffer(offset, limit - offset):tvb(), pinfo, subtree, {})
+      index = index + 1
+    end
+  end
+  subtree:set_len(offset)
+  return offset
+end
+
+function proto.dissector(buffer, pinfo, tree)
+  pinfo.cols.protocol = proto.name
+  local subtree = tree:add(proto, buffer())
+  dissect.Command(buffer, pinfo, subtree, {})
+end
+
+-- Register the dissector for captures using the USER0 link-layer type.
+DissectorTable.get("wtap_encap"):add(wtap.USER0, proto)
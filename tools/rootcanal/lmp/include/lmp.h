@@ -11,7 +11,13 @@ struct LinkManagerOps {
   void* user_pointer;
   uint16_t (*get_handle)(void* user, const uint8_t (*address)[6]);
   void (*get_address)(void* user, uint16_t handle, uint8_t (*result)[6]);
+  void (*get_local_address)(void* user, uint8_t (*result)[6]);
+  uint8_t (*get_role)(void* user, uint16_t handle);
   uint64_t (*extended_features)(void* user, uint8_t features_page);
+  uint8_t (*min_encryption_key_size)(void* user);
+  bool (*secure_connections_only)(void* user);
+  void (*set_encryption_key)(void* user, uint16_t handle,
+                             const uint8_t (*key)[16]);
   void (*send_hci_event)(void* user, const uint8_t* data, uintptr_t len);
   void (*send_lmp_packet)(void* user, const uint8_t (*to)[6],
                           const uint8_t* data, uintptr_t len);
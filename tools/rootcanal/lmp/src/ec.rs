@@ -111,6 +111,15 @@ impl DhKey {
             None
         }
     }
+
+    /// X coordinate of the shared point, most significant octet first.
+    pub fn x_coordinate(&self) -> Vec<u8> {
+        let (x, _) = match self {
+            DhKey::P192(inner) => inner.split_at(P192r1::PRIVATE_KEY_SIZE),
+            DhKey::P256(inner) => inner.split_at(P256r1::PRIVATE_KEY_SIZE),
+        };
+        x.iter().rev().copied().collect()
+    }
 }
 
 impl PrivateKey {
@@ -2,6 +2,8 @@ use std::mem::ManuallyDrop;
 use std::rc::Rc;
 use std::slice;
 
+use num_traits::FromPrimitive;
+
 use crate::manager::LinkManager;
 use crate::packets::{hci, lmp};
 
@@ -12,7 +14,12 @@ pub struct LinkManagerOps {
     user_pointer: *mut (),
     get_handle: unsafe extern "C" fn(user: *mut (), address: *const [u8; 6]) -> u16,
     get_address: unsafe extern "C" fn(user: *mut (), handle: u16, result: *mut [u8; 6]),
+    get_local_address: unsafe extern "C" fn(user: *mut (), result: *mut [u8; 6]),
+    get_role: unsafe extern "C" fn(user: *mut (), handle: u16) -> u8,
     extended_features: unsafe extern "C" fn(user: *mut (), features_page: u8) -> u64,
+    min_encryption_key_size: unsafe extern "C" fn(user: *mut ()) -> u8,
+    secure_connections_only: unsafe extern "C" fn(user: *mut ()) -> bool,
+    set_encryption_key: unsafe extern "C" fn(user: *mut (), handle: u16, key: *const [u8; 16]),
     send_hci_event: unsafe extern "C" fn(user: *mut (), data: *const u8, len: usize),
     send_lmp_packet:
         unsafe extern "C" fn(user: *mut (), to: *const [u8; 6], data: *const u8, len: usize),
@@ -25,6 +32,17 @@ impl LinkManagerOps {
         result
     }
 
+    pub(crate) fn get_local_address(&self) -> hci::Address {
+        let mut result = hci::EMPTY_ADDRESS;
+        unsafe { (self.get_local_address)(self.user_pointer, &mut result.bytes as *mut _) };
+        result
+    }
+
+    pub(crate) fn get_role(&self, handle: u16) -> Option<hci::Role> {
+        let role = unsafe { (self.get_role)(self.user_pointer, handle) };
+        hci::Role::from_u8(role)
+    }
+
     pub(crate) fn get_handle(&self, addr: hci::Address) -> u16 {
         unsafe { (self.get_handle)(self.user_pointer, &addr.bytes as *const _) }
     }
@@ -33,6 +51,18 @@ impl LinkManagerOps {
         unsafe { (self.extended_features)(self.user_pointer, features_page) }
     }
 
+    pub(crate) fn min_encryption_key_size(&self) -> u8 {
+        unsafe { (self.min_encryption_key_size)(self.user_pointer) }
+    }
+
+    pub(crate) fn secure_connections_only(&self) -> bool {
+        unsafe { (self.secure_connections_only)(self.user_pointer) }
+    }
+
+    pub(crate) fn set_encryption_key(&self, handle: u16, key: &[u8; 16]) {
+        unsafe { (self.set_encryption_key)(self.user_pointer, handle, key as *const _) }
+    }
+
     pub(crate) fn send_hci_event(&self, packet: &[u8]) {
         unsafe { (self.send_hci_event)(self.user_pointer, packet.as_ptr(), packet.len()) }
     }
@@ -7,6 +7,8 @@ mod future;
 mod manager;
 mod packets;
 mod procedure;
+mod safer;
+mod sha256;
 
 #[cfg(test)]
 mod test;
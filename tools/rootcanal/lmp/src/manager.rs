@@ -12,6 +12,7 @@ use crate::ffi::LinkManagerOps;
 use crate::future::noop_waker;
 use crate::packets::{hci, lmp};
 use crate::procedure;
+use crate::procedure::authentication::SecureAuthentication;
 
 use hci::Packet as _;
 use lmp::Packet as _;
@@ -27,6 +28,9 @@ struct Link {
     // is always 1
     hci: Cell<Option<hci::CommandPacket>>,
     lmp: RefCell<VecDeque<lmp::PacketPacket>>,
+    secure_authentication: Cell<Option<SecureAuthentication>>,
+    encryption_key: Cell<Option<[u8; 16]>>,
+    encryption_key_size: Cell<Option<u8>>,
 }
 
 impl Default for Link {
@@ -35,6 +39,9 @@ impl Default for Link {
             peer: Cell::new(hci::EMPTY_ADDRESS),
             hci: Default::default(),
             lmp: Default::default(),
+            secure_authentication: Default::default(),
+            encryption_key: Default::default(),
+            encryption_key_size: Default::default(),
         }
     }
 }
@@ -75,6 +82,9 @@ impl Link {
         self.peer.set(hci::EMPTY_ADDRESS);
         self.hci.set(None);
         self.lmp.borrow_mut().clear();
+        self.secure_authentication.set(None);
+        self.encryption_key.set(None);
+        self.encryption_key_size.set(None);
     }
 }
 
@@ -221,6 +231,21 @@ impl procedure::Context for LinkContext {
         }
     }
 
+    fn local_address(&self) -> hci::Address {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.ops.get_local_address()
+        } else {
+            hci::EMPTY_ADDRESS
+        }
+    }
+
+    fn role(&self) -> hci::Role {
+        self.manager
+            .upgrade()
+            .and_then(|manager| manager.ops.get_role(self.peer_handle()))
+            .unwrap_or(hci::Role::Central)
+    }
+
     fn extended_features(&self, features_page: u8) -> u64 {
         if let Some(manager) = self.manager.upgrade() {
             manager.ops.extended_features(features_page)
@@ -228,4 +253,55 @@ impl procedure::Context for LinkContext {
             0
         }
     }
+
+    fn min_encryption_key_size(&self) -> u8 {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.ops.min_encryption_key_size()
+        } else {
+            0
+        }
+    }
+
+    fn secure_connections_only(&self) -> bool {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.ops.secure_connections_only()
+        } else {
+            false
+        }
+    }
+
+    fn get_secure_authentication(&self) -> Option<SecureAuthentication> {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.link(self.index).secure_authentication.get()
+        } else {
+            None
+        }
+    }
+
+    fn set_secure_authentication(&self, authentication: &SecureAuthentication) {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.link(self.index).secure_authentication.set(Some(*authentication))
+        }
+    }
+
+    fn set_encryption_key(&self, key: &[u8; 16]) {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.link(self.index).encryption_key.set(Some(*key));
+            manager.ops.set_encryption_key(self.peer_handle(), key)
+        }
+    }
+
+    fn get_encryption_key_size(&self) -> Option<u8> {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.link(self.index).encryption_key_size.get()
+        } else {
+            None
+        }
+    }
+
+    fn set_encryption_key_size(&self, key_size: Option<u8>) {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.link(self.index).encryption_key_size.set(key_size)
+        }
+    }
 }
@@ -44,6 +44,15 @@ pub mod hci {
                 }
                 _ => None,
             },
+            CommandChild::SecurityCommand(command) => match command.specialize() {
+                SecurityCommandChild::RefreshEncryptionKey(packet) => {
+                    Some(packet.get_connection_handle())
+                }
+                SecurityCommandChild::ReadEncryptionKeySize(packet) => {
+                    Some(packet.get_connection_handle())
+                }
+                _ => None,
+            },
             _ => None,
         }
     }
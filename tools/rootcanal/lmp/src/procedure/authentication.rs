@@ -1,5 +1,8 @@
 // Bluetooth Core, Vol 2, Part C, 4.2.1
 
+use num_traits::{FromPrimitive, ToPrimitive};
+use rand::{thread_rng, Rng};
+
 use crate::either::Either;
 use crate::num_hci_command_packets;
 use crate::packets::{hci, lmp};
@@ -7,24 +10,152 @@ use crate::procedure::features;
 use crate::procedure::legacy_pairing;
 use crate::procedure::secure_simple_pairing;
 use crate::procedure::Context;
+use crate::safer;
+use crate::sha256;
+
+use hci::LMPFeaturesPage1Bits::SecureConnectionsHostSupport;
+use hci::LMPFeaturesPage2Bits::SecureConnectionsControllerSupport;
+
+/// Link key and authenticated ciphering offset of the latest
+/// secure authentication, the AES encryption key is derived from them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SecureAuthentication {
+    pub link_key: [u8; 16],
+    pub aco: [u8; 8],
+}
+
+/// Addresses of the central and of the peripheral of the link,
+/// most significant octet first as expected by h3 and h4
+pub fn central_and_peripheral_addresses(ctx: &impl Context) -> ([u8; 6], [u8; 6]) {
+    let mut local_address = ctx.local_address().bytes;
+    let mut peer_address = ctx.peer_address().bytes;
+    local_address.reverse();
+    peer_address.reverse();
+    match ctx.role() {
+        hci::Role::Central => (local_address, peer_address),
+        hci::Role::Peripheral => (peer_address, local_address),
+    }
+}
+
+pub async fn secure_connections_supported(ctx: &impl Context) -> bool {
+    features::supported_on_both_page1(ctx, SecureConnectionsHostSupport).await
+        && features::supported_on_both_page2(ctx, SecureConnectionsControllerSupport).await
+}
 
+/// In Secure Connections Only mode, links with peers that do not
+/// support Secure Connections are not authenticated nor encrypted
+// Bluetooth Core, Vol 3, Part C, 5.2.2.8
+pub async fn insufficient_security(ctx: &impl Context) -> bool {
+    ctx.secure_connections_only() && !secure_connections_supported(ctx).await
+}
+
+fn not_accepted_error(not_accepted: lmp::NotAcceptedPacket) -> hci::ErrorCode {
+    hci::ErrorCode::from_u8(not_accepted.get_error_code())
+        .unwrap_or(hci::ErrorCode::AuthenticationFailure)
+}
+
+/// Challenge the peer to prove that it shares `link_key`,
+/// returns the authenticated ciphering offset on success
 pub async fn send_challenge(
     ctx: &impl Context,
     transaction_id: u8,
-    _link_key: [u8; 16],
-) -> Result<(), ()> {
-    let random_number = [0; 16];
+    link_key: [u8; 16],
+) -> Result<[u8; 12], hci::ErrorCode> {
+    let random_number: [u8; 16] = thread_rng().gen();
     ctx.send_lmp_packet(lmp::AuRandBuilder { transaction_id, random_number }.build());
 
+    // The claimant computes the response with its own address
+    let (sres, aco) = safer::e1(&link_key, &random_number, &ctx.peer_address().bytes);
+
     match ctx.receive_lmp_packet::<Either<lmp::SresPacket, lmp::NotAcceptedPacket>>().await {
-        Either::Left(_response) => Ok(()),
-        Either::Right(_) => Err(()),
+        Either::Left(response) if *response.get_authentication_rsp() == sres => Ok(aco),
+        Either::Left(_) => Err(hci::ErrorCode::AuthenticationFailure),
+        Either::Right(not_accepted) => Err(not_accepted_error(not_accepted)),
+    }
+}
+
+/// Answer the challenge of the peer with `link_key`,
+/// returns the authenticated ciphering offset
+pub async fn receive_challenge(ctx: &impl Context, link_key: [u8; 16]) -> [u8; 12] {
+    let random_number = *ctx.receive_lmp_packet::<lmp::AuRandPacket>().await.get_random_number();
+    send_response(ctx, &link_key, &random_number)
+}
+
+fn send_response(ctx: &impl Context, link_key: &[u8; 16], random_number: &[u8; 16]) -> [u8; 12] {
+    let (authentication_rsp, aco) = safer::e1(link_key, random_number, &ctx.local_address().bytes);
+    ctx.send_lmp_packet(lmp::SresBuilder { transaction_id: 0, authentication_rsp }.build());
+    aco
+}
+
+/// Secure authentication, both devices send a challenge and
+/// verify the response of the other
+// Bluetooth Core, Vol 2, Part H, 5
+pub async fn send_secure_challenge(
+    ctx: &impl Context,
+    transaction_id: u8,
+    link_key: [u8; 16],
+) -> Result<(), hci::ErrorCode> {
+    let random_number: [u8; 16] = thread_rng().gen();
+    ctx.send_lmp_packet(lmp::AuRandBuilder { transaction_id, random_number }.build());
+
+    match ctx.receive_lmp_packet::<Either<lmp::AuRandPacket, lmp::NotAcceptedPacket>>().await {
+        Either::Left(challenge) => {
+            exchange_secure_responses(ctx, link_key, random_number, *challenge.get_random_number())
+                .await
+        }
+        Either::Right(not_accepted) => Err(not_accepted_error(not_accepted)),
     }
 }
 
-pub async fn receive_challenge(ctx: &impl Context, _link_key: [u8; 16]) {
-    let _random_number = *ctx.receive_lmp_packet::<lmp::AuRandPacket>().await.get_random_number();
-    ctx.send_lmp_packet(lmp::SresBuilder { transaction_id: 0, authentication_rsp: [0; 4] }.build());
+pub async fn receive_secure_challenge(
+    ctx: &impl Context,
+    link_key: [u8; 16],
+) -> Result<(), hci::ErrorCode> {
+    let random_number = *ctx.receive_lmp_packet::<lmp::AuRandPacket>().await.get_random_number();
+    answer_secure_challenge(ctx, link_key, random_number).await
+}
+
+async fn answer_secure_challenge(
+    ctx: &impl Context,
+    link_key: [u8; 16],
+    peer_random_number: [u8; 16],
+) -> Result<(), hci::ErrorCode> {
+    let random_number: [u8; 16] = thread_rng().gen();
+    ctx.send_lmp_packet(lmp::AuRandBuilder { transaction_id: 0, random_number }.build());
+    exchange_secure_responses(ctx, link_key, random_number, peer_random_number).await
+}
+
+async fn exchange_secure_responses(
+    ctx: &impl Context,
+    link_key: [u8; 16],
+    random_number: [u8; 16],
+    peer_random_number: [u8; 16],
+) -> Result<(), hci::ErrorCode> {
+    let (central_address, peripheral_address) = central_and_peripheral_addresses(ctx);
+    let device_authentication_key =
+        sha256::h4(&link_key, b"btdk", &central_address, &peripheral_address);
+
+    let (sres, peer_sres, aco) = match ctx.role() {
+        hci::Role::Central => {
+            sha256::h5(&device_authentication_key, &random_number, &peer_random_number)
+        }
+        hci::Role::Peripheral => {
+            let (central_sres, peripheral_sres, aco) =
+                sha256::h5(&device_authentication_key, &peer_random_number, &random_number);
+            (peripheral_sres, central_sres, aco)
+        }
+    };
+
+    ctx.send_lmp_packet(lmp::SresBuilder { transaction_id: 0, authentication_rsp: sres }.build());
+
+    match ctx.receive_lmp_packet::<Either<lmp::SresPacket, lmp::NotAcceptedPacket>>().await {
+        Either::Left(response) if *response.get_authentication_rsp() == peer_sres => {
+            ctx.set_secure_authentication(&SecureAuthentication { link_key, aco });
+            Ok(())
+        }
+        Either::Left(_) => Err(hci::ErrorCode::AuthenticationFailure),
+        Either::Right(not_accepted) => Err(not_accepted_error(not_accepted)),
+    }
 }
 
 pub async fn initiate(ctx: &impl Context) {
@@ -37,13 +168,24 @@ pub async fn initiate(ctx: &impl Context) {
         .build(),
     );
 
+    if insufficient_security(ctx).await {
+        ctx.send_hci_event(
+            hci::AuthenticationCompleteBuilder {
+                status: hci::ErrorCode::InsufficientSecurity,
+                connection_handle: ctx.peer_handle(),
+            }
+            .build(),
+        );
+        return;
+    }
+
     ctx.send_hci_event(hci::LinkKeyRequestBuilder { bd_addr: ctx.peer_address() }.build());
 
     let status = match ctx.receive_hci_command::<Either<
         hci::LinkKeyRequestReplyPacket,
         hci::LinkKeyRequestNegativeReplyPacket,
     >>().await {
-        Either::Left(_reply) => {
+        Either::Left(reply) => {
             ctx.send_hci_event(
                 hci::LinkKeyRequestReplyCompleteBuilder {
                     num_hci_command_packets,
@@ -52,7 +194,18 @@ pub async fn initiate(ctx: &impl Context) {
                 }
                 .build(),
             );
-            hci::ErrorCode::Success
+
+            let link_key = *reply.get_link_key();
+            let result = if secure_connections_supported(ctx).await {
+                send_secure_challenge(ctx, 0, link_key).await
+            } else {
+                send_challenge(ctx, 0, link_key).await.map(|_| ())
+            };
+
+            match result {
+                Ok(()) => hci::ErrorCode::Success,
+                Err(error) => error,
+            }
         },
         Either::Right(_) => {
             ctx.send_hci_event(
@@ -66,13 +219,14 @@ pub async fn initiate(ctx: &impl Context) {
 
             let result = if features::supported_on_both_page1(ctx, hci::LMPFeaturesPage1Bits::SecureSimplePairingHostSupport).await {
                 secure_simple_pairing::initiate(ctx).await
+                    .map_err(|_| hci::ErrorCode::AuthenticationFailure)
             } else {
                 legacy_pairing::initiate(ctx).await
             };
 
             match result {
                 Ok(_) => hci::ErrorCode::Success,
-                Err(_) => hci::ErrorCode::AuthenticationFailure
+                Err(error) => error,
             }
         }
     };
@@ -83,24 +237,548 @@ pub async fn initiate(ctx: &impl Context) {
 }
 
 pub async fn respond(ctx: &impl Context) {
-    match ctx.receive_lmp_packet::<Either<
+    let request = ctx.receive_lmp_packet::<Either<
         lmp::AuRandPacket,
         Either<lmp::IoCapabilityReqPacket, lmp::InRandPacket>
     >>()
-    .await
-    {
-        Either::Left(_random_number) => {
-            // TODO: Resolve authentication challenge
-            // TODO: Ask for link key
-            ctx.send_lmp_packet(lmp::SresBuilder { transaction_id: 0, authentication_rsp: [0; 4] }.build());
+    .await;
+
+    if insufficient_security(ctx).await {
+        let error_code = hci::ErrorCode::InsufficientSecurity.to_u8().unwrap();
+        match request {
+            Either::Left(_) => ctx.send_lmp_packet(
+                lmp::NotAcceptedBuilder {
+                    transaction_id: 0,
+                    not_accepted_opcode: lmp::Opcode::AuRand,
+                    error_code,
+                }
+                .build(),
+            ),
+            Either::Right(Either::Left(_)) => ctx.send_lmp_packet(
+                lmp::NotAcceptedExtBuilder {
+                    transaction_id: 0,
+                    not_accepted_opcode: lmp::ExtendedOpcode::IoCapabilityReq,
+                    error_code,
+                }
+                .build(),
+            ),
+            Either::Right(Either::Right(_)) => ctx.send_lmp_packet(
+                lmp::NotAcceptedBuilder {
+                    transaction_id: 0,
+                    not_accepted_opcode: lmp::Opcode::InRand,
+                    error_code,
+                }
+                .build(),
+            ),
+        }
+        return;
+    }
+
+    match request {
+        Either::Left(challenge) => {
+            ctx.send_hci_event(hci::LinkKeyRequestBuilder { bd_addr: ctx.peer_address() }.build());
+
+            match ctx.receive_hci_command::<Either<
+                hci::LinkKeyRequestReplyPacket,
+                hci::LinkKeyRequestNegativeReplyPacket,
+            >>().await {
+                Either::Left(reply) => {
+                    ctx.send_hci_event(
+                        hci::LinkKeyRequestReplyCompleteBuilder {
+                            num_hci_command_packets,
+                            status: hci::ErrorCode::Success,
+                            bd_addr: ctx.peer_address(),
+                        }
+                        .build(),
+                    );
+                    let link_key = *reply.get_link_key();
+                    if secure_connections_supported(ctx).await {
+                        let random_number = *challenge.get_random_number();
+                        let _ = answer_secure_challenge(ctx, link_key, random_number).await;
+                    } else {
+                        send_response(ctx, &link_key, challenge.get_random_number());
+                    }
+                },
+                Either::Right(_) => {
+                    ctx.send_hci_event(
+                        hci::LinkKeyRequestNegativeReplyCompleteBuilder {
+                            num_hci_command_packets,
+                            status: hci::ErrorCode::Success,
+                            bd_addr: ctx.peer_address(),
+                        }
+                        .build(),
+                    );
+                    ctx.send_lmp_packet(
+                        lmp::NotAcceptedBuilder {
+                            transaction_id: 0,
+                            not_accepted_opcode: lmp::Opcode::AuRand,
+                            error_code: hci::ErrorCode::PinOrKeyMissing.to_u8().unwrap(),
+                        }
+                        .build(),
+                    );
+                }
+            }
         },
         Either::Right(pairing) => {
             let _result = match pairing {
                 Either::Left(io_capability_request) =>
-                    secure_simple_pairing::respond(ctx, io_capability_request).await,
+                    secure_simple_pairing::respond(ctx, io_capability_request).await
+                        .map_err(|_| hci::ErrorCode::AuthenticationFailure),
                 Either::Right(in_rand) =>
                     legacy_pairing::respond(ctx, in_rand).await,
             };
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::central_and_peripheral_addresses;
+    use super::initiate;
+    use super::respond;
+    use super::SecureAuthentication;
+    use crate::procedure::Context;
+    use crate::safer;
+    use crate::sha256;
+    use crate::test::{sequence, TestContext};
+
+    use crate::packets::hci::LMPFeaturesPage1Bits::SecureConnectionsHostSupport;
+    use crate::packets::hci::LMPFeaturesPage2Bits::SecureConnectionsControllerSupport;
+
+    fn secure_connections_context() -> TestContext {
+        TestContext::new()
+            .with_page_1_feature(SecureConnectionsHostSupport)
+            .with_page_2_feature(SecureConnectionsControllerSupport)
+            .with_peer_page_1_feature(SecureConnectionsHostSupport)
+            .with_peer_page_2_feature(SecureConnectionsControllerSupport)
+    }
+
+    /// Responses of the central and of the peripheral, and authenticated
+    /// ciphering offset of a secure authentication with `link_key`
+    fn secure_responses(
+        context: &TestContext,
+        link_key: &[u8; 16],
+        central_random_number: &[u8; 16],
+        peripheral_random_number: &[u8; 16],
+    ) -> ([u8; 4], [u8; 4], [u8; 8]) {
+        let (central_address, peripheral_address) = central_and_peripheral_addresses(context);
+        let key = sha256::h4(link_key, b"btdk", &central_address, &peripheral_address);
+        sha256::h5(&key, central_random_number, peripheral_random_number)
+    }
+
+    #[test]
+    fn initiate_with_link_key() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &[0x11; 16],
+                    au_rand.get_random_number(),
+                    &context.peer_address().bytes,
+                )
+                .0,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_with_wrong_link_key() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            // Response computed with an all zero link key
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &[0; 16],
+                    au_rand.get_random_number(),
+                    &context.peer_address().bytes,
+                )
+                .0,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::AuthenticationFailure,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_with_link_key_missing_on_peer() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: AuRand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::AuRand,
+                error_code: 0x06,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::PinOrKeyMissing,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn respond_with_link_key() {
+        let context = TestContext::new();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: Sres {
+                transaction_id: 0,
+                authentication_rsp: [0x8d, 0x18, 0x42, 0xc9],
+            }
+        }
+    }
+
+    #[test]
+    fn respond_without_link_key() {
+        let context = TestContext::new();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: LinkKeyRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::AuRand,
+                error_code: 0x06,
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_secure_authentication() {
+        let context = secure_connections_context();
+        let procedure = initiate;
+        let aco = Cell::new([0; 8]);
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Sres {
+                transaction_id: 0,
+                authentication_rsp: secure_responses(
+                    &context,
+                    &[0x11; 16],
+                    au_rand.get_random_number(),
+                    &[0; 16],
+                )
+                .0,
+            }
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: {
+                    let (_, peripheral_sres, expected_aco) = secure_responses(
+                        &context,
+                        &[0x11; 16],
+                        au_rand.get_random_number(),
+                        &[0; 16],
+                    );
+                    aco.set(expected_aco);
+                    peripheral_sres
+                },
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+
+        assert_eq!(
+            context.get_secure_authentication(),
+            Some(SecureAuthentication { link_key: [0x11; 16], aco: aco.get() })
+        );
+    }
+
+    #[test]
+    fn initiate_secure_authentication_with_wrong_link_key() {
+        let context = secure_connections_context();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Sres {
+                transaction_id: 0,
+                authentication_rsp: secure_responses(
+                    &context,
+                    &[0x11; 16],
+                    au_rand.get_random_number(),
+                    &[0; 16],
+                )
+                .0,
+            }
+            // Response computed with an all zero link key
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: secure_responses(
+                    &context,
+                    &[0; 16],
+                    au_rand.get_random_number(),
+                    &[0; 16],
+                )
+                .1,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::AuthenticationFailure,
+                connection_handle: context.peer_handle(),
+            }
+        }
+
+        assert_eq!(context.get_secure_authentication(), None);
+    }
+
+    #[test]
+    fn respond_secure_authentication() {
+        let context = secure_connections_context();
+        let procedure = respond;
+        let aco = Cell::new([0; 8]);
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestReply {
+                bd_addr: context.peer_address(),
+                link_key: [0x11; 16],
+            }
+            IUT -> Upper Tester: LinkKeyRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: Sres {
+                transaction_id: 0,
+                authentication_rsp: secure_responses(
+                    &context,
+                    &[0x11; 16],
+                    au_rand.get_random_number(),
+                    &[0; 16],
+                )
+                .0,
+            }
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: {
+                    let (_, peripheral_sres, expected_aco) = secure_responses(
+                        &context,
+                        &[0x11; 16],
+                        au_rand.get_random_number(),
+                        &[0; 16],
+                    );
+                    aco.set(expected_aco);
+                    peripheral_sres
+                },
+            }
+        }
+
+        assert_eq!(
+            context.get_secure_authentication(),
+            Some(SecureAuthentication { link_key: [0x11; 16], aco: aco.get() })
+        );
+    }
+
+    #[test]
+    fn initiate_in_secure_connections_only_mode() {
+        let context = TestContext::new().with_secure_connections_only();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::InsufficientSecurity,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn respond_to_pairing_in_secure_connections_only_mode() {
+        let context = TestContext::new().with_secure_connections_only();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: IoCapabilityReq {
+                transaction_id: 0,
+                io_capabilities: 0x01,
+                oob_authentication_data: 0x00,
+                authentication_requirement: 0x01,
+            }
+            IUT -> Lower Tester: NotAcceptedExt {
+                transaction_id: 0,
+                not_accepted_opcode: ExtendedOpcode::IoCapabilityReq,
+                error_code: 0x2f,
+            }
+        }
+    }
+}
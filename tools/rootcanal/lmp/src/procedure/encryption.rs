@@ -1,79 +1,155 @@
 // Bluetooth Core, Vol 2, Part C, 4.2.5
 
-use super::features;
+use std::convert::TryFrom;
+
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use crate::either::Either;
 use crate::num_hci_command_packets;
 use crate::packets::{hci, lmp};
-use crate::procedure::Context;
+use crate::procedure::{authentication, Context};
+use crate::sha256;
 
-use hci::LMPFeaturesPage1Bits::SecureConnectionsHostSupport;
-use hci::LMPFeaturesPage2Bits::SecureConnectionsControllerSupport;
+/// Largest encryption key size supported, in bytes
+const MAX_ENCRYPTION_KEY_SIZE: u8 = 16;
 
-pub async fn initiate(ctx: &impl Context) {
-    // TODO: handle turn off
-    let _ = ctx.receive_hci_command::<hci::SetConnectionEncryptionPacket>().await;
-    ctx.send_hci_event(
-        hci::SetConnectionEncryptionStatusBuilder {
-            num_hci_command_packets,
-            status: hci::ErrorCode::Success,
-        }
-        .build(),
-    );
+/// Derive the AES encryption key from the latest secure authentication
+// Bluetooth Core, Vol 2, Part H, 7.7.8
+fn derive_aes_encryption_key(ctx: &impl Context) {
+    if let Some(authentication) = ctx.get_secure_authentication() {
+        let (central_address, peripheral_address) =
+            authentication::central_and_peripheral_addresses(ctx);
+        ctx.set_encryption_key(&sha256::h3(
+            &authentication.link_key,
+            b"btak",
+            &central_address,
+            &peripheral_address,
+            &authentication.aco,
+        ));
+    }
+}
 
-    // TODO: handle failure
-    let _ = ctx
-        .send_accepted_lmp_packet(
-            lmp::EncryptionModeReqBuilder { transaction_id: 0, encryption_mode: 0x1 }.build(),
-        )
-        .await;
-
-    // TODO: handle failure
-    let _ = ctx
-        .send_accepted_lmp_packet(
-            lmp::EncryptionKeySizeReqBuilder { transaction_id: 0, key_size: 16 }.build(),
-        )
-        .await;
-
-    // TODO: handle failure
-    let _ = ctx
-        .send_accepted_lmp_packet(
-            lmp::StartEncryptionReqBuilder { transaction_id: 0, random_number: [0; 16] }.build(),
-        )
-        .await;
-
-    let aes_ccm = features::supported_on_both_page1(ctx, SecureConnectionsHostSupport).await
-        && features::supported_on_both_page2(ctx, SecureConnectionsControllerSupport).await;
+/// Encryption mode reported to the host once encryption is enabled
+async fn encryption_enabled(ctx: &impl Context) -> hci::EncryptionEnabled {
+    if authentication::secure_connections_supported(ctx).await {
+        hci::EncryptionEnabled::BrEdrAesCcm
+    } else {
+        hci::EncryptionEnabled::On
+    }
+}
 
-    ctx.send_hci_event(
-        hci::EncryptionChangeBuilder {
-            status: hci::ErrorCode::Success,
-            connection_handle: ctx.peer_handle(),
-            encryption_enabled: if aes_ccm {
-                hci::EncryptionEnabled::BrEdrAesCcm
-            } else {
-                hci::EncryptionEnabled::On
-            },
-        }
-        .build(),
-    );
+/// HCI status for the error code of a rejected LMP request
+fn error_code(code: u8) -> hci::ErrorCode {
+    hci::ErrorCode::from_u8(code).unwrap_or(hci::ErrorCode::UnspecifiedError)
 }
 
-pub async fn respond(ctx: &impl Context) {
-    // TODO: handle
-    let _ = ctx.receive_lmp_packet::<lmp::EncryptionModeReqPacket>().await;
+/// Smallest key size accepted, Secure Connections Only mode requires 128-bit keys
+fn min_encryption_key_size(ctx: &impl Context) -> u8 {
+    if ctx.secure_connections_only() {
+        MAX_ENCRYPTION_KEY_SIZE
+    } else {
+        ctx.min_encryption_key_size()
+    }
+}
+
+/// Accept the key size proposed by the peer if it is within the supported range,
+/// returns the negotiated key size on success
+fn accept_key_size(ctx: &impl Context, key_size: u8) -> Result<u8, u8> {
+    if (min_encryption_key_size(ctx)..=MAX_ENCRYPTION_KEY_SIZE).contains(&key_size) {
+        ctx.send_lmp_packet(
+            lmp::AcceptedBuilder {
+                transaction_id: 0,
+                accepted_opcode: lmp::Opcode::EncryptionKeySizeReq,
+            }
+            .build(),
+        );
+        Ok(key_size)
+    } else {
+        let error_code = hci::ErrorCode::UnsupportedLmpOrLlParameter.to_u8().unwrap();
+        ctx.send_lmp_packet(
+            lmp::NotAcceptedBuilder {
+                transaction_id: 0,
+                not_accepted_opcode: lmp::Opcode::EncryptionKeySizeReq,
+                error_code,
+            }
+            .build(),
+        );
+        Err(error_code)
+    }
+}
+
+/// Propose the largest supported key size, the peer either accepts it
+/// or steps down with a smaller proposal,
+/// returns the negotiated key size on success
+// Bluetooth Core, Vol 2, Part C, 4.2.5.4
+async fn propose_key_size(ctx: &impl Context) -> Result<u8, u8> {
     ctx.send_lmp_packet(
-        lmp::AcceptedBuilder { transaction_id: 0, accepted_opcode: lmp::Opcode::EncryptionModeReq }
+        lmp::EncryptionKeySizeReqBuilder { transaction_id: 0, key_size: MAX_ENCRYPTION_KEY_SIZE }
             .build(),
     );
 
-    let _ = ctx.receive_lmp_packet::<lmp::EncryptionKeySizeReqPacket>().await;
-    ctx.send_lmp_packet(
-        lmp::AcceptedBuilder {
-            transaction_id: 0,
-            accepted_opcode: lmp::Opcode::EncryptionKeySizeReq,
+    // Like send_accepted_lmp_packet, ignore answers to other requests
+    loop {
+        match ctx
+            .receive_lmp_packet::<Either<
+                Either<lmp::AcceptedPacket, lmp::NotAcceptedPacket>,
+                lmp::EncryptionKeySizeReqPacket,
+            >>()
+            .await
+        {
+            Either::Left(Either::Left(accepted))
+                if accepted.get_accepted_opcode() == lmp::Opcode::EncryptionKeySizeReq =>
+            {
+                return Ok(MAX_ENCRYPTION_KEY_SIZE)
+            }
+            Either::Left(Either::Right(not_accepted))
+                if not_accepted.get_not_accepted_opcode() == lmp::Opcode::EncryptionKeySizeReq =>
+            {
+                return Err(not_accepted.get_error_code())
+            }
+            Either::Left(_) => continue,
+            Either::Right(proposal) => return accept_key_size(ctx, proposal.get_key_size()),
         }
-        .build(),
-    );
+    }
+}
+
+async fn start_encryption(ctx: &impl Context) -> Result<(), u8> {
+    ctx.send_accepted_lmp_packet(
+        lmp::EncryptionModeReqBuilder { transaction_id: 0, encryption_mode: 0x1 }.build(),
+    )
+    .await?;
+    let key_size = propose_key_size(ctx).await?;
+    send_start_encryption(ctx).await?;
+    ctx.set_encryption_key_size(Some(key_size));
+    Ok(())
+}
 
+async fn stop_encryption(ctx: &impl Context) -> Result<(), u8> {
+    ctx.send_accepted_lmp_packet(
+        lmp::EncryptionModeReqBuilder { transaction_id: 0, encryption_mode: 0x0 }.build(),
+    )
+    .await?;
+    ctx.send_accepted_lmp_packet(lmp::StopEncryptionReqBuilder { transaction_id: 0 }.build())
+        .await?;
+    ctx.set_encryption_key_size(None);
+    Ok(())
+}
+
+/// Start encryption, only sent by the central
+async fn send_start_encryption(ctx: &impl Context) -> Result<(), u8> {
+    ctx.send_accepted_lmp_packet(
+        lmp::StartEncryptionReqBuilder { transaction_id: 0, random_number: [0; 16] }.build(),
+    )
+    .await?;
+
+    if authentication::secure_connections_supported(ctx).await {
+        derive_aes_encryption_key(ctx);
+    }
+    Ok(())
+}
+
+/// Wait for the central to start encryption
+async fn receive_start_encryption(ctx: &impl Context) {
     let _ = ctx.receive_lmp_packet::<lmp::StartEncryptionReqPacket>().await;
     ctx.send_lmp_packet(
         lmp::AcceptedBuilder {
@@ -83,18 +159,275 @@ pub async fn respond(ctx: &impl Context) {
         .build(),
     );
 
-    let aes_ccm = features::supported_on_both_page1(ctx, SecureConnectionsHostSupport).await
-        && features::supported_on_both_page2(ctx, SecureConnectionsControllerSupport).await;
+    if authentication::secure_connections_supported(ctx).await {
+        derive_aes_encryption_key(ctx);
+    }
+}
+
+/// Stop encryption on the central side, once asked by the peripheral
+async fn receive_stop_encryption(ctx: &impl Context) {
+    let _ = ctx.receive_lmp_packet::<lmp::StopEncryptionReqPacket>().await;
+    ctx.send_lmp_packet(
+        lmp::AcceptedBuilder { transaction_id: 0, accepted_opcode: lmp::Opcode::StopEncryptionReq }
+            .build(),
+    );
+}
+
+/// Wait for the answer of the peer to a pause of encryption
+async fn receive_pause_answer<P: TryFrom<lmp::PacketPacket>>(ctx: &impl Context) -> Result<P, u8> {
+    match ctx.receive_lmp_packet::<Either<P, lmp::NotAcceptedExtPacket>>().await {
+        Either::Left(answer) => Ok(answer),
+        Either::Right(not_accepted) => Err(not_accepted.get_error_code()),
+    }
+}
+
+/// Pause encryption, in both roles the peripheral then asks the central
+/// to stop encryption
+// Bluetooth Core, Vol 2, Part C, 4.2.5.5
+async fn pause_encryption(ctx: &impl Context) -> Result<(), u8> {
+    ctx.send_lmp_packet(lmp::PauseEncryptionReqBuilder { transaction_id: 0 }.build());
+
+    match ctx.role() {
+        hci::Role::Central => {
+            receive_pause_answer::<lmp::StopEncryptionReqPacket>(ctx).await?;
+            ctx.send_lmp_packet(
+                lmp::AcceptedBuilder {
+                    transaction_id: 0,
+                    accepted_opcode: lmp::Opcode::StopEncryptionReq,
+                }
+                .build(),
+            );
+            Ok(())
+        }
+        hci::Role::Peripheral => {
+            // The central answers with its own pause request
+            receive_pause_answer::<lmp::PauseEncryptionReqPacket>(ctx).await?;
+            ctx.send_accepted_lmp_packet(
+                lmp::StopEncryptionReqBuilder { transaction_id: 0 }.build(),
+            )
+            .await
+        }
+    }
+}
+
+/// Resume encryption after a pause, the central always starts encryption
+// Bluetooth Core, Vol 2, Part C, 4.2.5.6
+async fn resume_encryption(ctx: &impl Context) -> Result<(), u8> {
+    match ctx.role() {
+        hci::Role::Central => send_start_encryption(ctx).await,
+        hci::Role::Peripheral => {
+            ctx.send_lmp_packet(lmp::ResumeEncryptionReqBuilder { transaction_id: 0 }.build());
+            receive_start_encryption(ctx).await;
+            Ok(())
+        }
+    }
+}
+
+/// Answer a pause of encryption initiated by the peer,
+/// then wait for the peer to resume encryption
+async fn respond_pause_encryption(ctx: &impl Context) -> Result<(), u8> {
+    match ctx.role() {
+        hci::Role::Central => {
+            ctx.send_lmp_packet(lmp::PauseEncryptionReqBuilder { transaction_id: 0 }.build());
+            receive_stop_encryption(ctx).await;
+
+            let _ = ctx.receive_lmp_packet::<lmp::ResumeEncryptionReqPacket>().await;
+            send_start_encryption(ctx).await
+        }
+        hci::Role::Peripheral => {
+            ctx.send_accepted_lmp_packet(
+                lmp::StopEncryptionReqBuilder { transaction_id: 0 }.build(),
+            )
+            .await?;
+
+            receive_start_encryption(ctx).await;
+            Ok(())
+        }
+    }
+}
+
+pub async fn initiate(ctx: &impl Context) {
+    match ctx
+        .receive_hci_command::<Either<
+            hci::SetConnectionEncryptionPacket,
+            hci::RefreshEncryptionKeyPacket,
+        >>()
+        .await
+    {
+        Either::Left(command) => {
+            ctx.send_hci_event(
+                hci::SetConnectionEncryptionStatusBuilder {
+                    num_hci_command_packets,
+                    status: hci::ErrorCode::Success,
+                }
+                .build(),
+            );
+
+            let (status, encryption_enabled) = match command.get_encryption_enable() {
+                hci::Enable::Enabled if authentication::insufficient_security(ctx).await => {
+                    (hci::ErrorCode::InsufficientSecurity, hci::EncryptionEnabled::Off)
+                }
+                hci::Enable::Enabled => match start_encryption(ctx).await {
+                    Ok(()) => (hci::ErrorCode::Success, encryption_enabled(ctx).await),
+                    Err(error) => (error_code(error), hci::EncryptionEnabled::Off),
+                },
+                hci::Enable::Disabled => match stop_encryption(ctx).await {
+                    Ok(()) => (hci::ErrorCode::Success, hci::EncryptionEnabled::Off),
+                    Err(error) => (error_code(error), encryption_enabled(ctx).await),
+                },
+            };
+
+            ctx.send_hci_event(
+                hci::EncryptionChangeBuilder {
+                    status,
+                    connection_handle: ctx.peer_handle(),
+                    encryption_enabled,
+                }
+                .build(),
+            );
+        }
+        Either::Right(_) => {
+            ctx.send_hci_event(
+                hci::RefreshEncryptionKeyStatusBuilder {
+                    num_hci_command_packets,
+                    status: hci::ErrorCode::Success,
+                }
+                .build(),
+            );
+
+            let status = match pause_encryption(ctx).await {
+                Ok(()) => resume_encryption(ctx).await,
+                Err(error) => Err(error),
+            };
+
+            ctx.send_hci_event(
+                hci::EncryptionKeyRefreshCompleteBuilder {
+                    status: status.map_or_else(error_code, |()| hci::ErrorCode::Success),
+                    connection_handle: ctx.peer_handle(),
+                }
+                .build(),
+            );
+        }
+    }
+}
+
+pub async fn respond(ctx: &impl Context) {
+    match ctx
+        .receive_lmp_packet::<Either<lmp::EncryptionModeReqPacket, lmp::PauseEncryptionReqPacket>>()
+        .await
+    {
+        Either::Left(request) => {
+            if request.get_encryption_mode() != 0x0
+                && authentication::insufficient_security(ctx).await
+            {
+                ctx.send_lmp_packet(
+                    lmp::NotAcceptedBuilder {
+                        transaction_id: 0,
+                        not_accepted_opcode: lmp::Opcode::EncryptionModeReq,
+                        error_code: hci::ErrorCode::InsufficientSecurity.to_u8().unwrap(),
+                    }
+                    .build(),
+                );
+                return;
+            }
 
+            ctx.send_lmp_packet(
+                lmp::AcceptedBuilder {
+                    transaction_id: 0,
+                    accepted_opcode: lmp::Opcode::EncryptionModeReq,
+                }
+                .build(),
+            );
+
+            let encryption_enabled = if request.get_encryption_mode() == 0x0 {
+                let _ = ctx.receive_lmp_packet::<lmp::StopEncryptionReqPacket>().await;
+                ctx.send_lmp_packet(
+                    lmp::AcceptedBuilder {
+                        transaction_id: 0,
+                        accepted_opcode: lmp::Opcode::StopEncryptionReq,
+                    }
+                    .build(),
+                );
+
+                ctx.set_encryption_key_size(None);
+                hci::EncryptionEnabled::Off
+            } else {
+                let proposal = ctx.receive_lmp_packet::<lmp::EncryptionKeySizeReqPacket>().await;
+                // A proposal larger than the supported key size is answered
+                // with a smaller proposal
+                let key_size = if proposal.get_key_size() > MAX_ENCRYPTION_KEY_SIZE {
+                    propose_key_size(ctx).await
+                } else {
+                    accept_key_size(ctx, proposal.get_key_size())
+                };
+                let key_size = match key_size {
+                    Ok(key_size) => key_size,
+                    Err(error) => {
+                        ctx.send_hci_event(
+                            hci::EncryptionChangeBuilder {
+                                status: error_code(error),
+                                connection_handle: ctx.peer_handle(),
+                                encryption_enabled: hci::EncryptionEnabled::Off,
+                            }
+                            .build(),
+                        );
+                        return;
+                    }
+                };
+
+                let _ = ctx.receive_lmp_packet::<lmp::StartEncryptionReqPacket>().await;
+                ctx.send_lmp_packet(
+                    lmp::AcceptedBuilder {
+                        transaction_id: 0,
+                        accepted_opcode: lmp::Opcode::StartEncryptionReq,
+                    }
+                    .build(),
+                );
+
+                let encryption_enabled = encryption_enabled(ctx).await;
+                if encryption_enabled == hci::EncryptionEnabled::BrEdrAesCcm {
+                    derive_aes_encryption_key(ctx);
+                }
+                ctx.set_encryption_key_size(Some(key_size));
+                encryption_enabled
+            };
+
+            ctx.send_hci_event(
+                hci::EncryptionChangeBuilder {
+                    status: hci::ErrorCode::Success,
+                    connection_handle: ctx.peer_handle(),
+                    encryption_enabled,
+                }
+                .build(),
+            );
+        }
+        Either::Right(_) => {
+            let status = respond_pause_encryption(ctx).await;
+
+            ctx.send_hci_event(
+                hci::EncryptionKeyRefreshCompleteBuilder {
+                    status: status.map_or_else(error_code, |()| hci::ErrorCode::Success),
+                    connection_handle: ctx.peer_handle(),
+                }
+                .build(),
+            );
+        }
+    }
+}
+
+pub async fn read_encryption_key_size(ctx: &impl Context) {
+    let _ = ctx.receive_hci_command::<hci::ReadEncryptionKeySizePacket>().await;
+    let key_size = ctx.get_encryption_key_size();
     ctx.send_hci_event(
-        hci::EncryptionChangeBuilder {
-            status: hci::ErrorCode::Success,
-            connection_handle: ctx.peer_handle(),
-            encryption_enabled: if aes_ccm {
-                hci::EncryptionEnabled::BrEdrAesCcm
+        hci::ReadEncryptionKeySizeCompleteBuilder {
+            num_hci_command_packets,
+            status: if key_size.is_some() {
+                hci::ErrorCode::Success
             } else {
-                hci::EncryptionEnabled::On
+                hci::ErrorCode::InsufficientSecurity
             },
+            connection_handle: ctx.peer_handle(),
+            key_size: key_size.unwrap_or(0),
         }
         .build(),
     );
@@ -103,12 +436,15 @@ pub async fn respond(ctx: &impl Context) {
 #[cfg(test)]
 mod tests {
     use super::initiate;
+    use super::read_encryption_key_size;
     use super::respond;
+    use crate::procedure::authentication::SecureAuthentication;
     use crate::procedure::Context;
     use crate::test::{sequence, TestContext};
 
     use crate::packets::hci::LMPFeaturesPage1Bits::SecureConnectionsHostSupport;
     use crate::packets::hci::LMPFeaturesPage2Bits::SecureConnectionsControllerSupport;
+    use crate::packets::hci::Role;
 
     #[test]
     fn accept_encryption() {
@@ -149,4 +485,657 @@ mod tests {
 
         include!("../../test/ENC/BV-34-C.in");
     }
+
+    #[test]
+    fn initiate_aes_ccm_encryption_after_secure_authentication() {
+        let context = TestContext::new()
+            .with_page_1_feature(SecureConnectionsHostSupport)
+            .with_page_2_feature(SecureConnectionsControllerSupport)
+            .with_peer_page_1_feature(SecureConnectionsHostSupport)
+            .with_peer_page_2_feature(SecureConnectionsControllerSupport);
+        context.set_secure_authentication(&SecureAuthentication {
+            link_key: [0x11; 16],
+            aco: [0x22; 8],
+        });
+        let procedure = initiate;
+
+        include!("../../test/ENC/BV-34-C.in");
+
+        assert_eq!(
+            *context.encryption_key.borrow(),
+            Some([
+                0x05, 0x2e, 0x2d, 0x48, 0xfc, 0x4a, 0x95, 0x88, 0x5f, 0x96, 0x20, 0x5b, 0x5b, 0xe8,
+                0xa0, 0xaf
+            ])
+        );
+    }
+
+    #[test]
+    fn initiate_encryption_not_accepted() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Enabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            Lower Tester -> IUT: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::EncryptionModeReq,
+                error_code: 0x25,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::EncryptionModeNotAcceptable,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_encryption_in_secure_connections_only_mode() {
+        let context = TestContext::new().with_secure_connections_only();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Enabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::InsufficientSecurity,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+    }
+
+    #[test]
+    fn accept_encryption_in_secure_connections_only_mode() {
+        let context = TestContext::new().with_secure_connections_only();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            IUT -> Lower Tester: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::EncryptionModeReq,
+                error_code: 0x2f,
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_stop_encryption() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Disabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x00,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            IUT -> Lower Tester: StopEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StopEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+    }
+
+    #[test]
+    fn accept_stop_encryption() {
+        let context = TestContext::new();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x00,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            Lower Tester -> IUT: StopEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StopEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_encryption_key_refresh() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: RefreshEncryptionKey {
+                connection_handle: context.peer_handle(),
+            }
+            IUT -> Upper Tester: RefreshEncryptionKeyStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: StopEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StopEncryptionReq,
+            }
+            IUT -> Lower Tester: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionKeyRefreshComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_encryption_key_refresh_not_accepted() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: RefreshEncryptionKey {
+                connection_handle: context.peer_handle(),
+            }
+            IUT -> Upper Tester: RefreshEncryptionKeyStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: NotAcceptedExt {
+                transaction_id: 0,
+                not_accepted_opcode: ExtendedOpcode::PauseEncryptionReq,
+                error_code: 0x20,
+            }
+            IUT -> Upper Tester: EncryptionKeyRefreshComplete {
+                status: ErrorCode::UnsupportedLmpOrLlParameter,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_encryption_key_refresh_as_peripheral() {
+        let context = TestContext::new().with_role(Role::Peripheral);
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: RefreshEncryptionKey {
+                connection_handle: context.peer_handle(),
+            }
+            IUT -> Upper Tester: RefreshEncryptionKeyStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: StopEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StopEncryptionReq,
+            }
+            IUT -> Lower Tester: ResumeEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionKeyRefreshComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn accept_encryption_key_refresh_as_peripheral() {
+        let context = TestContext::new().with_role(Role::Peripheral);
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: StopEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StopEncryptionReq,
+            }
+            Lower Tester -> IUT: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionKeyRefreshComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn accept_encryption_key_refresh_as_central() {
+        let context = TestContext::new();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: PauseEncryptionReq {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: StopEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StopEncryptionReq,
+            }
+            Lower Tester -> IUT: ResumeEncryptionReq {
+                transaction_id: 0,
+            }
+            IUT -> Lower Tester: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionKeyRefreshComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_encryption_with_smaller_key_size() {
+        let context = TestContext::new().with_min_encryption_key_size(7);
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Enabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            IUT -> Lower Tester: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x10,
+            }
+            Lower Tester -> IUT: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x07,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionKeySizeReq,
+            }
+            IUT -> Lower Tester: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::On,
+            }
+        }
+
+        assert_eq!(*context.encryption_key_size.borrow(), Some(7));
+    }
+
+    #[test]
+    fn initiate_encryption_with_key_size_below_minimum() {
+        let context = TestContext::new().with_min_encryption_key_size(16);
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Enabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            IUT -> Lower Tester: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x10,
+            }
+            Lower Tester -> IUT: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x01,
+            }
+            IUT -> Lower Tester: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::EncryptionKeySizeReq,
+                error_code: 0x20,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::UnsupportedLmpOrLlParameter,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+
+        assert_eq!(*context.encryption_key_size.borrow(), None);
+    }
+
+    #[test]
+    fn initiate_encryption_ignores_answers_to_other_requests() {
+        let context = TestContext::new();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Enabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            IUT -> Lower Tester: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x10,
+            }
+            Lower Tester -> IUT: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::AuRand,
+                error_code: 0x20,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionKeySizeReq,
+            }
+            IUT -> Lower Tester: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::On,
+            }
+        }
+
+        assert_eq!(*context.encryption_key_size.borrow(), Some(16));
+    }
+
+    #[test]
+    fn initiate_encryption_with_smaller_key_size_in_secure_connections_only_mode() {
+        let context = TestContext::new()
+            .with_page_1_feature(SecureConnectionsHostSupport)
+            .with_page_2_feature(SecureConnectionsControllerSupport)
+            .with_peer_page_1_feature(SecureConnectionsHostSupport)
+            .with_peer_page_2_feature(SecureConnectionsControllerSupport)
+            .with_min_encryption_key_size(7)
+            .with_secure_connections_only();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: SetConnectionEncryption {
+                connection_handle: context.peer_handle(),
+                encryption_enable: Enable::Enabled
+            }
+            IUT -> Upper Tester: SetConnectionEncryptionStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Lower Tester: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            IUT -> Lower Tester: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x10,
+            }
+            Lower Tester -> IUT: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x07,
+            }
+            IUT -> Lower Tester: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::EncryptionKeySizeReq,
+                error_code: 0x20,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::UnsupportedLmpOrLlParameter,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+
+        assert_eq!(*context.encryption_key_size.borrow(), None);
+    }
+
+    #[test]
+    fn accept_encryption_with_larger_key_size() {
+        let context = TestContext::new();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            Lower Tester -> IUT: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x20,
+            }
+            IUT -> Lower Tester: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x10,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionKeySizeReq,
+            }
+            Lower Tester -> IUT: StartEncryptionReq {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::StartEncryptionReq,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::On,
+            }
+        }
+
+        assert_eq!(*context.encryption_key_size.borrow(), Some(16));
+    }
+
+    #[test]
+    fn reject_encryption_with_key_size_below_minimum() {
+        let context = TestContext::new().with_min_encryption_key_size(7);
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: EncryptionModeReq {
+                transaction_id: 0,
+                encryption_mode: 0x01,
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::EncryptionModeReq,
+            }
+            Lower Tester -> IUT: EncryptionKeySizeReq {
+                transaction_id: 0,
+                key_size: 0x01,
+            }
+            IUT -> Lower Tester: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::EncryptionKeySizeReq,
+                error_code: 0x20,
+            }
+            IUT -> Upper Tester: EncryptionChange {
+                status: ErrorCode::UnsupportedLmpOrLlParameter,
+                connection_handle: context.peer_handle(),
+                encryption_enabled: EncryptionEnabled::Off,
+            }
+        }
+
+        assert_eq!(*context.encryption_key_size.borrow(), None);
+    }
+
+    #[test]
+    fn read_negotiated_encryption_key_size() {
+        let context = TestContext::new();
+        context.set_encryption_key_size(Some(7));
+        let procedure = read_encryption_key_size;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: ReadEncryptionKeySize {
+                connection_handle: context.peer_handle(),
+            }
+            IUT -> Upper Tester: ReadEncryptionKeySizeComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+                key_size: 7,
+            }
+        }
+    }
+
+    #[test]
+    fn read_encryption_key_size_without_encryption() {
+        let context = TestContext::new();
+        let procedure = read_encryption_key_size;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: ReadEncryptionKeySize {
+                connection_handle: context.peer_handle(),
+            }
+            IUT -> Upper Tester: ReadEncryptionKeySizeComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::InsufficientSecurity,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
 }
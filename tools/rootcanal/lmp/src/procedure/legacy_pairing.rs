@@ -1,43 +1,105 @@
 // Bluetooth Core, Vol 2, Part C, 4.2.2
 
+use num_traits::{FromPrimitive, ToPrimitive};
+use rand::{thread_rng, Rng};
+
+use crate::either::Either;
 use crate::packets::{hci, lmp};
 use crate::procedure::{authentication, Context};
+use crate::safer;
 
 use crate::num_hci_command_packets;
 
-pub async fn initiate(ctx: &impl Context) -> Result<(), ()> {
-    ctx.send_hci_event(hci::PinCodeRequestBuilder { bd_addr: ctx.peer_address() }.build());
+fn xor(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let mut result = *a;
+    for (x, y) in result.iter_mut().zip(b) {
+        *x ^= y;
+    }
+    result
+}
 
-    let _pin_code = ctx.receive_hci_command::<hci::PinCodeRequestReplyPacket>().await;
+/// Ask the host for the PIN, returns None if the host does not provide one
+async fn request_pin_code(ctx: &impl Context) -> Option<Vec<u8>> {
+    ctx.send_hci_event(hci::PinCodeRequestBuilder { bd_addr: ctx.peer_address() }.build());
 
-    ctx.send_hci_event(
-        hci::PinCodeRequestReplyCompleteBuilder {
-            num_hci_command_packets: 1,
-            status: hci::ErrorCode::Success,
-            bd_addr: ctx.peer_address(),
+    match ctx
+        .receive_hci_command::<Either<
+            hci::PinCodeRequestReplyPacket,
+            hci::PinCodeRequestNegativeReplyPacket,
+        >>()
+        .await
+    {
+        Either::Left(reply) => {
+            ctx.send_hci_event(
+                hci::PinCodeRequestReplyCompleteBuilder {
+                    num_hci_command_packets,
+                    status: hci::ErrorCode::Success,
+                    bd_addr: ctx.peer_address(),
+                }
+                .build(),
+            );
+            let pin_code_length = (reply.get_pin_code_length() as usize).min(16);
+            Some(reply.get_pin_code()[..pin_code_length].to_vec())
         }
-        .build(),
-    );
+        Either::Right(_) => {
+            ctx.send_hci_event(
+                hci::PinCodeRequestNegativeReplyCompleteBuilder {
+                    num_hci_command_packets,
+                    status: hci::ErrorCode::Success,
+                    bd_addr: ctx.peer_address(),
+                }
+                .build(),
+            );
+            None
+        }
+    }
+}
 
-    // TODO: handle result
-    let _ = ctx
-        .send_accepted_lmp_packet(
-            lmp::InRandBuilder { transaction_id: 0, random_number: [0; 16] }.build(),
-        )
-        .await;
+/// Combine the unit keys of both devices, the random number of the peer
+/// is received encrypted with the initialization key
+// Bluetooth Core, Vol 2, Part H, 3.2.4
+fn combination_key(
+    ctx: &impl Context,
+    init_key: &[u8; 16],
+    random_number: &[u8; 16],
+    peer_comb_key: &lmp::CombKeyPacket,
+) -> [u8; 16] {
+    let peer_random_number = xor(peer_comb_key.get_random_number(), init_key);
+    xor(
+        &safer::e21(random_number, &ctx.local_address().bytes),
+        &safer::e21(&peer_random_number, &ctx.peer_address().bytes),
+    )
+}
+
+pub async fn initiate(ctx: &impl Context) -> Result<(), hci::ErrorCode> {
+    let pin_code = request_pin_code(ctx).await.ok_or(hci::ErrorCode::PinOrKeyMissing)?;
+
+    let in_rand: [u8; 16] = thread_rng().gen();
+    ctx.send_accepted_lmp_packet(
+        lmp::InRandBuilder { transaction_id: 0, random_number: in_rand }.build(),
+    )
+    .await
+    .map_err(|error_code| {
+        hci::ErrorCode::from_u8(error_code).unwrap_or(hci::ErrorCode::AuthenticationFailure)
+    })?;
+
+    // The initialization key is augmented with the address of
+    // the device receiving IN_RAND
+    let init_key = safer::e22(&in_rand, &pin_code, &ctx.peer_address().bytes);
 
-    ctx.send_lmp_packet(lmp::CombKeyBuilder { transaction_id: 0, random_number: [0; 16] }.build());
+    let random_number: [u8; 16] = thread_rng().gen();
+    ctx.send_lmp_packet(
+        lmp::CombKeyBuilder { transaction_id: 0, random_number: xor(&random_number, &init_key) }
+            .build(),
+    );
 
-    let _ = ctx.receive_lmp_packet::<lmp::CombKeyPacket>().await;
+    let peer_comb_key = ctx.receive_lmp_packet::<lmp::CombKeyPacket>().await;
+    let link_key = combination_key(ctx, &init_key, &random_number, &peer_comb_key);
 
     // Post pairing authentication
-    let link_key = [0; 16];
-    let auth_result = authentication::send_challenge(ctx, 0, link_key).await;
+    authentication::send_challenge(ctx, 0, link_key).await?;
     authentication::receive_challenge(ctx, link_key).await;
 
-    if auth_result.is_err() {
-        return Err(());
-    }
     ctx.send_hci_event(
         hci::LinkKeyNotificationBuilder {
             bd_addr: ctx.peer_address(),
@@ -50,36 +112,42 @@ pub async fn initiate(ctx: &impl Context) -> Result<(), ()> {
     Ok(())
 }
 
-pub async fn respond(ctx: &impl Context, _request: lmp::InRandPacket) -> Result<(), ()> {
-    ctx.send_hci_event(hci::PinCodeRequestBuilder { bd_addr: ctx.peer_address() }.build());
-
-    let _pin_code = ctx.receive_hci_command::<hci::PinCodeRequestReplyPacket>().await;
-
-    ctx.send_hci_event(
-        hci::PinCodeRequestReplyCompleteBuilder {
-            num_hci_command_packets,
-            status: hci::ErrorCode::Success,
-            bd_addr: ctx.peer_address(),
+pub async fn respond(ctx: &impl Context, request: lmp::InRandPacket) -> Result<(), hci::ErrorCode> {
+    let pin_code = match request_pin_code(ctx).await {
+        Some(pin_code) => pin_code,
+        None => {
+            ctx.send_lmp_packet(
+                lmp::NotAcceptedBuilder {
+                    transaction_id: 0,
+                    not_accepted_opcode: lmp::Opcode::InRand,
+                    error_code: hci::ErrorCode::PinOrKeyMissing.to_u8().unwrap(),
+                }
+                .build(),
+            );
+            return Err(hci::ErrorCode::PinOrKeyMissing);
         }
-        .build(),
-    );
+    };
 
     ctx.send_lmp_packet(
         lmp::AcceptedBuilder { transaction_id: 0, accepted_opcode: lmp::Opcode::InRand }.build(),
     );
 
-    let _ = ctx.receive_lmp_packet::<lmp::CombKeyPacket>().await;
+    let init_key = safer::e22(request.get_random_number(), &pin_code, &ctx.local_address().bytes);
+
+    let peer_comb_key = ctx.receive_lmp_packet::<lmp::CombKeyPacket>().await;
+
+    let random_number: [u8; 16] = thread_rng().gen();
+    ctx.send_lmp_packet(
+        lmp::CombKeyBuilder { transaction_id: 0, random_number: xor(&random_number, &init_key) }
+            .build(),
+    );
 
-    ctx.send_lmp_packet(lmp::CombKeyBuilder { transaction_id: 0, random_number: [0; 16] }.build());
+    let link_key = combination_key(ctx, &init_key, &random_number, &peer_comb_key);
 
     // Post pairing authentication
-    let link_key = [0; 16];
     authentication::receive_challenge(ctx, link_key).await;
-    let auth_result = authentication::send_challenge(ctx, 0, link_key).await;
+    authentication::send_challenge(ctx, 0, link_key).await?;
 
-    if auth_result.is_err() {
-        return Err(());
-    }
     ctx.send_hci_event(
         hci::LinkKeyNotificationBuilder {
             bd_addr: ctx.peer_address(),
@@ -91,3 +159,518 @@ pub async fn respond(ctx: &impl Context, _request: lmp::InRandPacket) -> Result<
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::xor;
+    use crate::procedure::Context;
+    use crate::safer;
+    use crate::test::{sequence, TestContext};
+    // legacy pairing is part of authentication procedure
+    use super::super::authentication::initiate;
+    use super::super::authentication::respond;
+
+    const PIN_CODE: [u8; 16] = *b"1234\0\0\0\0\0\0\0\0\0\0\0\0";
+    const IN_RAND: [u8; 16] = [
+        0x21, 0x87, 0xf0, 0x4a, 0xba, 0x90, 0x31, 0xd7, 0x80, 0xd7, 0x60, 0x38, 0xd2, 0x1b, 0x3e,
+        0x4f,
+    ];
+    const LK_RAND: [u8; 16] = [
+        0x70, 0xa3, 0x8c, 0x5f, 0x1e, 0x29, 0xd4, 0x86, 0x03, 0x6b, 0xf2, 0xc5, 0x9a, 0x44, 0x17,
+        0xe8,
+    ];
+
+    /// Initialization key computed by the Lower Tester from its PIN,
+    /// augmented with the address of the device receiving IN_RAND
+    fn init_key(pin_code: &[u8], in_rand: &[u8; 16], address: &[u8; 6]) -> [u8; 16] {
+        safer::e22(in_rand, pin_code, address)
+    }
+
+    /// Combination key computed by the Lower Tester from the
+    /// LMP_comb_key of the IUT and its own random number LK_RAND
+    fn link_key(context: &TestContext, init_key: &[u8; 16], comb_key: &[u8; 16]) -> [u8; 16] {
+        xor(
+            &safer::e21(&LK_RAND, &context.peer_address().bytes),
+            &safer::e21(&xor(comb_key, init_key), &context.local_address().bytes),
+        )
+    }
+
+    #[test]
+    fn initiate_pairing() {
+        let context = TestContext::default();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: LinkKeyRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestReply {
+                bd_addr: context.peer_address(),
+                pin_code_length: 4,
+                pin_code: PIN_CODE,
+            }
+            IUT -> Upper Tester: PinCodeRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: InRand as in_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::InRand,
+            }
+            IUT -> Lower Tester: CombKey as comb_key {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: CombKey {
+                transaction_id: 0,
+                random_number: xor(
+                    &LK_RAND,
+                    &init_key(
+                        &PIN_CODE[..4],
+                        in_rand.get_random_number(),
+                        &context.peer_address().bytes,
+                    ),
+                ),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &link_key(
+                        &context,
+                        &init_key(
+                            &PIN_CODE[..4],
+                            in_rand.get_random_number(),
+                            &context.peer_address().bytes,
+                        ),
+                        comb_key.get_random_number(),
+                    ),
+                    au_rand.get_random_number(),
+                    &context.peer_address().bytes,
+                )
+                .0,
+            }
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &link_key(
+                        &context,
+                        &init_key(
+                            &PIN_CODE[..4],
+                            in_rand.get_random_number(),
+                            &context.peer_address().bytes,
+                        ),
+                        comb_key.get_random_number(),
+                    ),
+                    &[0; 16],
+                    &context.local_address().bytes,
+                )
+                .0,
+            }
+            IUT -> Upper Tester: LinkKeyNotification {
+                bd_addr: context.peer_address(),
+                key_type: KeyType::Combination,
+                link_key: link_key(
+                    &context,
+                    &init_key(
+                        &PIN_CODE[..4],
+                        in_rand.get_random_number(),
+                        &context.peer_address().bytes,
+                    ),
+                    comb_key.get_random_number(),
+                ),
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::Success,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_pairing_with_different_pin_codes() {
+        let context = TestContext::default();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: LinkKeyRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestReply {
+                bd_addr: context.peer_address(),
+                pin_code_length: 4,
+                pin_code: PIN_CODE,
+            }
+            IUT -> Upper Tester: PinCodeRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: InRand as in_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::InRand,
+            }
+            IUT -> Lower Tester: CombKey as comb_key {
+                transaction_id: 0,
+            }
+            // Initialization key computed with the PIN "0000"
+            Lower Tester -> IUT: CombKey {
+                transaction_id: 0,
+                random_number: xor(
+                    &LK_RAND,
+                    &init_key(b"0000", in_rand.get_random_number(), &context.peer_address().bytes),
+                ),
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &link_key(
+                        &context,
+                        &init_key(
+                            b"0000",
+                            in_rand.get_random_number(),
+                            &context.peer_address().bytes,
+                        ),
+                        comb_key.get_random_number(),
+                    ),
+                    au_rand.get_random_number(),
+                    &context.peer_address().bytes,
+                )
+                .0,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::AuthenticationFailure,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_pairing_with_challenge_not_accepted() {
+        let context = TestContext::default();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: LinkKeyRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestReply {
+                bd_addr: context.peer_address(),
+                pin_code_length: 4,
+                pin_code: PIN_CODE,
+            }
+            IUT -> Upper Tester: PinCodeRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: InRand as in_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::InRand,
+            }
+            IUT -> Lower Tester: CombKey {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: CombKey {
+                transaction_id: 0,
+                random_number: xor(
+                    &LK_RAND,
+                    &init_key(
+                        &PIN_CODE[..4],
+                        in_rand.get_random_number(),
+                        &context.peer_address().bytes,
+                    ),
+                ),
+            }
+            IUT -> Lower Tester: AuRand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::AuRand,
+                error_code: 0x06,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::PinOrKeyMissing,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_pairing_without_pin_code() {
+        let context = TestContext::default();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: LinkKeyRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::PinOrKeyMissing,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn initiate_pairing_without_pin_code_on_peer() {
+        let context = TestContext::default();
+        let procedure = initiate;
+
+        sequence! { procedure, context,
+            Upper Tester -> IUT: AuthenticationRequested {
+                connection_handle: context.peer_handle()
+            }
+            IUT -> Upper Tester: AuthenticationRequestedStatus {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+            }
+            IUT -> Upper Tester: LinkKeyRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: LinkKeyRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: LinkKeyRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestReply {
+                bd_addr: context.peer_address(),
+                pin_code_length: 4,
+                pin_code: PIN_CODE,
+            }
+            IUT -> Upper Tester: PinCodeRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: InRand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::InRand,
+                error_code: 0x06,
+            }
+            IUT -> Upper Tester: AuthenticationComplete {
+                status: ErrorCode::PinOrKeyMissing,
+                connection_handle: context.peer_handle(),
+            }
+        }
+    }
+
+    #[test]
+    fn respond_pairing() {
+        let context = TestContext::default();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: InRand {
+                transaction_id: 0,
+                random_number: IN_RAND,
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestReply {
+                bd_addr: context.peer_address(),
+                pin_code_length: 4,
+                pin_code: PIN_CODE,
+            }
+            IUT -> Upper Tester: PinCodeRequestReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: Accepted {
+                transaction_id: 0,
+                accepted_opcode: Opcode::InRand,
+            }
+            Lower Tester -> IUT: CombKey {
+                transaction_id: 0,
+                random_number: xor(
+                    &LK_RAND,
+                    &init_key(&PIN_CODE[..4], &IN_RAND, &context.local_address().bytes),
+                ),
+            }
+            IUT -> Lower Tester: CombKey as comb_key {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: AuRand {
+                transaction_id: 0,
+                random_number: [0; 16],
+            }
+            IUT -> Lower Tester: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &link_key(
+                        &context,
+                        &init_key(&PIN_CODE[..4], &IN_RAND, &context.local_address().bytes),
+                        comb_key.get_random_number(),
+                    ),
+                    &[0; 16],
+                    &context.local_address().bytes,
+                )
+                .0,
+            }
+            IUT -> Lower Tester: AuRand as au_rand {
+                transaction_id: 0,
+            }
+            Lower Tester -> IUT: Sres {
+                transaction_id: 0,
+                authentication_rsp: safer::e1(
+                    &link_key(
+                        &context,
+                        &init_key(&PIN_CODE[..4], &IN_RAND, &context.local_address().bytes),
+                        comb_key.get_random_number(),
+                    ),
+                    au_rand.get_random_number(),
+                    &context.peer_address().bytes,
+                )
+                .0,
+            }
+            IUT -> Upper Tester: LinkKeyNotification {
+                bd_addr: context.peer_address(),
+                key_type: KeyType::Combination,
+                link_key: link_key(
+                    &context,
+                    &init_key(&PIN_CODE[..4], &IN_RAND, &context.local_address().bytes),
+                    comb_key.get_random_number(),
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn respond_pairing_without_pin_code() {
+        let context = TestContext::default();
+        let procedure = respond;
+
+        sequence! { procedure, context,
+            Lower Tester -> IUT: InRand {
+                transaction_id: 0,
+                random_number: IN_RAND,
+            }
+            IUT -> Upper Tester: PinCodeRequest {
+                bd_addr: context.peer_address(),
+            }
+            Upper Tester -> IUT: PinCodeRequestNegativeReply {
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Upper Tester: PinCodeRequestNegativeReplyComplete {
+                num_hci_command_packets: 1,
+                status: ErrorCode::Success,
+                bd_addr: context.peer_address(),
+            }
+            IUT -> Lower Tester: NotAccepted {
+                transaction_id: 0,
+                not_accepted_opcode: Opcode::InRand,
+                error_code: 0x06,
+            }
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::task::{self, Poll};
 
 use crate::ec::PrivateKey;
 use crate::packets::{hci, lmp};
+use crate::procedure::authentication::SecureAuthentication;
 
 pub trait Context {
     fn poll_hci_command<C: TryFrom<hci::CommandPacket>>(&self) -> Poll<C>;
@@ -15,6 +16,8 @@ pub trait Context {
 
     fn peer_address(&self) -> hci::Address;
     fn peer_handle(&self) -> u16;
+    fn local_address(&self) -> hci::Address;
+    fn role(&self) -> hci::Role;
 
     fn peer_extended_features(&self, _features_page: u8) -> Option<u64> {
         None
@@ -22,6 +25,10 @@ pub trait Context {
 
     fn extended_features(&self, features_page: u8) -> u64;
 
+    fn min_encryption_key_size(&self) -> u8;
+
+    fn secure_connections_only(&self) -> bool;
+
     fn receive_hci_command<C: TryFrom<hci::CommandPacket>>(&self) -> ReceiveFuture<'_, Self, C> {
         ReceiveFuture(Self::poll_hci_command, self)
     }
@@ -46,6 +53,20 @@ pub trait Context {
     }
 
     fn set_private_key(&self, _key: &PrivateKey) {}
+
+    fn get_secure_authentication(&self) -> Option<SecureAuthentication> {
+        None
+    }
+
+    fn set_secure_authentication(&self, _authentication: &SecureAuthentication) {}
+
+    fn set_encryption_key(&self, _key: &[u8; 16]) {}
+
+    fn get_encryption_key_size(&self) -> Option<u8> {
+        None
+    }
+
+    fn set_encryption_key_size(&self, _key_size: Option<u8>) {}
 }
 
 /// Future for Context::receive_hci_command and Context::receive_lmp_packet
@@ -136,6 +157,7 @@ pub async fn run(ctx: impl Context) {
         b { authentication::respond(&ctx) }
         c { encryption::initiate(&ctx) }
         d { encryption::respond(&ctx) }
-        e { features::respond(&ctx) }
+        e { encryption::read_encryption_key_size(&ctx) }
+        f { features::respond(&ctx) }
     }
 }
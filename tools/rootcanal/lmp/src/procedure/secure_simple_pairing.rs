@@ -8,6 +8,7 @@ use crate::ec::{DhKey, PrivateKey, PublicKey};
 use crate::either::Either;
 use crate::packets::{hci, lmp};
 use crate::procedure::{authentication, features, Context};
+use crate::sha256;
 
 use crate::num_hci_command_packets;
 
@@ -131,7 +132,11 @@ async fn receive_public_key(ctx: &impl Context, transaction_id: u8) -> PublicKey
 const COMMITMENT_VALUE_SIZE: usize = 16;
 const NONCE_SIZE: usize = 16;
 
-async fn receive_commitment(ctx: &impl Context, skip_first: bool) {
+/// Returns the local and peer nonces.
+async fn receive_commitment(
+    ctx: &impl Context,
+    skip_first: bool,
+) -> ([u8; NONCE_SIZE], [u8; NONCE_SIZE]) {
     let commitment_value = [0; COMMITMENT_VALUE_SIZE];
 
     if !skip_first {
@@ -145,7 +150,7 @@ async fn receive_commitment(ctx: &impl Context, skip_first: bool) {
         lmp::SimplePairingConfirmBuilder { transaction_id: 0, commitment_value }.build(),
     );
 
-    let _pairing_number = ctx.receive_lmp_packet::<lmp::SimplePairingNumberPacket>().await;
+    let pairing_number = ctx.receive_lmp_packet::<lmp::SimplePairingNumberPacket>().await;
     // TODO: check pairing number
     ctx.send_lmp_packet(
         lmp::AcceptedBuilder {
@@ -163,9 +168,15 @@ async fn receive_commitment(ctx: &impl Context, skip_first: bool) {
             lmp::SimplePairingNumberBuilder { transaction_id: 0, nonce }.build(),
         )
         .await;
+
+    (nonce, *pairing_number.get_nonce())
 }
 
-async fn send_commitment(ctx: &impl Context, skip_first: bool) {
+/// Returns the local and peer nonces.
+async fn send_commitment(
+    ctx: &impl Context,
+    skip_first: bool,
+) -> ([u8; NONCE_SIZE], [u8; NONCE_SIZE]) {
     let commitment_value = [0; COMMITMENT_VALUE_SIZE];
 
     if !skip_first {
@@ -188,7 +199,7 @@ async fn send_commitment(ctx: &impl Context, skip_first: bool) {
         )
         .await;
 
-    let _pairing_number = ctx.receive_lmp_packet::<lmp::SimplePairingNumberPacket>().await;
+    let pairing_number = ctx.receive_lmp_packet::<lmp::SimplePairingNumberPacket>().await;
     // TODO: check pairing number
     ctx.send_lmp_packet(
         lmp::AcceptedBuilder {
@@ -197,6 +208,31 @@ async fn send_commitment(ctx: &impl Context, skip_first: bool) {
         }
         .build(),
     );
+
+    (nonce, *pairing_number.get_nonce())
+}
+
+/// Derive the link key from the Diffie-Hellman key and the nonces
+/// exchanged during the last commitment.
+fn link_key(
+    ctx: &impl Context,
+    dh_key: &DhKey,
+    (local_nonce, peer_nonce): ([u8; NONCE_SIZE], [u8; NONCE_SIZE]),
+) -> [u8; 16] {
+    let (central_address, peripheral_address) =
+        authentication::central_and_peripheral_addresses(ctx);
+    let (central_nonce, peripheral_nonce) = match ctx.role() {
+        hci::Role::Central => (local_nonce, peer_nonce),
+        hci::Role::Peripheral => (peer_nonce, local_nonce),
+    };
+    sha256::f2(
+        &dh_key.x_coordinate(),
+        &central_nonce,
+        &peripheral_nonce,
+        b"btlk",
+        &central_address,
+        &peripheral_address,
+    )
 }
 
 async fn user_confirmation_request(ctx: &impl Context) -> Result<(), ()> {
@@ -359,7 +395,23 @@ pub async fn initiate(ctx: &impl Context) -> Result<(), ()> {
         }
     };
     let responder = {
-        let response = ctx.receive_lmp_packet::<lmp::IoCapabilityResPacket>().await;
+        let response = match ctx
+            .receive_lmp_packet::<Either<lmp::IoCapabilityResPacket, lmp::NotAcceptedExtPacket>>()
+            .await
+        {
+            Either::Left(response) => response,
+            Either::Right(not_accepted) => {
+                ctx.send_hci_event(
+                    hci::SimplePairingCompleteBuilder {
+                        status: hci::ErrorCode::from_u8(not_accepted.get_error_code())
+                            .unwrap_or(hci::ErrorCode::AuthenticationFailure),
+                        bd_addr: ctx.peer_address(),
+                    }
+                    .build(),
+                );
+                return Err(());
+            }
+        };
 
         let io_capability = hci::IoCapability::from_u8(response.get_io_capabilities()).unwrap();
         let oob_data_present =
@@ -400,14 +452,14 @@ pub async fn initiate(ctx: &impl Context) -> Result<(), ()> {
 
     // Authentication Stage 1
     let auth_method = authentication_method(initiator, responder);
-    let result: Result<(), ()> = async {
+    let result = async {
         match auth_method {
             AuthenticationMethod::NumericComparaisonJustWork
             | AuthenticationMethod::NumericComparaisonUserConfirm => {
-                send_commitment(ctx, true).await;
+                let nonces = send_commitment(ctx, true).await;
 
                 user_confirmation_request(ctx).await?;
-                Ok(())
+                Ok(nonces)
             }
             AuthenticationMethod::PasskeyEntry => {
                 if initiator.io_capability == hci::IoCapability::KeyboardOnly {
@@ -421,34 +473,37 @@ pub async fn initiate(ctx: &impl Context) -> Result<(), ()> {
                         .build(),
                     );
                 }
+                let mut nonces = Default::default();
                 for _ in 0..PASSKEY_ENTRY_REPEAT_NUMBER {
-                    send_commitment(ctx, false).await;
+                    nonces = send_commitment(ctx, false).await;
                 }
-                Ok(())
+                Ok(nonces)
             }
             AuthenticationMethod::OutOfBand => {
                 if initiator.oob_data_present != hci::OobDataPresent::NotPresent {
                     remote_oob_data_request(ctx).await?;
                 }
 
-                send_commitment(ctx, false).await;
-                Ok(())
+                Ok(send_commitment(ctx, false).await)
             }
         }
     }
     .await;
 
-    if result.is_err() {
-        ctx.send_lmp_packet(lmp::NumericComparaisonFailedBuilder { transaction_id: 0 }.build());
-        ctx.send_hci_event(
-            hci::SimplePairingCompleteBuilder {
-                status: hci::ErrorCode::AuthenticationFailure,
-                bd_addr: ctx.peer_address(),
-            }
-            .build(),
-        );
-        return Err(());
-    }
+    let link_key = match result {
+        Ok(nonces) => link_key(ctx, &dh_key, nonces),
+        Err(()) => {
+            ctx.send_lmp_packet(lmp::NumericComparaisonFailedBuilder { transaction_id: 0 }.build());
+            ctx.send_hci_event(
+                hci::SimplePairingCompleteBuilder {
+                    status: hci::ErrorCode::AuthenticationFailure,
+                    bd_addr: ctx.peer_address(),
+                }
+                .build(),
+            );
+            return Err(());
+        }
+    };
 
     // Authentication Stage 2
     {
@@ -490,9 +545,13 @@ pub async fn initiate(ctx: &impl Context) -> Result<(), ()> {
     );
 
     // Link Key Calculation
-    let link_key = [0; 16];
-    let auth_result = authentication::send_challenge(ctx, 0, link_key).await;
-    authentication::receive_challenge(ctx, link_key).await;
+    let auth_result = if authentication::secure_connections_supported(ctx).await {
+        authentication::send_secure_challenge(ctx, 0, link_key).await
+    } else {
+        let auth_result = authentication::send_challenge(ctx, 0, link_key).await;
+        authentication::receive_challenge(ctx, link_key).await;
+        auth_result.map(|_| ())
+    };
 
     if auth_result.is_err() {
         return Err(());
@@ -578,13 +637,13 @@ pub async fn respond(ctx: &impl Context, request: lmp::IoCapabilityReqPacket) ->
 
     // Authentication Stage 1
     let auth_method = authentication_method(initiator, responder);
-    let negative_user_confirmation = match auth_method {
+    let (nonces, negative_user_confirmation) = match auth_method {
         AuthenticationMethod::NumericComparaisonJustWork
         | AuthenticationMethod::NumericComparaisonUserConfirm => {
-            receive_commitment(ctx, true).await;
+            let nonces = receive_commitment(ctx, true).await;
 
             let user_confirmation = user_confirmation_request(ctx).await;
-            user_confirmation.is_err()
+            (nonces, user_confirmation.is_err())
         }
         AuthenticationMethod::PasskeyEntry => {
             if responder.io_capability == hci::IoCapability::KeyboardOnly {
@@ -596,10 +655,11 @@ pub async fn respond(ctx: &impl Context, request: lmp::IoCapabilityReqPacket) ->
                         .build(),
                 );
             }
+            let mut nonces = Default::default();
             for _ in 0..PASSKEY_ENTRY_REPEAT_NUMBER {
-                receive_commitment(ctx, false).await;
+                nonces = receive_commitment(ctx, false).await;
             }
-            false
+            (nonces, false)
         }
         AuthenticationMethod::OutOfBand => {
             if responder.oob_data_present != hci::OobDataPresent::NotPresent {
@@ -607,10 +667,10 @@ pub async fn respond(ctx: &impl Context, request: lmp::IoCapabilityReqPacket) ->
                 let _remote_oob_data = remote_oob_data_request(ctx).await;
             }
 
-            receive_commitment(ctx, false).await;
-            false
+            (receive_commitment(ctx, false).await, false)
         }
     };
+    let link_key = link_key(ctx, &dh_key, nonces);
 
     let _dhkey = match ctx
         .receive_lmp_packet::<Either<lmp::NumericComparaisonFailedPacket, lmp::DhkeyCheckPacket>>()
@@ -673,9 +733,12 @@ pub async fn respond(ctx: &impl Context, request: lmp::IoCapabilityReqPacket) ->
     );
 
     // Link Key Calculation
-    let link_key = [0; 16];
-    authentication::receive_challenge(ctx, link_key).await;
-    let auth_result = authentication::send_challenge(ctx, 0, link_key).await;
+    let auth_result = if authentication::secure_connections_supported(ctx).await {
+        authentication::receive_secure_challenge(ctx, link_key).await
+    } else {
+        authentication::receive_challenge(ctx, link_key).await;
+        authentication::send_challenge(ctx, 0, link_key).await.map(|_| ())
+    };
 
     if auth_result.is_err() {
         return Err(());
@@ -714,15 +777,30 @@ mod tests {
         buf
     }
 
+    // Bluetooth Core, Vol 2, Part G, P-192 sample private key B, stored
+    // least significant octet first
+    fn peer_private_key() -> PrivateKey {
+        PrivateKey::P192([
+            0x6d, 0xe1, 0x00, 0xde, 0x35, 0xd6, 0x11, 0x92, 0x30, 0x4e, 0x24, 0x86, 0xbe, 0x8d,
+            0x5d, 0xf1, 0x68, 0x0f, 0xb5, 0x90, 0xa7, 0x6c, 0x63, 0x1e,
+        ])
+    }
+
     fn peer_p192_public_key() -> [[u8; 16]; 3] {
         let mut buf = [[0; 16], [0; 16], [0; 16]];
-        let key = PrivateKey::generate_p192().derive();
+        let key = peer_private_key().derive();
         for (dst, src) in buf.iter_mut().zip(key.as_slice().chunks(16)) {
             dst.copy_from_slice(src);
         }
         buf
     }
 
+    // The nonces exchanged by the tests are all zero
+    fn link_key(context: &TestContext) -> [u8; 16] {
+        let dh_key = context.get_private_key().unwrap().shared_secret(peer_private_key().derive());
+        super::link_key(context, &dh_key, ([0; 16], [0; 16]))
+    }
+
     #[test]
     fn initiate_size() {
         let context = crate::test::TestContext::new();
@@ -0,0 +1,275 @@
+/******************************************************************************
+ *
+ *  Copyright 2022 The Android Open Source Project
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at:
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ *
+ ******************************************************************************/
+
+/******************************************************************************
+ *                                 IMPORTANT
+ *
+ * These cryptography methods do not provide any security or correctness
+ * ensurance.
+ * They should be used only in Bluetooth emulation, not including any production
+ * environment.
+ *
+ ******************************************************************************/
+
+use std::convert::TryInto;
+
+// FIPS 180-4, 4.2.2
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// FIPS 180-4, 5.3.3
+const INITIAL_HASH: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 digest of `message`.
+// FIPS 180-4, 6.2
+pub fn sha256(message: &[u8]) -> [u8; 32] {
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(message.len() as u64 * 8).to_be_bytes());
+
+    let mut hash = INITIAL_HASH;
+    for block in padded.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = hash;
+        for (k, w) in K.iter().zip(w.iter()) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(*w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (value, word) in hash.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *value = value.wrapping_add(*word);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_mut(4).zip(hash.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// HMAC-SHA-256 of `message` with `key`.
+// RFC 2104
+pub fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner: Vec<u8> =
+        block.iter().map(|byte| byte ^ 0x36).chain(message.iter().copied()).collect();
+    let outer: Vec<u8> = block.iter().map(|byte| byte ^ 0x5c).chain(sha256(&inner)).collect();
+    sha256(&outer)
+}
+
+/// Most significant 128 bits of HMAC-SHA-256 of the concatenation of `values`.
+fn truncated_hmac(key: &[u8], values: &[&[u8]]) -> [u8; 16] {
+    let mut result = [0; 16];
+    result.copy_from_slice(&hmac(key, &values.concat())[..16]);
+    result
+}
+
+/// Function f2, used to derive the link key of Secure Simple Pairing
+/// from the Diffie-Hellman key `w` and the nonces of both devices.
+// Bluetooth Core, Vol 2, Part H, 7.7.3
+pub fn f2(
+    w: &[u8],
+    central_nonce: &[u8; 16],
+    peripheral_nonce: &[u8; 16],
+    key_id: &[u8; 4],
+    central_address: &[u8; 6],
+    peripheral_address: &[u8; 6],
+) -> [u8; 16] {
+    truncated_hmac(
+        w,
+        &[central_nonce, peripheral_nonce, key_id, central_address, peripheral_address],
+    )
+}
+
+/// Function h3, used to derive the AES encryption key.
+// Bluetooth Core, Vol 2, Part H, 7.7.8
+pub fn h3(
+    key: &[u8; 16],
+    key_id: &[u8; 4],
+    central_address: &[u8; 6],
+    peripheral_address: &[u8; 6],
+    aco: &[u8; 8],
+) -> [u8; 16] {
+    truncated_hmac(key, &[key_id, central_address, peripheral_address, aco])
+}
+
+/// Function h4, used to derive the device authentication key.
+// Bluetooth Core, Vol 2, Part H, 7.7.9
+pub fn h4(
+    key: &[u8; 16],
+    key_id: &[u8; 4],
+    central_address: &[u8; 6],
+    peripheral_address: &[u8; 6],
+) -> [u8; 16] {
+    truncated_hmac(key, &[key_id, central_address, peripheral_address])
+}
+
+/// Function h5, returning the responses of the central and of the
+/// peripheral followed by the authenticated ciphering offset.
+// Bluetooth Core, Vol 2, Part H, 7.7.10
+pub fn h5(
+    key: &[u8; 16],
+    central_random_number: &[u8; 16],
+    peripheral_random_number: &[u8; 16],
+) -> ([u8; 4], [u8; 4], [u8; 8]) {
+    let output = truncated_hmac(key, &[central_random_number, peripheral_random_number]);
+    let mut central_sres = [0; 4];
+    let mut peripheral_sres = [0; 4];
+    let mut aco = [0; 8];
+    central_sres.copy_from_slice(&output[..4]);
+    peripheral_sres.copy_from_slice(&output[4..8]);
+    aco.copy_from_slice(&output[8..]);
+    (central_sres, peripheral_sres, aco)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sha256::*;
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(b"abc"),
+            [
+                0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae,
+                0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61,
+                0xf2, 0x00, 0x15, 0xad
+            ]
+        );
+    }
+
+    // RFC 4231, 4.3
+    #[test]
+    fn test_hmac() {
+        assert_eq!(
+            hmac(b"Jefe", b"what do ya want for nothing?"),
+            [
+                0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+                0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+                0x64, 0xec, 0x38, 0x43
+            ]
+        );
+    }
+
+    // Bluetooth Core, Vol 2, Part G, 7.7 sample data, with the
+    // values most significant octet first
+    const W: [u8; 16] = [
+        0xc2, 0x34, 0xc1, 0x19, 0x8f, 0x3b, 0x52, 0x01, 0x86, 0xab, 0x92, 0xa2, 0xf8, 0x74, 0x93,
+        0x4e,
+    ];
+    const A1: [u8; 6] = [0x56, 0x12, 0x37, 0x37, 0xbf, 0xce];
+    const A2: [u8; 6] = [0xa7, 0x13, 0x70, 0x2d, 0xcf, 0xc1];
+    const ACO: [u8; 8] = [0xc6, 0x83, 0xb9, 0x7d, 0x9d, 0x42, 0x1f, 0x91];
+    const R1: [u8; 16] = [
+        0xd5, 0xcb, 0x84, 0x54, 0xd1, 0x77, 0x73, 0x3e, 0xff, 0xff, 0xb2, 0xec, 0x71, 0x2b, 0xae,
+        0xab,
+    ];
+    const R2: [u8; 16] = [
+        0xa6, 0xe8, 0xe7, 0xcc, 0x25, 0xa7, 0x5f, 0x6e, 0x21, 0x65, 0x83, 0xf7, 0xff, 0x3d, 0xc4,
+        0xcf,
+    ];
+
+    #[test]
+    fn test_f2() {
+        let dh_key = [
+            0xfb, 0x3b, 0xa2, 0x01, 0x2c, 0x7e, 0x62, 0x46, 0x6e, 0x48, 0x6e, 0x22, 0x92, 0x90,
+            0x17, 0x5b, 0x4a, 0xfe, 0xbc, 0x13, 0xfd, 0xcc, 0xee, 0x46,
+        ];
+        assert_eq!(f2(&dh_key, &R1, &R2, b"btlk", &A1, &A2), W);
+    }
+
+    #[test]
+    fn test_h3() {
+        assert_eq!(
+            h3(&W, b"btak", &A1, &A2, &ACO),
+            [
+                0x67, 0x7b, 0x37, 0x7f, 0x74, 0xa5, 0xd5, 0x01, 0x12, 0x1c, 0x46, 0x49, 0x2d, 0x4c,
+                0xb4, 0x89
+            ]
+        );
+    }
+
+    #[test]
+    fn test_h4() {
+        assert_eq!(
+            h4(&W, b"btdk", &A1, &A2),
+            [
+                0xb0, 0x89, 0xc4, 0xe3, 0x9d, 0x7c, 0x19, 0x2c, 0x3a, 0xba, 0x3c, 0x21, 0x09, 0xd2,
+                0x4c, 0x0d
+            ]
+        );
+    }
+
+    #[test]
+    fn test_h5() {
+        let s = [
+            0xb0, 0x89, 0xc4, 0xe3, 0x9d, 0x7c, 0x19, 0x2c, 0x3a, 0xba, 0x3c, 0x21, 0x09, 0xd2,
+            0x4c, 0x0d,
+        ];
+        assert_eq!(
+            h5(&s, &R1, &R2),
+            (
+                [0x74, 0x6a, 0xf8, 0x7e],
+                [0x1e, 0xeb, 0x11, 0x37],
+                [0xc6, 0x83, 0xb9, 0x7d, 0x9d, 0x42, 0x1f, 0x91]
+            )
+        );
+    }
+}
@@ -10,6 +10,7 @@ use num_traits::ToPrimitive;
 use crate::ec::PrivateKey;
 use crate::packets::{hci, lmp};
 
+use crate::procedure::authentication::SecureAuthentication;
 use crate::procedure::Context;
 
 #[derive(Default)]
@@ -18,9 +19,15 @@ pub struct TestContext {
     pub out_lmp_packets: RefCell<VecDeque<lmp::PacketPacket>>,
     pub hci_events: RefCell<VecDeque<hci::EventPacket>>,
     pub hci_commands: RefCell<VecDeque<hci::CommandPacket>>,
+    pub encryption_key: RefCell<Option<[u8; 16]>>,
+    pub encryption_key_size: RefCell<Option<u8>>,
     private_key: RefCell<Option<PrivateKey>>,
+    secure_authentication: RefCell<Option<SecureAuthentication>>,
     features_pages: [u64; 3],
     peer_features_pages: [u64; 3],
+    min_encryption_key_size: u8,
+    secure_connections_only: bool,
+    role: Option<hci::Role>,
 }
 
 impl TestContext {
@@ -49,6 +56,21 @@ impl TestContext {
         self.peer_features_pages[2] |= feature.to_u64().unwrap();
         self
     }
+
+    pub fn with_min_encryption_key_size(mut self, key_size: u8) -> Self {
+        self.min_encryption_key_size = key_size;
+        self
+    }
+
+    pub fn with_secure_connections_only(mut self) -> Self {
+        self.secure_connections_only = true;
+        self
+    }
+
+    pub fn with_role(mut self, role: hci::Role) -> Self {
+        self.role = Some(role);
+        self
+    }
 }
 
 impl Context for TestContext {
@@ -92,6 +114,14 @@ impl Context for TestContext {
         0x42
     }
 
+    fn local_address(&self) -> hci::Address {
+        hci::Address { bytes: [1; 6] }
+    }
+
+    fn role(&self) -> hci::Role {
+        self.role.unwrap_or(hci::Role::Central)
+    }
+
     fn peer_extended_features(&self, features_page: u8) -> Option<u64> {
         Some(self.peer_features_pages[features_page as usize])
     }
@@ -100,6 +130,14 @@ impl Context for TestContext {
         self.features_pages[features_page as usize]
     }
 
+    fn min_encryption_key_size(&self) -> u8 {
+        self.min_encryption_key_size
+    }
+
+    fn secure_connections_only(&self) -> bool {
+        self.secure_connections_only
+    }
+
     fn get_private_key(&self) -> Option<PrivateKey> {
         self.private_key.borrow().clone()
     }
@@ -107,6 +145,26 @@ impl Context for TestContext {
     fn set_private_key(&self, key: &PrivateKey) {
         *self.private_key.borrow_mut() = Some(key.clone())
     }
+
+    fn get_secure_authentication(&self) -> Option<SecureAuthentication> {
+        *self.secure_authentication.borrow()
+    }
+
+    fn set_secure_authentication(&self, authentication: &SecureAuthentication) {
+        *self.secure_authentication.borrow_mut() = Some(*authentication)
+    }
+
+    fn set_encryption_key(&self, key: &[u8; 16]) {
+        *self.encryption_key.borrow_mut() = Some(*key)
+    }
+
+    fn get_encryption_key_size(&self) -> Option<u8> {
+        *self.encryption_key_size.borrow()
+    }
+
+    fn set_encryption_key_size(&self, key_size: Option<u8>) {
+        *self.encryption_key_size.borrow_mut() = key_size
+    }
 }
 
 pub fn poll(future: Pin<&mut impl Future<Output = ()>>) -> Poll<()> {
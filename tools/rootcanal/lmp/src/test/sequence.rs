@@ -76,6 +76,24 @@ macro_rules! sequence_body {
 
             sequence_body!($ctx, $($tail)*)
         }};
+        ($ctx:ident, IUT -> Lower Tester: $packet:ident as $var:ident {
+            $($name:ident: $expected_value:expr),* $(,)?
+        } $($tail:tt)*) => {{
+            use crate::packets::lmp::*;
+
+            paste! {
+                let $var: [<$packet Packet>] = $ctx.0.out_lmp_packets.borrow_mut().pop_front().expect("No lmp packet").try_into().unwrap();
+            }
+
+            $(
+                let value = paste! { $var.[<get_ $name>]() };
+                assert_eq!(value.clone(), $expected_value);
+            )*
+
+            println!("IUT -> Lower Tester: {}", stringify!($packet));
+
+            sequence_body!($ctx, $($tail)*)
+        }};
         ($ctx:ident, repeat $number:literal times with ($var:ident in $iterable:expr) {
             $($inner:tt)*
         } $($tail:tt)*) => {{
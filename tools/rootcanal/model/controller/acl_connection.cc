@@ -33,6 +33,14 @@ void AclConnection::Encrypt() { encrypted_ = true; };
 
 bool AclConnection::IsEncrypted() const { return encrypted_; };
 
+void AclConnection::SetEncryptionKey(std::array<uint8_t, 16> key) {
+  encryption_key_ = key;
+}
+
+std::array<uint8_t, 16> AclConnection::GetEncryptionKey() const {
+  return encryption_key_;
+}
+
 AddressWithType AclConnection::GetAddress() const { return address_; }
 
 void AclConnection::SetAddress(AddressWithType address) { address_ = address; }
@@ -16,6 +16,7 @@
 
 #pragma once
 
+#include <array>
 #include <chrono>
 #include <cstdint>
 
@@ -39,6 +40,10 @@ class AclConnection {
 
   bool IsEncrypted() const;
 
+  void SetEncryptionKey(std::array<uint8_t, 16> key);
+
+  std::array<uint8_t, 16> GetEncryptionKey() const;
+
   AddressWithType GetAddress() const;
 
   void SetAddress(AddressWithType address);
@@ -77,6 +82,7 @@ class AclConnection {
 
   // State variables
   bool encrypted_{false};
+  std::array<uint8_t, 16> encryption_key_{};
   uint16_t link_policy_settings_{0};
   bluetooth::hci::Role role_{bluetooth::hci::Role::CENTRAL};
   std::chrono::steady_clock::time_point last_packet_timestamp_;
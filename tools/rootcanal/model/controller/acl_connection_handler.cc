@@ -226,6 +226,14 @@ bool AclConnectionHandler::IsEncrypted(uint16_t handle) const {
   return acl_connections_.at(handle).IsEncrypted();
 }
 
+void AclConnectionHandler::SetEncryptionKey(uint16_t handle,
+                                            std::array<uint8_t, 16> key) {
+  if (!HasHandle(handle)) {
+    return;
+  }
+  acl_connections_.at(handle).SetEncryptionKey(key);
+}
+
 Phy::Type AclConnectionHandler::GetPhyType(uint16_t handle) const {
   if (!HasHandle(handle)) {
     return Phy::Type::BR_EDR;
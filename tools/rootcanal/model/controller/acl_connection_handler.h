@@ -16,6 +16,7 @@
 
 #pragma once
 
+#include <array>
 #include <chrono>
 #include <cstdint>
 #include <set>
@@ -91,6 +92,7 @@ class AclConnectionHandler {
 
   void Encrypt(uint16_t handle);
   bool IsEncrypted(uint16_t handle) const;
+  void SetEncryptionKey(uint16_t handle, std::array<uint8_t, 16> key);
 
   Phy::Type GetPhyType(uint16_t handle) const;
 
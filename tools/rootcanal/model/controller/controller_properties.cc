@@ -144,6 +144,24 @@ static bool ParseUint(Json::Value root, std::string field_name,
   return false;
 }
 
+static bool ParseBool(Json::Value root, std::string field_name,
+                      bool& output_value) {
+  Json::Value value = root[field_name];
+
+  if (value.empty()) {
+    return false;
+  }
+
+  if (!value.isBool()) {
+    LOG_INFO("invalid value for %s is discarded: not a boolean",
+             field_name.c_str());
+    return false;
+  }
+
+  output_value = value.asBool();
+  return true;
+}
+
 template <typename T, std::size_t N>
 static bool ParseUintArray(Json::Value root, std::string field_name,
                            std::array<T, N>& output_value) {
@@ -319,6 +337,7 @@ ControllerProperties::ControllerProperties(const std::string& file_name)
             le_max_advertising_data_length);
   ParseUint(root, "le_num_supported_advertising_sets",
             le_num_supported_advertising_sets);
+  ParseBool(root, "secure_connections_only", secure_connections_only);
 
   ParseUintVector(root, "le_vendor_capabilities", le_vendor_capabilities);
 
@@ -108,6 +108,11 @@ struct ControllerProperties {
   // at any time. This behaviour is not emulated here.
   uint8_t le_num_supported_advertising_sets{8};
 
+  // Secure Connections Only Mode (Vol 3, Part C § 5.2.2.8).
+  // The link manager rejects authentication and encryption with peers
+  // that do not support Secure Connections.
+  bool secure_connections_only{false};
+
   // Vendor Information.
   // Provide parameters returned by vendor specific commands.
   std::vector<uint8_t> le_vendor_capabilities{};
@@ -157,6 +157,7 @@ DualModeController::DualModeController(const std::string& properties_filename,
   SET_SUPPORTED(WRITE_LE_HOST_SUPPORT, WriteLeHostSupport);
   SET_SUPPORTED(WRITE_SECURE_CONNECTIONS_HOST_SUPPORT,
                 WriteSecureConnectionsHostSupport);
+  SET_SUPPORTED(SET_MIN_ENCRYPTION_KEY_SIZE, SetMinEncryptionKeySize);
   SET_SUPPORTED(SET_EVENT_MASK, SetEventMask);
   SET_SUPPORTED(READ_INQUIRY_MODE, ReadInquiryMode);
   SET_SUPPORTED(WRITE_INQUIRY_MODE, WriteInquiryMode);
@@ -510,6 +511,9 @@ void DualModeController::ReadBufferSize(CommandView command) {
 }
 
 void DualModeController::ReadEncryptionKeySize(CommandView command) {
+#ifdef ROOTCANAL_LMP
+  link_layer_controller_.ForwardToLm(command);
+#else
   auto command_view = gd_hci::ReadEncryptionKeySizeView::Create(
       gd_hci::SecurityCommandView::Create(command));
   ASSERT(command_view.IsValid());
@@ -518,6 +522,7 @@ void DualModeController::ReadEncryptionKeySize(CommandView command) {
       kNumCommandPackets, ErrorCode::SUCCESS,
       command_view.GetConnectionHandle(),
       link_layer_controller_.GetEncryptionKeySize()));
+#endif /* ROOTCANAL_LMP */
 }
 
 void DualModeController::HostBufferSize(CommandView command) {
@@ -1288,6 +1293,21 @@ void DualModeController::ReadLocalOobExtendedData(CommandView command) {
   link_layer_controller_.ReadLocalOobExtendedData();
 }
 
+void DualModeController::SetMinEncryptionKeySize(CommandView command) {
+  auto command_view = gd_hci::SetMinEncryptionKeySizeView::Create(command);
+  ASSERT(command_view.IsValid());
+
+  uint8_t min_encryption_key_size = command_view.GetMinEncryptionKeySize();
+  auto status = ErrorCode::SUCCESS;
+  if (min_encryption_key_size < 1 || min_encryption_key_size > 16) {
+    status = ErrorCode::INVALID_HCI_COMMAND_PARAMETERS;
+  } else {
+    link_layer_controller_.SetMinEncryptionKeySize(min_encryption_key_size);
+  }
+  send_event_(bluetooth::hci::SetMinEncryptionKeySizeCompleteBuilder::Create(
+      kNumCommandPackets, status));
+}
+
 void DualModeController::WriteSimplePairingMode(CommandView command) {
   auto command_view = gd_hci::WriteSimplePairingModeView::Create(
       gd_hci::SecurityCommandView::Create(command));
@@ -1707,6 +1727,9 @@ void DualModeController::WriteExtendedInquiryResponse(CommandView command) {
 }
 
 void DualModeController::RefreshEncryptionKey(CommandView command) {
+#ifdef ROOTCANAL_LMP
+  link_layer_controller_.ForwardToLm(command);
+#else
   auto command_view = gd_hci::RefreshEncryptionKeyView::Create(
       gd_hci::SecurityCommandView::Create(command));
   ASSERT(command_view.IsValid());
@@ -1716,6 +1739,7 @@ void DualModeController::RefreshEncryptionKey(CommandView command) {
   // TODO: Support this in the link layer
   send_event_(bluetooth::hci::EncryptionKeyRefreshCompleteBuilder::Create(
       ErrorCode::SUCCESS, handle));
+#endif /* ROOTCANAL_LMP */
 }
 
 void DualModeController::WriteVoiceSetting(CommandView command) {
@@ -387,6 +387,9 @@ class DualModeController : public Device {
   // 7.3.95
   void ReadLocalOobExtendedData(CommandView args);
 
+  // 7.3.102
+  void SetMinEncryptionKeySize(CommandView args);
+
   // Informational Parameters Commands
   // Bluetooth Core Specification Version 4.2 Volume 2 Part E 7.4
 
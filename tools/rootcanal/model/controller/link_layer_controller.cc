@@ -1383,12 +1383,49 @@ LinkLayerController::LinkLayerController(const Address& address,
                       reinterpret_cast<uint8_t*>(result));
           },
 
+      .get_local_address =
+          [](void* user, uint8_t(*result)[6]) {
+            auto controller = static_cast<LinkLayerController*>(user);
+
+            auto address = controller->GetAddress();
+            std::copy(address.data(), address.data() + 6,
+                      reinterpret_cast<uint8_t*>(result));
+          },
+
+      .get_role =
+          [](void* user, uint16_t handle) {
+            auto controller = static_cast<LinkLayerController*>(user);
+            return static_cast<uint8_t>(
+                controller->connections_.GetAclRole(handle));
+          },
+
       .extended_features =
           [](void* user, uint8_t features_page) {
             auto controller = static_cast<LinkLayerController*>(user);
             return controller->GetLmpFeatures(features_page);
           },
 
+      .min_encryption_key_size =
+          [](void* user) {
+            auto controller = static_cast<LinkLayerController*>(user);
+            return controller->min_encryption_key_size_;
+          },
+
+      .secure_connections_only =
+          [](void* user) {
+            auto controller = static_cast<LinkLayerController*>(user);
+            return controller->properties_.secure_connections_only;
+          },
+
+      .set_encryption_key =
+          [](void* user, uint16_t handle, const uint8_t(*key)[16]) {
+            auto controller = static_cast<LinkLayerController*>(user);
+
+            std::array<uint8_t, 16> encryption_key;
+            std::copy(*key, *key + 16, encryption_key.begin());
+            controller->connections_.SetEncryptionKey(handle, encryption_key);
+          },
+
       .send_hci_event =
           [](void* user, const uint8_t* data, uintptr_t len) {
             auto controller = static_cast<LinkLayerController*>(user);
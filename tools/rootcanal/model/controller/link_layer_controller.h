@@ -718,6 +718,10 @@ class LinkLayerController {
     authentication_enable_ = enable;
   }
 
+  void SetMinEncryptionKeySize(uint8_t min_encryption_key_size) {
+    min_encryption_key_size_ = min_encryption_key_size;
+  }
+
   void SetScoFlowControlEnable(bool enable) {
     sco_flow_control_enable_ = enable;
   }
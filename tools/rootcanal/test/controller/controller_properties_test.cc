@@ -0,0 +1,65 @@
+/*
+ * Copyright 2022 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#include "model/controller/controller_properties.h"
+
+#include <gtest/gtest.h>
+
+#include <fstream>
+#include <string>
+
+namespace rootcanal {
+
+class ControllerPropertiesTest : public ::testing::Test {
+ public:
+  ControllerPropertiesTest() = default;
+  ~ControllerPropertiesTest() override = default;
+
+ protected:
+  // Write the configuration to a temporary file, and return its path.
+  std::string WriteConfiguration(std::string const& configuration) {
+    std::string file_name =
+        ::testing::TempDir() + "controller_properties_test.json";
+    std::ofstream file(file_name);
+    file << configuration;
+    return file_name;
+  }
+};
+
+TEST_F(ControllerPropertiesTest, SecureConnectionsOnlyDefault) {
+  ControllerProperties properties{};
+  ASSERT_FALSE(properties.secure_connections_only);
+}
+
+TEST_F(ControllerPropertiesTest, SecureConnectionsOnlyEnabled) {
+  ControllerProperties properties{
+      WriteConfiguration(R"({ "secure_connections_only": true })")};
+  ASSERT_TRUE(properties.secure_connections_only);
+}
+
+TEST_F(ControllerPropertiesTest, SecureConnectionsOnlyDisabled) {
+  ControllerProperties properties{
+      WriteConfiguration(R"({ "secure_connections_only": false })")};
+  ASSERT_FALSE(properties.secure_connections_only);
+}
+
+TEST_F(ControllerPropertiesTest, SecureConnectionsOnlyInvalid) {
+  ControllerProperties properties{
+      WriteConfiguration(R"({ "secure_connections_only": "1" })")};
+  ASSERT_FALSE(properties.secure_connections_only);
+}
+
+}  // namespace rootcanal